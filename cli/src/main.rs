@@ -5,13 +5,48 @@
 
 #![warn(clippy::pedantic)]
 
+use anyhow::Context as _;
 use cambridge_asm::{
-    compile::{self, CompiledProg},
-    exec::Io,
-    parse::{self, DefaultSet},
+    challenge::{Case, Challenge, Fixture},
+    compile::{self, cache::Cache, CompileOptions, CompiledProg, Envelope},
+    exec::{Encoding, Executor, Exit, Io, JsonTrace, Memory, Radix, Status, TextTrace, Width},
+    inst::InstSet,
+    parse::{self, DefaultSet, Diagnostics, ErrorMap, LintConfig, Severity, Syntax},
+    registry::{DynInstSet, InstRegistry},
 };
 use clap::{Parser, ValueEnum};
-use std::{fs::File, io::Read, path::PathBuf};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[cfg(not(debug_assertions))]
+use {cambridge_asm::exec::FlightRecorder, std::sync::Mutex};
+
+mod terminal;
+
+/// Context saved in a crash report if the interpreter panics
+///
+/// Crash reports are only written by release builds, since debug builds keep the default panic
+/// hook and its backtrace. Only `run` saves context, from the moment its input has been read, so
+/// panics while parsing are reported with the source but without any instructions.
+#[cfg(not(debug_assertions))]
+struct CrashInfo {
+    path: PathBuf,
+    /// Kept rather than read again, since standard input cannot be
+    source: String,
+    recorder: Option<FlightRecorder>,
+}
+
+#[cfg(not(debug_assertions))]
+static CRASH_INFO: Mutex<Option<CrashInfo>> = Mutex::new(None);
+
+/// Number of instructions kept in crash reports
+#[cfg(not(debug_assertions))]
+const CRASH_TRACE_LEN: usize = 64;
 
 #[derive(Parser)]
 #[clap(name = "Cambridge Pseudoassembly Interpreter")]
@@ -20,30 +55,39 @@ use std::{fs::File, io::Read, path::PathBuf};
 #[clap(about = "Run pseudoassembly from Cambridge International syllabus 9618 (2021)")]
 enum Commands {
     /// Run compiled or plaintext pseudoassembly
-    Run {
-        /// Path to the input file containing compiled or plaintext pseudoassembly
+    ///
+    /// Exits with 0 if the program reaches `END`, 1 if it cannot be parsed, 2 on a runtime error, and 3 if it runs past its last instruction without `END`.
+    Run(RunArgs),
+    /// Run pseudoassembly, printing each executed instruction, the time it took and what it changed
+    Trace {
+        /// Path to the input file containing pseudoassembly
         path: PathBuf,
 
-        /// Increase logging level
-        #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
-        verbosity: u8,
-
-        /// Show execution time
-        #[arg(short = 't', long = "bench")]
-        bench: bool,
-
-        /// Format of input file
+        /// Format of the trace
         #[arg(value_enum)]
         #[arg(short = 'f', long = "format")]
-        #[arg(default_value_t = InFormats::Pasm)]
-        format: InFormats,
+        #[arg(default_value_t = TraceFormat::Text)]
+        format: TraceFormat,
+
+        /// Path to write the trace to, or standard output if not given
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Stop after this many instructions
+        #[arg(short = 'n', long = "max-steps")]
+        max_steps: Option<u64>,
+
+        /// Reject syntax that is not in the Cambridge instruction table
+        #[arg(short = 'c', long = "cambridge")]
+        cambridge: bool,
     },
+
     /// Compile pseudoassembly
     Compile {
-        /// Path to the input file containing pseudoassembly
+        /// Path to the input file containing pseudoassembly, or `-` for standard input
         input: PathBuf,
 
-        /// Path to output file
+        /// Path to output file. Defaults to the input path with the extension of the format, or standard output if reading standard input
         #[arg(short = 'o', long = "output")]
         output: Option<PathBuf>,
 
@@ -64,16 +108,327 @@ enum Commands {
         /// Include debuginfo
         #[arg(short, long)]
         debug: bool,
+
+        /// Reject syntax that is not in the Cambridge instruction table
+        #[arg(short = 'c', long = "cambridge")]
+        cambridge: bool,
+
+        /// Compress the output. Compressed programs are detected and decompressed when read.
+        #[arg(value_enum)]
+        #[arg(short = 'z', long = "compress")]
+        compress: Option<Compression>,
+
+        /// Remove instructions that can never run
+        #[arg(long = "remove-dead-code")]
+        remove_dead_code: bool,
+
+        /// Precompute arithmetic on literals, e.g. `ADD ACC,#2,#3` becomes `LDM #5`
+        #[arg(long = "opt")]
+        opt: bool,
+
+        /// Directory to keep compiled programs in, so that unchanged programs are not compiled again
+        #[arg(long = "cache", value_name = "DIR")]
+        cache: Option<PathBuf>,
+
+        /// Compile again whenever the input changes, until stopped
+        #[arg(short = 'W', long = "watch")]
+        watch: bool,
+    },
+
+    /// Reconstruct pseudoassembly from a compiled program
+    Disasm {
+        /// Path to the input file containing compiled pseudoassembly
+        input: PathBuf,
+
+        /// Path to output file, or standard output if not given
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Format of input file
+        #[arg(value_enum)]
+        #[arg(short = 'f', long = "format")]
+        #[arg(default_value_t = OutFormats::Json)]
+        format: OutFormats,
     },
+
+    /// Check that pseudoassembly parses, without running it
+    Check {
+        /// Path to the input file containing pseudoassembly
+        path: PathBuf,
+
+        /// Print diagnostics as JSON lines instead of annotated snippets
+        #[arg(long = "json")]
+        json: bool,
+
+        /// Reject syntax that is not in the Cambridge instruction table
+        #[arg(short = 'c', long = "cambridge")]
+        cambridge: bool,
+    },
+
+    /// Format pseudoassembly consistently
+    Fmt {
+        /// Path to the input file containing pseudoassembly
+        path: PathBuf,
+
+        /// Overwrite the file instead of printing the formatted source
+        #[arg(short = 'w', long = "write")]
+        write: bool,
+    },
+
+    /// Check pseudoassembly for style problems and likely mistakes
+    Lint {
+        /// Path to the input file containing pseudoassembly
+        path: PathBuf,
+
+        /// Path to the level of each rule, in JSON, RON or YAML depending on the extension
+        #[arg(short = 'c', long = "config")]
+        config: Option<PathBuf>,
+    },
+
+    /// Run a program against test cases from fixture files
+    Test {
+        /// Path to the input file containing pseudoassembly
+        path: PathBuf,
+
+        /// Paths to the test cases, in JSON, TOML, RON or YAML depending on the extension
+        #[arg(required = true)]
+        fixtures: Vec<PathBuf>,
+    },
+
+    /// Run every submission in a directory against test cases, and report the results
+    Grade {
+        /// Path to the directory containing `.pasm` submissions
+        dir: PathBuf,
+
+        /// Path to the test cases, in JSON, TOML, RON or YAML depending on the extension
+        fixture: PathBuf,
+
+        /// Path to the report, or standard output if not given
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Format of the report
+        #[arg(value_enum)]
+        #[arg(short = 'f', long = "format")]
+        #[arg(default_value_t = ReportFormat::Csv)]
+        format: ReportFormat,
+
+        /// Maximum number of instructions executed per case, unless the case sets its own
+        #[arg(short = 's', long = "max-steps")]
+        #[arg(default_value_t = 1_000_000)]
+        max_steps: u64,
+    },
+
+    /// Grade one submission, writing its result to standard output as JSON
+    ///
+    /// Run by `grade` in a separate process for each submission, so that one that crashes the
+    /// interpreter cannot affect the others.
+    #[command(hide = true)]
+    GradeSubmission {
+        path: PathBuf,
+
+        fixture: PathBuf,
+
+        #[arg(long = "max-steps")]
+        max_steps: u64,
+    },
+
+    /// Run a program against a challenge, and score it
+    Challenge {
+        /// Path to the challenge, in JSON, RON or YAML depending on the extension
+        spec: PathBuf,
+
+        /// Path to the input file containing pseudoassembly
+        path: PathBuf,
+
+        /// Increase logging level
+        #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
+        verbosity: u8,
+    },
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Path to the input file containing compiled or plaintext pseudoassembly, or `-` for standard input
+    path: PathBuf,
+
+    /// Increase logging level
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
+    verbosity: u8,
+
+    /// Show execution time
+    #[arg(short = 't', long = "bench")]
+    bench: bool,
+
+    /// Format of input file
+    #[arg(value_enum)]
+    #[arg(short = 'f', long = "format")]
+    #[arg(default_value_t = InFormats::Pasm)]
+    format: InFormats,
+
+    /// Base used to display values, e.g. in `DBG` output
+    #[arg(value_enum)]
+    #[arg(short = 'r', long = "radix")]
+    #[arg(default_value_t = DisplayRadix::Dec)]
+    radix: DisplayRadix,
+
+    /// Width of registers and memory cells in bits, 8, 16, 32 or 64. Values wrap around and are displayed with this width, e.g. 8 for `B00000101`
+    #[arg(short = 'w', long = "width")]
+    width: Option<Width>,
+
+    /// Treat values as two's complement signed words
+    #[arg(short = 's', long = "signed")]
+    signed: bool,
+
+    /// Number of memory addresses, from 0. Accessing memory at or beyond this address is an error, even if the program defines it
+    #[arg(long = "mem-size")]
+    mem_size: Option<usize>,
+
+    /// Path to a file to load into memory before execution, replacing the program's values at the same addresses. CSV files have an address and a value on each line, and any other file is loaded one byte per address
+    #[arg(long = "mem")]
+    mem: Option<PathBuf>,
+
+    /// Address of the first byte of a binary file loaded with `--mem`
+    #[arg(long = "mem-base", default_value_t = 0)]
+    mem_base: usize,
+
+    /// Reject syntax that is not in the Cambridge instruction table
+    #[arg(short = 'c', long = "cambridge")]
+    cambridge: bool,
+
+    /// How `IN` and `OUT` read and write characters. With `utf8`, values are Unicode code points, so that e.g. `OUT #233` prints `é`
+    #[arg(value_enum)]
+    #[arg(short = 'e', long = "encoding")]
+    #[arg(default_value_t = CharEncoding::Byte)]
+    encoding: CharEncoding,
+
+    /// Path to a description of the instructions to accept, in JSON, TOML, RON or YAML depending on the extension
+    #[arg(short = 'i', long = "inst-set")]
+    inst_set: Option<PathBuf>,
+
+    #[command(flatten)]
+    redirect: Redirect,
+
+    /// Print the registers and memory after execution
+    #[arg(value_enum)]
+    #[arg(long = "dump-state", value_name = "FORMAT")]
+    #[arg(num_args = 0..=1, default_missing_value = "text")]
+    dump_state: Option<TraceFormat>,
+
+    /// Directory to keep compiled programs in, so that unchanged pseudoassembly is not parsed again
+    #[arg(long = "cache", value_name = "DIR")]
+    cache: Option<PathBuf>,
+}
+
+/// Files to use instead of the terminal for the input and output of a program
+#[derive(clap::Args)]
+struct Redirect {
+    /// Path to read the input of the program from, instead of standard input
+    #[arg(long = "stdin")]
+    stdin: Option<PathBuf>,
+
+    /// Path to write the output of the program to, instead of standard output
+    #[arg(long = "stdout")]
+    stdout: Option<PathBuf>,
+
+    /// Numbered output port and the path to write it to, e.g. `1=log.txt` for `OUTP #1,ACC`. Can be given more than once
+    #[arg(long = "port", value_name = "PORT=PATH", value_parser = port_path)]
+    ports: Vec<(usize, PathBuf)>,
+}
+
+fn port_path(arg: &str) -> Result<(usize, PathBuf), String> {
+    let (port, path) = arg
+        .split_once('=')
+        .ok_or_else(|| "expected PORT=PATH".to_string())?;
+    let port = port.parse().map_err(|e| format!("invalid port: {e}"))?;
+
+    Ok((port, path.into()))
+}
+
+impl Redirect {
+    fn io(self) -> std::io::Result<Io> {
+        let mut io = Io::default();
+
+        match self.stdin {
+            Some(path) => io.read = std::io::BufReader::new(Box::new(File::open(path)?)),
+            // Input from a file is not typed, so there is nothing to hide
+            None => io.set_echo_control(terminal::echo()),
+        }
+
+        if let Some(path) = self.stdout {
+            io.write = Box::new(std::io::BufWriter::new(File::create(path)?));
+        }
+
+        for (port, path) in self.ports {
+            io.attach_output(port, std::io::BufWriter::new(File::create(path)?));
+        }
+
+        Ok(io)
+    }
 }
 
 #[derive(ValueEnum, Clone)]
 enum InFormats {
     Pasm,
+    /// Address/instruction table, as in question papers
+    Table,
     Json,
     Ron,
     Yaml,
     Cbor,
+    Msgpack,
+}
+
+#[derive(ValueEnum, Clone)]
+enum DisplayRadix {
+    Dec,
+    Hex,
+    Bin,
+}
+
+impl From<DisplayRadix> for Radix {
+    fn from(radix: DisplayRadix) -> Self {
+        match radix {
+            DisplayRadix::Dec => Radix::Dec,
+            DisplayRadix::Hex => Radix::Hex,
+            DisplayRadix::Bin => Radix::Bin,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone)]
+enum CharEncoding {
+    /// One byte per character
+    Byte,
+    /// Unicode code points, encoded as UTF-8
+    Utf8,
+}
+
+impl From<CharEncoding> for Encoding {
+    fn from(encoding: CharEncoding) -> Self {
+        match encoding {
+            CharEncoding::Byte => Encoding::Byte,
+            CharEncoding::Utf8 => Encoding::Utf8,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum TraceFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Compression {
+    Gzip,
+    Zstd,
 }
 
 #[derive(ValueEnum, Clone)]
@@ -82,6 +437,7 @@ enum OutFormats {
     Ron,
     Yaml,
     Cbor,
+    Msgpack,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -90,15 +446,31 @@ fn main() -> anyhow::Result<()> {
 
     let command = Commands::parse();
 
-    let io = Io::default();
+    let mut io = Io::default();
+    io.set_echo_control(terminal::echo());
 
     match command {
-        Commands::Run {
+        Commands::Run(args) => match args.inst_set.clone() {
+            Some(spec) => {
+                let registry = InstRegistry::from_spec(&read_config(&spec)?)?;
+                registry.scope(|| run::<DynInstSet>(args))?;
+            }
+            None => run::<DefaultSet>(args)?,
+        },
+        Commands::Trace {
             path,
-            verbosity,
-            bench,
             format,
-        } => run(path, verbosity, bench, format, io)?,
+            output,
+            max_steps,
+            cambridge,
+        } => trace(
+            &path,
+            format,
+            output.as_deref(),
+            max_steps,
+            syntax(cambridge),
+            io,
+        )?,
         Commands::Compile {
             input,
             output,
@@ -106,39 +478,176 @@ fn main() -> anyhow::Result<()> {
             format,
             minify,
             debug,
-        } => compile(input, output, verbosity, format, minify, debug)?,
+            cambridge,
+            compress,
+            remove_dead_code,
+            opt,
+            cache,
+            watch,
+        } => {
+            let args = CompileArgs {
+                output,
+                format,
+                minify,
+                compress,
+                options: CompileOptions {
+                    debug,
+                    syntax: syntax(cambridge),
+                    remove_dead_code,
+                    fold_constants: opt,
+                },
+                cache: cache.map(Cache::new),
+            };
+
+            compile(input, verbosity, args, watch)?;
+        }
+        Commands::Disasm {
+            input,
+            output,
+            format,
+        } => disasm(input, output, format)?,
+        Commands::Check {
+            path,
+            json,
+            cambridge,
+        } => check(&path, json, syntax(cambridge))?,
+        Commands::Fmt { path, write } => fmt(&path, write)?,
+        Commands::Lint { path, config } => lint(&path, config.as_deref())?,
+        Commands::Test { path, fixtures } => test(&path, &fixtures)?,
+        Commands::Grade {
+            dir,
+            fixture,
+            output,
+            format,
+            max_steps,
+        } => grade(&dir, &fixture, output.as_deref(), format, max_steps)?,
+        Commands::GradeSubmission {
+            path,
+            fixture,
+            max_steps,
+        } => grade_submission(&path, &fixture, max_steps)?,
+        Commands::Challenge {
+            spec,
+            path,
+            verbosity,
+        } => challenge(spec, path, verbosity)?,
     }
 
     Ok(())
 }
 
-#[allow(clippy::enum_glob_use, clippy::needless_pass_by_value)]
-fn run(path: PathBuf, verbosity: u8, bench: bool, format: InFormats, io: Io) -> anyhow::Result<()> {
+/// Process exit code for how a program stopped. Failing to parse the program exits with 1.
+fn exit_code(exit: &Exit) -> i32 {
+    match exit {
+        Exit::End => 0,
+        Exit::Error(_) => 2,
+        Exit::FellOff => 3,
+        Exit::Cancelled => 4,
+    }
+}
+
+fn syntax(cambridge: bool) -> Syntax {
+    if cambridge {
+        Syntax::Cambridge
+    } else {
+        Syntax::Extended
+    }
+}
+
+#[allow(clippy::enum_glob_use)]
+fn run<T>(args: RunArgs) -> anyhow::Result<()>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
     use InFormats::*;
 
+    let RunArgs {
+        path,
+        verbosity,
+        bench,
+        format,
+        radix,
+        width,
+        signed,
+        mem_size,
+        mem,
+        mem_base,
+        cambridge,
+        encoding,
+        inst_set: _,
+        redirect,
+        dump_state,
+        cache,
+    } = args;
+
+    let syntax = syntax(cambridge);
+    let mut io = redirect.io()?;
+    io.set_encoding(encoding.into());
+    // Lets `INKEY` check for input without blocking
+    io.set_polled_input();
+
     init_logger(verbosity);
 
-    let file = File::open(path)?;
+    let mut input = Vec::new();
+    open_input(&path)?.read_to_end(&mut input)?;
 
-    let mut timer = bench.then(std::time::Instant::now);
+    #[cfg(not(debug_assertions))]
+    if let Ok(mut info) = CRASH_INFO.lock() {
+        *info = Some(CrashInfo {
+            path: path.clone(),
+            source: String::from_utf8_lossy(&input).into_owned(),
+            recorder: None,
+        });
+    }
 
-    let read_to_string = |mut f: File| -> std::io::Result<_> {
-        #[allow(clippy::cast_possible_truncation)]
-        let mut buf = String::with_capacity(f.metadata()?.len() as usize);
-        f.read_to_string(&mut buf)?;
-        Ok(buf)
-    };
+    let file = input.as_slice();
+
+    let mut timer = bench.then(std::time::Instant::now);
 
     let mut executor = match format {
-        Pasm => parse::jit::<DefaultSet>(read_to_string(file)?, io).unwrap(),
-        Json => serde_json::from_str::<CompiledProg>(&read_to_string(file)?)?
-            .to_executor::<DefaultSet>(io),
-        Ron => ron::from_str::<CompiledProg>(&read_to_string(file)?)?.to_executor::<DefaultSet>(io),
-        Yaml => serde_yaml::from_str::<CompiledProg>(&read_to_string(file)?)?
-            .to_executor::<DefaultSet>(io),
-        Cbor => ciborium::from_reader::<CompiledProg, _>(file)?.to_executor::<DefaultSet>(io),
+        Pasm | Table => {
+            let src = match format {
+                Table => parse::from_table(&read_to_string(file)?),
+                _ => read_to_string(file)?,
+            };
+
+            jit::<T>(&path, &src, io, syntax, cache.as_deref())?
+        }
+        Json => read_compiled(file, &OutFormats::Json)?.try_to_executor::<T>(io)?,
+        Ron => read_compiled(file, &OutFormats::Ron)?.try_to_executor::<T>(io)?,
+        Yaml => read_compiled(file, &OutFormats::Yaml)?.try_to_executor::<T>(io)?,
+        Cbor => read_compiled(file, &OutFormats::Cbor)?.try_to_executor::<T>(io)?,
+        Msgpack => read_compiled(file, &OutFormats::Msgpack)?.try_to_executor::<T>(io)?,
     };
 
+    executor.ctx.radix = radix.into();
+    executor.ctx.signed = signed;
+    executor.ctx.set_width(width);
+    executor.ctx.mem.set_size(mem_size);
+
+    if let Some(path) = mem {
+        let loaded = load_mem(&path, mem_base)?;
+        executor.ctx.mem.extend(&loaded);
+
+        for (addr, ty) in loaded.types() {
+            executor.ctx.mem.set_type(addr, ty);
+        }
+    }
+
+    // Recording slows down execution, so it is skipped when benchmarking
+    #[cfg(not(debug_assertions))]
+    if !bench {
+        let recorder = FlightRecorder::new(CRASH_TRACE_LEN);
+        executor.set_hook(recorder.clone());
+
+        if let Ok(mut info) = CRASH_INFO.lock() {
+            if let Some(info) = info.as_mut() {
+                info.recorder = Some(recorder);
+            }
+        }
+    }
+
     timer = timer.map(|t| {
         println!("Total parse time: {:?}", t.elapsed());
         std::time::Instant::now()
@@ -148,50 +657,223 @@ fn run(path: PathBuf, verbosity: u8, bench: bool, format: InFormats, io: Io) ->
         println!("Execution starts on next line");
     }
 
-    executor.exec::<DefaultSet>();
+    let exit = executor.exec_and_report();
 
     if let Some(t) = timer {
         println!("Execution done\nExecution time: {:?}", t.elapsed());
     }
 
+    match dump_state {
+        Some(TraceFormat::Text) => print!("{}", executor.context_snapshot()),
+        Some(TraceFormat::Json) => println!(
+            "{}",
+            serde_json::to_string_pretty(&executor.context_snapshot())?
+        ),
+        None => {}
+    }
+
+    let code = exit_code(&exit);
+
+    if code != 0 {
+        std::process::exit(code);
+    }
+
     Ok(())
 }
 
-#[allow(clippy::enum_glob_use, clippy::needless_pass_by_value)]
-fn compile(
-    mut input: PathBuf,
+/// Parse `src`, or load it from `cache` if it was compiled with the same options before
+fn jit<T>(
+    path: &Path,
+    src: &str,
+    io: Io,
+    syntax: Syntax,
+    cache: Option<&Path>,
+) -> anyhow::Result<Executor>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let Some(cache) = cache else {
+        let executor = parse::jit_with_syntax::<T>(src, io, syntax)
+            .map_err(|errors| parse_failed(path, src, &errors))?;
+        report(path, src, &executor.debug_info.warnings);
+        return Ok(executor);
+    };
+
+    // Debug info is kept for the labels and source lines in runtime errors
+    let options = CompileOptions {
+        debug: true,
+        syntax,
+        ..CompileOptions::default()
+    };
+
+    let compiled = Cache::new(cache)
+        .get_or_compile::<T>(src, &options)
+        .map_err(|errors| parse_failed(path, src, &errors))?;
+    report(path, src, compiled.warnings());
+
+    Ok(compiled.try_to_executor::<T>(io)?)
+}
+
+/// Read a memory file given with `--mem`
+fn load_mem(path: &Path, base: usize) -> anyhow::Result<Memory> {
+    let is_csv = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+    let mem = if is_csv {
+        Memory::from_csv(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("Unable to read memory from {}", path.display()))?
+    } else {
+        Memory::from_bin(&std::fs::read(path)?, base)
+    };
+
+    Ok(mem)
+}
+
+fn trace(
+    path: &Path,
+    format: TraceFormat,
+    output: Option<&Path>,
+    max_steps: Option<u64>,
+    syntax: Syntax,
+    io: Io,
+) -> anyhow::Result<()> {
+    let src = std::fs::read_to_string(path)?;
+
+    let mut executor = parse::jit_with_syntax::<DefaultSet>(src.as_str(), io, syntax)
+        .map_err(|errors| parse_failed(path, &src, &errors))?;
+    report(path, &src, &executor.debug_info.warnings);
+
+    let out: Box<dyn Write + Send + Sync> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout()),
+    };
+
+    match format {
+        TraceFormat::Text => executor.set_hook(TextTrace::new(out).with_timing()),
+        TraceFormat::Json => executor.set_hook(JsonTrace::new(out).with_timing()),
+    }
+
+    loop {
+        if max_steps.is_some_and(|max| executor.count() >= max) {
+            eprintln!("Stopped after {} instructions", executor.count());
+            break;
+        }
+
+        match executor.step() {
+            Status::Continue | Status::Breakpoint(_) => {}
+            Status::Complete | Status::Cancelled => break,
+            Status::Error(e) => {
+                eprintln!("Runtime error: {e}");
+                break;
+            }
+        }
+    }
+
+    // Flush the trace
+    drop(executor.take_hook());
+
+    Ok(())
+}
+
+/// Options of the `compile` command
+struct CompileArgs {
     output: Option<PathBuf>,
-    verbosity: u8,
     format: OutFormats,
     minify: bool,
-    debug: bool,
-) -> anyhow::Result<()> {
-    use OutFormats::*;
+    compress: Option<Compression>,
+    options: CompileOptions,
+    cache: Option<Cache>,
+}
 
+#[allow(clippy::needless_pass_by_value)]
+fn compile(input: PathBuf, verbosity: u8, args: CompileArgs, watch: bool) -> anyhow::Result<()> {
     init_logger(verbosity);
 
-    let prog = std::fs::read_to_string(&input)?;
+    if !watch {
+        return compile_once(input, &args);
+    }
+
+    anyhow::ensure!(!is_stdin(&input), "Standard input cannot be watched");
+
+    let mut compiled_at = None;
+
+    loop {
+        let modified = std::fs::metadata(&input)?.modified()?;
 
-    let compiled = compile::compile::<DefaultSet>(prog, debug).unwrap();
+        if compiled_at != Some(modified) {
+            compiled_at = Some(modified);
 
-    let output_path = output.unwrap_or_else(|| {
+            // Errors are shown, and the file is compiled again once it is fixed
+            match compile_once(input.clone(), &args) {
+                Ok(()) => eprintln!("Compiled {}", input.display()),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// How often `compile --watch` checks whether the input has changed
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+#[allow(clippy::enum_glob_use)]
+fn compile_once(mut input: PathBuf, args: &CompileArgs) -> anyhow::Result<()> {
+    use OutFormats::*;
+
+    let CompileArgs {
+        ref output,
+        ref format,
+        minify,
+        compress,
+        ref options,
+        ref cache,
+    } = *args;
+
+    let prog = read_to_string(open_input(&input)?)?;
+
+    let compiled = match cache {
+        Some(cache) => cache.get_or_compile::<DefaultSet>(&prog, options),
+        None => compile::compile_with_options::<DefaultSet>(prog.as_str(), options),
+    }
+    .map_err(|errors| parse_failed(&input, &prog, &errors))?;
+    report(&input, &prog, compiled.warnings());
+
+    let output_path = output.clone().or_else(|| {
         let ext = match format {
             Json => "json",
             Ron => "ron",
             Yaml => "yaml",
             Cbor => "cbor",
+            Msgpack => "msgpack",
+        };
+
+        let ext = match compress {
+            Some(Compression::Gzip) => format!("{ext}.gz"),
+            Some(Compression::Zstd) => format!("{ext}.zst"),
+            None => ext.to_string(),
         };
-        input.set_extension(ext);
-        input
+
+        (!is_stdin(&input)).then(|| {
+            input.set_extension(ext);
+            input
+        })
     });
 
-    let file = std::fs::OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(output_path)?;
+    let mut file: Box<dyn Write> = match output_path {
+        Some(path) => Box::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(true)
+                .write(true)
+                .open(path)?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
 
-    let json = |w: File, v: &CompiledProg| {
+    let json = |w: &mut dyn Write, v: &CompiledProg| {
         if minify {
             serde_json::to_writer(w, v)
         } else {
@@ -199,7 +881,7 @@ fn compile(
         }
     };
 
-    let ron = |w: File, v: &CompiledProg| {
+    let ron = |w: &mut dyn Write, v: &CompiledProg| {
         if minify {
             ron::ser::to_writer(w, v)
         } else {
@@ -207,20 +889,476 @@ fn compile(
         }
     };
 
-    let yaml = |w: File, v: &CompiledProg| serde_yaml::to_writer(w, v);
+    let yaml = |w: &mut dyn Write, v: &CompiledProg| serde_yaml::to_writer(w, v);
 
-    let cbor = |w: File, v: &CompiledProg| ciborium::ser::into_writer(v, w);
+    let cbor = |w: &mut dyn Write, v: &CompiledProg| ciborium::ser::into_writer(v, w);
 
-    match format {
-        Json => json(file, &compiled)?,
-        Ron => ron(file, &compiled)?,
-        Yaml => yaml(file, &compiled)?,
-        Cbor => cbor(file, &compiled)?,
+    // Field names are kept, so that the output is readable without this crate's types
+    let msgpack = |w: &mut dyn Write, v: &CompiledProg| rmp_serde::encode::write_named(w, v);
+
+    let write = |w: &mut dyn Write| -> anyhow::Result<()> {
+        match format {
+            Json => json(w, &compiled)?,
+            Ron => ron(w, &compiled)?,
+            Yaml => yaml(w, &compiled)?,
+            Cbor => cbor(w, &compiled)?,
+            Msgpack => msgpack(w, &compiled)?,
+        }
+
+        Ok(())
     };
 
+    // The encoders are finished explicitly, since finishing them on drop hides any error
+    match compress {
+        Some(Compression::Gzip) => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut file, flate2::Compression::default());
+            write(&mut encoder)?;
+            encoder.finish()?;
+        }
+        Some(Compression::Zstd) => {
+            let mut encoder = zstd::Encoder::new(&mut file, 0)?;
+            write(&mut encoder)?;
+            encoder.finish()?;
+        }
+        None => write(&mut file)?,
+    }
+
+    file.flush()?;
+
     Ok(())
 }
 
+#[allow(clippy::enum_glob_use, clippy::needless_pass_by_value)]
+fn disasm(input: PathBuf, output: Option<PathBuf>, format: OutFormats) -> anyhow::Result<()> {
+    let compiled = read_compiled(open_input(&input)?, &format)?;
+
+    let src = compile::decompile(&compiled);
+
+    match output {
+        Some(path) => std::fs::write(path, src)?,
+        None => print!("{src}"),
+    }
+
+    Ok(())
+}
+
+fn check(path: &Path, json: bool, syntax: Syntax) -> anyhow::Result<()> {
+    let src = std::fs::read_to_string(path)?;
+
+    let (_, diagnostics) = parse::jit_partial::<DefaultSet>(&src, Io::default(), syntax);
+    let errors = diagnostics.errors();
+
+    if json {
+        for d in diagnostics.iter() {
+            let severity = match d.kind.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+
+            println!(
+                "{}",
+                serde_json::json!({
+                    "path": path.display().to_string(),
+                    "line": d.line,
+                    "column": d.column,
+                    "start": d.span.start,
+                    "end": d.span.end,
+                    "severity": severity,
+                    "message": d.kind.to_string(),
+                })
+            );
+        }
+    } else {
+        // Nothing else can be done if stderr is unavailable
+        let _ = diagnostics
+            .with_path(path.display())
+            .render(&mut std::io::stderr());
+    }
+
+    if errors > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn fmt(path: &Path, write: bool) -> anyhow::Result<()> {
+    let src = std::fs::read_to_string(path)?;
+
+    let formatted = parse::format(&src).map_err(|errors| parse_failed(path, &src, &errors))?;
+
+    if write {
+        std::fs::write(path, formatted)?;
+    } else {
+        print!("{formatted}");
+    }
+
+    Ok(())
+}
+
+fn lint(path: &Path, config: Option<&Path>) -> anyhow::Result<()> {
+    let config = match config {
+        Some(config) => read_config(config)?,
+        None => LintConfig::default(),
+    };
+
+    let src = std::fs::read_to_string(path)?;
+
+    let lints = parse::lint::<DefaultSet>(&src, &config)
+        .map_err(|errors| parse_failed(path, &src, &errors))?;
+    report(path, &src, &lints);
+
+    if lints
+        .values()
+        .any(|kind| kind.severity() == Severity::Error)
+    {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Read a compiled program, checking its header first so that files from other versions fail
+/// with a clear error
+fn read_compiled(mut r: impl Read, format: &OutFormats) -> anyhow::Result<CompiledProg> {
+    let mut bytes = Vec::new();
+    r.read_to_end(&mut bytes)?;
+
+    let Envelope { header } = deserialize(&bytes, format)?;
+    header.check_version()?;
+
+    deserialize(&bytes, format)
+        .context("Artifact corrupted: the compiled file is damaged or truncated")
+}
+
+#[allow(clippy::enum_glob_use)]
+fn deserialize<T: serde::de::DeserializeOwned>(
+    bytes: &[u8],
+    format: &OutFormats,
+) -> anyhow::Result<T> {
+    use OutFormats::*;
+
+    Ok(match format {
+        Json => serde_json::from_slice(bytes)?,
+        Ron => ron::de::from_bytes(bytes)?,
+        Yaml => serde_yaml::from_slice(bytes)?,
+        Cbor => ciborium::from_reader(bytes)?,
+        Msgpack => rmp_serde::from_slice(bytes)?,
+    })
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Open `path` for reading, or standard input if it is `-`
+///
+/// Compressed input is decompressed, detected by the magic number at the start.
+fn open_input(path: &Path) -> std::io::Result<Box<dyn Read>> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+    let read: Box<dyn Read> = if is_stdin(path) {
+        Box::new(std::io::stdin().lock())
+    } else {
+        Box::new(File::open(path)?)
+    };
+
+    let mut read = std::io::BufReader::new(read);
+    let header = read.fill_buf()?;
+
+    if header.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(flate2::read::GzDecoder::new(read)))
+    } else if header.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(zstd::Decoder::with_buffer(read)?))
+    } else {
+        Ok(Box::new(read))
+    }
+}
+
+fn read_to_string(mut r: impl Read) -> std::io::Result<String> {
+    let mut buf = String::new();
+    r.read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+/// Read a JSON, TOML, RON or YAML file, depending on its extension
+fn read_config<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let src = std::fs::read_to_string(path)?;
+
+    Ok(match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&src)?,
+        Some("ron") => ron::from_str(&src)?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&src)?,
+        _ => serde_json::from_str(&src)?,
+    })
+}
+
+fn test(path: &Path, fixtures: &[PathBuf]) -> anyhow::Result<()> {
+    let prog = std::fs::read_to_string(path)?;
+
+    let (mut passed, mut failed) = (0, 0);
+
+    for fixture in fixtures {
+        let Fixture { cases } = read_config(fixture)?;
+
+        for (idx, case) in cases.iter().enumerate() {
+            let verdict = case
+                .run::<DefaultSet>(&prog)
+                .map_err(|errors| parse_failed(path, &prog, &errors))?;
+
+            let name = if case.name.is_empty() {
+                format!("case {}", idx + 1)
+            } else {
+                case.name.clone()
+            };
+
+            if verdict.passed() {
+                passed += 1;
+                println!("{}: {name} ... ok", fixture.display());
+            } else {
+                failed += 1;
+                println!("{}: {name} ... FAILED", fixture.display());
+
+                for failure in &verdict.failures {
+                    println!("  - {failure}");
+                }
+            }
+        }
+    }
+
+    println!("\n{passed} passed, {failed} failed");
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Result of one submission in a [`grade`] report
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Grade {
+    submission: String,
+    passed: usize,
+    cases: usize,
+    /// Number of instructions in the program
+    instructions: usize,
+    /// Number of instructions executed over every case
+    executed: u64,
+    errors: Vec<String>,
+}
+
+fn grade(
+    dir: &Path,
+    fixture: &Path,
+    output: Option<&Path>,
+    format: ReportFormat,
+    max_steps: u64,
+) -> anyhow::Result<()> {
+    let Fixture { cases } = read_config(fixture)?;
+
+    let mut submissions = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    submissions.retain(|path| path.extension().is_some_and(|ext| ext == "pasm"));
+    submissions.sort();
+
+    let exe = std::env::current_exe()?;
+    let mut grades = Vec::with_capacity(submissions.len());
+
+    for path in submissions {
+        // Each submission runs in its own process, so that one that crashes the interpreter,
+        // even by overflowing the stack, is recorded in the report instead
+        let out = std::process::Command::new(&exe)
+            .arg("grade-submission")
+            .arg(&path)
+            .arg(fixture)
+            .arg("--max-steps")
+            .arg(max_steps.to_string())
+            .stdin(std::process::Stdio::null())
+            .output()?;
+
+        let grade = if out.status.success() {
+            serde_json::from_slice(&out.stdout)?
+        } else {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            let msg = stderr
+                .lines()
+                .find(|line| !line.trim().is_empty())
+                .map_or_else(|| out.status.to_string(), str::to_string);
+
+            Grade {
+                submission: submission_name(&path),
+                passed: 0,
+                cases: cases.len(),
+                instructions: 0,
+                executed: 0,
+                errors: vec![msg],
+            }
+        };
+
+        grades.push(grade);
+    }
+
+    let report = match format {
+        ReportFormat::Csv => {
+            use std::fmt::Write;
+
+            let mut report = String::from("submission,passed,cases,instructions,executed,errors\n");
+
+            for g in &grades {
+                // Writing to a `String` cannot fail
+                let _ = writeln!(
+                    report,
+                    "{},{},{},{},{},{}",
+                    csv_field(&g.submission),
+                    g.passed,
+                    g.cases,
+                    g.instructions,
+                    g.executed,
+                    csv_field(&g.errors.join("; "))
+                );
+            }
+
+            report
+        }
+        ReportFormat::Json => serde_json::to_string_pretty(&grades)? + "\n",
+    };
+
+    match output {
+        Some(path) => std::fs::write(path, report)?,
+        None => print!("{report}"),
+    }
+
+    Ok(())
+}
+
+fn submission_name(path: &Path) -> String {
+    path.file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+}
+
+/// Grade the submission at `path` for [`grade`], which runs this in a separate process
+fn grade_submission(path: &Path, fixture: &Path, max_steps: u64) -> anyhow::Result<()> {
+    // The panic is reported by `grade`, from standard error, without a crash report
+    std::panic::set_hook(Box::new(|info| {
+        let payload = info.payload();
+        let msg = payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+
+        eprintln!("Crashed: {msg}");
+    }));
+
+    let Fixture { cases } = read_config(fixture)?;
+    let prog = std::fs::read_to_string(path)?;
+
+    let mut grade = Grade {
+        submission: submission_name(path),
+        passed: 0,
+        cases: cases.len(),
+        instructions: 0,
+        executed: 0,
+        errors: Vec::new(),
+    };
+
+    for (idx, case) in cases.iter().enumerate() {
+        let case = Case {
+            max_steps: case.max_steps.or(Some(max_steps)),
+            ..case.clone()
+        };
+
+        match case.run::<DefaultSet>(&prog) {
+            Ok(verdict) => {
+                grade.instructions = verdict.size;
+                grade.executed += verdict.steps;
+
+                if verdict.passed() {
+                    grade.passed += 1;
+                }
+
+                let name = if case.name.is_empty() {
+                    format!("case {}", idx + 1)
+                } else {
+                    case.name.clone()
+                };
+
+                grade.errors.extend(
+                    verdict
+                        .failures
+                        .iter()
+                        .map(|failure| format!("{name}: {failure}")),
+                );
+            }
+            Err(errors) => {
+                grade.errors.extend(
+                    Diagnostics::new(&prog, &errors)
+                        .iter()
+                        .filter(|d| d.kind.severity() == Severity::Error)
+                        .map(|d| format!("{}:{}: {}", d.line, d.column, d.kind)),
+                );
+                break;
+            }
+        }
+    }
+
+    println!("{}", serde_json::to_string(&grade)?);
+
+    Ok(())
+}
+
+/// Quote `field` if it cannot be written in a CSV file as it is
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn challenge(spec: PathBuf, path: PathBuf, verbosity: u8) -> anyhow::Result<()> {
+    init_logger(verbosity);
+
+    let challenge: Challenge = read_config(&spec)?;
+
+    let prog = std::fs::read_to_string(&path)?;
+
+    let verdict = challenge
+        .run::<DefaultSet>(&prog)
+        .map_err(|errors| parse_failed(&path, &prog, &errors))?;
+
+    if !challenge.name.is_empty() {
+        println!("{}", challenge.name);
+    }
+
+    println!("{verdict}");
+
+    if !verdict.passed() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Print errors and warnings from parsing `src` as annotated snippets
+fn report(path: &Path, src: &str, diagnostics: &ErrorMap) {
+    // Nothing else can be done if stderr is unavailable
+    let _ = Diagnostics::new(src, diagnostics)
+        .with_path(path.display())
+        .render(&mut std::io::stderr());
+}
+
+/// Print the errors from parsing `src`, and return an error to stop with
+fn parse_failed(path: &Path, src: &str, errors: &ErrorMap) -> anyhow::Error {
+    report(path, src, errors);
+    anyhow::anyhow!("Unable to parse {}", path.display())
+}
+
 fn init_logger(verbosity: u8) {
     set_log_level(verbosity);
     env_logger::builder()
@@ -242,6 +1380,7 @@ fn set_log_level(v: u8) {
 }
 
 #[cfg(not(debug_assertions))]
+#[allow(deprecated)] // `PanicHookInfo` is not available in the minimum supported Rust version
 fn handle_panic(info: &std::panic::PanicInfo) {
     if let Some(l) = info.location() {
         println!(
@@ -260,4 +1399,54 @@ fn handle_panic(info: &std::panic::PanicInfo) {
     }
 
     println!("To debug, try increasing the verbosity by passing -v flags if the error message is unclear.\nOpen an issue on github if the panic appears to be an internal error.");
+
+    match write_crash_report(info) {
+        Ok(Some(path)) => println!(
+            "A crash report was saved to {}, please attach it to the issue.",
+            path.display()
+        ),
+        Ok(None) => {}
+        Err(e) => println!("Unable to save a crash report: {e}"),
+    }
+}
+
+/// Write the source, options, state and last instructions to a temporary file
+#[cfg(not(debug_assertions))]
+fn write_crash_report(panic: &dyn std::fmt::Display) -> std::io::Result<Option<PathBuf>> {
+    use std::fmt::Write as _;
+
+    // The panic may have happened while the lock was held
+    let Ok(guard) = CRASH_INFO.try_lock() else {
+        return Ok(None);
+    };
+
+    let Some(CrashInfo {
+        path,
+        source,
+        recorder,
+    }) = &*guard
+    else {
+        return Ok(None);
+    };
+
+    let mut report = String::new();
+
+    // Writing to a `String` cannot fail
+    let _ = writeln!(report, "casm v{}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        report,
+        "Arguments: {:?}",
+        std::env::args().skip(1).collect::<Vec<_>>()
+    );
+    let _ = writeln!(report, "Panic: {panic}\n");
+    if let Some(recorder) = recorder {
+        let _ = writeln!(report, "{recorder}");
+    }
+    let _ = writeln!(report, "Source ({}):", path.display());
+    report.push_str(source);
+
+    let report_path = std::env::temp_dir().join(format!("casm-crash-{}.txt", std::process::id()));
+    std::fs::write(&report_path, report)?;
+
+    Ok(Some(report_path))
 }
@@ -0,0 +1,71 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use cambridge_asm::exec::EchoControl;
+#[cfg(unix)]
+use std::io;
+
+/// Controls echoing of the terminal attached to stdin
+///
+/// The terminal's settings are read on first use and kept, so turning echo on and off only
+/// writes them back.
+#[cfg(unix)]
+#[derive(Default)]
+enum Terminal {
+    #[default]
+    Unread,
+    /// stdin is not a terminal
+    Missing,
+    Settings(libc::termios),
+}
+
+#[cfg(unix)]
+impl EchoControl for Terminal {
+    fn set_echo(&mut self, enabled: bool) -> io::Result<()> {
+        if let Self::Unread = self {
+            let mut termios = std::mem::MaybeUninit::uninit();
+
+            // SAFETY: `tcgetattr` only writes to the pointer, and fully initializes it on success
+            *self = unsafe {
+                if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) == 0 {
+                    Self::Settings(termios.assume_init())
+                } else {
+                    Self::Missing
+                }
+            };
+        }
+
+        let Self::Settings(termios) = self else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stdin is not a terminal",
+            ));
+        };
+
+        if enabled {
+            termios.c_lflag |= libc::ECHO;
+        } else {
+            termios.c_lflag &= !libc::ECHO;
+        }
+
+        // SAFETY: `termios` was filled in by `tcgetattr`
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, termios) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+/// Echo control for the terminal attached to stdin, if the platform supports it
+// Always `Some` on unix
+#[allow(clippy::unnecessary_wraps)]
+pub fn echo() -> Option<Box<dyn EchoControl>> {
+    #[cfg(unix)]
+    return Some(Box::<Terminal>::default());
+
+    #[cfg(not(unix))]
+    return None;
+}
@@ -0,0 +1,424 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Practice challenges with goals, constraints and scores
+//!
+//! A [`Challenge`] describes what a program must achieve, and which constraints it must respect.
+//! [`Challenge::run`] runs a program against it and returns a [`Verdict`], which passes if every
+//! goal is met and awards up to three stars for short and fast solutions.
+//!
+//! A [`Fixture`] holds test [`Case`]s, which check the same goals without constraints or scores.
+//!
+//! # Example
+//! ```
+//! use cambridge_asm::{challenge::{Challenge, Goal, Stars}, parse::DefaultSet};
+//!
+//! let challenge = Challenge {
+//!     name: "Next letter".into(),
+//!     input: "a".into(),
+//!     goal: Goal {
+//!         output: Some("b".into()),
+//!         ..Goal::default()
+//!     },
+//!     allowed: vec!["IN".into(), "INC".into(), "OUT".into(), "END".into()],
+//!     stars: Stars {
+//!         max_size: Some(4),
+//!         max_steps: None,
+//!     },
+//!     ..Challenge::default()
+//! };
+//!
+//! let verdict = challenge.run::<DefaultSet>("IN\nINC ACC\nOUT\nEND\n\nNONE:\n")?;
+//!
+//! assert!(verdict.passed());
+//! assert_eq!(verdict.stars, 3);
+//! # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+//! ```
+
+use crate::{
+    exec::{Capture, RtError, Status},
+    inst::InstSet,
+    make_io,
+    parse::{jit, ErrorMap},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    io::{self, Write},
+    str::FromStr,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Goals and constraints for a program
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Challenge {
+    pub name: String,
+    pub description: String,
+    /// Input given to the program
+    pub input: String,
+    pub goal: Goal,
+    /// Maximum number of instructions executed before the program is stopped
+    pub max_steps: Option<u64>,
+    /// Opcodes the program may use, not case sensitive. Any opcode is allowed if empty.
+    pub allowed: Vec<String>,
+    pub stars: Stars,
+}
+
+/// State the program must finish in. Unset goals are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Goal {
+    /// Everything the program writes, ignoring trailing whitespace
+    pub output: Option<String>,
+    pub acc: Option<usize>,
+    pub memory: BTreeMap<usize, usize>,
+}
+
+/// Thresholds for the second and third stars
+///
+/// A passing program gets one star, and another for each threshold it is within. Unset
+/// thresholds are always met.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Stars {
+    /// Maximum number of instructions in the program
+    pub max_size: Option<usize>,
+    /// Maximum number of instructions executed
+    pub max_steps: Option<u64>,
+}
+
+/// Cases to test a program against, e.g. read from a fixture file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Fixture {
+    pub cases: Vec<Case>,
+}
+
+/// Input for a program and the state it must finish in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Case {
+    pub name: String,
+    /// Input given to the program
+    pub input: String,
+    pub expected: Goal,
+    /// Maximum number of instructions executed before the program is stopped
+    pub max_steps: Option<u64>,
+}
+
+impl Case {
+    /// Run `prog` with the input of the case
+    ///
+    /// Fails only if `prog` cannot be parsed. Everything else is reported in the [`Verdict`].
+    pub fn run<T>(&self, prog: &str) -> Result<Verdict, ErrorMap>
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        Challenge {
+            input: self.input.clone(),
+            goal: self.expected.clone(),
+            max_steps: self.max_steps,
+            ..Challenge::default()
+        }
+        .run::<T>(prog)
+    }
+}
+
+/// A goal or constraint that was not met
+#[derive(Debug)]
+pub enum Failure {
+    Opcode(String),
+    StepLimit(u64),
+    Error(RtError),
+    Output {
+        expected: String,
+        actual: String,
+    },
+    Acc {
+        expected: usize,
+        actual: usize,
+    },
+    Memory {
+        addr: usize,
+        expected: usize,
+        actual: Option<usize>,
+    },
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Opcode(opcode) => write!(f, "`{opcode}` is not allowed in this challenge"),
+            Self::StepLimit(limit) => write!(f, "Did not finish within {limit} instructions"),
+            Self::Error(e) => write!(f, "Runtime error: {e}"),
+            Self::Output { expected, actual } => {
+                write!(f, "Output was {actual:?}, expected {expected:?}")
+            }
+            Self::Acc { expected, actual } => write!(f, "ACC was {actual}, expected {expected}"),
+            Self::Memory {
+                addr,
+                expected,
+                actual: Some(actual),
+            } => write!(f, "Memory address {addr} was {actual}, expected {expected}"),
+            Self::Memory {
+                addr,
+                expected,
+                actual: None,
+            } => write!(
+                f,
+                "Memory address {addr} does not exist, expected {expected}"
+            ),
+        }
+    }
+}
+
+/// Result of running a program against a [`Challenge`]
+#[derive(Debug)]
+pub struct Verdict {
+    /// Number of instructions in the program
+    pub size: usize,
+    /// Number of instructions executed
+    pub steps: u64,
+    /// `0` if the program failed, otherwise `1` to `3`
+    pub stars: u8,
+    pub failures: Vec<Failure>,
+}
+
+impl Verdict {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            let stars = (0..3)
+                .map(|n| if n < self.stars { '★' } else { '☆' })
+                .collect::<String>();
+
+            write!(f, "PASSED {stars}")?;
+        } else {
+            f.write_str("FAILED")?;
+        }
+
+        write!(f, " ({} instructions, {} executed)", self.size, self.steps)?;
+
+        for failure in &self.failures {
+            write!(f, "\n  - {failure}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Challenge {
+    /// Run `prog` against the challenge
+    ///
+    /// Fails only if `prog` cannot be parsed. Everything else is reported in the [`Verdict`].
+    pub fn run<T>(&self, prog: &str) -> Result<Verdict, ErrorMap>
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        let out = Capture::default();
+        let mut exec = jit::<T>(
+            prog,
+            make_io!(
+                io::Cursor::new(self.input.clone().into_bytes()),
+                out.clone()
+            ),
+        )?;
+
+        let mut failures = Vec::new();
+
+        if !self.allowed.is_empty() {
+            for inst in exec.prog.values() {
+                let opcode = &inst.mnemonic;
+                let allowed = self
+                    .allowed
+                    .iter()
+                    .any(|other| other.eq_ignore_ascii_case(opcode));
+
+                if !allowed
+                    && !failures
+                        .iter()
+                        .any(|f| matches!(f, Failure::Opcode(o) if o == opcode))
+                {
+                    failures.push(Failure::Opcode(opcode.clone()));
+                }
+            }
+        }
+
+        loop {
+            if let Some(limit) = self.max_steps {
+                if exec.count() >= limit {
+                    failures.push(Failure::StepLimit(limit));
+                    break;
+                }
+            }
+
+            match exec.step() {
+                Status::Complete | Status::Cancelled => break,
+                Status::Continue | Status::Breakpoint(_) => {}
+                Status::Error(e) => {
+                    failures.push(Failure::Error(e));
+                    break;
+                }
+            }
+        }
+
+        // Errors are reported as failures instead, and the output cannot be checked after all
+        let _ = exec.ctx.io.write.flush();
+
+        if let Some(expected) = &self.goal.output {
+            let actual = String::from_utf8_lossy(&out.take()).trim_end().to_string();
+
+            if actual != expected.trim_end() {
+                failures.push(Failure::Output {
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        if let Some(expected) = self.goal.acc {
+            if exec.ctx.acc != expected {
+                failures.push(Failure::Acc {
+                    expected,
+                    actual: exec.ctx.acc,
+                });
+            }
+        }
+
+        for (&addr, &expected) in &self.goal.memory {
+            let actual = exec.ctx.mem.get(&addr).ok().copied();
+
+            if actual != Some(expected) {
+                failures.push(Failure::Memory {
+                    addr,
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        let size = exec.prog.len();
+        let steps = exec.count();
+
+        let stars = if failures.is_empty() {
+            1 + u8::from(self.stars.max_size.map_or(true, |max| size <= max))
+                + u8::from(self.stars.max_steps.map_or(true, |max| steps <= max))
+        } else {
+            0
+        };
+
+        Ok(Verdict {
+            size,
+            steps,
+            stars,
+            failures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod challenge_tests {
+    use super::{Challenge, Failure, Goal, Stars};
+    use crate::parse::DefaultSet;
+
+    #[test]
+    fn verdicts() {
+        let challenge = Challenge {
+            name: "Sum".into(),
+            goal: Goal {
+                acc: Some(6),
+                memory: [(201, 6)].into(),
+                ..Goal::default()
+            },
+            max_steps: Some(50),
+            allowed: vec!["LDD".into(), "ADD".into(), "STO".into(), "END".into()],
+            stars: Stars {
+                max_size: Some(5),
+                max_steps: Some(4),
+            },
+            ..Challenge::default()
+        };
+
+        let verdict = challenge
+            .run::<DefaultSet>("LDD 200\nADD 200\nADD 200\nSTO 201\nEND\n\n200 2\n201 0\n")
+            .unwrap();
+        assert!(verdict.passed());
+        assert_eq!((verdict.size, verdict.steps, verdict.stars), (5, 5, 2));
+        assert_eq!(
+            verdict.to_string(),
+            "PASSED ★★☆ (5 instructions, 5 executed)"
+        );
+
+        let verdict = challenge
+            .run::<DefaultSet>("LDM #6\nSTO 201\nX: JMP X\n\n201 0\n")
+            .unwrap();
+        assert_eq!(verdict.stars, 0);
+        assert!(matches!(
+            verdict.failures.as_slice(),
+            [Failure::Opcode(ldm), Failure::Opcode(jmp), Failure::StepLimit(50)] if ldm == "LDM" && jmp == "JMP"
+        ));
+
+        let verdict = challenge
+            .run::<DefaultSet>("LDD 200\nSTO 202\nEND\n\n200 2\n")
+            .unwrap();
+        assert_eq!(
+            verdict.to_string(),
+            "FAILED (3 instructions, 2 executed)
+  - Runtime error: Invalid memory address `202`
+  - ACC was 2, expected 6
+  - Memory address 201 does not exist, expected 6"
+        );
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn cases() {
+        use super::Case;
+
+        let prog = "RIN\nADD ACC,ACC\nOUTN\nSTO 200\nEND\n\n200 0\n";
+
+        let mut case = Case {
+            name: "Double".into(),
+            input: "21\n".into(),
+            expected: Goal {
+                output: Some("42\n".into()),
+                acc: Some(42),
+                memory: [(200, 42)].into(),
+            },
+            max_steps: Some(10),
+        };
+
+        assert!(case.run::<DefaultSet>(prog).unwrap().passed());
+
+        case.input = "1\n".into();
+        let verdict = case.run::<DefaultSet>(prog).unwrap();
+        assert!(matches!(
+            verdict.failures.as_slice(),
+            [
+                Failure::Output { .. },
+                Failure::Acc { actual: 2, .. },
+                Failure::Memory {
+                    actual: Some(2),
+                    ..
+                }
+            ]
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Deprecated shims for APIs from older releases
+//!
+//! The module layout mirrors the old crate, so most code can be migrated by
+//! replacing `cambridge_asm::` with `cambridge_asm::compat::` in imports, and then
+//! fixing the deprecation warnings one by one.
+
+/// Shims for the old `exec` module
+pub mod exec {
+    use crate::exec::{RtError, RtResult};
+
+    /// Old name of [`RtResult`]
+    #[deprecated(since = "0.22.0", note = "use `exec::RtResult` instead")]
+    pub type PasmResult<T = ()> = RtResult<T>;
+
+    /// Old name of [`RtError`]
+    #[deprecated(since = "0.22.0", note = "use `exec::RtError` instead")]
+    pub type PasmError = RtError;
+
+    /// Old name of [`ExecFunc`](crate::exec::ExecFunc)
+    #[deprecated(since = "0.22.0", note = "use `exec::ExecFunc` instead")]
+    pub type PasmFunc = crate::exec::ExecFunc;
+}
+
+/// Shims for the old `parse` module
+pub mod parse {
+    use crate::{
+        exec::{ExecFunc, Executor, Io},
+        parse::ErrorMap,
+        registry::InstRegistry,
+    };
+    use std::{fmt::Display, fs, path::Path, str::FromStr};
+
+    /// Old instruction set, a function from an instruction's name to its implementation
+    #[deprecated(
+        since = "0.22.0",
+        note = "define an instruction set with `inst_set!` or use `registry::InstRegistry`"
+    )]
+    pub type InstSet = fn(&str) -> Result<ExecFunc, String>;
+
+    /// Parse a file into an [`Executor`] using standard I/O
+    ///
+    /// Only the built-in instructions that `inst_set` gives a function for can be used, with the
+    /// operands of the built-in instruction of the same name.
+    ///
+    /// # Panics
+    /// If the file cannot be read.
+    #[deprecated(since = "0.22.0", note = "use `parse::jit_from_file` instead")]
+    #[allow(deprecated)]
+    pub fn parse(path: impl AsRef<Path>, inst_set: InstSet) -> Result<Executor, ErrorMap> {
+        let prog = fs::read_to_string(path).expect("Cannot read file");
+
+        InstRegistry::from_lookup(|name| inst_set(name).ok()).jit(prog, Io::default())
+    }
+
+    /// Get the function implementing an instruction of `T` from its name
+    ///
+    /// `get_fn::<T>` can be passed to [`parse`] as its instruction set.
+    #[deprecated(
+        since = "0.22.0",
+        note = "parse the instruction with `FromStr` and use `InstSet::as_func_ptr` instead"
+    )]
+    pub fn get_fn<T>(op: &str) -> Result<ExecFunc, String>
+    where
+        T: crate::inst::InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        op.parse::<T>()
+            .map(|inst| inst.as_func_ptr())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod compat_tests {
+    use crate::{
+        compat::parse::{get_fn, parse},
+        exec::{Context, ExecFunc},
+        inst::Op,
+        parse::Core,
+    };
+
+    #[test]
+    fn old_get_fn() {
+        let mut ctx = Context::default();
+
+        get_fn::<Core>("end").unwrap()(&mut ctx, &Op::Null).unwrap();
+
+        assert!(ctx.end);
+        assert!(get_fn::<Core>("foo").is_err());
+    }
+
+    #[test]
+    fn old_parse() {
+        fn no_add(op: &str) -> Result<ExecFunc, String> {
+            match op {
+                "ADD" => Err("no ADD".into()),
+                op => get_fn::<Core>(op),
+            }
+        }
+
+        let path = std::env::temp_dir().join("cambridge_asm_old_parse.pasm");
+        std::fs::write(&path, "LDM #1\nADD #1\nEND\n\nNONE:\n").unwrap();
+
+        let mut exec = parse(&path, get_fn::<Core>).unwrap();
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.acc, 2);
+
+        assert!(parse(&path, no_add).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
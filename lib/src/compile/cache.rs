@@ -0,0 +1,210 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! On-disk cache of compiled programs
+//!
+//! Programs are stored under a hash of their source, instruction set, and compile options, so
+//! unchanged files are only compiled once.
+
+use super::{compile_with_options, CompileOptions, CompiledProg, Fnv};
+use crate::{
+    inst::InstSet,
+    parse::{ErrorMap, Syntax},
+};
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Directory of compiled programs, keyed by a hash of everything that affects compilation
+///
+/// # Example
+/// ```no_run
+/// use cambridge_asm::{
+///     compile::{cache::Cache, CompileOptions},
+///     make_io,
+///     parse::DefaultSet,
+/// };
+///
+/// let cache = Cache::new("target/pasm-cache");
+/// let src = std::fs::read_to_string("prog.pasm").unwrap();
+///
+/// // Only compiled the first time
+/// let prog = cache
+///     .get_or_compile::<DefaultSet>(&src, &CompileOptions::default())
+///     .unwrap();
+/// prog.to_executor::<DefaultSet>(make_io!()).exec_and_report();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Use `dir` as the cache directory. It is created when the first program is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Key under which a program is stored
+    ///
+    /// Includes the crate version, since the compiled format may change between versions.
+    pub fn key<T>(src: &str, options: &CompileOptions) -> u64 {
+        let CompileOptions {
+            debug,
+            syntax,
+            remove_dead_code,
+            fold_constants,
+        } = *options;
+
+        let syntax = match syntax {
+            Syntax::Extended => 0,
+            Syntax::Cambridge => 1,
+        };
+
+        let mut hash = Fnv::default();
+
+        hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+        hash.write(std::any::type_name::<T>().as_bytes());
+        hash.write(&[
+            u8::from(debug),
+            syntax,
+            u8::from(remove_dead_code),
+            u8::from(fold_constants),
+        ]);
+        hash.write(src.as_bytes());
+
+        hash.0
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.json"))
+    }
+
+    /// Load a program from the cache, if present
+    pub fn get<T>(&self, src: &str, options: &CompileOptions) -> Option<CompiledProg> {
+        let path = self.path(Self::key::<T>(src, options));
+        let file = fs::File::open(path).ok()?;
+
+        match serde_json::from_reader(io::BufReader::new(file)) {
+            Ok(prog) => Some(prog),
+            Err(e) => {
+                warn!("Ignoring corrupted cache entry: {e}");
+                None
+            }
+        }
+    }
+
+    /// Store a compiled program in the cache
+    pub fn insert<T>(
+        &self,
+        src: &str,
+        options: &CompileOptions,
+        prog: &CompiledProg,
+    ) -> io::Result<()> {
+        let path = self.path(Self::key::<T>(src, options));
+
+        fs::create_dir_all(&self.dir)?;
+
+        // Write to a temporary file first, so that readers never see a partial entry
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        serde_json::to_writer(io::BufWriter::new(fs::File::create(&tmp)?), prog)?;
+        fs::rename(tmp, path)
+    }
+
+    /// Load a program from the cache, or compile and store it if it is not present
+    ///
+    /// Failing to store the program is not an error, since it can be compiled again. Warnings are
+    /// not stored, so programs loaded from the cache have none.
+    pub fn get_or_compile<T>(
+        &self,
+        src: &str,
+        options: &CompileOptions,
+    ) -> Result<CompiledProg, ErrorMap>
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        if let Some(prog) = self.get::<T>(src, options) {
+            debug!("Loaded program from cache");
+            return Ok(prog);
+        }
+
+        let prog = compile_with_options::<T>(src, options)?;
+
+        if let Err(e) = self.insert::<T>(src, options, &prog) {
+            warn!("Unable to store program in cache: {e}");
+        }
+
+        Ok(prog)
+    }
+
+    /// Delete all cached programs
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::{Cache, CompileOptions};
+    use crate::parse::{DefaultSet, Syntax};
+
+    #[test]
+    fn cache() {
+        let dir = std::env::temp_dir().join(format!("casm-cache-test-{}", std::process::id()));
+        let cache = Cache::new(&dir);
+        let src = include_str!("../../examples/hello.pasm");
+        let opts = CompileOptions::default();
+
+        assert!(cache.get::<DefaultSet>(src, &opts).is_none());
+
+        let mut compiled = cache.get_or_compile::<DefaultSet>(src, &opts).unwrap();
+        let cached = cache.get::<DefaultSet>(src, &opts).unwrap();
+        // Warnings are not serialized
+        assert!(cached.warnings.is_empty());
+        compiled.warnings.clear();
+        assert_eq!(format!("{compiled:?}"), format!("{cached:?}"));
+
+        // Options and instruction set are part of the key
+        for changed in [
+            CompileOptions {
+                debug: true,
+                ..opts
+            },
+            CompileOptions {
+                syntax: Syntax::Cambridge,
+                ..opts
+            },
+            CompileOptions {
+                remove_dead_code: true,
+                ..opts
+            },
+            CompileOptions {
+                fold_constants: true,
+                ..opts
+            },
+        ] {
+            assert!(cache.get::<DefaultSet>(src, &changed).is_none());
+        }
+        #[cfg(feature = "extended")]
+        assert_ne!(
+            Cache::key::<crate::parse::Core>(src, &opts),
+            Cache::key::<DefaultSet>(src, &opts)
+        );
+
+        cache.clear().unwrap();
+        assert!(cache.get::<DefaultSet>(src, &opts).is_none());
+    }
+}
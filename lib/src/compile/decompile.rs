@@ -0,0 +1,193 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{CompiledInst, CompiledProg};
+use crate::{exec::MemEntry, inst::Op};
+use std::{collections::BTreeMap, fmt::Write as _};
+
+/// Instructions whose address operands refer to instructions rather than memory
+const JUMPS: &[&str] = &["JMP", "JPE", "JPN", "JPG", "JPL", "JGE", "JLE", "CALL"];
+
+/// Reconstruct source code from a [`CompiledProg`]
+///
+/// Labels from the debug info are used where present. Otherwise, jump targets are given
+/// labels like `L3`, and memory is written with bare addresses. If the instructions are not at
+/// consecutive addresses from 0, every instruction is written with its bare address instead.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{compile::{compile, decompile}, parse::DefaultSet};
+///
+/// let src = "LOOP: LDD X\nINC ACC\nSTO X\nCMP #3\nJPN LOOP\nEND\n\nX: 0\n";
+///
+/// assert_eq!(
+///     decompile(&compile::<DefaultSet>(src, false)?),
+///     "L0: LDD 1\nINC ACC\nSTO 1\nCMP #3\nJPN L0\nEND\n\n1 0\n"
+/// );
+/// assert_eq!(decompile(&compile::<DefaultSet>(src, true)?), src);
+/// # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+/// ```
+pub fn decompile(prog: &CompiledProg) -> String {
+    let debug = prog.debug_info.as_ref();
+    let bare = prog.prog.keys().copied().ne(0..prog.prog.len());
+
+    let mut inst_labels = BTreeMap::new();
+    let mem_labels = debug.map(|d| d.mem.clone()).unwrap_or_default();
+
+    if !bare {
+        inst_labels = debug.map(|d| d.prog.clone()).unwrap_or_default();
+
+        let targets = prog
+            .prog
+            .values()
+            .filter(|inst| is_jump(inst))
+            .flat_map(|inst| addrs(&inst.op))
+            .filter(|addr| prog.prog.contains_key(addr))
+            .collect::<Vec<_>>();
+
+        for addr in targets {
+            if !inst_labels.contains_key(&addr) {
+                let mut label = format!("L{addr}");
+
+                while inst_labels
+                    .values()
+                    .chain(mem_labels.values())
+                    .any(|l| *l == label)
+                {
+                    label.push('_');
+                }
+
+                inst_labels.insert(addr, label);
+            }
+        }
+    }
+
+    let aliases = debug.map(|d| d.aliases.clone()).unwrap_or_default();
+    let mut out = String::new();
+
+    // Writing to a `String` cannot fail
+    for (reg, name) in &aliases {
+        let _ = writeln!(out, "{name} ALIAS r{reg}");
+    }
+
+    for (addr, inst) in &prog.prog {
+        if bare {
+            let _ = write!(out, "{addr} ");
+        } else if let Some(label) = inst_labels.get(addr) {
+            let _ = write!(out, "{label}: ");
+        }
+
+        let labels = if is_jump(inst) {
+            &inst_labels
+        } else {
+            &mem_labels
+        };
+
+        out.push_str(&inst.inst);
+
+        if !matches!(inst.op, Op::Null) {
+            let _ = write!(out, " {}", relabel(&inst.op, labels, &aliases));
+        }
+
+        if let Some(comment) = debug.and_then(|d| d.comment(*addr)) {
+            let _ = write!(out, " // {comment}");
+        }
+
+        out.push('\n');
+    }
+
+    out.push('\n');
+
+    let mut mem = prog.mem.clone();
+
+    for (&addr, &ty) in debug.into_iter().flat_map(|d| &d.mem_types) {
+        mem.set_type(addr, ty);
+    }
+
+    // Labelled cells are placed when parsing, so the unlabelled cells right after one are written
+    // in a list with it to keep them after it. Each line is its start, its values, the address
+    // after it, and whether more values can be added to it.
+    let mut lines: Vec<(String, Vec<String>, Option<usize>, bool)> = Vec::new();
+
+    for (addr, entry) in mem.entries() {
+        let cells = match entry {
+            // Strings are written as literals, unless they have characters that cannot be escaped
+            MemEntry::Str(s) if !format!("{s:?}").contains("\\u{") => {
+                vec![(format!("{s:?}"), s.chars().count(), false)]
+            }
+            MemEntry::Str(s) => s
+                .chars()
+                .map(|c| (u32::from(c).to_string(), 1, true))
+                .collect(),
+            MemEntry::Word(val) => vec![(val.to_string(), 1, true)],
+            MemEntry::Byte(val) => vec![(val.to_string(), 1, true)],
+        };
+
+        let mut addr = Some(addr);
+
+        for (val, len, is_number) in cells {
+            let start = match addr {
+                Some(start) => start,
+                None => break,
+            };
+            let next = start.checked_add(len);
+
+            match (mem_labels.get(&start), lines.last_mut()) {
+                (Some(label), _) => lines.push((format!("{label}:"), vec![val], next, is_number)),
+                (None, Some((_, vals, end, true))) if *end == addr && is_number => {
+                    vals.push(val);
+                    *end = next;
+                }
+                (None, _) => lines.push((start.to_string(), vec![val], next, false)),
+            }
+
+            addr = next;
+        }
+    }
+
+    for (start, vals, ..) in lines {
+        if let [val] = &vals[..] {
+            let _ = writeln!(out, "{start} {val}");
+        } else {
+            let _ = writeln!(out, "{start} [{}]", vals.join(", "));
+        }
+    }
+
+    if prog.mem.is_empty() {
+        out.push_str("NONE:\n");
+    }
+
+    out
+}
+
+fn is_jump(inst: &CompiledInst) -> bool {
+    JUMPS.iter().any(|j| inst.inst.eq_ignore_ascii_case(j))
+}
+
+/// Addresses directly in `op`
+pub(super) fn addrs(op: &Op) -> Vec<usize> {
+    match op {
+        Op::Addr(addr) => vec![*addr],
+        Op::MultiOp(ops) => ops.iter().flat_map(addrs).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `op` with addresses replaced by their labels, and registers by their aliases
+fn relabel(op: &Op, labels: &BTreeMap<usize, String>, aliases: &BTreeMap<usize, String>) -> String {
+    match op {
+        Op::Addr(addr) => labels
+            .get(addr)
+            .cloned()
+            .unwrap_or_else(|| addr.to_string()),
+        Op::Indirect(op) => format!("({})", relabel(op, labels, aliases)),
+        Op::MultiOp(ops) => ops
+            .iter()
+            .map(|op| relabel(op, labels, aliases))
+            .collect::<Vec<_>>()
+            .join(","),
+        op => op.display_aliased(aliases),
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{compile_with_syntax, optimize, CompiledProg};
+use crate::{
+    inst::{InstSet, Op},
+    parse::{outline, ErrorKind, ErrorMap, Span, Syntax},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    str::FromStr,
+};
+
+/// Source of routines to multiply, divide, and read and write numbers, which can be linked into
+/// programs with [`Unit::stdlib`] or `IMPORT stdlib`
+///
+/// The routines use `CALL` and `RET`, so they need the [`Extended`](crate::parse::Extended) set.
+#[cfg(feature = "extended")]
+pub const STDLIB: &str = include_str!("stdlib.pasm");
+
+/// A source file to be linked with others, see [`link`]
+#[derive(Debug, Clone)]
+pub struct Unit {
+    /// Name used to report errors, such as the path of the file
+    pub name: String,
+    pub src: String,
+}
+
+impl Unit {
+    pub fn new(name: impl Into<String>, src: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            src: src.into(),
+        }
+    }
+
+    /// The [`STDLIB`] as a unit
+    #[cfg(feature = "extended")]
+    pub fn stdlib() -> Self {
+        Self::new("stdlib", STDLIB)
+    }
+}
+
+/// Problems in each [`Unit`] that could not be linked, by name, with spans in its source
+pub type LinkErrors = BTreeMap<String, ErrorMap>;
+
+/// Part of a [`Unit`] copied into the linked source
+struct Piece {
+    unit: usize,
+    /// Offset in the linked source
+    at: usize,
+    /// Span in the source of the unit
+    from: Span,
+}
+
+/// Combine several sources into one [`CompiledProg`]
+///
+/// The instructions of each unit are placed one after the other, in order, followed by all of
+/// their memory, so execution starts at the first instruction of the first unit. Labels and
+/// constants are shared by all units, so a unit can jump to or load from labels in another.
+/// Addresses written as numbers are not moved, and refer to the linked program.
+///
+/// Units may leave out the memory if they have none. A line with `IMPORT stdlib` links the
+/// [`STDLIB`] after the other units. Labels defined more than once and labels
+/// that are never defined are errors, unlike in [`compile`](super::compile). Warnings are not
+/// kept, as they cannot refer to a unit.
+pub fn link<T>(units: &[Unit], debug: bool) -> Result<CompiledProg, LinkErrors>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let units = &imports(units)[..];
+    let mut errors = LinkErrors::new();
+    let mut defined = HashMap::new();
+
+    let mut src = String::new();
+    let mut pieces = Vec::new();
+    let mut mems = Vec::new();
+
+    let mut copy = |src: &mut String, unit: usize, from: Span| {
+        pieces.push(Piece {
+            unit,
+            at: src.len(),
+            from: from.clone(),
+        });
+
+        src.push_str(&units[unit].src[from]);
+
+        if !src.ends_with('\n') {
+            src.push('\n');
+        }
+    };
+
+    for (idx, unit) in units.iter().enumerate() {
+        let outline = outline(&unit.src);
+
+        for (span, label) in outline.labels {
+            if defined.insert(label.clone(), idx).is_some() {
+                errors
+                    .entry(unit.name.clone())
+                    .or_default()
+                    .insert(span, ErrorKind::DuplicateLabel(label));
+            }
+        }
+
+        let prog_end = outline.mem.as_ref().map_or(unit.src.len(), |mem| mem.start);
+        copy(&mut src, idx, 0..prog_end);
+        mems.extend(outline.mem.map(|mem| (idx, mem)));
+    }
+
+    src.push('\n');
+
+    if mems.is_empty() {
+        src.push_str("NONE:\n");
+    }
+
+    for (idx, mem) in mems {
+        copy(&mut src, idx, mem);
+    }
+
+    // Spans between pieces are moved to the end of the piece before
+    let locate = |span: &Span| {
+        let piece = pieces
+            .iter()
+            .rev()
+            .find(|p| p.at <= span.start)
+            .unwrap_or(&pieces[0]);
+        let offset =
+            |idx: usize| piece.from.start + idx.saturating_sub(piece.at).min(piece.from.len());
+
+        (piece.unit, offset(span.start)..offset(span.end))
+    };
+
+    let mut compiled = match compile_with_syntax::<T>(src.as_str(), true, Syntax::default(), false)
+    {
+        Ok(compiled) => compiled,
+        Err(parse_errors) => {
+            for (span, err) in parse_errors {
+                let (unit, span) = locate(&span);
+                errors
+                    .entry(units[unit].name.clone())
+                    .or_default()
+                    .insert(span, err);
+            }
+
+            return Err(errors);
+        }
+    };
+
+    let mut debug_info = compiled.debug_info.take().unwrap_or_default();
+
+    for (addr, inst) in &compiled.prog {
+        if let (Some(label), Some(span)) = (undefined(&inst.op), debug_info.span_for_inst(*addr)) {
+            let (unit, span) = locate(span);
+            let label = label.split(['+', '-']).next().unwrap_or(label).to_string();
+
+            errors
+                .entry(units[unit].name.clone())
+                .or_default()
+                .insert(span, ErrorKind::UndefinedLabel(label));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    optimize::eliminate_dead_code::<T>(&mut compiled.prog, &mut debug_info);
+
+    if debug {
+        // Lines are counted from the start of each unit
+        for (line, span) in debug_info
+            .source_map
+            .values_mut()
+            .zip(&debug_info.inst_spans)
+        {
+            let (unit, span) = locate(span);
+            line.line = units[unit].src[..span.start].matches('\n').count() + 1;
+        }
+
+        compiled.debug_info = Some(debug_info);
+    }
+
+    compiled.warnings.clear();
+    compiled.checksum = Some(compiled.checksum());
+
+    Ok(compiled)
+}
+
+/// `units`, followed by the units they import, with `IMPORT` lines replaced by comments of the
+/// same length, so that spans are not moved
+fn imports(units: &[Unit]) -> Vec<Unit> {
+    #[allow(unused_mut)]
+    let mut units = units.to_vec();
+
+    #[cfg(feature = "extended")]
+    {
+        let mut stdlib = false;
+
+        for unit in &mut units {
+            let is_import = |line: &str| {
+                let mut words = line.split_whitespace();
+
+                matches!(
+                    (words.next(), words.next(), words.next()),
+                    (Some(import), Some("stdlib"), None) if import.eq_ignore_ascii_case("import")
+                )
+            };
+
+            if unit.src.lines().any(is_import) {
+                stdlib = true;
+                unit.src = unit
+                    .src
+                    .split_inclusive('\n')
+                    .map(|line| {
+                        if is_import(line) {
+                            line.replacen(|c: char| !c.is_whitespace(), "/", 2)
+                        } else {
+                            line.to_string()
+                        }
+                    })
+                    .collect();
+            }
+        }
+
+        if stdlib {
+            units.push(Unit::stdlib());
+        }
+    }
+
+    units
+}
+
+/// Label in `op` that was not linked to an address
+fn undefined(op: &Op) -> Option<&str> {
+    match op {
+        Op::Fail(label) => Some(label),
+        Op::Indirect(op) => undefined(op),
+        Op::MultiOp(ops) => ops.iter().find_map(undefined),
+        _ => None,
+    }
+}
@@ -0,0 +1,852 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::{
+    exec::{DebugInfo, ExecInst, Executor, Io, Memory, Source},
+    inst::{InstSet, Op},
+    parse::{parse, ErrorMap, Syntax},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    ops::{Deref, RangeInclusive},
+    path::Path,
+    str::FromStr,
+};
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "cache")]
+pub mod cache;
+
+mod decompile;
+mod link;
+mod optimize;
+
+pub use decompile::decompile;
+#[cfg(feature = "extended")]
+pub use link::STDLIB;
+pub use link::{link, LinkErrors, Unit};
+
+/// Version of the serialized format of [`CompiledProg`], increased whenever it changes
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Format version and instruction set of a [`CompiledProg`]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    /// [`FORMAT_VERSION`] of the crate that wrote the file, or `0` if it predates versioning
+    pub version: u32,
+    /// Fingerprint of the instruction set the program was compiled with, see [`Header::of`], or
+    /// empty if unknown
+    pub inst_set: String,
+    /// Name of the type of the instruction set, e.g. `Extended`, or empty if unknown
+    pub inst_set_name: String,
+}
+
+impl Header {
+    /// Header for a program compiled now with `T`
+    ///
+    /// The fingerprint is a hash of the mnemonics of `T` in order of their ids, since both must
+    /// match for the program to be loaded correctly.
+    pub fn of<T>() -> Self
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        let mut hash = Fnv::default();
+
+        for inst in (0..).map_while(|id| T::from_id(id).ok()) {
+            hash.write(inst.mnemonic().as_bytes());
+        }
+
+        let name = std::any::type_name::<T>();
+
+        Self {
+            version: FORMAT_VERSION,
+            inst_set: format!("{:016x}", hash.0),
+            inst_set_name: name.rsplit("::").next().unwrap_or(name).to_string(),
+        }
+    }
+
+    /// Check that a file with this header can be read by this version of the crate
+    pub fn check_version(&self) -> Result<(), LoadError> {
+        if self.version == FORMAT_VERSION {
+            Ok(())
+        } else {
+            Err(LoadError::IncompatibleVersion {
+                found: self.version,
+                expected: FORMAT_VERSION,
+            })
+        }
+    }
+}
+
+/// Only the [`Header`] of a serialized [`CompiledProg`], ignoring the rest of it
+///
+/// Deserialize this first to check a file with [`Header::check_version`], since a file from
+/// another version may not deserialize as a [`CompiledProg`], or may deserialize into the wrong
+/// program.
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[derive(Debug)]
+pub struct Envelope {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub header: Header,
+}
+
+/// Serializable representation of an instruction
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug)]
+pub struct CompiledInst {
+    pub id: u64,
+    pub inst: String,
+    pub op: Op,
+}
+
+impl CompiledInst {
+    pub fn new(id: u64, inst: String, op: Op) -> Self {
+        Self { id, inst, op }
+    }
+}
+
+type CompiledTree = BTreeMap<usize, CompiledInst>;
+
+/// Represents a compiled program ready to be serialized into a file
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug)]
+pub struct CompiledProg {
+    #[cfg_attr(feature = "serde", serde(default))]
+    header: Header,
+    prog: CompiledTree,
+    mem: Memory,
+    debug_info: Option<DebugInfo>,
+    /// Checksum of the instructions and memory, see [`CompiledProg::checksum`]. Not checked if
+    /// absent, e.g. in programs written by hand.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    checksum: Option<u64>,
+    /// Warnings from parsing, only available right after compiling. They are dropped when the
+    /// program is serialized, so a deserialized program never has any.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    warnings: ErrorMap,
+}
+
+impl CompiledProg {
+    fn new(header: Header, prog: CompiledTree, mem: Memory, debug_info: Option<DebugInfo>) -> Self {
+        let mut compiled = Self {
+            header,
+            prog,
+            mem,
+            debug_info,
+            checksum: None,
+            warnings: ErrorMap::new(),
+        };
+
+        compiled.checksum = Some(compiled.checksum());
+        compiled
+    }
+
+    /// Hash of the instructions and memory, which is saved with the program and checked when it
+    /// is loaded, so that corrupted files are rejected
+    pub fn checksum(&self) -> u64 {
+        let mut hash = Fnv::default();
+
+        for (addr, CompiledInst { inst, op, .. }) in &self.prog {
+            hash.write(&addr.to_le_bytes());
+            hash.write(inst.as_bytes());
+            hash.write(op.to_string().as_bytes());
+        }
+
+        for (addr, val) in &self.mem {
+            hash.write(&addr.to_le_bytes());
+            hash.write(&val.to_le_bytes());
+        }
+
+        hash.0
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Warnings from parsing the source
+    ///
+    /// These are not serialized, so this is always empty for a program that was loaded from a
+    /// file or from a cache.
+    pub fn warnings(&self) -> &ErrorMap {
+        &self.warnings
+    }
+
+    /// Make a program without debug info from its instructions and memory, by address
+    pub fn from_parts(
+        prog: impl IntoIterator<Item = (usize, CompiledInst)>,
+        mem: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Self {
+        let header = Header {
+            version: FORMAT_VERSION,
+            ..Header::default()
+        };
+
+        Self::new(
+            header,
+            prog.into_iter().collect(),
+            Memory::new(mem.into_iter().collect()),
+            None,
+        )
+    }
+
+    /// Attach debug info, such as labels, register aliases and types of memory cells, to a
+    /// program made with [`CompiledProg::from_parts`]
+    #[must_use]
+    pub fn with_debug_info(mut self, debug_info: DebugInfo) -> Self {
+        self.debug_info = Some(debug_info);
+        self
+    }
+
+    /// Precompute arithmetic on literals, e.g. `ADD ACC,#2,#3` becomes `LDM #5`, returning the
+    /// number of instructions changed
+    ///
+    /// Results are not wrapped to the width of words, so programs that are run with narrow words
+    /// may load values that do not fit.
+    pub fn fold_constants<T>(&mut self) -> usize
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        let folded = optimize::fold_constants::<T>(&mut self.prog);
+        self.checksum = Some(self.checksum());
+
+        if folded > 0 {
+            info!("Folded {folded} instructions with constant operands");
+        }
+
+        folded
+    }
+
+    /// Summary of the size of the program
+    pub fn stats(&self) -> ProgStats {
+        ProgStats {
+            insts: self.prog.len(),
+            mem_cells: self.mem.len(),
+            mem_range: self.mem.range(),
+            debug_info: self.debug_info.is_some(),
+        }
+    }
+
+    /// Serialized size of each instruction, by address
+    ///
+    /// Sizes depend on the format, so `size_of` must serialize an instruction and return its
+    /// size in bytes, e.g. `|inst| serde_json::to_vec(inst).map(|v| v.len())`
+    pub fn inst_sizes<E>(
+        &self,
+        mut size_of: impl FnMut(&CompiledInst) -> Result<usize, E>,
+    ) -> Result<BTreeMap<usize, usize>, E> {
+        self.prog
+            .iter()
+            .map(|(&addr, inst)| Ok((addr, size_of(inst)?)))
+            .collect()
+    }
+
+    /// Convert to an [`Executor`] so that program can be executed
+    ///
+    /// # Panics
+    /// If the program is invalid, e.g. if it was compiled with a different instruction set. Use
+    /// [`CompiledProg::try_to_executor`] to handle this.
+    pub fn to_executor<T>(self, io: Io) -> Executor
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        self.try_to_executor::<T>(io)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Convert to an [`Executor`], checking that every instruction and operand is valid
+    ///
+    /// Fails without checking the instructions if the program is from another
+    /// [`FORMAT_VERSION`], or does not match its checksum. If it was compiled with an instruction
+    /// set other than `T`, every opcode it uses must be in `T`.
+    pub fn try_to_executor<T>(self, io: Io) -> Result<Executor, LoadError>
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        fn valid_op(op: &Op) -> bool {
+            match op {
+                Op::Gpr(r) => *r < 30,
+                Op::Indirect(op) => valid_op(op),
+                Op::MultiOp(ops) => ops.iter().all(valid_op),
+                _ => true,
+            }
+        }
+
+        self.header.check_version()?;
+
+        if let Some(expected) = self.checksum {
+            let found = self.checksum();
+
+            if found != expected {
+                return Err(LoadError::Corrupted { expected, found });
+            }
+        }
+
+        if !self.header.inst_set.is_empty() && self.header.inst_set != Header::of::<T>().inst_set {
+            let mut missing = Vec::new();
+
+            for CompiledInst { inst, .. } in self.prog.values() {
+                if inst.parse::<T>().is_err() && !missing.contains(inst) {
+                    missing.push(inst.clone());
+                }
+            }
+
+            if !missing.is_empty() {
+                return Err(LoadError::InstSetMismatch {
+                    compiled_with: self.header.inst_set_name,
+                    missing,
+                });
+            }
+        }
+
+        let prog = self
+            .prog
+            .into_iter()
+            .map(|(addr, CompiledInst { inst, op, .. })| {
+                let parsed = inst.parse::<T>().map_err(|e| LoadError::InvalidInst {
+                    addr,
+                    inst: inst.clone(),
+                    msg: e.to_string(),
+                })?;
+
+                if !valid_op(&op) {
+                    return Err(LoadError::InvalidOperand { addr, op });
+                }
+
+                Ok((addr, ExecInst::from_inst(&parsed, op)))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let debug_info = self.debug_info.unwrap_or_default();
+
+        Ok(Executor::builder(prog)
+            .with_source(Source::from_source_map(&debug_info.source_map))
+            .with_mem(self.mem)
+            .with_io(io)
+            .with_debug_info(debug_info)
+            .build())
+    }
+}
+
+/// Errors encountered when loading a [`CompiledProg`]
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("Invalid instruction `{inst}` at address {addr}: {msg}")]
+    InvalidInst {
+        addr: usize,
+        inst: String,
+        msg: String,
+    },
+    #[error("Invalid operand `{op}` at address {addr}")]
+    InvalidOperand { addr: usize, op: Op },
+    #[error("Artifact corrupted: checksum is {found:016x}, expected {expected:016x}")]
+    Corrupted { expected: u64, found: u64 },
+    #[error("Incompatible file version {found}, expected {expected}. Compile the program again with this version.")]
+    IncompatibleVersion { found: u32, expected: u32 },
+    #[error(
+        "Compiled with the instruction set `{compiled_with}`, which has instructions that are not available: {}",
+        missing.join(", ")
+    )]
+    InstSetMismatch {
+        compiled_with: String,
+        missing: Vec<String>,
+    },
+}
+
+/// Size information about a [`CompiledProg`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgStats {
+    /// Number of instructions
+    pub insts: usize,
+    /// Number of memory cells
+    pub mem_cells: usize,
+    /// Lowest and highest memory address in use
+    pub mem_range: Option<RangeInclusive<usize>>,
+    /// Whether debug info is included
+    pub debug_info: bool,
+}
+
+/// Parses source code into a [`CompiledProg`] ready for serialization
+pub fn compile<T>(prog: impl Deref<Target = str>, debug: bool) -> Result<CompiledProg, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    compile_with_syntax::<T>(prog, debug, Syntax::default(), false)
+}
+
+/// Parses source code into a [`CompiledProg`], accepting only the given [`Syntax`]
+///
+/// If `remove_dead_code` is set, instructions that can never run are removed, as far as
+/// [`InstSet::flow`] tells. The remaining instructions are moved up to fill the gaps, and jumps
+/// are changed to match.
+pub fn compile_with_syntax<T>(
+    prog: impl Deref<Target = str>,
+    debug: bool,
+    syntax: Syntax,
+    remove_dead_code: bool,
+) -> Result<CompiledProg, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let (prog, mem, _, mut debug_info) = parse::<T>(prog, syntax)?;
+
+    let mut prog = prog
+        .iter()
+        .map(|(addr, &ExecInst { ref op, id, .. })| {
+            let str_inst = match T::from_id(id) {
+                Ok(inst) => inst,
+                Err(e) => panic!("{e}"),
+            }
+            .to_string();
+
+            (addr, CompiledInst::new(id, str_inst, op.clone()))
+        })
+        .collect();
+
+    if remove_dead_code {
+        let removed = optimize::eliminate_dead_code::<T>(&mut prog, &mut debug_info);
+
+        if removed > 0 {
+            info!("Removed {removed} unreachable instructions");
+        }
+    }
+
+    // Warnings are not serialized, so they are kept separately
+    let warnings = std::mem::take(&mut debug_info.warnings);
+    let mut compiled = CompiledProg::new(
+        Header::of::<T>(),
+        prog,
+        Memory::new(mem),
+        debug.then_some(debug_info),
+    );
+    compiled.warnings = warnings;
+
+    info!("Program compiled");
+
+    Ok(compiled)
+}
+
+/// Everything that changes how a program is compiled, see [`compile_with_options`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// Include debug info
+    pub debug: bool,
+    /// Operand syntax accepted
+    pub syntax: Syntax,
+    /// See [`compile_with_syntax`]
+    pub remove_dead_code: bool,
+    /// See [`CompiledProg::fold_constants`]
+    pub fold_constants: bool,
+}
+
+/// Parses source code into a [`CompiledProg`] with `options`
+pub fn compile_with_options<T>(
+    prog: impl Deref<Target = str>,
+    options: &CompileOptions,
+) -> Result<CompiledProg, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let mut compiled = compile_with_syntax::<T>(
+        prog,
+        options.debug,
+        options.syntax,
+        options.remove_dead_code,
+    )?;
+
+    if options.fold_constants {
+        compiled.fold_constants::<T>();
+    }
+
+    Ok(compiled)
+}
+
+/// Parses source code into a [`CompiledProg`] directly from a file
+pub fn from_file<T>(path: impl AsRef<Path>, debug: bool) -> Result<CompiledProg, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let prog = std::fs::read_to_string(path).expect("Cannot read file");
+    compile::<T>(prog, debug)
+}
+
+/// 64-bit FNV-1a, used because its output is stable across Rust versions, unlike `DefaultHasher`
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        // Length prefix, so that the boundaries between fields affect the hash
+        for &b in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod compile_tests {
+    #![allow(clippy::upper_case_acronyms)]
+
+    use crate::{
+        compile::{compile, CompiledProg},
+        make_io,
+        parse::DefaultSet,
+        TestStdio, PROGRAMS,
+    };
+    use std::time::Instant;
+
+    crate::extend! {
+        Jumpy extends DefaultSet use crate::exec::cmp; {
+            GOTO => cmp::jmp; [Addr],
+        }
+    }
+
+    #[test]
+    fn test() {
+        for (prog, exp, inp, out) in PROGRAMS {
+            let mut t = Instant::now();
+
+            let compiled = compile::<DefaultSet>(prog, false).unwrap();
+            let ser = serde_json::to_string(&compiled).unwrap();
+
+            println!("Compilation time: {:?}", t.elapsed());
+
+            t = Instant::now();
+            let s = TestStdio::new(vec![]);
+
+            let mut exe = serde_json::from_str::<CompiledProg>(&ser)
+                .unwrap()
+                .to_executor::<DefaultSet>(make_io!(TestStdio::new(inp), s.clone()));
+
+            println!("JIT time: {:?}", t.elapsed());
+
+            t = Instant::now();
+
+            exe.exec_and_report();
+
+            println!("Execution time: {:?}", t.elapsed());
+
+            assert_eq!(
+                exe.ctx.acc, exp,
+                "Expected '{}' in ACC, got '{}'",
+                exp, exe.ctx.acc
+            );
+            assert_eq!(
+                s.to_vec(),
+                out,
+                "Expected '{}' in output, got '{}'",
+                String::from_utf8_lossy(out),
+                s.try_to_string().unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let compiled =
+            compile::<DefaultSet>(include_str!("../../examples/hello.pasm"), false).unwrap();
+
+        let stats = compiled.stats();
+        assert_eq!(stats.insts, 8);
+        assert_eq!(stats.mem_cells, 7);
+        assert_eq!(stats.mem_range, Some(0..=206));
+        assert!(!stats.debug_info);
+
+        let sizes = compiled
+            .inst_sizes(|inst| serde_json::to_vec(inst).map(|v| v.len()))
+            .unwrap();
+        assert_eq!(sizes.len(), 8);
+        assert!(sizes.values().all(|&size| size > 0));
+    }
+
+    #[test]
+    fn decompile() {
+        use crate::{compile::decompile, parse::jit};
+
+        for (prog, exp, inp, out) in PROGRAMS {
+            for debug in [false, true] {
+                let src = decompile(&compile::<DefaultSet>(prog, debug).unwrap());
+                let s = TestStdio::new(vec![]);
+
+                let mut exe =
+                    jit::<DefaultSet>(src.as_str(), make_io!(TestStdio::new(inp), s.clone()))
+                        .unwrap_or_else(|e| panic!("{e:?}\n{src}"));
+                exe.exec_and_report();
+
+                assert_eq!(exe.ctx.acc, exp, "{src}");
+                assert_eq!(s.to_vec(), out, "{src}");
+            }
+        }
+
+        let src = "COUNT ALIAS r29\nMOV COUNT,ACC\nEND\n\nNONE:\n";
+        assert_eq!(decompile(&compile::<DefaultSet>(src, true).unwrap()), src);
+
+        let src = "LDD MSG\nEND\n\nMSG: \"Hi\\n\"\n";
+        assert_eq!(decompile(&compile::<DefaultSet>(src, true).unwrap()), src);
+    }
+
+    #[test]
+    fn header() {
+        use crate::{
+            compile::{Envelope, LoadError, FORMAT_VERSION},
+            parse::Core,
+        };
+
+        let src = "LDM #1\nEND\n\nNONE:\n";
+
+        let compiled = compile::<DefaultSet>(src, false).unwrap();
+        assert_eq!(compiled.header().version, FORMAT_VERSION);
+        let json = serde_json::to_string(&compiled).unwrap();
+
+        let load = |json: &str| {
+            serde_json::from_str::<CompiledProg>(json)
+                .unwrap()
+                .try_to_executor::<DefaultSet>(make_io!())
+        };
+
+        assert!(load(&json).is_ok());
+
+        let old = json.replacen(
+            &format!(r#""version":{FORMAT_VERSION}"#),
+            r#""version":0"#,
+            1,
+        );
+        assert!(matches!(
+            load(&old),
+            Err(LoadError::IncompatibleVersion { found: 0, .. })
+        ));
+
+        // The header can be read even if the rest of the file cannot
+        let envelope = serde_json::from_str::<Envelope>(
+            r#"{"header":{"version":7,"inst_set":""},"prog":"something else"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            envelope.header.check_version(),
+            Err(LoadError::IncompatibleVersion { found: 7, .. })
+        ));
+
+        // Programs that only use instructions in both sets can be loaded
+        let core = serde_json::to_string(&compile::<Core>(src, false).unwrap()).unwrap();
+        assert!(load(&core).is_ok());
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn provenance() {
+        use crate::{compile::LoadError, parse::Core};
+
+        let compiled =
+            compile::<DefaultSet>("RIN\nOUTN\nOUT\nOUTN\nEND\n\nNONE:\n", false).unwrap();
+        let name = compiled.header().inst_set_name.clone();
+        assert!(name == "Extended" || name == "Float", "{name}");
+
+        let e = compiled.try_to_executor::<Core>(make_io!()).err().unwrap();
+        assert!(matches!(
+            &e,
+            LoadError::InstSetMismatch { missing, .. } if *missing == ["RIN", "OUTN"]
+        ));
+        assert_eq!(
+            e.to_string(),
+            format!("Compiled with the instruction set `{name}`, which has instructions that are not available: RIN, OUTN")
+        );
+    }
+
+    #[test]
+    fn source_map() {
+        let src = "// Load\nLDM #1\n\n// Fails\nLDD 500 // oops\nEND\n\nNONE:\n";
+
+        let compiled = compile::<DefaultSet>(src, true).unwrap();
+        let json = serde_json::to_string(&compiled).unwrap();
+
+        let out = TestStdio::new(vec![]);
+        serde_json::from_str::<CompiledProg>(&json)
+            .unwrap()
+            .to_executor::<DefaultSet>(make_io!(std::io::empty(), out.clone()))
+            .exec_and_report();
+
+        assert_eq!(
+            out.try_to_string().unwrap(),
+            "Runtime Error:\n\n2    LDM #1\n5    LDD 500 // oops <-\n6    END\n\nmessage: Invalid memory address `500`\n\n"
+        );
+    }
+
+    #[test]
+    fn checksum() {
+        use crate::compile::LoadError;
+
+        let compiled = compile::<DefaultSet>("LDM #1\nSTO 200\nEND\n\n200 7\n", false).unwrap();
+        let json = serde_json::to_string(&compiled).unwrap();
+
+        let load = |json: &str| {
+            serde_json::from_str::<CompiledProg>(json)
+                .unwrap()
+                .try_to_executor::<DefaultSet>(make_io!())
+        };
+
+        assert!(load(&json).is_ok());
+
+        let corrupted = json.replacen(r#""200":7"#, r#""200":8"#, 1);
+        assert_ne!(corrupted, json);
+        assert!(matches!(load(&corrupted), Err(LoadError::Corrupted { .. })));
+
+        let corrupted = json.replacen(r#""Literal":1"#, r#""Literal":2"#, 1);
+        assert_ne!(corrupted, json);
+        assert!(matches!(load(&corrupted), Err(LoadError::Corrupted { .. })));
+    }
+
+    #[test]
+    fn dead_code() {
+        use crate::{compile::compile_with_syntax, parse::Syntax};
+
+        let src = "JMP START\nOUT\nEND\nSTART: LDM #65\nOUT\nJPE START\nEND\nLDM #66\n\nNONE:\n";
+        let run = |compiled: CompiledProg| {
+            let out = TestStdio::new(vec![]);
+            compiled
+                .to_executor::<DefaultSet>(make_io!(std::io::empty(), out.clone()))
+                .exec_and_report();
+            out.try_to_string().unwrap()
+        };
+
+        let kept = compile::<DefaultSet>(src, false).unwrap();
+
+        assert_eq!(kept.stats().insts, 8);
+        assert_eq!(run(kept), "A");
+
+        let compiled =
+            compile_with_syntax::<DefaultSet>(src, true, Syntax::default(), true).unwrap();
+        let debug_info = compiled.debug_info.as_ref().unwrap();
+
+        assert_eq!(compiled.stats().insts, 5);
+        assert_eq!(compiled.prog[&0].op.to_string(), "1");
+        assert_eq!(compiled.prog[&3].op.to_string(), "1");
+        assert_eq!(debug_info.prog[&1], "START");
+        assert_eq!(debug_info.inst_spans.len(), 5);
+        assert_eq!(debug_info.source_map[&4].line, 7);
+        assert_eq!(run(compiled), "A");
+    }
+
+    #[test]
+    fn dead_code_custom_jump() {
+        use crate::{compile::compile_with_syntax, parse::Syntax};
+
+        // `GOTO` does not say where it jumps, so nothing can be removed
+        let src = "GOTO L\nEND\nL: LDM #7\nEND\n\nNONE:\n";
+
+        for remove_dead_code in [false, true] {
+            let compiled =
+                compile_with_syntax::<Jumpy>(src, false, Syntax::default(), remove_dead_code)
+                    .unwrap();
+
+            assert_eq!(compiled.stats().insts, 4);
+
+            let mut exec = compiled.to_executor::<Jumpy>(make_io!());
+            exec.exec().unwrap();
+
+            assert_eq!(exec.ctx.acc, 7);
+        }
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn constant_folding() {
+        let src = "ADD ACC,#2,#3\nOUTN\nSUB IX,#1,#4\nMUL r0,#6,#7\nXOR ACC,#B110,#B011\nOUTN\nEND\n\nNONE:\n";
+
+        let mut compiled = compile::<DefaultSet>(src, false).unwrap();
+
+        assert_eq!(compiled.fold_constants::<DefaultSet>(), 3);
+        assert_eq!(compiled.prog[&0].inst, "LDM");
+        assert_eq!(compiled.prog[&0].op.to_string(), "#5");
+        assert_eq!(compiled.prog[&2].inst, "SUB");
+        assert_eq!(compiled.prog[&3].op.to_string(), "r0,#42");
+
+        let out = TestStdio::new(vec![]);
+        serde_json::from_str::<CompiledProg>(&serde_json::to_string(&compiled).unwrap())
+            .unwrap()
+            .to_executor::<DefaultSet>(make_io!(std::io::empty(), out.clone()))
+            .exec_and_report();
+
+        assert_eq!(out.try_to_string().unwrap(), "55");
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn link() {
+        use crate::{
+            compile::{link, Unit},
+            parse::ErrorKind,
+        };
+
+        let main = Unit::new(
+            "main.pasm",
+            "LDD COUNT\nCALL PRINT\nLDM #66\nCALL PRINT\nEND\n\nCOUNT: 65\n",
+        );
+        let lib = Unit::new("lib.pasm", "// Output ACC\nPRINT: OUT\nRET\n\nUNUSED: 0\n");
+
+        let compiled = link::<DefaultSet>(&[main.clone(), lib], true).unwrap();
+
+        assert_eq!(compiled.stats().insts, 7);
+        assert_eq!(compiled.debug_info.as_ref().unwrap().source_map[&5].line, 2);
+
+        let out = TestStdio::new(vec![]);
+        compiled
+            .to_executor::<DefaultSet>(make_io!(std::io::empty(), out.clone()))
+            .exec_and_report();
+        assert_eq!(out.try_to_string().unwrap(), "AB");
+
+        let lib = Unit::new("lib.pasm", "COUNT: OUT\nJMP DONE\n");
+        let errors = link::<DefaultSet>(&[main, lib], false).unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors["lib.pasm"].get(&(0..10)),
+            Some(&ErrorKind::DuplicateLabel("COUNT".into()))
+        );
+        assert_eq!(
+            errors["main.pasm"].get(&(10..20)),
+            Some(&ErrorKind::UndefinedLabel("PRINT".into()))
+        );
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn stdlib() {
+        use crate::compile::{link, Unit};
+
+        let src = "IMPORT stdlib\nCALL STD_READ\nSTO N\nLDM #7\nSTO STD_ARG\nLDD N\nCALL STD_MUL\nCALL STD_PRINT\nLDM #32\nOUT\nLDM #100\nSTO STD_ARG\nLDD N\nCALL STD_DIV\nMOV N,STD_REM\nCALL STD_PRINT\nLDD N\nCALL STD_PRINT\nEND\n\nN: 0\n";
+
+        let compiled = link::<DefaultSet>(&[Unit::new("main.pasm", src)], false).unwrap();
+
+        let out = TestStdio::new(vec![]);
+        compiled
+            .to_executor::<DefaultSet>(make_io!(std::io::Cursor::new("1203\n"), out.clone()))
+            .exec_and_report();
+
+        assert_eq!(out.try_to_string().unwrap(), "8421 123");
+    }
+}
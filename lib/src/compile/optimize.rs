@@ -0,0 +1,186 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Passes over compiled instructions that make programs smaller without changing what they do
+
+use super::{decompile::addrs, CompiledTree};
+use crate::{
+    exec::DebugInfo,
+    inst::{Flow, InstSet, Op},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+/// Remove instructions that cannot be reached from the first instruction, and number the rest
+/// from 0 again, updating jumps and `debug_info` to match
+///
+/// Reachable instructions are found by following execution from address 0, and the targets of
+/// jumps, as given by [`InstSet::flow`]. Programs with gaps in their addresses, or with an
+/// instruction whose flow `T` does not give, are left alone.
+///
+/// Returns the number of instructions removed.
+pub(super) fn eliminate_dead_code<T>(prog: &mut CompiledTree, debug_info: &mut DebugInfo) -> usize
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let len = prog.len();
+
+    if prog.keys().copied().ne(0..len) {
+        return 0;
+    }
+
+    let flows = match prog
+        .values()
+        .map(|inst| T::from_id(inst.id).ok().and_then(|inst| inst.flow()))
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(flows) => flows,
+        None => return 0,
+    };
+
+    let mut reachable = BTreeSet::new();
+    let mut pending = vec![0];
+
+    while let Some(addr) = pending.pop() {
+        let flow = match flows.get(addr) {
+            Some(&flow) if reachable.insert(addr) => flow,
+            _ => continue,
+        };
+
+        if matches!(flow, Flow::Next | Flow::Branch) {
+            pending.push(addr + 1);
+        }
+
+        if matches!(flow, Flow::Branch | Flow::Jump) {
+            pending.extend(addrs(&prog[&addr].op));
+        }
+    }
+
+    let removed = len - reachable.len();
+
+    if removed == 0 {
+        return 0;
+    }
+
+    // Jumping to the address after the last instruction ends the program
+    let new_addrs = reachable
+        .iter()
+        .copied()
+        .chain([len])
+        .zip(0..)
+        .collect::<BTreeMap<_, _>>();
+
+    let old = std::mem::take(prog);
+
+    for (addr, mut inst) in old {
+        if reachable.contains(&addr) {
+            if matches!(flows[addr], Flow::Branch | Flow::Jump) {
+                renumber(&mut inst.op, &new_addrs);
+            }
+
+            prog.insert(new_addrs[&addr], inst);
+        }
+    }
+
+    remap(&mut debug_info.prog, &new_addrs, &reachable);
+    remap(&mut debug_info.comments, &new_addrs, &reachable);
+    remap(&mut debug_info.source_map, &new_addrs, &reachable);
+
+    debug_info.inst_spans = std::mem::take(&mut debug_info.inst_spans)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(addr, span)| reachable.contains(&addr).then_some(span))
+        .collect();
+
+    removed
+}
+
+/// Keep the entries of `map` at `reachable` addresses, moved to their new addresses
+fn remap<V>(
+    map: &mut BTreeMap<usize, V>,
+    new_addrs: &BTreeMap<usize, usize>,
+    reachable: &BTreeSet<usize>,
+) {
+    *map = std::mem::take(map)
+        .into_iter()
+        .filter(|(addr, _)| reachable.contains(addr))
+        .map(|(addr, v)| (new_addrs[&addr], v))
+        .collect();
+}
+
+/// Replace addresses of instructions in `op` with their new addresses
+fn renumber(op: &mut Op, new_addrs: &BTreeMap<usize, usize>) {
+    match op {
+        Op::Addr(addr) => {
+            if let Some(&new) = new_addrs.get(addr) {
+                *addr = new;
+            }
+        }
+        Op::MultiOp(ops) => ops.iter_mut().for_each(|op| renumber(op, new_addrs)),
+        _ => {}
+    }
+}
+
+/// Replace arithmetic and bitwise instructions whose operands are all literals, such as
+/// `ADD ACC,#2,#3`, with an `LDM` of the result, e.g. `LDM #5`
+///
+/// Only results that are exact are folded, so additions and multiplications that overflow, and
+/// subtractions that go below zero, are left to be computed when the program runs.
+///
+/// Returns the number of instructions folded.
+pub(super) fn fold_constants<T>(prog: &mut CompiledTree) -> usize
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let ldm = match "LDM".parse::<T>() {
+        Ok(ldm) => ldm,
+        Err(_) => return 0,
+    };
+
+    let mut folded = 0;
+
+    for inst in prog.values_mut() {
+        let op = match &inst.op {
+            Op::MultiOp(ops) => match ops[..] {
+                [ref dest, Op::Literal(a), Op::Literal(b)]
+                    if matches!(dest, Op::Acc | Op::Ix | Op::Ar | Op::Gpr(_)) =>
+                {
+                    match fold(&inst.inst, a, b) {
+                        Some(val) if matches!(dest, Op::Acc) => Op::Literal(val),
+                        Some(val) => Op::MultiOp(vec![dest.clone(), Op::Literal(val)]),
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        inst.id = ldm.id();
+        inst.inst = ldm.to_string();
+        inst.op = op;
+        folded += 1;
+    }
+
+    folded
+}
+
+/// Result of the instruction `inst` on `a` and `b`, if it can be known before running
+fn fold(inst: &str, a: usize, b: usize) -> Option<usize> {
+    match inst.to_ascii_uppercase().as_str() {
+        "ADD" => a.checked_add(b),
+        "SUB" => a.checked_sub(b),
+        "MUL" => a.checked_mul(b),
+        "AND" => Some(a & b),
+        "OR" => Some(a | b),
+        "XOR" => Some(a ^ b),
+        _ => None,
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Embed programs in Rust binaries at build time
+//!
+//! Call [`build`] from a build script to parse a program, and [`include_pasm`](crate::include_pasm) to embed the
+//! result. The program is turned into Rust code that builds a [`CompiledProg`] directly, so
+//! nothing is parsed at startup.
+//!
+//! The embedded program is not a `const`, since a [`CompiledProg`] owns its instructions and
+//! memory. It is built from literals each time [`include_pasm`](crate::include_pasm) is
+//! evaluated. Its debug info, i.e. labels, comments, spans, source lines, register aliases and
+//! types of memory cells, is embedded with it. Warnings from parsing and the fingerprint of the
+//! instruction set in its [`Header`](crate::compile::Header) are not, so the program is not
+//! checked against the instruction set it is run with.
+//!
+//! ```ignore
+//! // build.rs
+//! use cambridge_asm::parse::DefaultSet;
+//!
+//! fn main() {
+//!     cambridge_asm::embed::build::<DefaultSet>("src/hello.pasm").unwrap();
+//! }
+//!
+//! // src/main.rs
+//! use cambridge_asm::{include_pasm, make_io, parse::DefaultSet};
+//!
+//! fn main() {
+//!     let prog = include_pasm!("src/hello.pasm");
+//!     prog.to_executor::<DefaultSet>(make_io!()).exec_and_report();
+//! }
+//! ```
+//!
+//! [`CompiledProg`]: crate::compile::CompiledProg
+
+use crate::{
+    exec::{DebugInfo, MemType},
+    inst::{InstSet, Op},
+    parse::{parse, ErrorMap, Syntax},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    io,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// Errors encountered when embedding a program
+#[derive(Debug, Error)]
+pub enum EmbedError {
+    #[error("Unable to read or write program: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unable to parse program: {0:?}")]
+    Parse(ErrorMap),
+    #[error("`OUT_DIR` is not set, `build` must be called from a build script")]
+    NoOutDir,
+    #[error("Program path `{}` must be relative, without `..`", .0.display())]
+    InvalidPath(PathBuf),
+}
+
+/// Parse the program at `path` and write it to `OUT_DIR` as Rust code, for [`include_pasm`](crate::include_pasm)
+///
+/// Must be called from a build script. Cargo is told to rerun the build script if the program
+/// changes. `path` must be relative to the package, and is kept in `OUT_DIR`, so that programs
+/// with the same file name in different directories do not overwrite each other.
+pub fn build<T>(path: impl AsRef<Path>) -> Result<(), EmbedError>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let path = path.as_ref();
+    let out_dir = std::env::var_os("OUT_DIR").ok_or(EmbedError::NoOutDir)?;
+
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let code = generate::<T>(&std::fs::read_to_string(path)?).map_err(EmbedError::Parse)?;
+
+    let out = out_path(Path::new(&out_dir), path)?;
+
+    if let Some(dir) = out.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(out, code)?;
+
+    Ok(())
+}
+
+/// Where the code for the program at `path` is written in `out_dir`
+fn out_path(out_dir: &Path, path: &Path) -> Result<PathBuf, EmbedError> {
+    let mut out = out_dir.to_path_buf();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            _ => return Err(EmbedError::InvalidPath(path.to_path_buf())),
+        }
+    }
+
+    let mut out = out.into_os_string();
+    out.push(".rs");
+
+    Ok(out.into())
+}
+
+/// Rust expression that builds the [`CompiledProg`](crate::compile::CompiledProg) for `src`
+pub fn generate<T>(src: &str) -> Result<String, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let (prog, mem, _, debug_info) = parse::<T>(src, Syntax::default())?;
+
+    let mut code =
+        String::from("::cambridge_asm::compile::CompiledProg::from_parts(\n    ::std::vec![\n");
+
+    for (addr, inst) in &prog {
+        // Writing to a `String` cannot fail
+        let _ = writeln!(
+            code,
+            "        ({addr}, ::cambridge_asm::compile::CompiledInst::new({id}, {opcode:?}.into(), {op})),",
+            id = inst.id,
+            opcode = inst.mnemonic,
+            op = op_to_rust(&inst.op),
+        );
+    }
+
+    code.push_str("    ],\n    [\n");
+
+    for (addr, data) in mem {
+        let _ = writeln!(code, "        ({addr}, {data}),");
+    }
+
+    code.push_str("    ],\n)\n");
+    code.push_str(&debug_to_rust(&debug_info));
+
+    Ok(code)
+}
+
+/// Rust code that attaches `debug_info` to the program built before it
+fn debug_to_rust(debug_info: &DebugInfo) -> String {
+    const MEM_TYPE: &str = "::cambridge_asm::exec::MemType";
+
+    fn map<T>(entries: &BTreeMap<usize, T>, to_rust: impl Fn(&T) -> String) -> String {
+        let entries = entries
+            .iter()
+            .map(|(addr, val)| format!("({addr}, {})", to_rust(val)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("::std::collections::BTreeMap::from([{entries}])")
+    }
+
+    let string = |s: &String| format!("{s:?}.into()");
+    let spans = debug_info
+        .inst_spans
+        .iter()
+        .map(|span| format!("{}..{}", span.start, span.end))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let mut code = String::from(".with_debug_info(::cambridge_asm::exec::DebugInfo {\n");
+
+    let _ = writeln!(code, "    prog: {},", map(&debug_info.prog, string));
+    let _ = writeln!(code, "    mem: {},", map(&debug_info.mem, string));
+    let _ = writeln!(code, "    inst_spans: ::std::vec![{spans}],");
+    let _ = writeln!(code, "    comments: {},", map(&debug_info.comments, string));
+    let _ = writeln!(code, "    aliases: {},", map(&debug_info.aliases, string));
+    let _ = writeln!(
+        code,
+        "    mem_types: {},",
+        map(&debug_info.mem_types, |ty| match ty {
+            MemType::Word => format!("{MEM_TYPE}::Word"),
+            MemType::Byte => format!("{MEM_TYPE}::Byte"),
+            MemType::Str(len) => format!("{MEM_TYPE}::Str({len})"),
+        })
+    );
+    let _ = writeln!(
+        code,
+        "    source_map: {},",
+        map(&debug_info.source_map, |line| format!(
+            "::cambridge_asm::exec::SourceLine {{ line: {}, text: {:?}.into() }}",
+            line.line, line.text
+        ))
+    );
+
+    code.push_str("    ..::std::default::Default::default()\n})\n");
+    code
+}
+
+/// Rust expression that builds `op`
+fn op_to_rust(op: &Op) -> String {
+    const OP: &str = "::cambridge_asm::inst::Op";
+
+    match op {
+        Op::Fail(s) => format!("{OP}::Fail({s:?}.into())"),
+        Op::Acc => format!("{OP}::Acc"),
+        Op::Ix => format!("{OP}::Ix"),
+        Op::Cmp => format!("{OP}::Cmp"),
+        Op::Ar => format!("{OP}::Ar"),
+        Op::Sp => format!("{OP}::Sp"),
+        Op::Indirect(op) => format!("{OP}::Indirect(::std::boxed::Box::new({}))", op_to_rust(op)),
+        Op::Addr(x) => format!("{OP}::Addr({x})"),
+        Op::Literal(x) => format!("{OP}::Literal({x})"),
+        Op::Gpr(x) => format!("{OP}::Gpr({x})"),
+        Op::MultiOp(ops) => format!(
+            "{OP}::MultiOp(::std::vec![{}])",
+            ops.iter().map(op_to_rust).collect::<Vec<_>>().join(", ")
+        ),
+        Op::Null => format!("{OP}::Null"),
+    }
+}
+
+/// Embed a program prepared by [`build`](crate::embed::build) in a build script
+///
+/// Takes the path of the program given to [`build`](crate::embed::build), and evaluates to a
+/// [`CompiledProg`](crate::compile::CompiledProg).
+#[macro_export]
+macro_rules! include_pasm {
+    ($file:literal) => {
+        include!(concat!(env!("OUT_DIR"), "/", $file, ".rs"))
+    };
+}
+
+#[cfg(test)]
+mod embed_tests {
+    use super::{generate, op_to_rust, out_path, EmbedError};
+    use crate::{
+        compile::{CompiledInst, CompiledProg},
+        exec::{DebugInfo, SourceLine},
+        inst::{InstSet, Op},
+        make_io,
+        parse::DefaultSet,
+        TestStdio,
+    };
+    use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+    #[test]
+    fn ops() {
+        let op = Op::MultiOp(vec![
+            Op::Acc,
+            Op::Indirect(Box::new(Op::Gpr(1))),
+            Op::Literal(5),
+        ]);
+
+        assert_eq!(
+            op_to_rust(&op),
+            "::cambridge_asm::inst::Op::MultiOp(::std::vec![::cambridge_asm::inst::Op::Acc, \
+             ::cambridge_asm::inst::Op::Indirect(::std::boxed::Box::new(::cambridge_asm::inst::Op::Gpr(1))), \
+             ::cambridge_asm::inst::Op::Literal(5)])"
+        );
+    }
+
+    #[test]
+    fn paths() {
+        let out = Path::new("out");
+
+        assert_eq!(
+            out_path(out, Path::new("src/a/prog.pasm")).unwrap(),
+            Path::new("out/src/a/prog.pasm.rs")
+        );
+        assert_ne!(
+            out_path(out, Path::new("src/b/prog.pasm")).unwrap(),
+            out_path(out, Path::new("src/a/prog.pasm")).unwrap()
+        );
+        assert_eq!(
+            out_path(out, Path::new("./prog.pasm")).unwrap(),
+            Path::new("out/prog.pasm.rs")
+        );
+
+        for path in ["../prog.pasm", "/prog.pasm"] {
+            assert!(matches!(
+                out_path(out, Path::new(path)),
+                Err(EmbedError::InvalidPath(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn generated() {
+        let id = |inst| DefaultSet::from_str(inst).unwrap().id();
+        let code = generate::<DefaultSet>("LDD 2\nOUT\nEND\n\n2 72\n").unwrap();
+
+        assert_eq!(
+            code,
+            format!(
+                "::cambridge_asm::compile::CompiledProg::from_parts(
+    ::std::vec![
+        (0, ::cambridge_asm::compile::CompiledInst::new({}, \"LDD\".into(), ::cambridge_asm::inst::Op::Addr(2))),
+        (1, ::cambridge_asm::compile::CompiledInst::new({}, \"OUT\".into(), ::cambridge_asm::inst::Op::Null)),
+        (2, ::cambridge_asm::compile::CompiledInst::new({}, \"END\".into(), ::cambridge_asm::inst::Op::Null)),
+    ],
+    [
+        (2, 72),
+    ],
+)
+.with_debug_info(::cambridge_asm::exec::DebugInfo {{
+    prog: ::std::collections::BTreeMap::from([]),
+    mem: ::std::collections::BTreeMap::from([]),
+    inst_spans: ::std::vec![0..5, 6..9, 10..13],
+    comments: ::std::collections::BTreeMap::from([]),
+    aliases: ::std::collections::BTreeMap::from([]),
+    mem_types: ::std::collections::BTreeMap::from([]),
+    source_map: ::std::collections::BTreeMap::from([\
+(0, ::cambridge_asm::exec::SourceLine {{ line: 1, text: \"LDD 2\".into() }}), \
+(1, ::cambridge_asm::exec::SourceLine {{ line: 2, text: \"OUT\".into() }}), \
+(2, ::cambridge_asm::exec::SourceLine {{ line: 3, text: \"END\".into() }})]),
+    ..::std::default::Default::default()
+}})
+",
+                id("LDD"),
+                id("OUT"),
+                id("END")
+            )
+        );
+
+        // What the generated code builds
+        let prog = CompiledProg::from_parts(
+            vec![
+                (0, CompiledInst::new(id("LDD"), "LDD".into(), Op::Addr(2))),
+                (1, CompiledInst::new(id("OUT"), "OUT".into(), Op::Null)),
+                (2, CompiledInst::new(id("END"), "END".into(), Op::Null)),
+            ],
+            [(2, 72)],
+        )
+        .with_debug_info(DebugInfo {
+            inst_spans: vec![0..5, 6..9, 10..13],
+            source_map: BTreeMap::from([
+                (
+                    0,
+                    SourceLine {
+                        line: 1,
+                        text: "LDD 2".into(),
+                    },
+                ),
+                (
+                    1,
+                    SourceLine {
+                        line: 2,
+                        text: "OUT".into(),
+                    },
+                ),
+                (
+                    2,
+                    SourceLine {
+                        line: 3,
+                        text: "END".into(),
+                    },
+                ),
+            ]),
+            ..Default::default()
+        });
+
+        let out = TestStdio::new(vec![]);
+        prog.to_executor::<DefaultSet>(make_io!(std::io::empty(), out.clone()))
+            .exec_and_report();
+
+        assert_eq!(out.to_vec(), b"H");
+    }
+
+    #[test]
+    fn debug_info() {
+        let code =
+            generate::<DefaultSet>("N ALIAS r1\nSTART: LDD MSG // first\nEND\n\nMSG: \"Hi\"\n")
+                .unwrap();
+
+        for line in [
+            "    prog: ::std::collections::BTreeMap::from([(0, \"START\".into())]),",
+            "    mem: ::std::collections::BTreeMap::from([(1, \"MSG\".into())]),",
+            "    comments: ::std::collections::BTreeMap::from([(0, \"first\".into())]),",
+            "    aliases: ::std::collections::BTreeMap::from([(1, \"N\".into())]),",
+            "    mem_types: ::std::collections::BTreeMap::from([(1, ::cambridge_asm::exec::MemType::Str(2))]),",
+        ] {
+            assert!(code.contains(line), "{line} not in {code}");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::GPRS;
+use crate::inst::Op;
+
+/// Register named by an operand, with the number of a general purpose register checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Reg {
+    Acc,
+    Ix,
+    Ar,
+    Sp,
+    Gpr(usize),
+}
+
+impl Reg {
+    fn resolve(op: &Op) -> Option<Self> {
+        Some(match *op {
+            Op::Acc => Self::Acc,
+            Op::Ix => Self::Ix,
+            Op::Ar => Self::Ar,
+            Op::Sp => Self::Sp,
+            Op::Gpr(x) if x < GPRS => Self::Gpr(x),
+            _ => return None,
+        })
+    }
+}
+
+/// Operand of an instruction, checked and flattened once when an
+/// [`Executor`](super::Executor) is made, so that [`Context::read`](super::Context::read) and
+/// [`Context::modify`](super::Context::modify) do not check its shape on every step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    Literal(usize),
+    Addr(usize),
+    Reg(Reg),
+    /// Value at the address stored at an address
+    IndirectAddr(usize),
+    /// Value at the address stored in a register
+    IndirectReg(Reg),
+}
+
+impl Access {
+    /// `None` if `op` is not a single value, such as [`Op::Null`] or [`Op::MultiOp`], or is
+    /// invalid
+    pub(crate) fn resolve(op: &Op) -> Option<Self> {
+        Some(match op {
+            &Op::Literal(val) => Self::Literal(val),
+            &Op::Addr(addr) => Self::Addr(addr),
+            Op::Indirect(op) => match **op {
+                Op::Addr(addr) => Self::IndirectAddr(addr),
+                ref op => Self::IndirectReg(Reg::resolve(op)?),
+            },
+            op => Self::Reg(Reg::resolve(op)?),
+        })
+    }
+}
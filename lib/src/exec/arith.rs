@@ -3,17 +3,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use super::{Context, RtError::*, RtResult};
+use super::{Context, RtError::*, RtResult, Word};
 use crate::inst::Op::{self, *};
 
 #[inline]
-fn checked_add(dest: &mut usize, val: usize, mar: usize) {
-    if let Some(res) = dest.checked_add(val) {
-        *dest = res;
-    } else {
+fn checked_add(dest: &mut usize, val: usize, mar: usize, word: Word) {
+    if !word.fits(word.value(*dest) + word.value(val)) {
         warn!("Addition overflow detected at line {}", mar + 1);
-        *dest += val;
     }
+
+    *dest = word.wrap(dest.wrapping_add(val));
 }
 
 /// Add values
@@ -26,15 +25,15 @@ pub fn add(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         MultiOp(ops) => match ops[..] {
             [ref dest, ref val] if dest.is_read_write() && val.is_usizeable() => {
-                let line = ctx.mar;
+                let (line, word) = (ctx.mar, ctx.word());
                 let val = ctx.read(val)?;
-                ctx.modify(dest, |d| checked_add(d, val, line))?;
+                ctx.modify(dest, |d| checked_add(d, val, line, word))?;
             }
             [ref dest, ref a, ref b]
                 if dest.is_read_write() && a.is_usizeable() && b.is_usizeable() =>
             {
                 let mut a = ctx.read(a)?;
-                checked_add(&mut a, ctx.read(b)?, ctx.mar);
+                checked_add(&mut a, ctx.read(b)?, ctx.mar, ctx.word());
                 ctx.modify(dest, |d| *d = a)?;
             }
             _ => return Err(InvalidMultiOp),
@@ -42,7 +41,8 @@ pub fn add(ctx: &mut Context, op: &Op) -> RtResult {
         Null => return Err(NoOperand),
         val if val.is_usizeable() => {
             let val = ctx.read(val)?;
-            checked_add(&mut ctx.acc, val, ctx.mar);
+            let word = ctx.word();
+            checked_add(&mut ctx.acc, val, ctx.mar, word);
         }
         _ => return Err(InvalidOperand),
     }
@@ -50,14 +50,14 @@ pub fn add(ctx: &mut Context, op: &Op) -> RtResult {
     Ok(())
 }
 
+/// Values that go below zero wrap around, which gives a negative value in two's complement
 #[inline]
-fn checked_sub(dest: &mut usize, val: usize, mar: usize) {
-    if let Some(res) = dest.checked_sub(val) {
-        *dest = res;
-    } else {
+fn checked_sub(dest: &mut usize, val: usize, mar: usize, word: Word) {
+    if !word.fits(word.value(*dest) - word.value(val)) {
         warn!("Subtraction overflow detected at line {}", mar + 1);
-        *dest -= val;
     }
+
+    *dest = word.wrap(dest.wrapping_sub(val));
 }
 
 /// Subtract values
@@ -70,22 +70,23 @@ pub fn sub(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         MultiOp(ops) => match ops[..] {
             [ref dest, ref val] if dest.is_read_write() && val.is_usizeable() => {
-                let line = ctx.mar;
+                let (line, word) = (ctx.mar, ctx.word());
                 let val = ctx.read(val)?;
-                ctx.modify(dest, |d| checked_sub(d, val, line))?;
+                ctx.modify(dest, |d| checked_sub(d, val, line, word))?;
             }
             [ref dest, ref a, ref b]
                 if dest.is_read_write() && a.is_usizeable() && b.is_usizeable() =>
             {
                 let mut a = ctx.read(a)?;
-                checked_sub(&mut a, ctx.read(b)?, ctx.mar);
+                checked_sub(&mut a, ctx.read(b)?, ctx.mar, ctx.word());
                 ctx.modify(dest, |d| *d = a)?;
             }
             _ => return Err(InvalidMultiOp),
         },
         val if val.is_usizeable() => {
             let val = ctx.read(val)?;
-            checked_sub(&mut ctx.acc, val, ctx.mar);
+            let word = ctx.word();
+            checked_sub(&mut ctx.acc, val, ctx.mar, word);
         }
         Null => return Err(NoOperand),
         _ => return Err(InvalidOperand),
@@ -101,8 +102,8 @@ pub fn sub(ctx: &mut Context, op: &Op) -> RtResult {
 pub fn inc(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         dest if dest.is_read_write() => {
-            let line = ctx.mar;
-            ctx.modify(dest, |d| checked_add(d, 1, line))?;
+            let (line, word) = (ctx.mar, ctx.word());
+            ctx.modify(dest, |d| checked_add(d, 1, line, word))?;
         }
         Null => return Err(NoOperand),
         _ => return Err(InvalidOperand),
@@ -118,8 +119,8 @@ pub fn inc(ctx: &mut Context, op: &Op) -> RtResult {
 pub fn dec(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         dest if dest.is_read_write() => {
-            let line = ctx.mar;
-            ctx.modify(dest, |d| checked_sub(d, 1, line))?;
+            let (line, word) = (ctx.mar, ctx.word());
+            ctx.modify(dest, |d| checked_sub(d, 1, line, word))?;
         }
         Null => return Err(NoOperand),
         _ => return Err(InvalidOperand),
@@ -128,6 +129,108 @@ pub fn dec(ctx: &mut Context, op: &Op) -> RtResult {
     Ok(())
 }
 
+/// Apply `f` to two values, with the same syntax as `ADD`
+#[cfg(feature = "extended")]
+pub(super) fn binary_op(
+    ctx: &mut Context,
+    op: &Op,
+    f: impl Fn(usize, usize, usize, Word) -> RtResult<usize>,
+) -> RtResult {
+    let (line, word) = (ctx.mar, ctx.word());
+
+    match op {
+        MultiOp(ops) => match ops[..] {
+            [ref dest, ref val] if dest.is_read_write() && val.is_usizeable() => {
+                let res = f(ctx.read(dest)?, ctx.read(val)?, line, word)?;
+                ctx.modify(dest, |d| *d = res)?;
+            }
+            [ref dest, ref a, ref b]
+                if dest.is_read_write() && a.is_usizeable() && b.is_usizeable() =>
+            {
+                let res = f(ctx.read(a)?, ctx.read(b)?, line, word)?;
+                ctx.modify(dest, |d| *d = res)?;
+            }
+            _ => return Err(InvalidMultiOp),
+        },
+        val if val.is_usizeable() => {
+            ctx.acc = f(ctx.acc, ctx.read(val)?, line, word)?;
+        }
+        Null => return Err(NoOperand),
+        _ => return Err(InvalidOperand),
+    }
+
+    Ok(())
+}
+
+/// Multiply values
+///
+/// # Syntax
+/// 1. `MUL [lit | reg | addr]` - multiply `ACC`
+/// 2. `MUL [reg | addr],[lit | reg | addr]` - multiply first value by second
+/// 3. `MUL [reg | addr],[lit | reg | addr],[lit | reg | addr]` - multiply second and third value, store to first
+#[cfg(feature = "extended")]
+pub fn mul(ctx: &mut Context, op: &Op) -> RtResult {
+    binary_op(ctx, op, |a, b, line, word| {
+        let fits = word
+            .value(a)
+            .checked_mul(word.value(b))
+            .map_or(false, |res| word.fits(res));
+
+        if !fits {
+            warn!("Multiplication overflow detected at line {}", line + 1);
+        }
+
+        Ok(word.wrap(a.wrapping_mul(b)))
+    })
+}
+
+/// Divide `a` by `b` with `f`, as signed values if words are signed
+#[cfg(feature = "extended")]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn checked_div(a: usize, b: usize, word: Word, f: fn(i128, i128) -> i128) -> RtResult<usize> {
+    if word.value(b) == 0 {
+        return Err(DivisionByZero);
+    }
+
+    // Two's complement, so the result is correct for signed words after wrapping
+    Ok(word.wrap(f(word.value(a), word.value(b)) as usize))
+}
+
+/// Integer division, rounding towards zero
+///
+/// Fails with [`DivisionByZero`](super::RtError::DivisionByZero) if the divisor is zero.
+///
+/// # Syntax
+/// 1. `DIV [lit | reg | addr]` - divide `ACC`
+/// 2. `DIV [reg | addr],[lit | reg | addr]` - divide first value by second
+/// 3. `DIV [reg | addr],[lit | reg | addr],[lit | reg | addr]` - divide second value by third, store to first
+#[cfg(feature = "extended")]
+pub fn div(ctx: &mut Context, op: &Op) -> RtResult {
+    binary_op(ctx, op, |a, b, line, word| {
+        // Only the most negative value divided by -1 overflows
+        if word.value(b) == -1 && !word.fits(-word.value(a)) {
+            warn!("Division overflow detected at line {}", line + 1);
+        }
+
+        checked_div(a, b, word, |a, b| a / b)
+    })
+}
+
+/// Remainder of integer division, which has the sign of the dividend
+///
+/// Fails with [`DivisionByZero`](super::RtError::DivisionByZero) if the divisor is zero.
+///
+/// # Syntax
+/// 1. `MOD [lit | reg | addr]` - remainder of `ACC`
+/// 2. `MOD [reg | addr],[lit | reg | addr]` - remainder of first value divided by second
+/// 3. `MOD [reg | addr],[lit | reg | addr],[lit | reg | addr]` - remainder of second value divided by third, store to first
+#[cfg(feature = "extended")]
+pub fn rem(ctx: &mut Context, op: &Op) -> RtResult {
+    binary_op(ctx, op, |a, b, _, word| {
+        checked_div(a, b, word, |a, b| a % b)
+    })
+}
+
 /// Zero a register or memory address
 ///
 /// # Syntax
@@ -106,7 +106,7 @@ pub fn lsl(ctx: &mut Context, op: &Op) -> RtResult {
             *dest = res;
         } else {
             warn!("Shift left overflow detected at line {}", mar + 1);
-            *dest <<= val;
+            *dest = dest.wrapping_shl(val as u32);
         }
     }
 
@@ -145,22 +145,28 @@ pub fn lsl(ctx: &mut Context, op: &Op) -> RtResult {
 /// 2. `LSR [reg | addr],[lit | reg | addr]` - store second LSR first to first
 /// 3. `LSR [reg | addr],[lit | reg | addr],[lit | reg | addr]` - store second LSR third to first
 pub fn lsr(ctx: &mut Context, op: &Op) -> RtResult {
+    // Shifting by the width or more is masked, like a release build would do with `>>`
+    #[allow(clippy::cast_possible_truncation)]
+    fn shr(val: usize, by: usize) -> usize {
+        val.wrapping_shr(by as u32)
+    }
+
     match op {
         MultiOp(ops) => match ops[..] {
             [ref dest, ref val] if dest.is_read_write() && val.is_usizeable() => {
                 let val = ctx.read(val)?;
-                ctx.modify(dest, |d| *d >>= val)
+                ctx.modify(dest, |d| *d = shr(*d, val))
             }
             [ref dest, ref a, ref b]
                 if dest.is_read_write() && a.is_usizeable() && b.is_usizeable() =>
             {
-                let val = ctx.read(a)? >> ctx.read(b)?;
+                let val = shr(ctx.read(a)?, ctx.read(b)?);
                 ctx.modify(dest, |d| *d = val)
             }
             _ => Err(InvalidMultiOp),
         },
         val if val.is_usizeable() => {
-            ctx.acc >>= ctx.read(val)?;
+            ctx.acc = shr(ctx.acc, ctx.read(val)?);
             Ok(())
         }
         Null => Err(NoOperand),
@@ -0,0 +1,162 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{
+    CancellationToken, Context, DebugInfo, ExTree, ExecHook, Executor, Io, Memory, Source, Width,
+};
+use std::collections::BTreeSet;
+
+/// Configures an [`Executor`] in one place, see [`Executor::builder`]
+///
+/// # Example
+/// ```
+/// # use cambridge_asm::{exec::{DebugInfo, Executor, ExecInst, io, Memory, Width}, inst::Op, make_io};
+/// let prog = [(0, ExecInst::new(0, "END", io::end, Op::Null))].into_iter().collect();
+///
+/// let mut exec = Executor::builder(prog)
+///     .with_mem(Memory::new([(1, 5)].into()))
+///     .with_io(make_io!())
+///     .with_width(Width::W8)
+///     .with_step_limit(100)
+///     .with_breakpoint(0)
+///     .build();
+///
+/// assert_eq!(exec.breakpoints().collect::<Vec<_>>(), [0]);
+/// ```
+pub struct ExecutorBuilder {
+    prog: ExTree,
+    source: Source,
+    mem: Memory,
+    mem_size: Option<usize>,
+    io: Io,
+    debug_info: DebugInfo,
+    width: Option<Width>,
+    step_limit: Option<u64>,
+    history_limit: usize,
+    hook: Option<Box<dyn ExecHook>>,
+    breakpoints: BTreeSet<usize>,
+    cancel: Option<CancellationToken>,
+}
+
+impl ExecutorBuilder {
+    pub(super) fn new(prog: ExTree) -> Self {
+        Self {
+            prog,
+            source: Source::default(),
+            mem: Memory::default(),
+            mem_size: None,
+            io: Io::default(),
+            debug_info: DebugInfo::default(),
+            width: None,
+            step_limit: None,
+            history_limit: 0,
+            hook: None,
+            breakpoints: BTreeSet::new(),
+            cancel: None,
+        }
+    }
+
+    /// Source code used to report errors, see [`Source`]
+    #[must_use]
+    pub fn with_source(mut self, source: impl Into<Source>) -> Self {
+        self.source = source.into();
+        self
+    }
+
+    /// Initial memory, empty by default
+    #[must_use]
+    pub fn with_mem(mut self, mem: Memory) -> Self {
+        self.mem = mem;
+        self
+    }
+
+    /// Number of memory addresses, see [`Memory::set_size`]
+    #[must_use]
+    pub fn with_mem_size(mut self, size: usize) -> Self {
+        self.mem_size = Some(size);
+        self
+    }
+
+    /// Input and output of the program, see [`Context::io`]
+    #[must_use]
+    pub fn with_io(mut self, io: Io) -> Self {
+        self.io = io;
+        self
+    }
+
+    #[must_use]
+    pub fn with_debug_info(mut self, debug_info: DebugInfo) -> Self {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// Word width in bits, see [`Context::set_width`]
+    #[must_use]
+    pub fn with_width(mut self, width: Width) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// See [`Executor::set_step_limit`]
+    #[must_use]
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    /// See [`Executor::set_history_limit`]
+    #[must_use]
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit;
+        self
+    }
+
+    /// See [`Executor::set_hook`]
+    #[must_use]
+    pub fn with_hook(mut self, hook: impl ExecHook + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// See [`Executor::add_breakpoint`]
+    #[must_use]
+    pub fn with_breakpoint(mut self, addr: usize) -> Self {
+        self.breakpoints.insert(addr);
+        self
+    }
+
+    /// See [`Executor::set_cancellation_token`]
+    #[must_use]
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    pub fn build(mut self) -> Executor {
+        if self.mem_size.is_some() {
+            self.mem.set_size(self.mem_size);
+        }
+
+        for (&addr, &ty) in &self.debug_info.mem_types {
+            self.mem.set_type(addr, ty);
+        }
+
+        let mut ctx = Context::with_io(self.mem, self.io);
+        ctx.aliases = self.debug_info.aliases.clone();
+
+        if self.width.is_some() {
+            ctx.set_width(self.width);
+        }
+
+        let mut exec = Executor::new(self.source, self.prog, ctx, self.debug_info);
+        exec.step_limit = self.step_limit;
+        exec.history.set_limit(self.history_limit);
+        exec.hook = self.hook;
+        exec.breakpoints = self.breakpoints;
+        exec.cancel = self.cancel;
+
+        exec
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    task::Waker,
+};
+
+/// Handle for stopping an [`Executor`](super::Executor) from another thread
+///
+/// Clones share the same state, so one clone can be given to the executor with
+/// [`Executor::set_cancellation_token`](super::Executor::set_cancellation_token) and another
+/// kept to cancel it, e.g. from a Stop button. Once cancelled, the executor returns
+/// [`Status::Cancelled`](super::Status::Cancelled) before executing the next instruction, and
+/// keeps doing so until the token is [reset](CancellationToken::reset).
+///
+/// An [`ExecAsync`](super::ExecAsync) that is waiting for input is woken up when the token is
+/// cancelled, so it stops without waiting any longer.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{exec::{CancellationToken, Status}, make_io, parse::{jit, DefaultSet}};
+///
+/// let mut exec = jit::<DefaultSet>("LOOP: JMP LOOP\n\nNONE:\n", make_io!())?;
+/// let token = CancellationToken::new();
+/// exec.set_cancellation_token(token.clone());
+///
+/// std::thread::spawn(move || token.cancel());
+///
+/// let status = loop {
+///     match exec.step() {
+///         Status::Continue => {}
+///         status => break status,
+///     }
+/// };
+///
+/// assert!(matches!(status, Status::Cancelled));
+/// # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// Task to wake when cancelled
+    waker: Mutex<Option<Waker>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+
+        if let Some(waker) = self.waker().take() {
+            waker.wake();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Allow execution to continue after being cancelled
+    pub fn reset(&self) {
+        self.0.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    /// Wake `waker` the next time the token is cancelled, replacing any previous waker
+    pub(crate) fn register(&self, waker: &Waker) {
+        *self.waker() = Some(waker.clone());
+    }
+
+    fn waker(&self) -> MutexGuard<'_, Option<Waker>> {
+        self.0.waker.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
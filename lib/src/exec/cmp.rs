@@ -37,18 +37,25 @@ pub fn jmp(ctx: &mut Context, op: &Op) -> RtResult {
 
 /// Compare
 ///
+/// Sets the comparison flag if the values are equal, and records whether the first is less than
+/// the second, see [`Context::compare`]
+///
 /// # Syntax
-/// 1. `CMP [lit | reg | addr]` - compare to ACC
+/// 1. `CMP [lit | reg | addr]` - compare ACC to value
 /// 2. `CMP [lit | reg | addr],[lit | reg | addr]` - compare both values
 pub fn cmp(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         MultiOp(ops) => match ops[..] {
             [ref a, ref b] if a.is_usizeable() && b.is_usizeable() => {
-                ctx.cmp = ctx.read(a)? == ctx.read(b)?;
+                let (a, b) = (ctx.read(a)?, ctx.read(b)?);
+                ctx.compare(a, b);
             }
             _ => return Err(InvalidMultiOp),
         },
-        val if val.is_usizeable() => ctx.cmp = ctx.acc == ctx.read(val)?,
+        val if val.is_usizeable() => {
+            let val = ctx.read(val)?;
+            ctx.compare(ctx.acc, val);
+        }
         Null => return Err(NoOperand),
         _ => return Err(InvalidOperand),
     }
@@ -66,8 +73,24 @@ pub fn cmi(ctx: &mut Context, op: &Op) -> RtResult {
         &Addr(addr) => {
             let addr2 = ctx.mem.get(&addr)?;
 
-            ctx.cmp = ctx.acc
-                == ctx
+            let val = ctx
+                .mem
+                .get(addr2)
+                .copied()
+                .map_err(|_| InvalidIndirectAddr {
+                    src: addr,
+                    redirect: *addr2,
+                })?;
+
+            ctx.compare(ctx.acc, val);
+
+            Ok(())
+        }
+        MultiOp(ops) => match ops[..] {
+            [ref dest, Addr(addr)] if dest.is_usizeable() => {
+                let addr2 = ctx.mem.get(&addr)?;
+
+                let val = ctx
                     .mem
                     .get(addr2)
                     .copied()
@@ -76,21 +99,7 @@ pub fn cmi(ctx: &mut Context, op: &Op) -> RtResult {
                         redirect: *addr2,
                     })?;
 
-            Ok(())
-        }
-        MultiOp(ops) => match ops[..] {
-            [ref dest, Addr(addr)] if dest.is_usizeable() => {
-                let addr2 = ctx.mem.get(&addr)?;
-
-                ctx.cmp = ctx.read(dest)?
-                    == ctx
-                        .mem
-                        .get(addr2)
-                        .copied()
-                        .map_err(|_| InvalidIndirectAddr {
-                            src: addr,
-                            redirect: *addr2,
-                        })?;
+                ctx.compare(ctx.read(dest)?, val);
 
                 Ok(())
             }
@@ -106,18 +115,7 @@ pub fn cmi(ctx: &mut Context, op: &Op) -> RtResult {
 /// # Syntax
 /// `JPE [addr]`
 pub fn jpe(ctx: &mut Context, op: &Op) -> RtResult {
-    match op {
-        &Addr(addr) => {
-            if ctx.cmp {
-                ctx.override_flow_control();
-                ctx.mar = addr;
-            }
-
-            Ok(())
-        }
-        Null => Err(NoOperand),
-        _ => Err(InvalidOperand),
-    }
+    jump_if(ctx, op, ctx.cmp)
 }
 
 /// Jump if not equal
@@ -125,9 +123,14 @@ pub fn jpe(ctx: &mut Context, op: &Op) -> RtResult {
 /// # Syntax
 /// `JPN [addr]`
 pub fn jpn(ctx: &mut Context, op: &Op) -> RtResult {
+    jump_if(ctx, op, !ctx.cmp)
+}
+
+/// Jump to `op` if `cond` holds for the result of the last comparison
+fn jump_if(ctx: &mut Context, op: &Op, cond: bool) -> RtResult {
     match op {
         &Addr(addr) => {
-            if !ctx.cmp {
+            if cond {
                 ctx.override_flow_control();
                 ctx.mar = addr;
             }
@@ -138,3 +141,43 @@ pub fn jpn(ctx: &mut Context, op: &Op) -> RtResult {
         _ => Err(InvalidOperand),
     }
 }
+
+/// Jump if greater
+///
+/// Jumps if the first value in the last comparison was greater than the second
+///
+/// # Syntax
+/// `JPG [addr]`
+#[cfg(feature = "extended")]
+pub fn jpg(ctx: &mut Context, op: &Op) -> RtResult {
+    jump_if(ctx, op, !ctx.cmp && !ctx.lt)
+}
+
+/// Jump if less
+///
+/// Jumps if the first value in the last comparison was less than the second
+///
+/// # Syntax
+/// `JPL [addr]`
+#[cfg(feature = "extended")]
+pub fn jpl(ctx: &mut Context, op: &Op) -> RtResult {
+    jump_if(ctx, op, ctx.lt)
+}
+
+/// Jump if greater or equal
+///
+/// # Syntax
+/// `JGE [addr]`
+#[cfg(feature = "extended")]
+pub fn jge(ctx: &mut Context, op: &Op) -> RtResult {
+    jump_if(ctx, op, !ctx.lt)
+}
+
+/// Jump if less or equal
+///
+/// # Syntax
+/// `JLE [addr]`
+#[cfg(feature = "extended")]
+pub fn jle(ctx: &mut Context, op: &Op) -> RtResult {
+    jump_if(ctx, op, ctx.cmp || ctx.lt)
+}
@@ -3,7 +3,8 @@ use std::collections::BTreeMap;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-use crate::parse::Span;
+use super::MemType;
+use crate::parse::{ErrorMap, Span};
 
 /// Struct to store original labels of shuffled addresses
 #[derive(Default, Debug, Clone)]
@@ -15,4 +16,74 @@ pub struct DebugInfo {
     pub mem: BTreeMap<usize, String>,
     /// Portions of source recognised as instructions
     pub inst_spans: Vec<Span>,
+    /// Comments at the end of the lines of instructions, without the `//`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub comments: BTreeMap<usize, String>,
+    /// Names given to general purpose registers with `NAME ALIAS rN`, by register number
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub aliases: BTreeMap<usize, String>,
+    /// Types of memory cells that are not words, such as strings, see
+    /// [`Memory::set_type`](super::Memory::set_type)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mem_types: BTreeMap<usize, MemType>,
+    /// Original line of each instruction, by address, so that errors can be shown in context
+    /// when running a compiled program
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub source_map: BTreeMap<usize, SourceLine>,
+    /// Problems that did not stop the source from being parsed, such as unused labels
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub warnings: ErrorMap,
+}
+
+impl DebugInfo {
+    /// Original label of the instruction at `addr`
+    pub fn label_for_inst(&self, addr: usize) -> Option<&str> {
+        self.prog.get(&addr).map(String::as_str)
+    }
+
+    /// Original label of the memory at `addr`
+    pub fn label_for_mem(&self, addr: usize) -> Option<&str> {
+        self.mem.get(&addr).map(String::as_str)
+    }
+
+    /// Span of the instruction at `addr` in the source it was parsed from
+    pub fn span_for_inst(&self, addr: usize) -> Option<&Span> {
+        self.inst_spans.get(addr)
+    }
+
+    /// Comment at the end of the line of the instruction at `addr`
+    pub fn comment(&self, addr: usize) -> Option<&str> {
+        self.comments.get(&addr).map(String::as_str)
+    }
+
+    /// Line of source of the instruction at `addr`
+    pub fn source_line(&self, addr: usize) -> Option<&SourceLine> {
+        self.source_map.get(&addr)
+    }
+}
+
+/// A line of source code, see [`DebugInfo::source_map`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SourceLine {
+    /// Line number, from 1
+    pub line: usize,
+    /// Text of the line, without surrounding whitespace
+    pub text: String,
+}
+
+impl SourceLine {
+    /// The line of `src` that `span` starts on
+    pub(crate) fn of_span(src: &str, span: &Span) -> Self {
+        let before = &src[..span.start];
+        let start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let end = src[span.start..]
+            .find('\n')
+            .map_or(src.len(), |idx| span.start + idx);
+
+        Self {
+            line: before.matches('\n').count() + 1,
+            text: src[start..end].trim().to_string(),
+        }
+    }
 }
@@ -6,20 +6,34 @@
 #![allow(clippy::module_name_repetitions)]
 
 use std::{
+    collections::BTreeMap,
     fmt::{Debug, Display, Formatter, Result as FmtResult},
     ops::Deref,
 };
 use thiserror::Error;
 
+use super::{
+    debug::SourceLine,
+    state::ContextSnapshot,
+    stream::{InputTimedOut, LimitExceeded},
+};
+use crate::inst::Op;
+
 /// Represents all possible runtime errors
 #[derive(Debug, Error)]
 pub enum RtError {
     #[error("{0}")]
     Other(String),
     #[error("Unexpected I/O error, caused by: {0}")]
-    IoError(#[from] std::io::Error),
+    IoError(std::io::Error),
+    #[error("Timed out while waiting for input")]
+    InputTimeout,
+    #[error("Input cannot be checked without waiting for it")]
+    InputNotPolled,
     #[error("#x{0:X} is not a valid UTF-8 byte.")]
     InvalidUtf8Byte(usize),
+    #[error("#x{0:X} is not a valid Unicode code point.")]
+    InvalidCodePoint(usize),
     #[error("Operand is not a memory address, register, or literal")]
     InvalidOperand,
     #[error("No operand needed")]
@@ -28,12 +42,81 @@ pub enum RtError {
     NoOperand,
     #[error("Invalid memory address `{0}`")]
     InvalidAddr(usize),
+    #[error("Nothing is attached to port {0}")]
+    InvalidPort(usize),
     #[error("Invalid indirect access address {redirect} at memory address {src}")]
     InvalidIndirectAddr { src: usize, redirect: usize },
     #[error("Invalid indexed access address `{}` from {src} + {offset}", .src +.offset)]
     InvalidIndexedAddr { src: usize, offset: usize },
     #[error("Invalid operand sequence")]
     InvalidMultiOp,
+    #[error("No instruction at address `{0}`")]
+    InvalidInstAddr(usize),
+    #[error("Execution timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("Input exceeded the limit of {0} bytes")]
+    InputLimit(u64),
+    #[error("Output exceeded the limit of {0} bytes")]
+    OutputLimit(u64),
+    #[error("Stack overflow, no memory below stack pointer {0}")]
+    StackOverflow(usize),
+    #[error("Stack underflow, stack pointer {0} is not in memory")]
+    StackUnderflow(usize),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Execution stopped after the limit of {0} instructions")]
+    StepLimit(u64),
+    #[error("Line of {len} characters does not fit in {max} memory cells")]
+    LineTooLong { len: usize, max: usize },
+    #[error("Output width {width} is wider than the maximum of {max}")]
+    WidthTooLarge { width: usize, max: usize },
+}
+
+impl RtError {
+    /// Name of the kind of error, e.g. `InvalidAddr`, for grouping errors
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Other(_) => "Other",
+            Self::IoError(_) => "IoError",
+            Self::InputTimeout => "InputTimeout",
+            Self::InputNotPolled => "InputNotPolled",
+            Self::InvalidUtf8Byte(_) => "InvalidUtf8Byte",
+            Self::InvalidCodePoint(_) => "InvalidCodePoint",
+            Self::InvalidOperand => "InvalidOperand",
+            Self::NoOpInst => "NoOpInst",
+            Self::NoOperand => "NoOperand",
+            Self::InvalidAddr(_) => "InvalidAddr",
+            Self::InvalidPort(_) => "InvalidPort",
+            Self::InvalidIndirectAddr { .. } => "InvalidIndirectAddr",
+            Self::InvalidIndexedAddr { .. } => "InvalidIndexedAddr",
+            Self::InvalidMultiOp => "InvalidMultiOp",
+            Self::InvalidInstAddr(_) => "InvalidInstAddr",
+            Self::Timeout(_) => "Timeout",
+            Self::InputLimit(_) => "InputLimit",
+            Self::OutputLimit(_) => "OutputLimit",
+            Self::StackOverflow(_) => "StackOverflow",
+            Self::StackUnderflow(_) => "StackUnderflow",
+            Self::DivisionByZero => "DivisionByZero",
+            Self::StepLimit(_) => "StepLimit",
+            Self::LineTooLong { .. } => "LineTooLong",
+            Self::WidthTooLarge { .. } => "WidthTooLarge",
+        }
+    }
+}
+
+impl From<std::io::Error> for RtError {
+    fn from(value: std::io::Error) -> Self {
+        if InputTimedOut::is_cause_of(&value) {
+            Self::InputTimeout
+        } else if let Some(limit) = LimitExceeded::cause_of(&value) {
+            match *limit {
+                LimitExceeded::Input(limit) => Self::InputLimit(limit),
+                LimitExceeded::Output(limit) => Self::OutputLimit(limit),
+            }
+        } else {
+            Self::IoError(value)
+        }
+    }
 }
 
 impl From<&'static str> for RtError {
@@ -50,12 +133,55 @@ impl From<String> for RtError {
 
 pub type RtResult<T = ()> = Result<T, RtError>;
 
+/// A [`RtError`] with the instruction that caused it, and the registers and memory at the time
+#[derive(Debug)]
+pub struct RuntimeFault {
+    pub error: RtError,
+    /// Address of the instruction
+    pub addr: usize,
+    /// Mnemonic of the instruction, empty if there is no instruction at `addr`
+    pub mnemonic: String,
+    pub op: Op,
+    pub context: Box<ContextSnapshot>,
+}
+
+impl Display for RuntimeFault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} at address {}", self.error, self.addr)?;
+
+        match (self.mnemonic.as_str(), &self.op) {
+            ("", _) => Ok(()),
+            (mnemonic, Op::Null) => write!(f, " (`{mnemonic}`)"),
+            (mnemonic, op) => write!(f, " (`{mnemonic} {op}`)"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeFault {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
 /// Stores original source code during execution
 #[derive(Debug, Default, Clone)]
-#[repr(transparent)]
-pub struct Source(Vec<String>);
+pub struct Source {
+    /// Line number, from 1, and text of the line shown for each instruction address
+    lines: BTreeMap<usize, (usize, String)>,
+}
 
 impl Source {
+    /// Show the original lines of instructions from a
+    /// [`DebugInfo::source_map`](super::DebugInfo::source_map)
+    pub fn from_source_map(map: &BTreeMap<usize, SourceLine>) -> Self {
+        Self {
+            lines: map
+                .iter()
+                .map(|(&addr, SourceLine { line, text })| (addr, (*line, text.clone())))
+                .collect(),
+        }
+    }
+
     pub fn handle_err(
         &self,
         write: &mut impl std::io::Write,
@@ -65,61 +191,58 @@ impl Source {
         writeln!(write, "Runtime Error:")?;
         writeln!(write)?;
 
-        if self.0.is_empty() {
+        if self.lines.is_empty() {
             writeln!(write, "(source empty, error at position {pos})")?;
             return writeln!(write, "message: {err}");
         }
 
-        for (i, s) in self.0.iter().enumerate() {
-            if pos == i {
-                if let Some(prev) = self.0.get(i - 1) {
-                    writeln!(write, "{num:>w$}    {prev}", num = i, w = self.whitespace())?;
-                }
-
-                writeln!(
-                    write,
-                    "{num:>w$}    {s} <-",
-                    num = i + 1,
-                    w = self.whitespace()
-                )?;
-
-                if let Some(next) = self.0.get(i + 1) {
-                    writeln!(
-                        write,
-                        "{num:>w$}    {next}",
-                        num = i + 2,
-                        w = self.whitespace()
-                    )?;
-                }
-
-                writeln!(write)?;
-                writeln!(write, "message: {err}")?;
-                break;
+        if let Some((num, s)) = self.lines.get(&pos) {
+            let w = self.whitespace();
+
+            if let Some((_, (prev_num, prev))) = self.lines.range(..pos).next_back() {
+                writeln!(write, "{prev_num:>w$}    {prev}")?;
+            }
+
+            writeln!(write, "{num:>w$}    {s} <-")?;
+
+            if let Some((_, (next_num, next))) = self.lines.range(pos + 1..).next() {
+                writeln!(write, "{next_num:>w$}    {next}")?;
             }
+
+            writeln!(write)?;
+            writeln!(write, "message: {err}")?;
         }
+
         writeln!(write)
     }
 
     fn whitespace(&self) -> usize {
-        self.0.len().to_string().len()
+        self.lines
+            .values()
+            .map(|(num, _)| *num)
+            .max()
+            .unwrap_or_default()
+            .to_string()
+            .len()
     }
 }
 
 impl<T: Deref<Target = str>> From<T> for Source {
     fn from(s: T) -> Self {
-        Source(
-            s.to_string()
+        Source {
+            lines: s
                 .lines()
                 .filter(|&el| !el.starts_with("//"))
-                .map(String::from)
+                .enumerate()
+                .map(|(i, el)| (i, (i + 1, el.to_string())))
                 .collect(),
-        )
+        }
     }
 }
 
 impl Display for Source {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        for inst in &self.0 {
+        for (_, inst) in self.lines.values() {
             writeln!(f, "    {inst}")?;
         }
 
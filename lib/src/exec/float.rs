@@ -0,0 +1,160 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{arith::binary_op, Context, RtError::*, RtResult};
+use crate::inst::Op::{self, *};
+use std::io::Write;
+
+#[inline]
+#[allow(clippy::cast_possible_truncation)]
+fn to_float(val: usize) -> f64 {
+    f64::from_bits(val as u64)
+}
+
+#[inline]
+#[allow(clippy::cast_possible_truncation)]
+fn from_float(val: f64) -> usize {
+    val.to_bits() as usize
+}
+
+/// Apply `f` to two floats, with the same syntax as `ADD`
+fn float_op(ctx: &mut Context, op: &Op, f: fn(f64, f64) -> f64) -> RtResult {
+    binary_op(ctx, op, |a, b, _, _| {
+        Ok(from_float(f(to_float(a), to_float(b))))
+    })
+}
+
+/// Add floats
+///
+/// # Syntax
+/// 1. `FADD [lit | reg | addr]` - add to `ACC`
+/// 2. `FADD [reg | addr],[lit | reg | addr]` - add second value to first
+/// 3. `FADD [reg | addr],[lit | reg | addr],[lit | reg | addr]` - add second and third value, store to first
+pub fn fadd(ctx: &mut Context, op: &Op) -> RtResult {
+    float_op(ctx, op, |a, b| a + b)
+}
+
+/// Subtract floats
+///
+/// # Syntax
+/// 1. `FSUB [lit | reg | addr]` - subtract from `ACC`
+/// 2. `FSUB [reg | addr],[lit | reg | addr]` - subtract second value from first
+/// 3. `FSUB [reg | addr],[lit | reg | addr],[lit | reg | addr]` - subtract third from second value, store to first
+pub fn fsub(ctx: &mut Context, op: &Op) -> RtResult {
+    float_op(ctx, op, |a, b| a - b)
+}
+
+/// Multiply floats
+///
+/// # Syntax
+/// 1. `FMUL [lit | reg | addr]` - multiply `ACC`
+/// 2. `FMUL [reg | addr],[lit | reg | addr]` - multiply first value by second
+/// 3. `FMUL [reg | addr],[lit | reg | addr],[lit | reg | addr]` - multiply second and third value, store to first
+pub fn fmul(ctx: &mut Context, op: &Op) -> RtResult {
+    float_op(ctx, op, |a, b| a * b)
+}
+
+/// Divide floats
+///
+/// Dividing by zero gives an infinity or NaN, as in IEEE 754.
+///
+/// # Syntax
+/// 1. `FDIV [lit | reg | addr]` - divide `ACC`
+/// 2. `FDIV [reg | addr],[lit | reg | addr]` - divide first value by second
+/// 3. `FDIV [reg | addr],[lit | reg | addr],[lit | reg | addr]` - divide second value by third, store to first
+pub fn fdiv(ctx: &mut Context, op: &Op) -> RtResult {
+    float_op(ctx, op, |a, b| a / b)
+}
+
+/// Convert an integer to a float
+///
+/// The integer is signed if [`Context::signed`] is set.
+///
+/// # Syntax
+/// 1. `ITOF` - convert `ACC`
+/// 2. `ITOF [reg | addr]`
+#[allow(clippy::cast_precision_loss)]
+pub fn itof(ctx: &mut Context, op: &Op) -> RtResult {
+    let signed = ctx.signed;
+    let width = ctx.display_bits();
+
+    let convert = |val: &mut usize| {
+        let float = if signed {
+            super::to_signed(*val, width) as f64
+        } else {
+            *val as f64
+        };
+
+        *val = from_float(float);
+    };
+
+    match op {
+        Null => convert(&mut ctx.acc),
+        dest if dest.is_read_write() => ctx.modify(dest, convert)?,
+        _ => return Err(InvalidOperand),
+    }
+
+    Ok(())
+}
+
+/// Convert a float to an integer, rounding towards zero
+///
+/// Values out of range saturate, and NaN becomes 0. The integer is signed if
+/// [`Context::signed`] is set.
+///
+/// # Syntax
+/// 1. `FTOI` - convert `ACC`
+/// 2. `FTOI [reg | addr]`
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn ftoi(ctx: &mut Context, op: &Op) -> RtResult {
+    let signed = ctx.signed;
+
+    let convert = |val: &mut usize| {
+        let float = to_float(*val);
+
+        *val = if signed {
+            float as isize as usize
+        } else {
+            float as usize
+        };
+    };
+
+    match op {
+        Null => convert(&mut ctx.acc),
+        dest if dest.is_read_write() => ctx.modify(dest, convert)?,
+        _ => return Err(InvalidOperand),
+    }
+
+    Ok(())
+}
+
+/// Float output
+///
+/// # Syntax
+/// 1. `FOUT` - output `ACC`
+/// 2. `FOUT [lit | reg | addr]`
+/// 3. `FOUT [lit | reg | addr],[lit | reg | addr]` - output with the number of decimal places given by second value
+pub fn fout(ctx: &mut Context, op: &Op) -> RtResult {
+    let (val, places) = match op {
+        Null => (ctx.acc, None),
+        MultiOp(ops) => match ops[..] {
+            [ref val, ref places] if val.is_usizeable() && places.is_usizeable() => {
+                (ctx.read(val)?, Some(ctx.read(places)?))
+            }
+            _ => return Err(InvalidMultiOp),
+        },
+        val if val.is_usizeable() => (ctx.read(val)?, None),
+        _ => return Err(InvalidOperand),
+    };
+
+    let val = to_float(val);
+
+    match places {
+        Some(places) => write!(ctx.io.write, "{val:.places$}")?,
+        None => write!(ctx.io.write, "{val}")?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{ExecSummary, Executor, RtError, RuntimeFault, Status};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    future::Future,
+    io::{self, BufRead, BufReader, Read},
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context as TaskContext, Poll},
+};
+
+/// Source of input that does not block, for [`Executor::exec_async`]
+///
+/// This has the same contract as `AsyncRead::poll_read` from the `futures` crate, so adapting
+/// an existing async reader only takes a few lines.
+pub trait AsyncInput {
+    /// Read into `buf`, returning the number of bytes read, or `0` at the end of the input
+    ///
+    /// If no input is available yet, return [`Poll::Pending`] and wake the task in `cx` once
+    /// there is.
+    fn poll_read(&mut self, cx: &mut TaskContext<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+/// Marker carried by the [`io::Error`] returned when the input of an [`ExecAsync`] has run out
+/// for now
+#[derive(Debug)]
+pub(crate) struct InputPending;
+
+impl Display for InputPending {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("waiting for input")
+    }
+}
+
+impl Error for InputPending {}
+
+impl InputPending {
+    pub(crate) fn is_cause_of(err: &RtError) -> bool {
+        matches!(err, RtError::IoError(e) if matches!(e.get_ref(), Some(e) if e.is::<Self>()))
+    }
+}
+
+#[derive(Default)]
+struct PipeState {
+    buf: VecDeque<u8>,
+    /// Bytes read by the current instruction, to be put back if it has to be retried
+    taken: Vec<u8>,
+    eof: bool,
+}
+
+/// Reader given to the executor in place of its own, filled from an [`AsyncInput`]
+#[derive(Clone, Default)]
+struct Pipe(Arc<Mutex<PipeState>>);
+
+impl Pipe {
+    fn with<U>(&self, f: impl FnOnce(&mut PipeState) -> U) -> U {
+        f(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.with(|state| {
+            if state.buf.is_empty() && !state.eof && !out.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, InputPending));
+            }
+
+            let len = out.len().min(state.buf.len());
+
+            for (dest, byte) in out.iter_mut().zip(state.buf.drain(..len)) {
+                *dest = byte;
+                state.taken.push(byte);
+            }
+
+            Ok(len)
+        })
+    }
+}
+
+/// Execution of a program that waits for input without blocking, see [`Executor::exec_async`]
+///
+/// Awaiting this runs the program until it ends, like [`Executor::exec`]. Use
+/// [`ExecAsync::step`] instead to see the state after each instruction.
+///
+/// The input of the executor is replaced until this is dropped. Input that was read from the
+/// [`AsyncInput`] but not used by the program is lost.
+pub struct ExecAsync<'a, R> {
+    exec: &'a mut Executor,
+    input: R,
+    pipe: Pipe,
+    saved: Option<BufReader<Box<dyn Read + Send + Sync>>>,
+    /// The current instruction is waiting for more input
+    waiting: bool,
+}
+
+impl<'a, R> ExecAsync<'a, R>
+where
+    R: AsyncInput,
+{
+    /// Instructions executed before yielding to other tasks, so that the host stays responsive
+    const BUDGET: usize = 1024;
+
+    pub(super) fn new(exec: &'a mut Executor, input: R) -> Self {
+        let pipe = Pipe::default();
+        let read = BufReader::new(Box::new(pipe.clone()) as Box<dyn Read + Send + Sync>);
+        let saved = Some(std::mem::replace(&mut exec.ctx.io.read, read));
+
+        Self {
+            exec,
+            input,
+            pipe,
+            saved,
+            waiting: false,
+        }
+    }
+
+    pub fn executor(&self) -> &Executor {
+        self.exec
+    }
+
+    /// Advance execution by one instruction, waiting for input if it needs any
+    ///
+    /// Resolves to the same [`Status`] as [`Executor::step`].
+    pub fn step(&mut self) -> StepAsync<'_, 'a, R> {
+        StepAsync(self)
+    }
+
+    fn poll_step(&mut self, cx: &mut TaskContext<'_>) -> Poll<Status> {
+        loop {
+            if self.waiting {
+                if let Some(token) = &self.exec.cancel {
+                    // Registered first, so a cancellation between the two is not missed
+                    token.register(cx.waker());
+
+                    if token.is_cancelled() {
+                        return Poll::Ready(Status::Cancelled);
+                    }
+                }
+
+                let mut buf = [0; 256];
+
+                match self.input.poll_read(cx, &mut buf) {
+                    Poll::Ready(Ok(n)) => self.pipe.with(|state| {
+                        state.buf.extend(&buf[..n]);
+                        state.eof = n == 0;
+                    }),
+                    Poll::Ready(Err(e)) => {
+                        self.waiting = false;
+                        return Poll::Ready(Status::Error(e.into()));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+
+                self.waiting = false;
+            }
+
+            // Bytes already buffered may be used by this instruction too
+            let buffered = self.exec.ctx.io.read.buffer().to_vec();
+            self.pipe.with(|state| state.taken.clear());
+
+            match self.exec.step() {
+                Status::Error(e) if InputPending::is_cause_of(&e) => {
+                    // Put back everything the instruction read, so that it can be retried
+                    let read = &mut self.exec.ctx.io.read;
+                    read.consume(read.buffer().len());
+
+                    self.pipe.with(|state| {
+                        for &byte in buffered.iter().chain(&state.taken).rev() {
+                            state.buf.push_front(byte);
+                        }
+                    });
+
+                    self.waiting = true;
+                }
+                status => return Poll::Ready(status),
+            }
+        }
+    }
+}
+
+impl<R> Future for ExecAsync<'_, R>
+where
+    R: AsyncInput + Unpin,
+{
+    type Output = Result<ExecSummary, RuntimeFault>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        for _ in 0..Self::BUDGET {
+            let res = match this.poll_step(cx) {
+                Poll::Ready(Status::Complete) => Ok(this.exec.summary(false)),
+                Poll::Ready(Status::Cancelled) => Ok(this.exec.summary(true)),
+                Poll::Ready(Status::Continue | Status::Breakpoint(_)) => continue,
+                Poll::Ready(Status::Error(e)) => Err(this.exec.fault(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            this.exec.finish(&res);
+
+            return Poll::Ready(res);
+        }
+
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<R> Drop for ExecAsync<'_, R> {
+    fn drop(&mut self) {
+        if let Some(read) = self.saved.take() {
+            self.exec.ctx.io.read = read;
+        }
+    }
+}
+
+/// Future returned by [`ExecAsync::step`]
+pub struct StepAsync<'b, 'a, R>(&'b mut ExecAsync<'a, R>);
+
+impl<R> Future for StepAsync<'_, '_, R>
+where
+    R: AsyncInput,
+{
+    type Output = Status;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Status> {
+        self.get_mut().0.poll_step(cx)
+    }
+}
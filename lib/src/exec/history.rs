@@ -0,0 +1,119 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::Context;
+use std::collections::VecDeque;
+
+/// Register file of a [`Context`]
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+pub(crate) struct Registers {
+    cmp: bool,
+    lt: bool,
+    mar: usize,
+    acc: usize,
+    ix: usize,
+    flow_override_reg: bool,
+    ret: usize,
+    sp: usize,
+    /// One step pushes or pops at most one return address, so the length and the innermost
+    /// address are enough to restore the call stack
+    call_depth: usize,
+    caller_ret: Option<usize>,
+    gprs: [usize; 30],
+    end: bool,
+}
+
+impl Registers {
+    pub(crate) fn save(ctx: &Context) -> Self {
+        Self {
+            cmp: ctx.cmp,
+            lt: ctx.lt,
+            mar: ctx.mar,
+            acc: ctx.acc,
+            ix: ctx.ix,
+            flow_override_reg: ctx.flow_override_reg,
+            ret: ctx.ret,
+            sp: ctx.sp,
+            call_depth: ctx.call_stack.len(),
+            caller_ret: ctx.call_stack.last().copied(),
+            gprs: ctx.gprs,
+            end: ctx.end,
+        }
+    }
+
+    pub(crate) fn restore(self, ctx: &mut Context) {
+        ctx.cmp = self.cmp;
+        ctx.lt = self.lt;
+        ctx.mar = self.mar;
+        ctx.acc = self.acc;
+        ctx.ix = self.ix;
+        ctx.flow_override_reg = self.flow_override_reg;
+        ctx.ret = self.ret;
+        ctx.sp = self.sp;
+        ctx.call_stack.truncate(self.call_depth);
+        if ctx.call_stack.len() < self.call_depth {
+            ctx.call_stack.extend(self.caller_ret);
+        }
+        ctx.gprs = self.gprs;
+        ctx.end = self.end;
+    }
+}
+
+/// State overwritten by one step
+#[derive(Debug)]
+struct Delta {
+    regs: Registers,
+    mem: Vec<(usize, usize)>,
+}
+
+/// Ring buffer of the most recent [`Delta`]s
+#[derive(Debug, Default)]
+pub(crate) struct History {
+    deltas: VecDeque<Delta>,
+    limit: usize,
+}
+
+impl History {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.limit > 0
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+
+        while self.deltas.len() > limit {
+            self.deltas.pop_front();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.deltas.clear();
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Remember a step, given the registers before it and its memory journal
+    pub(crate) fn commit(&mut self, regs: &Registers, mem: Vec<(usize, usize)>) {
+        if self.deltas.len() == self.limit {
+            self.deltas.pop_front();
+        }
+
+        self.deltas.push_back(Delta { regs: *regs, mem });
+    }
+
+    /// Undo the most recent step, returning `false` if there is nothing to undo
+    pub(crate) fn undo(&mut self, ctx: &mut Context) -> bool {
+        if let Some(Delta { regs, mem }) = self.deltas.pop_back() {
+            ctx.mem.rewind(&mem);
+            regs.restore(ctx);
+            true
+        } else {
+            false
+        }
+    }
+}
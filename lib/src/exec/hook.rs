@@ -0,0 +1,55 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{Context, RtResult};
+use crate::inst::Op;
+use std::time::Duration;
+
+/// Decoded instruction passed to an [`ExecHook`]
+#[derive(Debug, Clone, Copy)]
+pub struct HookInst<'a> {
+    /// Address of the instruction
+    pub addr: usize,
+    /// Mnemonic of the instruction, e.g. `LDM`
+    pub opcode: &'a str,
+    pub op: &'a Op,
+    /// Comment at the end of the line of the instruction, from [`DebugInfo`](super::DebugInfo)
+    pub comment: Option<&'a str>,
+    /// Addresses of the memory cells written by the instruction, in order. Always empty in
+    /// [`ExecHook::before`].
+    pub writes: &'a [usize],
+    /// Wall time taken to execute the instruction. Always zero in [`ExecHook::before`].
+    pub elapsed: Duration,
+}
+
+/// Observer called around each instruction executed by an [`Executor`](super::Executor)
+///
+/// Set with [`Executor::set_hook`](super::Executor::set_hook). Both methods do nothing by
+/// default, so only the ones needed have to be implemented.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::exec::{Context, ExecHook, HookInst, RtResult};
+///
+/// #[derive(Default)]
+/// struct Counter(usize);
+///
+/// impl ExecHook for Counter {
+///     fn after(&mut self, _: &HookInst<'_>, _: &Context, res: &RtResult) {
+///         if res.is_ok() {
+///             self.0 += 1;
+///         }
+///     }
+/// }
+/// ```
+pub trait ExecHook: Send + Sync {
+    /// Called before `inst` is executed
+    #[allow(unused_variables)]
+    fn before(&mut self, inst: &HookInst<'_>, ctx: &Context) {}
+
+    /// Called after `inst` is executed, with the result of executing it
+    #[allow(unused_variables)]
+    fn after(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) {}
+}
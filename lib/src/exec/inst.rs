@@ -4,8 +4,17 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::{
-    exec::{Context, RtResult},
-    inst::Op,
+    exec::{access::Access, Context, RtError, RtResult},
+    inst::{InstSet, Op},
+};
+use std::{
+    collections::{btree_map, BTreeMap},
+    fmt::Display,
+    iter::Enumerate,
+    ops::Index,
+    slice,
+    str::FromStr,
+    vec,
 };
 
 /// Function pointer of an instruction called with [`Context`] and [`Op`] at runtime
@@ -16,16 +25,236 @@ pub type ExecFunc = fn(&mut Context, &Op) -> RtResult;
 pub struct ExecInst {
     /// Identifies the instruction with an integer, fixes rust-lang/rfcs#3535
     pub id: u64,
+    /// Name of the instruction, as written in source, for traces and hooks
+    pub mnemonic: String,
     pub func: ExecFunc,
     pub op: Op,
 }
 
 impl ExecInst {
-    pub fn new(id: u64, inst: ExecFunc, op: Op) -> Self {
-        Self { func: inst, op, id }
+    pub fn new(id: u64, mnemonic: impl Into<String>, inst: ExecFunc, op: Op) -> Self {
+        Self {
+            func: inst,
+            op,
+            id,
+            mnemonic: mnemonic.into(),
+        }
+    }
+
+    pub fn from_inst<T>(inst: &T, op: Op) -> Self
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        Self::new(inst.id(), inst.mnemonic(), inst.as_func_ptr(), op)
+    }
+}
+
+/// Runtime representation of a program, by address
+///
+/// Instructions are kept in a `Vec` when their addresses run from 0 without gaps, as they do
+/// unless the source gives addresses, so that each step indexes the next instruction instead of
+/// searching a tree. Their operands are then resolved when an [`Executor`](super::Executor) is
+/// made, see [`ExTree::get_mut`].
+#[derive(Clone, Default)]
+pub struct ExTree(pub(super) Insts, Vec<Option<Access>>);
+
+#[derive(Clone)]
+pub(super) enum Insts {
+    Dense(Vec<ExecInst>),
+    Sparse(BTreeMap<usize, ExecInst>),
+}
+
+impl Default for Insts {
+    fn default() -> Self {
+        Self::Dense(Vec::new())
+    }
+}
+
+impl ExTree {
+    pub fn get(&self, addr: usize) -> Option<&ExecInst> {
+        match &self.0 {
+            Insts::Dense(insts) => insts.get(addr),
+            Insts::Sparse(insts) => insts.get(&addr),
+        }
+    }
+
+    /// Changing the operand of an instruction makes it slower to execute, since it is no longer
+    /// resolved in advance
+    pub fn get_mut(&mut self, addr: usize) -> Option<&mut ExecInst> {
+        if let Some(access) = self.1.get_mut(addr) {
+            *access = None;
+        }
+
+        match &mut self.0 {
+            Insts::Dense(insts) => insts.get_mut(addr),
+            Insts::Sparse(insts) => insts.get_mut(&addr),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.0 {
+            Insts::Dense(insts) => insts.len(),
+            Insts::Sparse(insts) => insts.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Instructions with their addresses, in order
+    pub fn iter(&self) -> ExTreeIter<'_> {
+        match &self.0 {
+            Insts::Dense(insts) => ExTreeIter {
+                dense: Some(insts.iter().enumerate()),
+                sparse: None,
+            },
+            Insts::Sparse(insts) => ExTreeIter {
+                dense: None,
+                sparse: Some(insts.iter()),
+            },
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = usize> + '_ {
+        self.iter().map(|(addr, _)| addr)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &ExecInst> + '_ {
+        self.iter().map(|(_, inst)| inst)
+    }
+
+    /// Resolve the operands of a dense program, so that they are not checked on every step
+    pub(super) fn resolve(&mut self) {
+        self.1 = match &self.0 {
+            Insts::Dense(insts) => insts.iter().map(|inst| Access::resolve(&inst.op)).collect(),
+            Insts::Sparse(_) => Vec::new(),
+        };
+    }
+
+    /// Operand of the instruction at `addr`, if it has been resolved
+    #[inline]
+    pub(super) fn access(&self, addr: usize) -> Option<Access> {
+        self.1.get(addr).copied().flatten()
+    }
+}
+
+impl From<BTreeMap<usize, ExecInst>> for ExTree {
+    fn from(insts: BTreeMap<usize, ExecInst>) -> Self {
+        if insts.keys().copied().eq(0..insts.len()) {
+            Self(Insts::Dense(insts.into_values().collect()), Vec::new())
+        } else {
+            Self(Insts::Sparse(insts), Vec::new())
+        }
     }
 }
 
+impl<const N: usize> From<[(usize, ExecInst); N]> for ExTree {
+    fn from(insts: [(usize, ExecInst); N]) -> Self {
+        insts.into_iter().collect()
+    }
+}
+
+impl FromIterator<(usize, ExecInst)> for ExTree {
+    fn from_iter<I: IntoIterator<Item = (usize, ExecInst)>>(iter: I) -> Self {
+        iter.into_iter().collect::<BTreeMap<_, _>>().into()
+    }
+}
+
+impl IntoIterator for ExTree {
+    type IntoIter = ExTreeIntoIter;
+    type Item = (usize, ExecInst);
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self.0 {
+            Insts::Dense(insts) => ExTreeIntoIter {
+                dense: Some(insts.into_iter().enumerate()),
+                sparse: None,
+            },
+            Insts::Sparse(insts) => ExTreeIntoIter {
+                dense: None,
+                sparse: Some(insts.into_iter()),
+            },
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ExTree {
+    type IntoIter = ExTreeIter<'a>;
+    type Item = (usize, &'a ExecInst);
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the instructions of an [`ExTree`] with their addresses, made with
+/// [`ExTree::iter`]
+#[derive(Clone)]
+pub struct ExTreeIter<'a> {
+    dense: Option<Enumerate<slice::Iter<'a, ExecInst>>>,
+    sparse: Option<btree_map::Iter<'a, usize, ExecInst>>,
+}
+
+impl<'a> Iterator for ExTreeIter<'a> {
+    type Item = (usize, &'a ExecInst);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (&mut self.dense, &mut self.sparse) {
+            (Some(dense), _) => dense.next(),
+            (_, Some(sparse)) => sparse.next().map(|(&addr, inst)| (addr, inst)),
+            _ => None,
+        }
+    }
+}
+
+/// Owning iterator over the instructions of an [`ExTree`] with their addresses
+pub struct ExTreeIntoIter {
+    dense: Option<Enumerate<vec::IntoIter<ExecInst>>>,
+    sparse: Option<btree_map::IntoIter<usize, ExecInst>>,
+}
+
+impl Iterator for ExTreeIntoIter {
+    type Item = (usize, ExecInst);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (&mut self.dense, &mut self.sparse) {
+            (Some(dense), _) => dense.next(),
+            (_, Some(sparse)) => sparse.next(),
+            _ => None,
+        }
+    }
+}
+
+/// # Panics
+/// If there is no instruction at `addr`. To avoid this, use [`ExTree::get`]. With the
+/// `strict-no-panic` feature, an instruction that fails with [`RtError::InvalidInstAddr`] when
+/// executed is returned instead.
+impl Index<usize> for ExTree {
+    type Output = ExecInst;
+
+    fn index(&self, addr: usize) -> &ExecInst {
+        match self.get(addr) {
+            Some(inst) => inst,
+            None if cfg!(feature = "strict-no-panic") => &MISSING_INST,
+            None => panic!("No instruction at address {addr}"),
+        }
+    }
+}
+
+/// Stands in for a missing instruction when indexing an [`ExTree`] with `strict-no-panic`
+static MISSING_INST: ExecInst = ExecInst {
+    id: u64::MAX,
+    mnemonic: String::new(),
+    func: missing_inst,
+    op: Op::Null,
+};
+
+fn missing_inst(ctx: &mut Context, _: &Op) -> RtResult {
+    Err(RtError::InvalidInstAddr(ctx.mar))
+}
+
 /// Macro to generate an instruction implementation
 ///
 /// # Examples
@@ -90,7 +319,11 @@ mod tests {
         ];
 
         for (op, res) in ops {
-            assert_eq!(Op::from(op), res);
+            assert_eq!(Op::try_from(op), Ok(res));
+        }
+
+        for op in ["r30", "#", "#xZZ", "#abc", "200,r99"] {
+            assert!(Op::try_from(op).is_err(), "{op}");
         }
     }
 }
@@ -3,6 +3,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use super::{Encoding, RtResult};
 use crate::{exec::RtError::*, inst};
 use std::io::{Read, Write};
 
@@ -29,46 +30,84 @@ inst!(
     ///
     /// Convert an ASCII code to a character and print to STDOUT
     ///
+    /// Values are Unicode code points instead if the [encoding](crate::exec::Io::encoding) is
+    /// [`Encoding::Utf8`](crate::exec::Encoding::Utf8)
+    ///
     /// # Syntax
     /// 1. `OUT` - output `ACC`
     /// 2. `OUT [lit | reg | addr]`
     pub out (ctx, op) {
         match op {
-            Null => {
-                let x = ctx.acc;
-
-                if x > 255 {
-                    return Err(InvalidUtf8Byte(x));
-                }
-
-                #[allow(clippy::cast_possible_truncation)]
-                let out = x as u8;
-
-                ctx.io.write.write_all(&[out])?;
-            }
+            Null => write_char(&mut ctx.io.write, ctx.io.encoding, ctx.acc)?,
             src if src.is_usizeable() => {
                 let src = ctx.read(src)?;
-
-                if src > 255 {
-                    return Err(InvalidUtf8Byte(src));
-                }
-
-                #[allow(clippy::cast_possible_truncation)]
-                let out = src as u8;
-
-                ctx.io.write.write_all(&[out])?;
+                write_char(&mut ctx.io.write, ctx.io.encoding, src)?;
             }
             _ => return Err(InvalidOperand),
         }
     }
 );
 
+/// Write `val` as a character in `encoding`
+fn write_char(write: &mut impl Write, encoding: Encoding, val: usize) -> RtResult<()> {
+    match encoding {
+        Encoding::Byte => {
+            let byte = u8::try_from(val).map_err(|_| InvalidUtf8Byte(val))?;
+            write.write_all(&[byte])?;
+        }
+        Encoding::Utf8 => {
+            let c = u32::try_from(val)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(InvalidCodePoint(val))?;
+            write.write_all(c.encode_utf8(&mut [0; 4]).as_bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read one character in `encoding`
+fn read_char(read: &mut impl Read, encoding: Encoding) -> RtResult<usize> {
+    let mut buf = [0; 4];
+    read.read_exact(&mut buf[..1])?;
+
+    if encoding == Encoding::Byte {
+        return Ok(buf[0].into());
+    }
+
+    // The number of leading ones in the first byte is the length of the sequence
+    let len = match buf[0].leading_ones() {
+        0 => 1,
+        n @ 2..=4 => n as usize,
+        _ => return Err(invalid_utf8().into()),
+    };
+
+    read.read_exact(&mut buf[1..len])?;
+
+    std::str::from_utf8(&buf[..len])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map(|c| c as usize)
+        .ok_or_else(|| invalid_utf8().into())
+}
+
+fn invalid_utf8() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
 inst!(
     /// Input
     ///
     /// Read a single character from input, convert to ASCII code and
     /// store
     ///
+    /// Characters are read as Unicode code points instead if the
+    /// [encoding](crate::exec::Io::encoding) is [`Encoding::Utf8`](crate::exec::Encoding::Utf8)
+    ///
     /// # Panics
     /// If error is encountered when reading input
     ///
@@ -76,21 +115,106 @@ inst!(
     /// 1. `INP` - read to `ACC`
     /// 2. `INP [reg | addr]`
     pub inp (ctx, op) {
+        // Make sure prompts are visible before blocking on input
+        ctx.io.write.flush()?;
+
         match op {
-            Null => {
-                let mut buf = [0; 1];
+            Null => ctx.acc = read_char(&mut ctx.io.read, ctx.io.encoding)?,
+            dest if dest.is_read_write() => {
+                let c = read_char(&mut ctx.io.read, ctx.io.encoding)?;
+                ctx.modify(dest, |d| *d = c)?;
+            }
+            _ => return Err(InvalidOperand),
+        }
+    }
+);
 
-                ctx.io.read.read_exact(&mut buf)?;
+inst!(
+    /// Key input
+    ///
+    /// Read a single character like `IN` if there is input, or store 0 without waiting for it
+    /// if there is not. Input must support polling, see
+    /// [`Io::set_polled_input`](crate::exec::Io::set_polled_input). Terminals usually only send
+    /// input once Enter is pressed.
+    ///
+    /// # Syntax
+    /// 1. `INKEY` - read to `ACC`
+    /// 2. `INKEY [reg | addr]`
+    #[cfg(feature = "extended")]
+    pub inkey (ctx, op) {
+        if !matches!(op, Null) && !op.is_read_write() {
+            return Err(InvalidOperand);
+        }
 
-                ctx.acc = buf[0] as usize;
-            }
-            dest if dest.is_read_write() => {
-                let mut buf = [0; 1];
+        ctx.io.write.flush()?;
 
-                ctx.io.read.read_exact(&mut buf)?;
+        let poll = ctx.io.poll.as_mut().ok_or(InputNotPolled)?;
+        let ready = !ctx.io.read.buffer().is_empty() || poll.is_ready()?;
+        let key = if ready {
+            read_char(&mut ctx.io.read, ctx.io.encoding)?
+        } else {
+            0
+        };
 
-                ctx.modify(dest, |d| *d = buf[0] as usize)?;
-            }
+        match op {
+            Null => ctx.acc = key,
+            dest => ctx.modify(dest, |d| *d = key)?,
+        }
+    }
+);
+
+inst!(
+    /// Output to a port
+    ///
+    /// Like `OUT`, but writes to the numbered port `port` instead, see
+    /// [`Io::attach_output`](crate::exec::Io::attach_output). Port 0 is the output of `OUT`.
+    ///
+    /// # Syntax
+    /// `OUTP [lit | reg | addr],[lit | reg | addr]` - output the second value to the port given
+    /// by the first
+    #[cfg(feature = "extended")]
+    pub port_out (ctx, op) {
+        match op {
+            MultiOp(ops) => match &ops[..] {
+                [port, src] if port.is_usizeable() && src.is_usizeable() => {
+                    let (port, val) = (ctx.read(port)?, ctx.read(src)?);
+                    let encoding = ctx.io.encoding;
+                    let write = ctx.io.output(port).ok_or(InvalidPort(port))?;
+
+                    write_char(write, encoding, val)?;
+                }
+                _ => return Err(InvalidMultiOp),
+            },
+            _ => return Err(InvalidOperand),
+        }
+    }
+);
+
+inst!(
+    /// Input from a port
+    ///
+    /// Like `IN`, but reads from the numbered port `port` instead, see
+    /// [`Io::attach_input`](crate::exec::Io::attach_input). Port 0 is the input of `IN`.
+    ///
+    /// # Syntax
+    /// `INP [lit | reg | addr],[reg | addr]` - read from the port given by the first operand
+    /// into the second
+    #[cfg(feature = "extended")]
+    pub port_in (ctx, op) {
+        match op {
+            MultiOp(ops) => match &ops[..] {
+                [port, dest] if port.is_usizeable() && dest.is_read_write() => {
+                    let port = ctx.read(port)?;
+                    ctx.io.flush()?;
+
+                    let encoding = ctx.io.encoding;
+                    let read = ctx.io.input(port).ok_or(InvalidPort(port))?;
+                    let c = read_char(read, encoding)?;
+
+                    ctx.modify(dest, |d| *d = c)?;
+                }
+                _ => return Err(InvalidMultiOp),
+            },
             _ => return Err(InvalidOperand),
         }
     }
@@ -104,14 +228,18 @@ inst!(
     /// 1. `DBG` - print entire execution context
     /// 2. `DBG [lit | reg | addr]` - print value
     /// 3. `DBG [lit | reg | addr], ...` - print value of all ops
+    ///
+    /// Values are printed in the base and width set by [`Context::radix`](crate::exec::Context::radix)
+    /// and [`Context::width`](crate::exec::Context::width)
     #[cfg(feature = "extended")]
     pub dbg (ctx, op) {
         let out = match op {
-            Null => format!("{ctx:?}"),
-            src if src.is_usizeable() => format!("{}", ctx.read(src)?),
+            Null => format!("{ctx}"),
+            src if src.is_usizeable() => format!("{}", ctx.display(ctx.read(src)?)),
             MultiOp(ops) if ops.iter().all(inst::Op::is_usizeable) => ops
                 .iter()
                 .filter_map(|op| ctx.read(op).ok())
+                .map(|val| ctx.display(val))
                 .enumerate()
                 .fold(String::new(), |acc, (idx, op)| {
                     if idx == ops.len() - 1 {
@@ -128,11 +256,47 @@ inst!(
     }
 );
 
+inst!(
+    /// Print a table of memory cells, eight to a row, like a monitor program
+    ///
+    /// Cells that do not exist are shown as `--`. Values are printed in the base and width set
+    /// by [`Context::radix`](crate::exec::Context::radix) and
+    /// [`Context::width`](crate::exec::Context::width)
+    ///
+    /// # Syntax
+    /// 1. `DMP` - print all of memory
+    /// 2. `DMP [addr]` - print 8 cells from `addr`
+    /// 3. `DMP [addr],[lit | reg | addr]` - print that many cells from `addr`
+    #[cfg(feature = "extended")]
+    pub dmp (ctx, op) {
+        let range = match op {
+            Null => ctx
+                .mem
+                .range()
+                .map_or(0..0, |range| *range.start()..range.end().saturating_add(1)),
+            &Addr(start) => start..start.saturating_add(8),
+            MultiOp(ops) => match ops[..] {
+                [Addr(start), ref len] if len.is_usizeable() => {
+                    start..start.saturating_add(ctx.read(len)?)
+                }
+                _ => return Err(InvalidMultiOp),
+            },
+            _ => return Err(InvalidOperand),
+        };
+
+        let table = ctx.dump_mem(range);
+        ctx.io.write.write_all(table.as_bytes())?;
+    }
+);
+
 // Raw input - directly input integers
 inst!(
     /// Raw input
     /// Take integer input and store
     ///
+    /// Negative integers are accepted if [`Context::signed`](crate::exec::Context::signed) is set,
+    /// and stored in two's complement
+    ///
     /// # Syntax
     /// 1. `RIN` - store to `ACC`
     /// 2. `RIN [reg | addr]`
@@ -142,22 +306,31 @@ inst!(
         use super::RtResult;
         const LF: u8 = 0xA;
 
-        fn input(inp: &mut impl BufRead) -> RtResult<usize> {
+        #[allow(clippy::cast_sign_loss)]
+        fn input(inp: &mut impl BufRead, signed: bool) -> RtResult<usize> {
             let mut buf = Vec::with_capacity(32);
             inp.read_until(LF, &mut buf)?;
 
             let str = String::from_utf8_lossy(&buf);
             let str = str.trim();
-            let res = str.parse()
-                .map_err(|e| format!("Unable to parse {str:?} because {e}"))?;
+            let res = if signed {
+                str.parse::<isize>().map(|val| val as usize)
+            } else {
+                str.parse()
+            }
+            .map_err(|e| format!("Unable to parse {str:?} because {e}"))?;
 
             Ok(res)
         }
 
+        ctx.io.write.flush()?;
+
+        let signed = ctx.signed;
+
         match op {
-            Null => ctx.acc = input(&mut ctx.io.read)?,
+            Null => ctx.acc = input(&mut ctx.io.read, signed)?,
             dest if dest.is_read_write() => {
-                let input = input(&mut ctx.io.read)?;
+                let input = input(&mut ctx.io.read, signed)?;
                 ctx.modify(dest, |d| *d = input)?;
             }
             _ => return Err(InvalidOperand),
@@ -165,6 +338,233 @@ inst!(
     }
 );
 
+/// Widest that `OUTN` pads a number to
+#[cfg(feature = "extended")]
+const MAX_OUTN_WIDTH: usize = 256;
+
+inst!(
+    /// Numeric output
+    ///
+    /// Print a value as a decimal number, optionally right-aligned to a width. The padding
+    /// defaults to spaces, but any ASCII code can be given instead (e.g. `#48` for zeroes).
+    ///
+    /// Negative values are printed with a minus sign if
+    /// [`Context::signed`](crate::exec::Context::signed) is set. Widths above 256 fail with
+    /// [`WidthTooLarge`](crate::exec::RtError::WidthTooLarge).
+    ///
+    /// # Syntax
+    /// 1. `OUTN` - output `ACC`
+    /// 2. `OUTN [lit | reg | addr]`
+    /// 3. `OUTN [lit | reg | addr],[lit | reg | addr]` - pad to width given by second value
+    /// 4. `OUTN [lit | reg | addr],[lit | reg | addr],[lit | reg | addr]` - pad with ASCII code given by third value
+    #[cfg(feature = "extended")]
+    pub outn (ctx, op) {
+        let (val, width, pad) = match op {
+            Null => (ctx.acc, 0, b' '),
+            MultiOp(ops) => match ops[..] {
+                [ref val, ref width] if val.is_usizeable() && width.is_usizeable() => {
+                    (ctx.read(val)?, ctx.read(width)?, b' ')
+                }
+                [ref val, ref width, ref pad]
+                    if val.is_usizeable() && width.is_usizeable() && pad.is_usizeable() =>
+                {
+                    let pad = ctx.read(pad)?;
+                    let pad = u8::try_from(pad).map_err(|_| InvalidUtf8Byte(pad))?;
+
+                    (ctx.read(val)?, ctx.read(width)?, pad)
+                }
+                _ => return Err(InvalidMultiOp),
+            },
+            val if val.is_usizeable() => (ctx.read(val)?, 0, b' '),
+            _ => return Err(InvalidOperand),
+        };
+
+        // A width read from memory could otherwise print almost without end
+        if width > MAX_OUTN_WIDTH {
+            return Err(WidthTooLarge {
+                width,
+                max: MAX_OUTN_WIDTH,
+            });
+        }
+
+        let num = if ctx.signed {
+            ctx.to_signed(val).to_string()
+        } else {
+            val.to_string()
+        };
+
+        for _ in num.len()..width {
+            ctx.io.write.write_all(&[pad])?;
+        }
+
+        ctx.io.write.write_all(num.as_bytes())?;
+    }
+);
+
+inst!(
+    /// Output a newline
+    ///
+    /// # Syntax
+    /// `NL`
+    #[cfg(feature = "extended")]
+    pub nl (ctx) {
+        writeln!(ctx.io.write)?;
+    }
+);
+
+inst!(
+    /// Flush output, so that everything written so far is visible
+    ///
+    /// Every output port is flushed. Output is also flushed before reading input and at the end
+    /// of execution.
+    ///
+    /// # Syntax
+    /// `FLUSH`
+    #[cfg(feature = "extended")]
+    pub flush (ctx) {
+        ctx.io.flush()?;
+    }
+);
+
+/// Read a line without the trailing line break
+#[cfg(feature = "extended")]
+fn read_line(inp: &mut impl std::io::BufRead) -> RtResult<Vec<u8>> {
+    let mut buf = Vec::with_capacity(32);
+    inp.read_until(b'\n', &mut buf)?;
+
+    while matches!(buf.last(), Some(b'\n' | b'\r')) {
+        buf.pop();
+    }
+
+    Ok(buf)
+}
+
+inst!(
+    /// Secure input
+    ///
+    /// Read a line without echoing it (if echo control is set with
+    /// [`Io::set_echo_control`](crate::exec::Io::set_echo_control)), and store its characters in
+    /// consecutive memory addresses, starting from `addr`. The number of characters read is stored
+    /// in `ACC`.
+    ///
+    /// If `max` is given, a line longer than `max` characters is an error. Otherwise, every
+    /// address the line would be stored in must exist. Nothing is stored if the line does not fit.
+    ///
+    /// # Syntax
+    /// 1. `INS [addr]`
+    /// 2. `INS [addr],[max]`
+    #[cfg(feature = "extended")]
+    pub ins (ctx, op) {
+        let (addr, max) = match op {
+            Null => return Err(NoOperand),
+            op => line_dest(ctx, op)?,
+        };
+
+        ctx.io.write.flush()?;
+
+        // Echo is left on if it cannot be turned off
+        let hidden = ctx.io.echo.as_mut().map_or(false, |e| e.set_echo(false).is_ok());
+
+        let line = read_line(&mut ctx.io.read);
+
+        if hidden {
+            if let Some(echo) = ctx.io.echo.as_mut() {
+                echo.set_echo(true)?;
+            }
+
+            // The line break typed by the user was not echoed either
+            writeln!(ctx.io.write)?;
+        }
+
+        store_line(ctx, addr, max, &line?)?;
+    }
+);
+
+inst!(
+    /// Line input
+    ///
+    /// Read a whole line, and store its characters in consecutive memory addresses, starting
+    /// from `addr`. The number of characters read is stored in `ACC`. Unlike `IN`, which reads
+    /// one character at a time, the line break typed to send the line is not left in the input.
+    ///
+    /// If `max` is given, a line longer than `max` characters is an error. Otherwise, every
+    /// address the line would be stored in must exist. Nothing is stored if the line does not fit.
+    ///
+    /// # Syntax
+    /// 1. `INL [addr]`
+    /// 2. `INL [addr],[max]`
+    #[cfg(feature = "extended")]
+    pub inl (ctx, op) {
+        let (addr, max) = match op {
+            Null => return Err(NoOperand),
+            op => line_dest(ctx, op)?,
+        };
+
+        ctx.io.write.flush()?;
+
+        let line = read_line(&mut ctx.io.read)?;
+        store_line(ctx, addr, max, &line)?;
+    }
+);
+
+/// Address to store a line at, and the most characters it can have, for `INS` and `INL`
+#[cfg(feature = "extended")]
+fn line_dest(ctx: &super::Context, op: &inst::Op) -> RtResult<(usize, Option<usize>)> {
+    use inst::Op::*;
+
+    match op {
+        &Addr(addr) => Ok((addr, None)),
+        MultiOp(ops) => match ops[..] {
+            [Addr(addr), ref max] if max.is_usizeable() => Ok((addr, Some(ctx.read(max)?))),
+            _ => Err(InvalidMultiOp),
+        },
+        _ => Err(InvalidOperand),
+    }
+}
+
+/// Store the characters of `line` from `addr` onwards, and their number in `ACC`, for `INS` and
+/// `INL`
+///
+/// Fails without storing anything if there are more than `max` characters, or if any of the
+/// addresses does not exist.
+#[cfg(feature = "extended")]
+fn store_line(
+    ctx: &mut super::Context,
+    addr: usize,
+    max: Option<usize>,
+    line: &[u8],
+) -> RtResult<()> {
+    let chars = match ctx.io.encoding {
+        Encoding::Byte => line.iter().map(|&byte| byte.into()).collect::<Vec<_>>(),
+        Encoding::Utf8 => std::str::from_utf8(line)
+            .map_err(|_| invalid_utf8())?
+            .chars()
+            .map(|c| c as usize)
+            .collect(),
+    };
+
+    let len = chars.len();
+
+    if let Some(max) = max.filter(|&max| len > max) {
+        return Err(LineTooLong { len, max });
+    }
+
+    let dests = (0..len)
+        .map(|offset| {
+            let dest = addr.checked_add(offset).ok_or(InvalidAddr(usize::MAX))?;
+            ctx.mem.get(&dest).map(|_| dest)
+        })
+        .collect::<RtResult<Vec<_>>>()?;
+
+    for (dest, c) in dests.into_iter().zip(chars) {
+        *ctx.mem.get_mut(&dest)? = c;
+    }
+
+    ctx.acc = len;
+
+    Ok(())
+}
+
 inst!(
     /// Call a function
     ///
@@ -174,7 +574,8 @@ inst!(
     pub call (ctx, op) {
         match op {
             &Addr(addr) => {
-                ctx.ret = ctx.mar + 1;
+                let ret = std::mem::replace(&mut ctx.ret, ctx.mar + 1);
+                ctx.call_stack.push(ret);
                 ctx.override_flow_control();
                 ctx.mar = addr;
             }
@@ -186,11 +587,17 @@ inst!(
 inst!(
     /// Return to address in `Ar`
     ///
+    /// The return address of the enclosing call, if any, is restored to `Ar`
+    ///
     /// # Syntax
     /// `RET`
     #[cfg(feature = "extended")]
     pub ret (ctx) {
         ctx.override_flow_control();
         ctx.mar = ctx.ret;
+
+        if let Some(ret) = ctx.call_stack.pop() {
+            ctx.ret = ret;
+        }
     }
 );
@@ -3,45 +3,575 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use super::{RtError, RtResult};
+use super::{Radix, RtError, RtResult};
+use once_cell::sync::OnceCell;
 use std::{
-    collections::btree_map::{BTreeMap, Iter},
-    fmt::Debug,
+    collections::{
+        btree_map::{self, BTreeMap},
+        BTreeSet,
+    },
+    fmt::{Debug, Write as _},
+    iter::Zip,
+    num::ParseIntError,
+    ops::{Range, RangeInclusive},
+    slice,
 };
+use thiserror::Error;
 
 #[cfg(feature = "serde")]
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 /// Struct providing random-access memory (RAM)
+///
+/// The longest run of consecutive addresses is stored in a `Vec`, so that accessing it does not
+/// search a tree, and any other addresses in a map. Cells cannot be added once it is made.
+///
+/// Serialized as a map of addresses to values, without the [size](Memory::set_size) or the
+/// [types](Memory::set_type) of cells
 #[derive(Debug, Default, Clone)]
+pub struct Memory {
+    /// Address of the first value in `dense`
+    base: usize,
+    dense: Vec<usize>,
+    /// Addresses of the cells in `dense`, so that [`Memory::iter`] can lend them
+    addrs: Vec<usize>,
+    /// Cells before `base` or after the end of `dense`
+    sparse: BTreeMap<usize, usize>,
+    /// Previous values of cells written to since the journal was started
+    journal: Option<Vec<(usize, usize)>>,
+    /// Number of addresses, from 0, that can be accessed
+    size: Option<usize>,
+    /// Writes made by each instruction, if enabled with [`Memory::set_logging`]
+    log: Option<Vec<MemWrite>>,
+    /// Types of cells that are not words, by address
+    types: BTreeMap<usize, MemType>,
+    /// All cells in one map, built by [`Memory::inner`] and cleared by any write
+    map: OnceCell<BTreeMap<usize, usize>>,
+}
+
+/// How cells are displayed, set with [`Memory::set_type`]
+///
+/// Types only affect display, instructions always see the numeric value of a cell.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemType {
+    /// A number as wide as [`Context::width`](super::Context::width)
+    #[default]
+    Word,
+    /// A number of 8 bits
+    Byte,
+    /// Characters of a string, in this many cells from this one
+    Str(usize),
+}
+
+/// Cells of [`Memory`] grouped and decoded by their [`MemType`], see [`Memory::entries`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemEntry {
+    Word(usize),
+    Byte(u8),
+    Str(String),
+}
+
+/// A write to a memory cell, recorded by [`Memory::set_logging`]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[repr(transparent)]
-pub struct Memory(BTreeMap<usize, usize>);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemWrite {
+    /// Number of the instruction that made the write, counting from 1, see
+    /// [`Executor::count`](super::Executor::count)
+    pub step: u64,
+    pub addr: usize,
+    pub old: usize,
+    pub new: usize,
+}
 
 impl Memory {
-    pub fn new(mem: BTreeMap<usize, usize>) -> Self {
-        Self(mem)
+    pub fn new(mut mem: BTreeMap<usize, usize>) -> Self {
+        let mut longest = (0, 0);
+        let mut run: Option<(usize, usize)> = None;
+
+        for &addr in mem.keys() {
+            run = match run {
+                Some((start, len)) if start.checked_add(len) == Some(addr) => {
+                    Some((start, len + 1))
+                }
+                _ => Some((addr, 1)),
+            };
+
+            if let Some(run @ (_, len)) = run {
+                if len > longest.1 {
+                    longest = run;
+                }
+            }
+        }
+
+        let (base, len) = longest;
+        let mut dense_cells = mem.split_off(&base);
+        // The run can end at the last address, with nothing after it
+        if let Some(end) = base.checked_add(len) {
+            mem.extend(dense_cells.split_off(&end));
+        }
+
+        Self {
+            base,
+            addrs: dense_cells.keys().copied().collect(),
+            dense: dense_cells.into_values().collect(),
+            sparse: mem,
+            journal: None,
+            size: None,
+            log: None,
+            types: BTreeMap::new(),
+            map: OnceCell::new(),
+        }
+    }
+
+    /// Read cells from CSV, with an address and a value on each line, e.g. `200,5`
+    ///
+    /// Blank lines are skipped, and so is the first line if it does not start with a number, so
+    /// that spreadsheets with a header row can be used.
+    ///
+    /// # Example
+    /// ```
+    /// # use cambridge_asm::exec::Memory;
+    /// let mem = Memory::from_csv("address,value\n200,5\n201, 7\n")?;
+    ///
+    /// assert_eq!(mem.iter().collect::<Vec<_>>(), [(&200, &5), (&201, &7)]);
+    /// # Ok::<_, cambridge_asm::exec::CsvError>(())
+    /// ```
+    pub fn from_csv(src: &str) -> Result<Self, CsvError> {
+        let mut mem = BTreeMap::new();
+
+        for (idx, line) in src.lines().enumerate() {
+            let line_no = idx + 1;
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+
+            let (addr, val) = match fields[..] {
+                [""] => continue,
+                [addr, _] if idx == 0 && !addr.starts_with(|c: char| c.is_ascii_digit()) => {
+                    continue
+                }
+                [addr, val] => (addr, val),
+                _ => return Err(CsvError::Syntax(line_no)),
+            };
+
+            let parse = |s: &str| s.parse().map_err(|e| CsvError::Int(line_no, e));
+            mem.insert(parse(addr)?, parse(val)?);
+        }
+
+        Ok(Self::new(mem))
+    }
+
+    /// One cell for each byte of `bytes`, from `base` onwards, with the type [`MemType::Byte`]
+    pub fn from_bin(bytes: &[u8], base: usize) -> Self {
+        let mut mem = Self::new(
+            (base..)
+                .zip(bytes.iter().map(|&b| usize::from(b)))
+                .collect(),
+        );
+
+        for addr in (base..).take(bytes.len()) {
+            mem.set_type(addr, MemType::Byte);
+        }
+
+        mem
+    }
+
+    /// Only allow access to addresses below `size`, like a machine with a fixed amount of memory
+    ///
+    /// Accessing a cell at or beyond `size` fails with [`RtError::InvalidAddr`], even if it
+    /// exists. Unlimited (`None`) by default.
+    pub fn set_size(&mut self, size: Option<usize>) {
+        self.size = size;
+    }
+
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    fn check_bounds(&self, addr: usize) -> RtResult<()> {
+        match self.size {
+            Some(size) if addr >= size => Err(RtError::InvalidAddr(addr)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Cells with their addresses, in order
+    pub fn iter(&self) -> Cells<'_> {
+        Cells {
+            before: self.sparse.range(..self.base),
+            dense: self.addrs.iter().zip(&self.dense),
+            after: self.sparse.range(self.base..),
+        }
     }
 
-    pub fn iter(&self) -> Iter<usize, usize> {
-        self.0.iter()
+    /// All cells in a map of addresses to values
+    ///
+    /// The map is built the first time this is called after memory is written to.
+    #[deprecated(note = "use `Memory::iter` or `Memory::get`, which do not copy the cells")]
+    pub fn inner(&self) -> &BTreeMap<usize, usize> {
+        self.map
+            .get_or_init(|| self.iter().map(|(&addr, &val)| (addr, val)).collect())
     }
 
     pub fn get(&self, addr: &usize) -> RtResult<&usize> {
-        self.0.get(addr).ok_or(RtError::InvalidAddr(*addr))
+        self.check_bounds(*addr)?;
+
+        match addr
+            .checked_sub(self.base)
+            .and_then(|idx| self.dense.get(idx))
+        {
+            Some(cell) => Ok(cell),
+            None => self.sparse.get(addr).ok_or(RtError::InvalidAddr(*addr)),
+        }
     }
 
     pub fn get_mut(&mut self, addr: &usize) -> RtResult<&mut usize> {
-        self.0.get_mut(addr).ok_or(RtError::InvalidAddr(*addr))
+        self.check_bounds(*addr)?;
+        self.map.take();
+
+        let journal = &mut self.journal;
+        let cell = match addr.checked_sub(self.base) {
+            Some(idx) if idx < self.dense.len() => &mut self.dense[idx],
+            _ => self
+                .sparse
+                .get_mut(addr)
+                .ok_or(RtError::InvalidAddr(*addr))?,
+        };
+
+        if let Some(journal) = journal {
+            journal.push((*addr, *cell));
+        }
+
+        Ok(cell)
     }
 
-    pub fn inner(&self) -> &BTreeMap<usize, usize> {
-        &self.0
+    /// Number of memory cells
+    pub fn len(&self) -> usize {
+        self.dense.len() + self.sparse.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lowest and highest address in use, or `None` if memory is empty
+    pub fn range(&self) -> Option<RangeInclusive<usize>> {
+        let first = *self.iter().next()?.0;
+        let last = match (self.sparse.keys().next_back(), self.dense.len()) {
+            (Some(&addr), _) if addr > self.base => addr,
+            (_, 0) => first,
+            (_, len) => self.base + (len - 1),
+        };
+
+        Some(first..=last)
+    }
+
+    /// Display the cell at `addr`, and for [`MemType::Str`] the cells after it, as `ty`
+    pub fn set_type(&mut self, addr: usize, ty: MemType) {
+        if ty == MemType::Word {
+            self.types.remove(&addr);
+        } else {
+            self.types.insert(addr, ty);
+        }
+    }
+
+    /// Type of the cell at `addr`, which is [`MemType::Str`] for every cell of a string
+    pub fn type_at(&self, addr: usize) -> MemType {
+        match self.types.range(..=addr).next_back() {
+            Some((&start, &ty @ MemType::Str(len))) if addr - start < len => ty,
+            Some((&start, &ty)) if start == addr => ty,
+            _ => MemType::Word,
+        }
+    }
+
+    /// Addresses of cells that are not [`MemType::Word`], with their types, in order
+    pub fn types(&self) -> impl Iterator<Item = (usize, MemType)> + '_ {
+        self.types.iter().map(|(&addr, &ty)| (addr, ty))
+    }
+
+    /// Cells with their addresses, in order, with each string in a single [`MemEntry::Str`] at
+    /// the address of its first cell
+    ///
+    /// # Example
+    /// ```
+    /// # use cambridge_asm::exec::{MemEntry, Memory, MemType};
+    /// let mut mem = Memory::new([(200, 72), (201, 73), (202, 5)].into());
+    /// mem.set_type(200, MemType::Str(2));
+    ///
+    /// assert_eq!(
+    ///     mem.entries(),
+    ///     [(200, MemEntry::Str("HI".into())), (202, MemEntry::Word(5))]
+    /// );
+    /// ```
+    pub fn entries(&self) -> Vec<(usize, MemEntry)> {
+        let mut entries = Vec::new();
+        let mut cells = self.iter().peekable();
+
+        while let Some((&addr, &val)) = cells.next() {
+            let entry = match self.types.get(&addr) {
+                Some(&MemType::Str(len)) => {
+                    let mut s = String::from(to_char(val));
+
+                    while let Some((_, &val)) =
+                        cells.next_if(|&(&next, _)| next > addr && next - addr < len)
+                    {
+                        s.push(to_char(val));
+                    }
+
+                    MemEntry::Str(s)
+                }
+                Some(MemType::Byte) => MemEntry::Byte(val.to_le_bytes()[0]),
+                _ => MemEntry::Word(val),
+            };
+
+            entries.push((addr, entry));
+        }
+
+        entries
+    }
+
+    /// Table of the cells in `range`, eight to a row, with values displayed in `radix`
+    ///
+    /// Each row starts with the address of its first cell. Cells that do not exist, or are beyond
+    /// the [size](Memory::set_size), are shown as `--`, and rows without any cells are left out.
+    ///
+    /// # Example
+    /// ```
+    /// # use cambridge_asm::exec::{Memory, Radix};
+    /// let mem = Memory::new([(200, 5), (201, 255), (209, 1)].into());
+    ///
+    /// assert_eq!(
+    ///     mem.dump(200..216, Radix::Hex),
+    ///     "200:  &5 &FF  --  --  --  --  --  --\n208:  --  &1  --  --  --  --  --  --\n"
+    /// );
+    /// ```
+    pub fn dump(&self, range: Range<usize>, radix: Radix) -> String {
+        self.dump_with(range, |entry| match *entry {
+            MemEntry::Byte(val) => radix.display_width(val.into(), Some(8)).to_string(),
+            MemEntry::Word(val) => radix.display(val).to_string(),
+            MemEntry::Str(ref s) => format!("{s:?}"),
+        })
+    }
+
+    /// [`Memory::dump`], with numbers displayed by `fmt`
+    pub(crate) fn dump_with(
+        &self,
+        range: Range<usize>,
+        fmt: impl Fn(&MemEntry) -> String,
+    ) -> String {
+        const COLS: usize = 8;
+
+        // Characters of strings are shown one to a cell
+        let fmt = |addr, val: usize| match self.type_at(addr) {
+            MemType::Str(_) => format!("{:?}", to_char(val)),
+            MemType::Byte => fmt(&MemEntry::Byte(val.to_le_bytes()[0])),
+            MemType::Word => fmt(&MemEntry::Word(val)),
+        };
+
+        let cells = self
+            .iter()
+            .map(|(&addr, &val)| (addr, val))
+            .filter(|&(addr, _)| range.contains(&addr) && self.check_bounds(addr).is_ok())
+            .collect::<Vec<_>>();
+
+        let width = cells
+            .iter()
+            .map(|&(addr, val)| fmt(addr, val).chars().count())
+            .fold(2, usize::max);
+        let addr_width = range.end.saturating_sub(1).to_string().len();
+        let rows = cells
+            .iter()
+            .map(|&(addr, _)| (addr - range.start) / COLS)
+            .collect::<BTreeSet<_>>();
+
+        let mut out = String::new();
+
+        // Writing to a `String` cannot fail
+        for row in rows {
+            let start = range.start + row * COLS;
+            let _ = write!(out, "{start:>addr_width$}:");
+
+            for addr in start..range.end.min(start.saturating_add(COLS)) {
+                match self.get(&addr) {
+                    Ok(&val) => {
+                        let _ = write!(out, " {:>width$}", fmt(addr, val));
+                    }
+                    Err(_) => {
+                        let _ = write!(out, " {:>width$}", "--");
+                    }
+                }
+            }
+
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Record every write made by instructions, to be retrieved with [`Memory::write_log`]
+    ///
+    /// Disabling the log clears it. Disabled by default, since the log grows for as long as the
+    /// program runs.
+    pub fn set_logging(&mut self, enabled: bool) {
+        self.log = enabled.then(Vec::new);
+    }
+
+    pub fn is_logging(&self) -> bool {
+        self.log.is_some()
+    }
+
+    /// Writes recorded since logging was enabled, in order, or an empty slice if it is disabled
+    pub fn write_log(&self) -> &[MemWrite] {
+        self.log.as_deref().unwrap_or_default()
+    }
+
+    /// Recorded writes to `addr`, in order
+    pub fn writes_to(&self, addr: usize) -> impl Iterator<Item = &MemWrite> + '_ {
+        self.write_log().iter().filter(move |w| w.addr == addr)
+    }
+
+    /// Add the writes in a journal taken after instruction number `step` to the log, if enabled
+    pub(crate) fn log_writes(&mut self, step: u64, journal: &[(usize, usize)]) {
+        if self.log.is_none() {
+            return;
+        }
+
+        let entries = journal
+            .iter()
+            .enumerate()
+            .map(|(idx, &(addr, old))| {
+                // The value written is the one replaced by the next write to the same cell
+                let new = journal[idx + 1..]
+                    .iter()
+                    .find(|&&(other, _)| other == addr)
+                    .map_or_else(
+                        || self.get(&addr).copied().unwrap_or_default(),
+                        |&(_, prev)| prev,
+                    );
+
+                MemWrite {
+                    step,
+                    addr,
+                    old,
+                    new,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(log) = &mut self.log {
+            log.extend(entries);
+        }
+    }
+
+    /// Forget the writes made after instruction number `step`, once they have been undone
+    pub(crate) fn forget_writes_after(&mut self, step: u64) {
+        if let Some(log) = &mut self.log {
+            log.retain(|w| w.step <= step);
+        }
+    }
+
+    /// Start recording the previous value of every cell borrowed mutably
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Stop recording, returning the `(addr, previous value)` pairs in the order they were written
+    pub(crate) fn take_journal(&mut self) -> Vec<(usize, usize)> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    /// Undo the writes recorded in a journal
+    pub(crate) fn rewind(&mut self, journal: &[(usize, usize)]) {
+        let recording = self.journal.take();
+
+        for &(addr, prev) in journal.iter().rev() {
+            if let Ok(cell) = self.get_mut(&addr) {
+                *cell = prev;
+            }
+        }
+
+        self.journal = recording;
+    }
+}
+
+/// Add cells, replacing the values of cells that already exist
+///
+/// Memory is rebuilt, so add everything at once, before execution.
+impl Extend<(usize, usize)> for Memory {
+    fn extend<T: IntoIterator<Item = (usize, usize)>>(&mut self, iter: T) {
+        let mut cells = self
+            .iter()
+            .map(|(&addr, &val)| (addr, val))
+            .collect::<BTreeMap<_, _>>();
+        cells.extend(iter);
+
+        let (journal, size, log) = (self.journal.take(), self.size, self.log.take());
+        let types = std::mem::take(&mut self.types);
+        *self = Self::new(cells);
+        self.journal = journal;
+        self.size = size;
+        self.log = log;
+        self.types = types;
+    }
+}
+
+impl<'a> Extend<(&'a usize, &'a usize)> for Memory {
+    fn extend<T: IntoIterator<Item = (&'a usize, &'a usize)>>(&mut self, iter: T) {
+        self.extend(iter.into_iter().map(|(&addr, &val)| (addr, val)));
+    }
+}
+
+/// Character with the code `val`, or `U+FFFD` if there is none
+fn to_char(val: usize) -> char {
+    u32::try_from(val)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// Errors encountered by [`Memory::from_csv`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CsvError {
+    #[error("Line {0}: expected an address and a value, separated by a comma")]
+    Syntax(usize),
+    #[error("Line {0}: {1}")]
+    Int(usize, ParseIntError),
+}
+
+/// Iterator over the cells of [`Memory`], made with [`Memory::iter`]
+#[derive(Debug, Clone)]
+pub struct Cells<'a> {
+    before: btree_map::Range<'a, usize, usize>,
+    dense: Zip<slice::Iter<'a, usize>, slice::Iter<'a, usize>>,
+    after: btree_map::Range<'a, usize, usize>,
+}
+
+impl<'a> Iterator for Cells<'a> {
+    type Item = (&'a usize, &'a usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.before
+            .next()
+            .or_else(|| self.dense.next())
+            .or_else(|| self.after.next())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Memory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Memory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        BTreeMap::deserialize(deserializer).map(Self::new)
     }
 }
 
 impl<'a> IntoIterator for &'a Memory {
-    type IntoIter = std::collections::btree_map::Iter<'a, usize, usize>;
+    type IntoIter = Cells<'a>;
     type Item = (&'a usize, &'a usize);
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
@@ -53,6 +583,6 @@ where
     T: Into<BTreeMap<usize, usize>>,
 {
     fn from(x: T) -> Self {
-        Self(x.into())
+        Self::new(x.into())
     }
 }
@@ -5,13 +5,20 @@
 
 #![allow(clippy::module_name_repetitions)]
 
-use crate::inst::{InstSet, Op};
+use crate::inst::Op;
 use std::{
-    collections::BTreeMap,
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
     fmt::{Debug, Display, Formatter, Result as FmtResult},
     io::{stdin, stdout, BufReader, Read, Write},
+    ops::Range,
     str::FromStr,
+    time::{Duration, Instant},
 };
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// # Arithmetic
 /// Arithmetic instructions
@@ -38,6 +45,12 @@ pub mod cmp;
 #[allow(clippy::needless_pass_by_value, clippy::enum_glob_use)]
 pub mod bitman;
 
+/// # Floating point
+/// Instructions for IEEE 754 floats, stored as bit patterns
+#[cfg(feature = "float")]
+#[allow(clippy::needless_pass_by_value, clippy::enum_glob_use)]
+pub mod float;
+
 #[allow(clippy::enum_glob_use)]
 mod error;
 
@@ -45,23 +58,85 @@ mod memory;
 
 mod debug;
 
+mod stream;
+
+mod hook;
+
+mod history;
+
+mod state;
+
+mod trace;
+
+mod cancel;
+
+mod recorder;
+
+mod future;
+
+mod builder;
+
 #[allow(clippy::enum_glob_use)]
 mod inst;
 
-pub use error::{RtError, RtResult, Source};
+mod access;
+
+pub use error::{RtError, RtResult, RuntimeFault, Source};
+
+use access::{Access, Reg};
+
+pub use memory::{Cells, CsvError, MemEntry, MemType, MemWrite, Memory};
+
+pub use inst::{ExTree, ExTreeIntoIter, ExTreeIter, ExecFunc, ExecInst};
 
-pub use memory::Memory;
+pub use debug::{DebugInfo, SourceLine};
 
-pub use inst::{ExecFunc, ExecInst};
+pub use stream::{EchoControl, InputPoll, Limited, PolledReader, TimeoutReader};
 
-pub use debug::DebugInfo;
+pub(crate) use stream::Capture;
+
+pub use hook::{ExecHook, HookInst};
+
+pub use state::{ContextSnapshot, ExecutorState};
+
+pub use trace::{CsvTrace, JsonTrace, TextTrace};
+
+pub use cancel::CancellationToken;
+
+pub use recorder::FlightRecorder;
+
+pub use future::{AsyncInput, ExecAsync, StepAsync};
+
+pub use builder::ExecutorBuilder;
 
 /// For platform independent I/O
 ///
-/// Boxed for convenience.
+/// Boxed for convenience. `read` and `write` are port 0, used by `IN` and `OUT`. Other numbered
+/// ports, used by `INP` and `OUTP`, can be attached with [`Io::attach_input`] and
+/// [`Io::attach_output`], so that e.g. a log is kept apart from the program's output.
 pub struct Io {
     pub read: BufReader<Box<dyn Read + Send + Sync>>,
     pub write: Box<dyn Write + Send + Sync>,
+    /// Input ports other than 0, by number, see [`Io::attach_input`]
+    inputs: BTreeMap<usize, BufReader<Box<dyn Read + Send + Sync>>>,
+    /// Output ports other than 0, by number, see [`Io::attach_output`]
+    outputs: BTreeMap<usize, Box<dyn Write + Send + Sync>>,
+    /// See [`Io::set_echo_control`]
+    echo: Option<Box<dyn EchoControl>>,
+    /// See [`Io::encoding`]
+    encoding: Encoding,
+    /// See [`Io::set_input_poll`]
+    poll: Option<Box<dyn InputPoll>>,
+}
+
+/// How characters are read and written, see [`Io::set_encoding`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// One byte per character, so only values up to 255 can be written
+    #[default]
+    Byte,
+    /// Unicode code points, encoded as UTF-8, so that characters such as `é` take one value
+    Utf8,
 }
 
 /// Quickly makes an [`Io`] struct
@@ -83,12 +158,139 @@ macro_rules! make_io {
     () => {
         $crate::exec::Io::default()
     };
-    ($read:expr, $write:expr) => {{
-        $crate::exec::Io {
-            read: std::io::BufReader::new(Box::new($read)),
-            write: Box::new($write),
+    ($read:expr, $write:expr) => {
+        $crate::exec::Io::new($read, $write)
+    };
+}
+
+impl Io {
+    /// Makes an [`Io`] without any optional capabilities. Prefer [`make_io`].
+    pub fn new(
+        read: impl Read + Send + Sync + 'static,
+        write: impl Write + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            read: BufReader::new(Box::new(read)),
+            write: Box::new(write),
+            inputs: BTreeMap::new(),
+            outputs: BTreeMap::new(),
+            echo: None,
+            encoding: Encoding::Byte,
+            poll: None,
+        }
+    }
+
+    /// Make reads fail with [`RtError::InputTimeout`] if no input arrives within `timeout`
+    ///
+    /// Useful in server environments, where a program waiting for input that never comes
+    /// would otherwise hang the worker. See [`TimeoutReader`] for caveats.
+    pub fn set_input_timeout(&mut self, timeout: Duration) {
+        let read = std::mem::replace(&mut self.read, BufReader::new(Box::new(std::io::empty())));
+        self.read = BufReader::new(Box::new(TimeoutReader::new(read, timeout)));
+    }
+
+    /// Read input port `port` from `read`, replacing [`Io::read`] for port 0
+    pub fn attach_input(&mut self, port: usize, read: impl Read + Send + Sync + 'static) {
+        let read = BufReader::new(Box::new(read) as Box<dyn Read + Send + Sync>);
+
+        if port == 0 {
+            self.read = read;
+        } else {
+            self.inputs.insert(port, read);
+        }
+    }
+
+    /// Write output port `port` to `write`, replacing [`Io::write`] for port 0
+    pub fn attach_output(&mut self, port: usize, write: impl Write + Send + Sync + 'static) {
+        if port == 0 {
+            self.write = Box::new(write);
+        } else {
+            self.outputs.insert(port, Box::new(write));
+        }
+    }
+
+    /// Reader of input port `port`, or `None` if nothing is attached to it
+    pub fn input(&mut self, port: usize) -> Option<&mut BufReader<Box<dyn Read + Send + Sync>>> {
+        if port == 0 {
+            Some(&mut self.read)
+        } else {
+            self.inputs.get_mut(&port)
+        }
+    }
+
+    /// Writer of output port `port`, or `None` if nothing is attached to it
+    pub fn output(&mut self, port: usize) -> Option<&mut Box<dyn Write + Send + Sync>> {
+        if port == 0 {
+            Some(&mut self.write)
+        } else {
+            self.outputs.get_mut(&port)
+        }
+    }
+
+    /// Flush every output port
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.write.flush()?;
+
+        for write in self.outputs.values_mut() {
+            write.flush()?;
         }
-    }};
+
+        Ok(())
+    }
+
+    /// How characters are read by `IN`, `INL` and `INS`, and written by `OUT`
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Set [`Io::encoding`]
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Turn echoing of input on and off with `echo`, or never if `None`, which is the default
+    ///
+    /// Terminals are left to applications, which can implement [`EchoControl`] for them.
+    pub fn set_echo_control(&mut self, echo: Option<Box<dyn EchoControl>>) {
+        self.echo = echo;
+    }
+
+    /// Check whether input is available without blocking with `poll`, or never if `None`
+    ///
+    /// `poll` must report input for the reader of port 0. Use [`Io::set_polled_input`] to do
+    /// this for any reader.
+    pub fn set_input_poll(&mut self, poll: Option<Box<dyn InputPoll>>) {
+        self.poll = poll;
+    }
+
+    /// Read input on a background thread, so that `INKEY` can check for it without blocking
+    ///
+    /// See [`PolledReader`]. Apply this after [`Io::set_input_timeout`] and
+    /// [`Io::set_input_limit`], since they replace the reader.
+    pub fn set_polled_input(&mut self) {
+        let read = std::mem::replace(&mut self.read, BufReader::new(Box::new(std::io::empty())));
+        let read = PolledReader::new(read);
+        self.poll = Some(Box::new(read.clone()));
+        self.read = BufReader::new(Box::new(read));
+    }
+
+    /// Make reads fail with [`RtError::InputLimit`] once more than `limit` bytes have been read
+    ///
+    /// Input is read in chunks, so this counts bytes taken from the reader rather than bytes
+    /// used by the program.
+    pub fn set_input_limit(&mut self, limit: u64) {
+        let read = std::mem::replace(&mut self.read, BufReader::new(Box::new(std::io::empty())));
+        self.read = BufReader::new(Box::new(Limited::new(read, limit)));
+    }
+
+    /// Make writes fail with [`RtError::OutputLimit`] once more than `limit` bytes have been
+    /// written
+    ///
+    /// Stops runaway output loops from filling up disks or memory.
+    pub fn set_output_limit(&mut self, limit: u64) {
+        let write = std::mem::replace(&mut self.write, Box::new(std::io::sink()));
+        self.write = Box::new(Limited::new(write, limit));
+    }
 }
 
 impl Debug for Io {
@@ -102,23 +304,256 @@ impl Default for Io {
         Self {
             read: BufReader::new(Box::new(stdin())),
             write: Box::new(stdout()),
+            inputs: BTreeMap::new(),
+            outputs: BTreeMap::new(),
+            echo: None,
+            encoding: Encoding::Byte,
+            poll: None,
+        }
+    }
+}
+
+/// Base used to display numeric values, e.g. in `DBG` output and [`Context`]'s [`Display`] impl
+///
+/// Hexadecimal and binary use the syllabus notation, so `255` is shown as `&FF` or `B11111111`
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    #[default]
+    Dec,
+    Hex,
+    Bin,
+}
+
+impl Radix {
+    /// Wrap `val` so that it is displayed in this base
+    pub fn display(self, val: usize) -> impl Display {
+        self.display_width(val, None)
+    }
+
+    /// Wrap `val` so that it is displayed in this base, as a `width`-bit value
+    ///
+    /// `val` is truncated to `width` bits, so values that wrapped below zero are shown in two's
+    /// complement. Binary and hexadecimal are padded with leading zeros, e.g. `5` is shown as
+    /// `B00000101` with a width of 8.
+    pub fn display_width(self, val: usize, width: Option<u32>) -> impl Display {
+        RadixVal::new(self, val, width, false)
+    }
+
+    /// Like [`Radix::display_width`], but decimal values are displayed as signed, e.g. `-3`
+    /// instead of `253` with a width of 8
+    pub fn display_signed(self, val: usize, width: Option<u32>) -> impl Display {
+        RadixVal::new(self, val, width, true)
+    }
+}
+
+struct RadixVal {
+    radix: Radix,
+    val: usize,
+    bits: usize,
+    signed: Option<isize>,
+}
+
+impl RadixVal {
+    fn new(radix: Radix, val: usize, width: Option<u32>, signed: bool) -> Self {
+        let (val, bits) = match width {
+            Some(bits) if bits < usize::BITS => (val & ((1 << bits) - 1), bits as usize),
+            Some(bits) => (val, bits.min(usize::BITS) as usize),
+            None => (val, 0),
+        };
+
+        Self {
+            radix,
+            val,
+            bits,
+            signed: signed.then(|| to_signed(val, width)),
+        }
+    }
+}
+
+impl Display for RadixVal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let Self {
+            radix,
+            val,
+            bits,
+            signed,
+        } = *self;
+
+        match (radix, signed) {
+            (Radix::Dec, Some(val)) => write!(f, "{val}"),
+            (Radix::Dec, None) => write!(f, "{val}"),
+            (Radix::Hex, _) => write!(f, "&{val:0digits$X}", digits = (bits + 3) / 4),
+            (Radix::Bin, _) => write!(f, "B{val:0bits$b}"),
+        }
+    }
+}
+
+/// Number of bits in a machine word, see [`Context::width`]
+///
+/// Serialized as the number of bits.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u32", into = "u32"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Width {
+    W8,
+    W16,
+    W32,
+    W64,
+}
+
+impl Width {
+    pub fn bits(self) -> u32 {
+        match self {
+            Self::W8 => 8,
+            Self::W16 => 16,
+            Self::W32 => 32,
+            Self::W64 => 64,
+        }
+    }
+}
+
+impl TryFrom<u32> for Width {
+    type Error = InvalidWidth;
+
+    fn try_from(bits: u32) -> Result<Self, Self::Error> {
+        match bits {
+            8 => Ok(Self::W8),
+            16 => Ok(Self::W16),
+            32 => Ok(Self::W32),
+            64 => Ok(Self::W64),
+            _ => Err(InvalidWidth(bits.to_string())),
+        }
+    }
+}
+
+impl From<Width> for u32 {
+    fn from(width: Width) -> Self {
+        width.bits()
+    }
+}
+
+impl FromStr for Width {
+    type Err = InvalidWidth;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u32>()
+            .map_err(|_| InvalidWidth(s.to_string()))
+            .and_then(Self::try_from)
+    }
+}
+
+impl Display for Width {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.bits())
+    }
+}
+
+/// Error for a [`Width`] other than 8, 16, 32 or 64 bits
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("`{0}` is not a word width, expected 8, 16, 32 or 64")]
+pub struct InvalidWidth(pub String);
+
+/// Width and signedness of machine words, see [`Context::width`] and [`Context::signed`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Word {
+    bits: u32,
+    signed: bool,
+}
+
+impl Word {
+    fn is_native(self) -> bool {
+        self.bits == usize::BITS
+    }
+
+    /// Truncate `val` to the width of a word
+    pub(crate) fn wrap(self, val: usize) -> usize {
+        if self.is_native() {
+            val
+        } else {
+            val & ((1 << self.bits) - 1)
+        }
+    }
+
+    /// Value of `val` as a word, which is negative if words are signed
+    pub(crate) fn value(self, val: usize) -> i128 {
+        if self.signed {
+            to_signed(val, Some(self.bits)) as i128
+        } else {
+            self.wrap(val) as i128
+        }
+    }
+
+    /// Whether `val` can be stored in a word without wrapping
+    pub(crate) fn fits(self, val: i128) -> bool {
+        if self.signed {
+            let half = 1 << (self.bits - 1);
+            (-half..half).contains(&val)
+        } else {
+            (0..1 << self.bits).contains(&val)
         }
     }
 }
 
+/// Number of general purpose registers
+const GPRS: usize = 30;
+
+/// Sign-extend the lowest `width` bits of `val`
+#[allow(clippy::cast_possible_wrap)]
+fn to_signed(val: usize, width: Option<u32>) -> isize {
+    match width {
+        Some(bits) if bits > 0 && bits < usize::BITS => {
+            let shift = usize::BITS - bits;
+            ((val << shift) as isize) >> shift
+        }
+        _ => val as isize,
+    }
+}
+
 /// Tracks state of the registers and memory during execution
+///
+/// The I/O provider is not serialized, and is replaced with the default when deserializing
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Context {
     pub cmp: bool,
+    /// Whether the first value was less than the second in the last comparison
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lt: bool,
     pub mar: usize,
     pub acc: usize,
     pub ix: usize,
     pub flow_override_reg: bool,
     pub mem: Memory,
     pub ret: usize,
-    pub gprs: [usize; 30],
+    /// Stack pointer, the address of the value on top of the stack
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sp: usize,
+    /// Return addresses of the calls below the current one, with the innermost last
+    ///
+    /// The return address of the current call is kept in `ret`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub call_stack: Vec<usize>,
+    pub gprs: [usize; GPRS],
     pub end: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub io: Io,
+    /// Base used to display values
+    pub radix: Radix,
+    /// Number of bits in a machine word, see [`Context::width`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    width: Option<Width>,
+    /// Treat values as two's complement signed words when comparing, displaying and reading
+    /// them. If [`Context::width`] is set, words are that many bits wide.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub signed: bool,
+    /// Names of general purpose registers, by register number, used when displaying them
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub aliases: BTreeMap<usize, String>,
+    /// Address of the operand of the instruction being executed, and its resolved access
+    #[cfg_attr(feature = "serde", serde(skip))]
+    operand: Option<(usize, Access)>,
 }
 
 impl Context {
@@ -137,35 +572,185 @@ impl Context {
         }
     }
 
+    /// Name of general purpose register `r`, its alias if it has one
+    pub fn gpr_name(&self, r: usize) -> Cow<'_, str> {
+        self.aliases
+            .get(&r)
+            .map_or_else(|| format!("r{r}").into(), |name| name.as_str().into())
+    }
+
     #[inline]
     pub fn override_flow_control(&mut self) {
         self.flow_override_reg = true;
     }
 
+    /// Number of bits in a machine word, e.g. `Some(Width::W8)` for 8-bit registers and memory
+    /// cells
+    ///
+    /// Registers and memory cells wrap around to this width after every instruction, and values
+    /// are displayed with it, see [`Radix::display_width`]. Words are native `usize`s if `None`,
+    /// and words wider than a `usize` are native too.
+    pub fn width(&self) -> Option<Width> {
+        self.width
+    }
+
+    /// Set [`Context::width`], wrapping the current values of registers and memory to it
+    pub fn set_width(&mut self, width: Option<Width>) {
+        self.width = width;
+
+        let word = self.word();
+
+        if word.is_native() {
+            return;
+        }
+
+        self.wrap_registers(word);
+
+        for addr in self.mem.iter().map(|(&addr, _)| addr).collect::<Vec<_>>() {
+            if let Ok(val) = self.mem.get_mut(&addr) {
+                *val = word.wrap(*val);
+            }
+        }
+    }
+
+    /// Width and signedness of words
+    ///
+    /// Values are displayed with the same number of bits, see [`Context::display_bits`]
+    pub(crate) fn word(&self) -> Word {
+        Word {
+            bits: self
+                .width
+                .map_or(usize::BITS, |width| width.bits().min(usize::BITS)),
+            signed: self.signed,
+        }
+    }
+
+    /// Wrap registers that hold words, and the memory cells at `writes`, to [`Context::width`]
+    pub(crate) fn wrap_words(&mut self, writes: &[(usize, usize)]) {
+        let word = self.word();
+
+        if word.is_native() {
+            return;
+        }
+
+        self.wrap_registers(word);
+
+        for (addr, _) in writes {
+            if let Ok(val) = self.mem.get_mut(addr) {
+                *val = word.wrap(*val);
+            }
+        }
+    }
+
+    fn wrap_registers(&mut self, word: Word) {
+        self.acc = word.wrap(self.acc);
+        self.ix = word.wrap(self.ix);
+
+        for gpr in &mut self.gprs {
+            *gpr = word.wrap(*gpr);
+        }
+    }
+
+    /// Wrap `val` so that it is displayed with [`Context::radix`] and [`Context::width`]
+    ///
+    /// If [`Context::signed`] is set, negative values are displayed with a minus sign in decimal.
+    pub fn display(&self, val: usize) -> impl Display {
+        RadixVal::new(self.radix, val, self.display_bits(), self.signed)
+    }
+
+    /// Number of bits values are displayed and sign extended with, the same as words wrap to
+    pub(crate) fn display_bits(&self) -> Option<u32> {
+        self.width.map(|_| self.word().bits)
+    }
+
+    /// Display `entry` like values in `DBG` output
+    ///
+    /// Bytes are displayed 8 bits wide, and strings in quotes.
+    pub fn display_entry(&self, entry: &MemEntry) -> String {
+        match *entry {
+            MemEntry::Word(val) => self.display(val).to_string(),
+            MemEntry::Byte(val) => {
+                RadixVal::new(self.radix, val.into(), Some(8), false).to_string()
+            }
+            MemEntry::Str(ref s) => format!("{s:?}"),
+        }
+    }
+
+    /// Table of the memory cells in `range`, displayed like values in `DBG` output, see
+    /// [`Memory::dump`]
+    pub fn dump_mem(&self, range: Range<usize>) -> String {
+        self.mem.dump_with(range, |entry| self.display_entry(entry))
+    }
+
+    /// Interpret `val` as a two's complement word of [`Context::width`] bits
+    pub fn to_signed(&self, val: usize) -> isize {
+        to_signed(val, self.display_bits())
+    }
+
+    /// Compare two values, setting [`Context::cmp`] if they are equal and [`Context::lt`] if `a`
+    /// is less than `b`. The values are wrapped to [`Context::width`] first, and compared as
+    /// signed if [`Context::signed`] is set.
+    pub fn compare(&mut self, a: usize, b: usize) {
+        let word = self.word();
+        let (a, b) = (word.wrap(a), word.wrap(b));
+
+        self.cmp = a == b;
+        self.lt = if self.signed {
+            self.to_signed(a) < self.to_signed(b)
+        } else {
+            a < b
+        };
+    }
+
+    /// Number of calls that have not returned yet
+    #[inline]
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.len()
+    }
+
     /// # Panics
-    /// If `op` is not a `usize` register. To avoid this, check `op` using [`Op::is_register`].
+    /// If `op` is not a `usize` register. To avoid this, check `op` using [`Op::is_register`], or
+    /// use [`Context::try_get_mut_register`].
     #[inline]
     pub fn get_mut_register(&mut self, op: &Op) -> &mut usize {
-        match op {
+        self.try_get_mut_register(op)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// # Panics
+    /// If `op` is not a `usize` register. To avoid this, check `op` using [`Op::is_register`], or
+    /// use [`Context::try_get_register`].
+    #[inline]
+    pub fn get_register(&self, op: &Op) -> usize {
+        self.try_get_register(op).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like [`Context::get_mut_register`], but returns [`RtError::InvalidOperand`] if `op` is not
+    /// a `usize` register
+    #[inline]
+    pub fn try_get_mut_register(&mut self, op: &Op) -> RtResult<&mut usize> {
+        Ok(match op {
             Op::Acc => &mut self.acc,
             Op::Ix => &mut self.ix,
             Op::Ar => &mut self.ret,
-            Op::Gpr(x) => &mut self.gprs[*x],
-            _ => unreachable!(),
-        }
+            Op::Sp => &mut self.sp,
+            Op::Gpr(x) => self.gprs.get_mut(*x).ok_or(RtError::InvalidOperand)?,
+            _ => return Err(RtError::InvalidOperand),
+        })
     }
 
-    /// # Panics
-    /// If `op` is not a `usize` register. To avoid this, check `op` using [`Op::is_register`].
+    /// Like [`Context::get_register`], but returns [`RtError::InvalidOperand`] if `op` is not a
+    /// `usize` register
     #[inline]
-    pub fn get_register(&self, op: &Op) -> usize {
-        match op {
+    pub fn try_get_register(&self, op: &Op) -> RtResult<usize> {
+        Ok(match op {
             Op::Acc => self.acc,
             Op::Ix => self.ix,
             Op::Ar => self.ret,
-            Op::Gpr(x) => self.gprs[*x],
-            _ => unreachable!(),
-        }
+            Op::Sp => self.sp,
+            Op::Gpr(x) => *self.gprs.get(*x).ok_or(RtError::InvalidOperand)?,
+            _ => return Err(RtError::InvalidOperand),
+        })
     }
 
     /// Read the given operand from the context
@@ -178,7 +763,8 @@ impl Context {
     ///
     /// # Panics
     ///
-    /// If `op` is not usizeable. To avoid this, check `op` using [`Op::is_usizeable`]
+    /// If `op` is not usizeable. To avoid this, check `op` using [`Op::is_usizeable`]. With the
+    /// `strict-no-panic` feature, [`RtError::InvalidOperand`] is returned instead.
     ///
     /// # Example
     ///
@@ -192,15 +778,23 @@ impl Context {
     /// ```
     #[inline]
     pub fn read(&self, op: &Op) -> RtResult<usize> {
-        match op {
-            &Op::Literal(val) => Ok(val),
-            Op::Addr(addr) => self.mem.get(addr).copied(),
-            Op::Indirect(op) if op.is_usizeable() => {
+        if let Some(access) = self.resolved(op) {
+            return self.read_access(access);
+        }
+
+        match *op {
+            Op::Literal(val) => Ok(val),
+            Op::Addr(addr) => self.mem.get(&addr).copied(),
+            Op::Acc => Ok(self.acc),
+            Op::Ix => Ok(self.ix),
+            Op::Ar => Ok(self.ret),
+            Op::Sp => Ok(self.sp),
+            Op::Gpr(x) => self.gprs.get(x).copied().ok_or(RtError::InvalidOperand),
+            Op::Indirect(ref op) if op.is_usizeable() => {
                 let addr = self.read(op)?;
                 self.mem.get(&addr).copied()
             }
-            reg if reg.is_register() => Ok(self.get_register(reg)),
-            _ => unreachable!(),
+            _ => invalid_operand(),
         }
     }
 
@@ -215,7 +809,8 @@ impl Context {
     ///
     /// # Panics
     ///
-    /// If `op` is not writeable. To avoid this, check `op` using [`Op::is_read_write`].
+    /// If `op` is not writeable. To avoid this, check `op` using [`Op::is_read_write`]. With the
+    /// `strict-no-panic` feature, [`RtError::InvalidOperand`] is returned instead.
     ///
     /// # Example
     ///
@@ -229,41 +824,127 @@ impl Context {
     /// ```
     #[inline]
     pub fn modify(&mut self, op: &Op, f: impl Fn(&mut usize)) -> RtResult {
-        match op {
-            Op::Addr(x) => f(self.mem.get_mut(x)?),
-            Op::Indirect(op) if op.is_usizeable() => {
+        if let Some(access) = self.resolved(op) {
+            f(self.access_mut(access)?);
+            return Ok(());
+        }
+
+        let val = match *op {
+            Op::Addr(addr) => self.mem.get_mut(&addr)?,
+            Op::Acc => &mut self.acc,
+            Op::Ix => &mut self.ix,
+            Op::Ar => &mut self.ret,
+            Op::Sp => &mut self.sp,
+            Op::Gpr(x) => self.gprs.get_mut(x).ok_or(RtError::InvalidOperand)?,
+            Op::Indirect(ref op) if op.is_usizeable() => {
                 let addr = self.read(op)?;
-                f(self.mem.get_mut(&addr)?);
+                self.mem.get_mut(&addr)?
             }
-            op if op.is_register() => f(self.get_mut_register(op)),
-            _ => unreachable!(),
-        }
+            _ => return invalid_operand(),
+        };
+
+        f(val);
 
         Ok(())
     }
+
+    /// Access resolved for `op`, if it is the operand of the instruction being executed
+    #[inline]
+    fn resolved(&self, op: &Op) -> Option<Access> {
+        match self.operand {
+            Some((addr, access)) if addr == op as *const Op as usize => Some(access),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    fn reg(&self, reg: Reg) -> usize {
+        match reg {
+            Reg::Acc => self.acc,
+            Reg::Ix => self.ix,
+            Reg::Ar => self.ret,
+            Reg::Sp => self.sp,
+            Reg::Gpr(x) => self.gprs[x],
+        }
+    }
+
+    #[inline]
+    fn reg_mut(&mut self, reg: Reg) -> &mut usize {
+        match reg {
+            Reg::Acc => &mut self.acc,
+            Reg::Ix => &mut self.ix,
+            Reg::Ar => &mut self.ret,
+            Reg::Sp => &mut self.sp,
+            Reg::Gpr(x) => &mut self.gprs[x],
+        }
+    }
+
+    #[inline]
+    fn read_access(&self, access: Access) -> RtResult<usize> {
+        match access {
+            Access::Literal(val) => Ok(val),
+            Access::Addr(addr) => self.mem.get(&addr).copied(),
+            Access::Reg(reg) => Ok(self.reg(reg)),
+            Access::IndirectAddr(ptr) => {
+                let addr = self.mem.get(&ptr).copied()?;
+                self.mem.get(&addr).copied()
+            }
+            Access::IndirectReg(reg) => self.mem.get(&self.reg(reg)).copied(),
+        }
+    }
+
+    #[inline]
+    fn access_mut(&mut self, access: Access) -> RtResult<&mut usize> {
+        match access {
+            Access::Literal(_) => invalid_operand(),
+            Access::Addr(addr) => self.mem.get_mut(&addr),
+            Access::Reg(reg) => Ok(self.reg_mut(reg)),
+            Access::IndirectAddr(ptr) => {
+                let addr = self.mem.get(&ptr).copied()?;
+                self.mem.get_mut(&addr)
+            }
+            Access::IndirectReg(reg) => {
+                let addr = self.reg(reg);
+                self.mem.get_mut(&addr)
+            }
+        }
+    }
+}
+
+#[cold]
+fn invalid_operand<T>() -> RtResult<T> {
+    if cfg!(feature = "strict-no-panic") {
+        Err(RtError::InvalidOperand)
+    } else {
+        unreachable!()
+    }
 }
 
 impl Display for Context {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let fmt = |val| self.display(val);
+
         f.write_str("Context {\n")?;
         writeln!(f, "{:>6}: {}", "mar", self.mar)?;
-        writeln!(f, "{:>6}: {}", "acc", self.acc)?;
-        writeln!(f, "{:>6}: {}", "ix", self.ix)?;
+        writeln!(f, "{:>6}: {}", "acc", fmt(self.acc))?;
+        writeln!(f, "{:>6}: {}", "ix", fmt(self.ix))?;
         writeln!(f, "{:>6}: {}", "cmp", self.cmp)?;
         write!(f, "{:>6}: [", "gprs")?;
 
-        for (idx, val) in self.gprs.iter().enumerate() {
+        for (idx, &val) in self.gprs.iter().enumerate() {
+            let (name, val) = (self.gpr_name(idx), fmt(val));
+
             if idx == self.gprs.len() - 1 {
-                writeln!(f, "r{idx} = {val}]")?;
+                writeln!(f, "{name} = {val}]")?;
             } else {
-                write!(f, "r{idx} = {val}, ")?;
+                write!(f, "{name} = {val}, ")?;
             }
         }
 
         writeln!(f, "{:>6}: Memory {{", "mem")?;
 
-        for (addr, entry) in &self.mem {
-            writeln!(f, "{addr:>8}: {entry},")?;
+        for (addr, entry) in self.mem.entries() {
+            writeln!(f, "{addr:>8}: {},", self.display_entry(&entry))?;
         }
 
         writeln!(f, "{:>6}}}", "")?;
@@ -272,9 +953,6 @@ impl Display for Context {
     }
 }
 
-/// Runtime representation of a program
-pub type ExTree = BTreeMap<usize, ExecInst>;
-
 /// Executes a program
 pub struct Executor {
     pub debug_info: DebugInfo,
@@ -282,6 +960,12 @@ pub struct Executor {
     pub prog: ExTree,
     pub ctx: Context,
     count: u64,
+    breakpoints: BTreeSet<usize>,
+    paused_at: Option<usize>,
+    hook: Option<Box<dyn ExecHook>>,
+    history: history::History,
+    cancel: Option<CancellationToken>,
+    step_limit: Option<u64>,
 }
 
 /// Shows execution status
@@ -292,53 +976,369 @@ pub enum Status {
     Continue,
     /// An error has been encountered during execution
     Error(RtError),
+    /// Execution is paused at a breakpoint, before the instruction at this address is executed
+    Breakpoint(usize),
+    /// Execution was stopped with a [`CancellationToken`], before the instruction at
+    /// [`Context::mar`] was executed. It can be resumed after resetting the token.
+    Cancelled,
 }
 
-impl Executor {
+/// How [`Executor::exec`] stopped, if there was no error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecSummary {
+    /// `END` was executed, rather than the last instruction without reaching `END`
+    pub ended: bool,
+    /// Execution was stopped with a [`CancellationToken`]
+    pub cancelled: bool,
+    /// Total instructions executed, see [`Executor::count`]
+    pub count: u64,
+}
+
+/// How [`Executor::exec_and_report`] stopped
+#[derive(Debug)]
+pub enum Exit {
+    /// `END` was executed
+    End,
+    /// The last instruction was executed without reaching `END`
+    FellOff,
+    /// Execution was stopped with a [`CancellationToken`]
+    Cancelled,
+    /// An error was encountered, and reported to the output
+    Error(RuntimeFault),
+}
+
+impl Exit {
+    /// Whether the program stopped by executing `END`
+    ///
+    /// Falling off the end of the program is not a success, as it usually means that `END` is
+    /// missing or was jumped over.
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::End)
+    }
+}
+
+impl From<Result<ExecSummary, RuntimeFault>> for Exit {
+    fn from(res: Result<ExecSummary, RuntimeFault>) -> Self {
+        match res {
+            Ok(ExecSummary {
+                cancelled: true, ..
+            }) => Self::Cancelled,
+            Ok(ExecSummary { ended: true, .. }) => Self::End,
+            Ok(_) => Self::FellOff,
+            Err(fault) => Self::Error(fault),
+        }
+    }
+}
+
+/// Run `inst` with its operand resolved as `access`, logging it and the time it took when
+/// tracing
+///
+/// The time is only measured when tracing or if `measure`, and is zero otherwise.
+fn run_traced(
+    ctx: &mut Context,
+    inst: &ExecInst,
+    access: Option<Access>,
+    measure: bool,
+) -> (RtResult, Duration) {
+    ctx.operand = access.map(|access| (std::ptr::addr_of!(inst.op) as usize, access));
+    let res = run_timed(ctx, inst, measure);
+    ctx.operand = None;
+
+    res
+}
+
+fn run_timed(ctx: &mut Context, inst: &ExecInst, measure: bool) -> (RtResult, Duration) {
+    let tracing = log_enabled!(log::Level::Trace);
+
+    // Only pay for the clock when the timings will actually be used
+    if !tracing && !measure {
+        return ((inst.func)(ctx, &inst.op), Duration::ZERO);
+    }
+
+    // Logged before running, so that an instruction that never returns is visible
+    trace!("Executing instruction {} {}", inst.mnemonic, inst.op);
+
+    let timer = Instant::now();
+    let res = (inst.func)(ctx, &inst.op);
+    let elapsed = timer.elapsed();
+
+    trace!(
+        "Executed instruction {} {} in {:?}",
+        inst.mnemonic,
+        inst.op,
+        elapsed
+    );
+
+    (res, elapsed)
+}
+
+impl Executor {
     pub fn new(
         source: impl Into<Source>,
-        prog: ExTree,
+        mut prog: ExTree,
         ctx: Context,
         debug_info: DebugInfo,
     ) -> Self {
+        prog.resolve();
+
         Self {
             debug_info,
             source: source.into(),
             prog,
             ctx,
             count: 0,
+            breakpoints: BTreeSet::new(),
+            paused_at: None,
+            hook: None,
+            history: history::History::default(),
+            cancel: None,
+            step_limit: None,
+        }
+    }
+
+    /// Configure an [`Executor`] for `prog`, including its memory, I/O and debugging options
+    pub fn builder(prog: ExTree) -> ExecutorBuilder {
+        ExecutorBuilder::new(prog)
+    }
+
+    /// Fail with [`RtError::StepLimit`] once `limit` instructions have been executed
+    ///
+    /// Useful for running untrusted programs that may never end. No limit (`None`) by default.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Remember the changes made by the last `limit` instructions, so that they can be undone
+    /// with [`Executor::step_back`]
+    ///
+    /// Disabled (`0`) by default, since it slows down execution.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    /// Undo up to `n` instructions, returning how many were undone
+    ///
+    /// Registers and memory are restored, but I/O cannot be undone. Execution does not pause
+    /// at a breakpoint on the instruction stepped back to.
+    pub fn step_back(&mut self, n: usize) -> usize {
+        let mut undone = 0;
+
+        while undone < n && self.history.undo(&mut self.ctx) {
+            undone += 1;
+        }
+
+        if undone > 0 {
+            self.count -= undone as u64;
+            self.ctx.mem.forget_writes_after(self.count);
+            self.paused_at = Some(self.ctx.mar);
+        }
+
+        undone
+    }
+
+    /// Number of instructions that can currently be undone
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Number of instructions executed so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Save the state of execution, so that it can be resumed later with [`Executor::restore`]
+    pub fn snapshot(&self) -> ExecutorState {
+        ExecutorState::new(&self.ctx, self.count)
+    }
+
+    /// Copy the registers and memory, with the labels of memory addresses
+    pub fn context_snapshot(&self) -> ContextSnapshot {
+        ContextSnapshot::new(&self.ctx, self.debug_info.mem.clone())
+    }
+
+    /// Attach the instruction at [`Context::mar`] and a copy of the registers and memory to
+    /// `error`, returned by [`Executor::step`]
+    pub fn fault(&self, error: RtError) -> RuntimeFault {
+        let addr = self.ctx.mar;
+        let (mnemonic, op) = self.prog.get(addr).map_or_else(
+            || (String::new(), Op::Null),
+            |inst| (inst.mnemonic.clone(), inst.op.clone()),
+        );
+
+        RuntimeFault {
+            error,
+            addr,
+            mnemonic,
+            op,
+            context: Box::new(self.context_snapshot()),
         }
     }
 
+    /// Resume execution from a state saved with [`Executor::snapshot`]
+    ///
+    /// The I/O provider, breakpoints and hook are kept, but the history used by
+    /// [`Executor::step_back`] is cleared.
+    pub fn restore(&mut self, state: ExecutorState) {
+        self.count = state.apply(&mut self.ctx);
+        self.paused_at = None;
+        self.history.clear();
+    }
+
+    /// Call `hook` before and after each instruction is executed, replacing any previous hook
+    pub fn set_hook(&mut self, hook: impl ExecHook + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Remove the hook, returning it so that any state it collected can be inspected
+    pub fn take_hook(&mut self) -> Option<Box<dyn ExecHook>> {
+        self.hook.take()
+    }
+
+    /// Stop with [`Status::Cancelled`] once `token` is cancelled, replacing any previous token
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancel = Some(token);
+    }
+
+    pub fn take_cancellation_token(&mut self) -> Option<CancellationToken> {
+        self.cancel.take()
+    }
+
+    /// Pause execution before the instruction at `addr` is executed
+    ///
+    /// Returns `false` if there already was a breakpoint at `addr`
+    pub fn add_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    /// Returns `false` if there was no breakpoint at `addr`
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
     /// Advance execution by one instruction
     ///
+    /// If there is a breakpoint at the current instruction, [`Status::Breakpoint`] is returned
+    /// instead, and the instruction is executed on the next call.
+    ///
+    /// # Panics
+    /// If there is no instruction at the current address, e.g. after jumping past the end of the
+    /// program. With the `strict-no-panic` feature, [`RtError::InvalidInstAddr`] is returned
+    /// instead.
+    ///
     /// # Example
     /// ```no_run
     ///
     /// ```
-    pub fn step<T>(&mut self) -> Status
-    where
-        T: InstSet,
-        <T as FromStr>::Err: Display,
-    {
+    pub fn step(&mut self) -> Status {
         if self.ctx.mar == self.prog.len() || self.ctx.end {
             Status::Complete
+        } else if self.is_cancelled() {
+            Status::Cancelled
+        } else if let Some(limit) = self.step_limit.filter(|&limit| self.count >= limit) {
+            Status::Error(RtError::StepLimit(limit))
+        } else if self.breakpoints.contains(&self.ctx.mar) && self.paused_at != Some(self.ctx.mar) {
+            self.paused_at = Some(self.ctx.mar);
+            Status::Breakpoint(self.ctx.mar)
         } else {
+            self.paused_at = None;
             self.count += 1;
 
-            let inst = if let Some(inst) = self.prog.get(&self.ctx.mar) {
+            let inst = if let Some(inst) = self.prog.get(self.ctx.mar) {
                 inst
+            } else if cfg!(feature = "strict-no-panic") {
+                let err = RtError::InvalidInstAddr(self.ctx.mar);
+                crate::metrics::record(|m| m.runtime_error(&err));
+                return Status::Error(err);
             } else {
                 panic!("Unable to fetch instruction. Please report this as a bug with full debug logs attached.")
             };
 
-            trace!(
-                "Executing instruction {} {}",
-                T::from_id(inst.id).unwrap_or_else(|msg| panic!("{msg}")),
-                inst.op
-            );
+            let addr = self.ctx.mar;
+
+            if let Some(hook) = &mut self.hook {
+                let hook_inst = HookInst {
+                    addr,
+                    opcode: &inst.mnemonic,
+                    op: &inst.op,
+                    comment: self.debug_info.comment(addr),
+                    writes: &[],
+                    elapsed: Duration::ZERO,
+                };
+
+                hook.before(&hook_inst, &self.ctx);
+            }
+
+            let saved = self
+                .history
+                .is_enabled()
+                .then(|| history::Registers::save(&self.ctx));
+
+            // Memory writes are needed to undo steps, are reported to hooks, and are wrapped to
+            // the word width
+            let wrap = !self.ctx.word().is_native();
+            let journal =
+                saved.is_some() || self.hook.is_some() || wrap || self.ctx.mem.is_logging();
+
+            if journal {
+                self.ctx.mem.start_journal();
+            }
+
+            let measure = self.hook.is_some();
+            let (res, elapsed) = run_traced(&mut self.ctx, inst, self.prog.access(addr), measure);
+
+            let res = match res {
+                Err(e) if future::InputPending::is_cause_of(&e) => return self.wait_for_input(e),
+                res => res,
+            };
+
+            let writes = if journal {
+                self.ctx.mem.take_journal()
+            } else {
+                Vec::new()
+            };
+
+            if wrap {
+                self.ctx.wrap_words(&writes);
+            }
+
+            self.ctx.mem.log_writes(self.count, &writes);
+
+            if let Some(hook) = &mut self.hook {
+                let mut addrs = Vec::with_capacity(writes.len());
+
+                for &(addr, _) in &writes {
+                    if !addrs.contains(&addr) {
+                        addrs.push(addr);
+                    }
+                }
+
+                let hook_inst = HookInst {
+                    addr,
+                    opcode: &inst.mnemonic,
+                    op: &inst.op,
+                    comment: self.debug_info.comment(addr),
+                    writes: &addrs,
+                    elapsed,
+                };
+
+                hook.after(&hook_inst, &self.ctx, &res);
+            }
+
+            if let Some(saved) = &saved {
+                self.history.commit(saved, writes);
+            }
+
+            crate::metrics::record(|m| m.instruction_executed());
 
-            match (inst.func)(&mut self.ctx, &inst.op) {
+            if let Err(e) = &res {
+                crate::metrics::record(|m| m.runtime_error(e));
+            }
+
+            match res {
                 Ok(()) => {
                     if self.ctx.flow_override_reg {
                         self.ctx.flow_override_reg = false;
@@ -353,33 +1353,205 @@ impl Executor {
         }
     }
 
-    pub fn exec<T>(&mut self)
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .map_or(false, CancellationToken::is_cancelled)
+    }
+
+    /// Undo the bookkeeping for an instruction that ran out of input, so that [`ExecAsync`] can
+    /// retry it once more arrives
+    fn wait_for_input(&mut self, err: RtError) -> Status {
+        self.ctx.mem.take_journal();
+        self.count -= 1;
+        self.paused_at = Some(self.ctx.mar);
+
+        Status::Error(err)
+    }
+
+    /// Execute the program until it ends
+    ///
+    /// Breakpoints are ignored. Errors are returned without being written to the output, use
+    /// [`Executor::exec_and_report`] for that.
+    pub fn exec(&mut self) -> Result<ExecSummary, RuntimeFault> {
+        let res = loop {
+            match self.step() {
+                Status::Complete => break Ok(self.summary(false)),
+                Status::Cancelled => break Ok(self.summary(true)),
+                Status::Continue | Status::Breakpoint(_) => {}
+                Status::Error(e) => break Err(self.fault(e)),
+            }
+        };
+
+        self.finish(&res);
+
+        res
+    }
+
+    /// Execute the program until it ends, reporting any error to the output
+    ///
+    /// Breakpoints are ignored. Returns how execution stopped.
+    pub fn exec_and_report(&mut self) -> Exit {
+        let res = self.exec();
+
+        if let Err(fault) = &res {
+            self.report(fault);
+        }
+
+        res.into()
+    }
+
+    /// Execute the program until it ends, without blocking while waiting for input
+    ///
+    /// Input is read from `input` instead of [`Context::io`], and the returned future waits
+    /// for it whenever an instruction needs more. Output is still written to [`Context::io`],
+    /// so write it somewhere that does not block, such as a buffer shown by the frontend.
+    ///
+    /// The future yields to other tasks every so often, so the host stays responsive even if
+    /// the program never ends. Like [`Executor::exec`], errors are not written to the output, and
+    /// breakpoints are ignored unless [`ExecAsync::step`] is used.
+    ///
+    /// An instruction that runs out of input is executed again once more arrives, so hooks
+    /// may see it more than once. Custom instructions should read all of their input before
+    /// changing anything.
+    pub fn exec_async<R>(&mut self, input: R) -> ExecAsync<'_, R>
     where
-        T: InstSet,
-        <T as FromStr>::Err: Display,
+        R: AsyncInput,
     {
-        let err = loop {
-            match self.step::<T>() {
-                Status::Complete => break None,
-                Status::Continue => continue,
-                Status::Error(e) => break Some(e),
+        ExecAsync::new(self, input)
+    }
+
+    fn summary(&self, cancelled: bool) -> ExecSummary {
+        ExecSummary {
+            ended: self.ctx.end,
+            cancelled,
+            count: self.count,
+        }
+    }
+
+    /// Log how execution stopped, then flush the output
+    fn finish(&mut self, res: &Result<ExecSummary, RuntimeFault>) {
+        match res {
+            Ok(summary) => {
+                if summary.cancelled {
+                    info!("Execution cancelled");
+                }
+
+                info!("Total instructions executed: {}", summary.count);
+            }
+            Err(fault) => info!("Execution failed: {fault}"),
+        }
+
+        if let Err(e) = self.ctx.io.flush() {
+            warn!("Unable to flush output: {e}");
+        }
+    }
+
+    /// Write an error to the output, then flush it
+    fn report(&mut self, fault: &RuntimeFault) {
+        let e = &fault.error;
+
+        if let Err(io_err) = self
+            .source
+            .handle_err(&mut self.ctx.io.write, e, fault.addr)
+        {
+            warn!("Unable to report error `{e}`: {io_err}");
+        }
+
+        if let Err(e) = self.ctx.io.write.flush() {
+            warn!("Unable to flush output: {e}");
+        }
+    }
+
+    /// Execute the program, failing with [`RtError::Timeout`] if it takes longer than `timeout`
+    ///
+    /// Like [`Executor::exec`], errors are returned with the instruction they happened at,
+    /// instead of being reported to the output. Breakpoints are ignored. The deadline is only
+    /// checked between instructions, so use [`Io::set_input_timeout`] as well if the program
+    /// reads input.
+    ///
+    /// If cancelled with a [`CancellationToken`], execution stops early without an error, and
+    /// [`ExecSummary::cancelled`] is set.
+    pub fn exec_with_timeout(&mut self, timeout: Duration) -> Result<ExecSummary, RuntimeFault> {
+        // Checking the clock after every instruction would slow down tight loops
+        const CHECK_INTERVAL: u64 = 256;
+
+        let start = Instant::now();
+
+        let res = loop {
+            match self.step() {
+                Status::Complete => break Ok(self.summary(false)),
+                Status::Cancelled => break Ok(self.summary(true)),
+                Status::Continue | Status::Breakpoint(_) => {}
+                Status::Error(e) => break Err(self.fault(e)),
+            }
+
+            if self.count % CHECK_INTERVAL == 0 && start.elapsed() > timeout {
+                break Err(self.fault(RtError::Timeout(timeout)));
             }
         };
 
-        if let Some(e) = err {
-            self.source
-                .handle_err(&mut self.ctx.io.write, &e, self.ctx.mar)
-                .unwrap();
+        self.finish(&res);
+
+        res
+    }
+
+    /// Execute until a breakpoint is hit, the program ends, or an error occurs
+    ///
+    /// Errors are returned as [`Status::Error`] instead of being reported.
+    pub fn run_until_break(&mut self) -> Status {
+        self.run_while(|_| true)
+    }
+
+    /// Execute at most `steps` instructions, e.g. once per frame of a game loop or UI
+    ///
+    /// Returns [`Status::Continue`] if the program can be resumed with another call, or stops
+    /// early with the status of a breakpoint, the end of the program, or an error, like
+    /// [`Executor::run_until_break`].
+    pub fn run_for(&mut self, steps: u64) -> Status {
+        for _ in 0..steps {
+            match self.step() {
+                Status::Continue => {}
+                status => return status,
+            }
+        }
+
+        Status::Continue
+    }
+
+    /// Execute one instruction, running any function it calls to completion
+    ///
+    /// Stops early if a breakpoint is hit, the program ends, or an error occurs, like
+    /// [`Executor::run_until_break`].
+    pub fn step_over(&mut self) -> Status {
+        let depth = self.ctx.call_depth();
+        self.run_while(|ctx| ctx.call_depth() > depth)
+    }
+
+    /// Execute until the current function returns
+    ///
+    /// Outside of a function, this runs until the program ends. Stops early if a breakpoint is
+    /// hit or an error occurs, like [`Executor::run_until_break`].
+    pub fn step_out(&mut self) -> Status {
+        if let Some(depth) = self.ctx.call_depth().checked_sub(1) {
+            self.run_while(|ctx| ctx.call_depth() > depth)
         } else {
-            info!("Total instructions executed: {}", self.count);
+            self.run_until_break()
         }
     }
 
-    pub fn display_with_opcodes<T>(&self) -> Result<String, <T as FromStr>::Err>
-    where
-        T: InstSet,
-        <T as FromStr>::Err: Display,
-    {
+    /// Step until `cond` no longer holds after an instruction, or any other [`Status`]
+    fn run_while(&mut self, cond: impl Fn(&Context) -> bool) -> Status {
+        loop {
+            match self.step() {
+                Status::Continue if cond(&self.ctx) => {}
+                status => break status,
+            }
+        }
+    }
+
+    /// List the instructions with their original labels and comments
+    pub fn display_with_opcodes(&self) -> String {
         use std::fmt::Write;
 
         let mut s = String::new();
@@ -388,13 +1560,30 @@ impl Executor {
 
         writeln!(s, "Executor {{").unwrap();
 
-        for (addr, ExecInst { id, op, .. }) in &self.prog {
-            writeln!(s, "{addr:>6}: {func} {op}", func = T::from_id(*id)?).unwrap();
+        for (addr, ExecInst { mnemonic, op, .. }) in &self.prog {
+            write!(s, "{addr:>6}: ").unwrap();
+
+            if let Some(label) = self.debug_info.label_for_inst(addr) {
+                write!(s, "{label}: ").unwrap();
+            }
+
+            write!(
+                s,
+                "{mnemonic} {}",
+                op.display_aliased(&self.debug_info.aliases)
+            )
+            .unwrap();
+
+            if let Some(comment) = self.debug_info.comment(addr) {
+                write!(s, " // {comment}").unwrap();
+            }
+
+            s.push('\n');
         }
 
         s.push('}');
 
-        Ok(s)
+        s
     }
 }
 
@@ -422,6 +1611,9 @@ impl Debug for Executor {
             )
             .field("ctx", &self.ctx)
             .field("count", &self.count)
+            .field("breakpoints", &self.breakpoints)
+            .field("hook", &self.hook.is_some())
+            .field("cancel", &self.cancel)
             .finish_non_exhaustive()
     }
 }
@@ -437,29 +1629,1343 @@ mod tests {
     }
 
     #[test]
-    fn exec() {
-        let prog =
-            // Division algorithm from examples/division.pasm
-            [
-                (0, ExecInst::new(0, arith::inc, "202".into())),
-                (1, ExecInst::new(0, arith::add, "203,201".into())),
-                (2, ExecInst::new(0, cmp::cmp, "203,204".into())),
-                (3, ExecInst::new(0, cmp::jpn, "0".into())),
-                (4, ExecInst::new(0, mov::ldd, "202".into())),
-                (5, ExecInst::new(0, io::end, "".into())),
-            ].into();
+    fn input_timeout() {
+        struct Stuck;
 
-        let mem = [(200, 0), (201, 5), (202, 0), (203, 0), (204, 15)].into();
+        impl Read for Stuck {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                std::thread::sleep(Duration::from_secs(1));
+                Ok(0)
+            }
+        }
 
-        let mut exec = Executor::new(
-            "None",
-            prog,
-            Context::new(Memory::new(mem)),
-            DebugInfo::default(),
+        let mut io = crate::make_io!(Stuck, std::io::sink());
+        io.set_input_timeout(Duration::from_millis(10));
+
+        let mut ctx = Context::with_io(Memory::default(), io);
+
+        assert!(matches!(
+            io::inp(&mut ctx, &Op::Null),
+            Err(RtError::InputTimeout)
+        ));
+    }
+
+    #[test]
+    fn io_limits() {
+        let out = crate::TestStdio::new(vec![]);
+        let mut io = crate::make_io!(crate::TestStdio::new(&b"abc"[..]), out.clone());
+        io.set_input_limit(2);
+        io.set_output_limit(2);
+
+        let mut ctx = Context::with_io(Memory::default(), io);
+
+        io::inp(&mut ctx, &Op::Null).unwrap();
+        io::out(&mut ctx, &Op::Null).unwrap();
+        io::inp(&mut ctx, &Op::Null).unwrap();
+        io::out(&mut ctx, &Op::Null).unwrap();
+
+        assert!(matches!(
+            io::inp(&mut ctx, &Op::Null),
+            Err(RtError::InputLimit(2))
+        ));
+        assert!(matches!(
+            io::out(&mut ctx, &Op::Null),
+            Err(RtError::OutputLimit(2))
+        ));
+        assert_eq!(out.to_vec(), b"ab");
+
+        // Checking for more input past the limit takes at most one byte from the reader
+        let mut inner = std::io::Cursor::new(b"abcd".to_vec());
+        let mut limited = Limited::new(&mut inner, 2);
+        let mut buf = [0; 4];
+
+        assert_eq!(std::io::Read::read(&mut limited, &mut buf).unwrap(), 2);
+        assert!(std::io::Read::read(&mut limited, &mut buf).is_err());
+        assert!(std::io::Read::read(&mut limited, &mut buf).is_err());
+        assert_eq!(limited.into_parts().1, Some(b'c'));
+        assert_eq!(inner.position(), 3);
+    }
+
+    #[test]
+    fn resolved_operands() {
+        use crate::parse::{jit, DefaultSet};
+
+        let indirect = |op| Op::Indirect(Box::new(op));
+
+        assert_eq!(
+            Access::resolve(&indirect(Op::Gpr(3))),
+            Some(Access::IndirectReg(Reg::Gpr(3)))
         );
 
-        exec.exec::<crate::parse::DefaultSet>();
+        for op in [
+            Op::Null,
+            Op::Gpr(GPRS),
+            indirect(Op::Literal(1)),
+            indirect(indirect(Op::Addr(0))),
+        ] {
+            assert_eq!(Access::resolve(&op), None, "{op}");
+        }
 
-        assert_eq!(exec.ctx.acc, 3);
+        // Resolved operands behave like the ones checked on every step
+        let run = |resolved: bool| {
+            let mut exec = jit::<DefaultSet>(
+                "LDI 200\nADD 201\nSTO 202\nINC 202\nLDR #1\nLDX 200\nEND\n\n200 201\n201 5\n202 0\n",
+                crate::make_io!(std::io::empty(), std::io::sink()),
+            )
+            .unwrap();
+
+            if !resolved {
+                for addr in 0..exec.prog.len() {
+                    exec.prog.get_mut(addr);
+                }
+            }
+
+            exec.exec().unwrap();
+            (exec.ctx.acc, exec.ctx.mem.get(&202).copied().unwrap())
+        };
+
+        assert_eq!(run(true), (5, 11));
+        assert_eq!(run(false), run(true));
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn newline_and_flush() {
+        use crate::{
+            parse::{jit, DefaultSet},
+            TestStdio,
+        };
+        use std::io::{BufWriter, Read, Write};
+
+        /// Input that saves what had been written to `out` when it is read
+        struct Prompted {
+            out: TestStdio,
+            seen: TestStdio,
+        }
+
+        impl Read for Prompted {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.seen.write_all(&self.out.to_vec())?;
+                buf[0] = b'a';
+                Ok(1)
+            }
+        }
+
+        let (out, seen) = (TestStdio::new(vec![]), TestStdio::new(vec![]));
+        let input = Prompted {
+            out: out.clone(),
+            seen: seen.clone(),
+        };
+        // Nothing reaches `out` until the output is flushed
+        let io = crate::make_io!(input, BufWriter::with_capacity(64, out.clone()));
+
+        let mut exec =
+            jit::<DefaultSet>("OUT #62\nIN\nOUT\nNL\nOUT #33\nFLUSH\nEND\n\nNONE:\n", io).unwrap();
+
+        assert!(matches!(exec.run_for(5), Status::Continue));
+        // The prompt was flushed before reading input, but not what was written after it
+        assert_eq!(seen.to_vec(), b">");
+        assert_eq!(out.to_vec(), b">");
+
+        assert!(matches!(exec.run_for(1), Status::Continue));
+        assert_eq!(out.to_vec(), b">a\n!");
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn secure_input() {
+        let out = crate::TestStdio::new(vec![]);
+        let io = crate::make_io!(crate::TestStdio::new(&b"pass\n"[..]), out.clone());
+        let mut ctx = Context::with_io(Memory::new((0..8).map(|a| (a, 0)).collect()), io);
+
+        io::ins(&mut ctx, &Op::Addr(2)).unwrap();
+
+        assert_eq!(ctx.acc, 4);
+        assert_eq!(
+            ctx.mem.iter().map(|(_, &v)| v).collect::<Vec<_>>(),
+            [0, 0, b'p', b'a', b's', b's', 0, 0].map(usize::from)
+        );
+        // Without echo control, nothing is printed
+        assert!(out.to_vec().is_empty());
+
+        ctx.io = crate::make_io!(crate::TestStdio::new(&b"password\n"[..]), std::io::sink());
+        let limited = Op::MultiOp(vec![Op::Addr(0), Op::Literal(4)]);
+        assert!(matches!(
+            io::ins(&mut ctx, &limited),
+            Err(RtError::LineTooLong { len: 8, max: 4 })
+        ));
+        assert_eq!(ctx.mem.get(&0).copied().unwrap(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn line_input() {
+        let io = crate::make_io!(crate::TestStdio::new(&b"hi\r\nx"[..]), std::io::sink());
+        let mut ctx = Context::with_io(Memory::new((0..4).map(|a| (a, 0)).collect()), io);
+
+        io::inl(&mut ctx, &Op::Addr(1)).unwrap();
+
+        assert_eq!(ctx.acc, 2);
+        assert_eq!(
+            ctx.mem.iter().map(|(_, &v)| v).collect::<Vec<_>>(),
+            [0, b'h', b'i', 0].map(usize::from)
+        );
+
+        // The line break was consumed with the line
+        io::inp(&mut ctx, &Op::Null).unwrap();
+        assert_eq!(ctx.acc, usize::from(b'x'));
+
+        // Lines longer than the memory given to them are an error, and nothing is stored
+        let io = crate::make_io!(crate::TestStdio::new(&b"hello\nabc\n"[..]), std::io::sink());
+        ctx.io = io;
+        assert!(matches!(
+            io::inl(&mut ctx, &Op::Addr(1)),
+            Err(RtError::InvalidAddr(4))
+        ));
+        assert_eq!(
+            ctx.mem.iter().map(|(_, &v)| v).collect::<Vec<_>>(),
+            [0, b'h', b'i', 0].map(usize::from)
+        );
+
+        // As are lines longer than the limit given, even if there is memory for them
+        let limited = Op::MultiOp(vec![Op::Addr(0), Op::Literal(2)]);
+        assert!(matches!(
+            io::inl(&mut ctx, &limited),
+            Err(RtError::LineTooLong { len: 3, max: 2 })
+        ));
+        assert_eq!(ctx.mem.get(&0).copied().unwrap(), 0);
+    }
+
+    #[test]
+    fn encoding() {
+        let out = crate::TestStdio::new(vec![]);
+        let io = crate::make_io!(
+            crate::TestStdio::new(&b"\xC3\xA9\xC3\xBF\xFF"[..]),
+            out.clone()
+        );
+        let mut ctx = Context::with_io(Memory::default(), io);
+
+        // One byte at a time by default
+        assert!(matches!(
+            io::out(&mut ctx, &Op::Literal(0x1F600)),
+            Err(RtError::InvalidUtf8Byte(0x1F600))
+        ));
+        io::inp(&mut ctx, &Op::Null).unwrap();
+        assert_eq!(ctx.acc, 0xC3);
+        io::inp(&mut ctx, &Op::Null).unwrap();
+        assert_eq!(ctx.acc, 0xA9);
+
+        ctx.io.set_encoding(Encoding::Utf8);
+        io::inp(&mut ctx, &Op::Null).unwrap();
+        assert_eq!(ctx.acc, 0xFF);
+        assert!(matches!(
+            io::inp(&mut ctx, &Op::Null),
+            Err(RtError::IoError(_))
+        ));
+
+        io::out(&mut ctx, &Op::Literal(233)).unwrap();
+        io::out(&mut ctx, &Op::Literal(0x1F600)).unwrap();
+        assert!(matches!(
+            io::out(&mut ctx, &Op::Literal(0xD800)),
+            Err(RtError::InvalidCodePoint(0xD800))
+        ));
+        assert_eq!(out.try_to_string().unwrap(), "é😀");
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn inkey() {
+        /// Reader that blocks until the sender is dropped
+        struct Never(std::sync::Mutex<std::sync::mpsc::Receiver<()>>);
+
+        impl std::io::Read for Never {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                let _ = self.0.lock().unwrap().recv();
+                Ok(0)
+            }
+        }
+
+        let mut ctx = Context::with_io(
+            Memory::default(),
+            crate::make_io!(&b"k"[..], std::io::sink()),
+        );
+        assert!(matches!(
+            io::inkey(&mut ctx, &Op::Null),
+            Err(RtError::InputNotPolled)
+        ));
+
+        ctx.io.set_polled_input();
+
+        // Input arrives from a background thread
+        let start = std::time::Instant::now();
+
+        while ctx.acc == 0 && start.elapsed() < std::time::Duration::from_secs(5) {
+            io::inkey(&mut ctx, &Op::Null).unwrap();
+        }
+
+        assert_eq!(ctx.acc, usize::from(b'k'));
+
+        // The end of the input is not waited for either
+        io::inkey(&mut ctx, &Op::Null).unwrap();
+        assert_eq!(ctx.acc, 0);
+
+        // Nor is input that never arrives
+        let (_tx, rx) = std::sync::mpsc::channel();
+        ctx.io = crate::make_io!(Never(std::sync::Mutex::new(rx)), std::io::sink());
+        ctx.io.set_polled_input();
+        ctx.acc = 5;
+        io::inkey(&mut ctx, &Op::Null).unwrap();
+        assert_eq!(ctx.acc, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn ports() {
+        use crate::parse::{jit, DefaultSet};
+
+        let (screen, log) = (crate::TestStdio::new(vec![]), crate::TestStdio::new(vec![]));
+        let mut exec = jit::<DefaultSet>(
+            "INP #2,ACC\nOUTP #0,ACC\nINP #0,r0\nOUTP #1,r0\nOUTP #3,#1\nEND\n\nNONE:\n",
+            crate::make_io!(&b"b"[..], screen.clone()),
+        )
+        .unwrap();
+        exec.ctx.io.attach_input(2, &b"a"[..]);
+        exec.ctx.io.attach_output(1, log.clone());
+
+        let fault = exec.exec().unwrap_err();
+
+        assert!(matches!(fault.error, RtError::InvalidPort(3)));
+        assert_eq!(screen.try_to_string().unwrap(), "a");
+        assert_eq!(log.try_to_string().unwrap(), "b");
+    }
+
+    fn division() -> Executor {
+        let prog =
+            // Division algorithm from examples/division.pasm
+            [
+                (0, ExecInst::new(0, "INC", arith::inc, Op::Addr(202))),
+                (1, ExecInst::new(0, "ADD", arith::add, Op::MultiOp(vec![Op::Addr(203), Op::Addr(201)]))),
+                (2, ExecInst::new(0, "CMP", cmp::cmp, Op::MultiOp(vec![Op::Addr(203), Op::Addr(204)]))),
+                (3, ExecInst::new(0, "JPN", cmp::jpn, Op::Addr(0))),
+                (4, ExecInst::new(0, "LDD", mov::ldd, Op::Addr(202))),
+                (5, ExecInst::new(0, "END", io::end, Op::Null)),
+            ].into();
+
+        let mem = [(200, 0), (201, 5), (202, 0), (203, 0), (204, 15)].into();
+
+        Executor::new(
+            "None",
+            prog,
+            Context::new(Memory::new(mem)),
+            DebugInfo::default(),
+        )
+    }
+
+    #[test]
+    fn exec() {
+        let mut exec = division();
+
+        exec.exec_and_report();
+
+        assert_eq!(exec.ctx.acc, 3);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut exec = division();
+        exec.add_breakpoint(1);
+
+        for i in 1..=3 {
+            assert!(matches!(exec.run_until_break(), Status::Breakpoint(1)));
+            assert_eq!(exec.ctx.mem.get(&202).copied().unwrap(), i);
+        }
+
+        assert!(exec.remove_breakpoint(1));
+        assert!(matches!(exec.run_until_break(), Status::Complete));
+        assert_eq!(exec.ctx.acc, 3);
+    }
+
+    #[test]
+    fn step_back() {
+        let mut exec = division();
+        exec.set_history_limit(4);
+
+        for _ in 0..4 {
+            exec.step();
+        }
+
+        // Back to the start of the second iteration
+        assert_eq!(exec.ctx.mar, 0);
+        assert_eq!(exec.ctx.mem.get(&202).copied().unwrap(), 1);
+        assert_eq!(exec.ctx.mem.get(&203).copied().unwrap(), 5);
+
+        for _ in 0..2 {
+            exec.step();
+        }
+
+        assert_eq!(exec.step_back(3), 3);
+        assert_eq!(exec.ctx.mar, 3);
+        assert!(!exec.ctx.cmp);
+        assert_eq!(exec.ctx.mem.get(&202).copied().unwrap(), 1);
+        assert_eq!(exec.ctx.mem.get(&203).copied().unwrap(), 5);
+
+        // Only 4 steps are remembered
+        assert_eq!(exec.history_len(), 1);
+        assert_eq!(exec.step_back(2), 1);
+        assert_eq!(exec.ctx.mar, 2);
+
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.acc, 3);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn snapshots() {
+        let mut exec = division();
+
+        for _ in 0..6 {
+            exec.step();
+        }
+
+        let saved = serde_json::to_string(&exec.snapshot()).unwrap();
+
+        let mut resumed = division();
+        resumed.restore(serde_json::from_str(&saved).unwrap());
+
+        assert_eq!(resumed.ctx.mar, 2);
+        assert_eq!(resumed.ctx.mem.get(&202).copied().unwrap(), 2);
+
+        resumed.exec_and_report();
+        assert_eq!(resumed.ctx.acc, 3);
+        assert_eq!(resumed.count, 14);
+    }
+
+    #[test]
+    fn exec_timeout() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LOOP: JMP LOOP\n\nNONE:\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            exec.exec_with_timeout(Duration::from_millis(10)),
+            Err(RuntimeFault {
+                error: RtError::Timeout(_),
+                addr: 0,
+                ..
+            })
+        ));
+
+        let mut exec = division();
+        let summary = exec.exec_with_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(exec.ctx.acc, 3);
+        assert!(summary.ended && !summary.cancelled);
+
+        let token = CancellationToken::new();
+        token.cancel();
+
+        let mut exec = division();
+        exec.set_cancellation_token(token);
+        let summary = exec.exec_with_timeout(Duration::from_secs(10)).unwrap();
+        assert!(summary.cancelled && !summary.ended);
+    }
+
+    #[test]
+    fn csv_trace() {
+        use crate::parse::{jit, DefaultSet};
+
+        let out = crate::TestStdio::new(vec![]);
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #5\nSTO 201 // save it, twice\nADD ACC,201\nLDD 500\n\n201 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.set_hook(CsvTrace::new(out.clone()).with_memory([201]));
+        exec.exec_and_report();
+
+        assert_eq!(
+            out.try_to_string().unwrap(),
+            "step,addr,opcode,op,acc,ix,ar,cmp,201,writes,error,comment
+1,0,LDM,#5,5,0,0,false,0,,,
+2,1,STO,201,5,0,0,false,5,201=5,,\"save it, twice\"
+3,2,ADD,\"ACC,201\",10,0,0,false,5,,,
+4,3,LDD,500,10,0,0,false,5,,Invalid memory address `500`,
+"
+        );
+    }
+
+    #[test]
+    fn exit() {
+        use crate::parse::{jit, DefaultSet};
+
+        let run = |src: &str| {
+            jit::<DefaultSet>(src, crate::make_io!(std::io::empty(), std::io::sink()))
+                .unwrap()
+                .exec_and_report()
+        };
+
+        assert!(matches!(run("LDM #1\nEND\nINC ACC\n\nNONE:\n"), Exit::End));
+        assert!(matches!(run("LDM #1\nINC ACC\n\nNONE:\n"), Exit::FellOff));
+        assert!(matches!(
+            run("LDD 500\nEND\n\nNONE:\n"),
+            Exit::Error(fault) if matches!(fault.error, RtError::InvalidAddr(500)) && fault.addr == 0
+        ));
+    }
+
+    #[test]
+    fn runtime_fault() {
+        use crate::parse::{jit, DefaultSet};
+
+        let src = "LDM #3\nADD #2\nLDX 200\nEND\n\nNONE:\n";
+        let out = crate::TestStdio::new(vec![]);
+        let mut exec =
+            jit::<DefaultSet>(src, crate::make_io!(std::io::empty(), out.clone())).unwrap();
+
+        let fault = exec.exec().unwrap_err();
+
+        // The error is only written to the output by `exec_and_report`
+        assert!(out.to_vec().is_empty());
+
+        assert!(matches!(fault.error, RtError::InvalidIndexedAddr { .. }));
+        assert_eq!(fault.addr, 2);
+        assert_eq!(fault.mnemonic, "LDX");
+        assert_eq!(fault.op, Op::Addr(200));
+        assert_eq!(fault.context.acc, 5);
+        assert!(fault.to_string().ends_with("at address 2 (`LDX 200`)"));
+
+        let mut exec =
+            jit::<DefaultSet>(src, crate::make_io!(std::io::empty(), out.clone())).unwrap();
+        assert!(matches!(exec.exec_and_report(), Exit::Error(_)));
+        assert!(!out.to_vec().is_empty());
+    }
+
+    #[test]
+    fn context_snapshot() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #5\nSTO X\nMOV r1,ACC\nEND\n\nX: 0\n201 7\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.exec_and_report();
+
+        let snapshot = exec.context_snapshot();
+        assert_eq!(snapshot.mem, [(0, 5), (201, 7)].into());
+        assert_eq!(
+            snapshot.to_string(),
+            "ACC: 5\nIX: 0\nAR: 0\nCMP: false\nr1: 5\n0 (X): 5\n201: 7\n"
+        );
+
+        exec.ctx.radix = Radix::Hex;
+        assert!(exec.context_snapshot().to_string().starts_with("ACC: &5\n"));
+    }
+
+    #[test]
+    fn text_trace() {
+        use crate::parse::{jit, DefaultSet};
+
+        let out = crate::TestStdio::new(vec![]);
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #5\nSTO 201\nCMP #5\nLDD 500\n\n201 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.set_hook(TextTrace::new(out.clone()));
+        exec.exec_and_report();
+
+        assert_eq!(
+            out.try_to_string().unwrap(),
+            "   1    0  LDM #5            ACC=5
+   2    1  STO 201           [201]=5
+   3    2  CMP #5            CMP=true
+   4    3  LDD 500           error: Invalid memory address `500`
+"
+        );
+    }
+
+    #[test]
+    fn cancellation() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #1\nINC ACC\nINC ACC\nEND\n\nNONE:\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+
+        let token = CancellationToken::new();
+        exec.set_cancellation_token(token.clone());
+
+        assert!(matches!(exec.step(), Status::Continue));
+        token.cancel();
+
+        // Stops before the next instruction, and keeps the state
+        assert!(matches!(exec.step(), Status::Cancelled));
+        assert!(matches!(exec.run_until_break(), Status::Cancelled));
+        exec.exec_and_report();
+        assert_eq!((exec.ctx.acc, exec.ctx.mar, exec.count()), (1, 1, 1));
+
+        token.reset();
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.acc, 3);
+    }
+
+    #[test]
+    fn json_trace() {
+        use crate::parse::{jit, DefaultSet};
+
+        let out = crate::TestStdio::new(vec![]);
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #5\nSTO X // \"X\" marks the spot\nLDD 500\n\nX: 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.set_hook(JsonTrace::new(out.clone()));
+        exec.exec_and_report();
+
+        let gprs = ["0"; 30].join(",");
+        let lines = out.try_to_string().unwrap();
+
+        assert_eq!(
+            lines.lines().collect::<Vec<_>>(),
+            [
+                format!(
+                    r##"{{"addr":0,"opcode":"LDM","op":"#5","comment":null,"acc":5,"ix":0,"ar":0,"cmp":false,"gprs":[{gprs}],"writes":[],"error":null}}"##
+                ),
+                format!(
+                    r#"{{"addr":1,"opcode":"STO","op":"1","comment":"\"X\" marks the spot","acc":5,"ix":0,"ar":0,"cmp":false,"gprs":[{gprs}],"writes":[[1,5]],"error":null}}"#
+                ),
+                format!(
+                    r#"{{"addr":2,"opcode":"LDD","op":"500","comment":null,"acc":5,"ix":0,"ar":0,"cmp":false,"gprs":[{gprs}],"writes":[],"error":"Invalid memory address `500`"}}"#
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn trace_timing() {
+        use crate::parse::{jit, DefaultSet};
+
+        let exec = || {
+            jit::<DefaultSet>(
+                "LDM #5\nEND\n\nNONE:\n",
+                crate::make_io!(std::io::empty(), std::io::sink()),
+            )
+            .unwrap()
+        };
+
+        let out = crate::TestStdio::new(vec![]);
+        let mut text = exec();
+        text.set_hook(TextTrace::new(out.clone()).with_timing());
+        text.exec_and_report();
+        let text = out.try_to_string().unwrap();
+        let line = text.lines().next().unwrap();
+
+        assert!(line.starts_with("   1    0  LDM #5            "));
+        assert!(line.ends_with("s  ACC=5"));
+
+        let out = crate::TestStdio::new(vec![]);
+        let mut json = exec();
+        json.set_hook(JsonTrace::new(out.clone()).with_timing());
+        json.exec_and_report();
+
+        for line in out.try_to_string().unwrap().lines() {
+            let (_, time) = line.split_once(r#""time_ns":"#).unwrap();
+            let (time, _) = time.split_once(',').unwrap();
+            assert!(time.parse::<u128>().is_ok());
+        }
+    }
+
+    #[test]
+    fn hooks() {
+        use crate::parse::{jit, DefaultSet};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl ExecHook for Recorder {
+            fn before(&mut self, inst: &HookInst<'_>, ctx: &Context) {
+                self.0.lock().unwrap().push(format!(
+                    "{} {} {} acc={}",
+                    inst.addr, inst.opcode, inst.op, ctx.acc
+                ));
+            }
+
+            fn after(&mut self, _: &HookInst<'_>, ctx: &Context, res: &RtResult) {
+                assert!(res.is_ok());
+                self.0.lock().unwrap().push(format!("acc={}", ctx.acc));
+            }
+        }
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #1\nINC ACC\nEND\n\nNONE:\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+
+        let recorder = Recorder::default();
+        let log = recorder.0.clone();
+
+        exec.set_hook(recorder);
+        exec.exec_and_report();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "0 LDM #1 acc=0",
+                "acc=1",
+                "1 INC ACC acc=1",
+                "acc=2",
+                "2 END  acc=2",
+                "acc=2"
+            ]
+        );
+        assert!(exec.take_hook().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn step_over_out() {
+        use crate::parse::{jit, DefaultSet};
+
+        let src = "CALL A\nINC ACC\nEND\nA: INC r1\nCALL B\nRET\nB: INC r2\nRET\n\nNONE:\n";
+        let make = || jit::<DefaultSet>(src, crate::make_io!(std::io::empty(), std::io::sink()));
+
+        let mut exec = make().unwrap();
+        assert!(matches!(exec.step_over(), Status::Continue));
+        assert_eq!(exec.ctx.mar, 1);
+        assert_eq!(exec.ctx.call_depth(), 0);
+        assert_eq!((exec.ctx.gprs[1], exec.ctx.gprs[2]), (1, 1));
+
+        let mut exec = make().unwrap();
+        exec.set_history_limit(8);
+        exec.step();
+        exec.step();
+        assert_eq!((exec.ctx.mar, exec.ctx.call_depth()), (4, 1));
+
+        assert!(matches!(exec.step_over(), Status::Continue));
+        assert_eq!((exec.ctx.mar, exec.ctx.call_depth()), (5, 1));
+        assert_eq!(exec.ctx.gprs[2], 1);
+
+        assert!(matches!(exec.step_out(), Status::Continue));
+        assert_eq!((exec.ctx.mar, exec.ctx.call_depth()), (1, 0));
+
+        // Returning is undone along with the call stack
+        assert_eq!(exec.step_back(1), 1);
+        assert_eq!(
+            (exec.ctx.mar, exec.ctx.ret, exec.ctx.call_stack.len()),
+            (5, 1, 1)
+        );
+
+        // Breakpoints inside the function stop stepping
+        exec.add_breakpoint(1);
+        assert!(matches!(exec.step_out(), Status::Continue));
+        assert!(matches!(exec.step_over(), Status::Breakpoint(1)));
+
+        // Outside of a function, runs to the end
+        assert!(matches!(exec.step_out(), Status::Complete));
+        assert_eq!(exec.ctx.acc, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn mul_div_mod() {
+        use crate::parse::{jit, DefaultSet};
+
+        let run = |src: &str, signed: bool| {
+            let mut exec = jit::<DefaultSet>(
+                format!("{src}\nEND\n\n200 7\n201 0\n"),
+                crate::make_io!(std::io::empty(), std::io::sink()),
+            )
+            .unwrap();
+            exec.ctx.signed = signed;
+            let res = exec
+                .exec_with_timeout(std::time::Duration::from_secs(1))
+                .map_err(|fault| fault.error);
+
+            (res, exec.ctx)
+        };
+
+        let (_, ctx) = run(
+            "LDM #6\nMUL 200\nMUL r1,ACC,#2\nDIV 201,r1,#5\nMOD r2,r1,#5",
+            false,
+        );
+        assert_eq!((ctx.acc, ctx.gprs[1], ctx.gprs[2]), (42, 84, 4));
+        assert_eq!(ctx.mem.get(&201).copied().unwrap(), 16);
+
+        // Rounds towards zero, and the remainder has the sign of the dividend
+        let (_, ctx) = run("LDM #0\nSUB #7\nMOV r1,ACC\nDIV #2\nMOD r1,#2", true);
+        assert_eq!(
+            (ctx.to_signed(ctx.acc), ctx.to_signed(ctx.gprs[1])),
+            (-3, -1)
+        );
+
+        let (res, _) = run("LDM #1\nDIV #0", false);
+        assert!(matches!(res, Err(RtError::DivisionByZero)));
+
+        let (res, _) = run("LDM #1\nMOD r1", false);
+        assert!(matches!(res, Err(RtError::DivisionByZero)));
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn ordered_jumps() {
+        use crate::parse::{jit, DefaultSet};
+
+        // Find the largest of 5 values
+        const MAX: &str = "LDM #0\nLDR #0\nLOOP: CMI PTR\nJGE NEXT\nLDI PTR\nNEXT: INC PTR\nINC IX\nCMP IX,#5\nJPL LOOP\nEND\n\nPTR: 100\n100 3\n101 9\n102 2\n103 9\n104 7\n";
+
+        let mut exec =
+            jit::<DefaultSet>(MAX, crate::make_io!(std::io::empty(), std::io::sink())).unwrap();
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.acc, 9);
+
+        for (a, b, signed, taken) in [
+            (2, 3, false, "JPL JLE"),
+            (3, 3, false, "JGE JLE"),
+            (4, 3, false, "JPG JGE"),
+            // -1 is less than 3 only when signed
+            (usize::MAX, 3, false, "JPG JGE"),
+            (usize::MAX, 3, true, "JPL JLE"),
+        ] {
+            for inst in ["JPG", "JPL", "JGE", "JLE"] {
+                let mut exec = jit::<DefaultSet>(
+                    format!("CMP r1,r2\n{inst} 3\nEND\nNOP\n\nNONE:\n"),
+                    crate::make_io!(std::io::empty(), std::io::sink()),
+                )
+                .unwrap();
+                exec.ctx.gprs[1] = a;
+                exec.ctx.gprs[2] = b;
+                exec.ctx.signed = signed;
+                exec.exec_and_report();
+
+                assert_eq!(exec.ctx.mar == 4, taken.contains(inst), "{a} {inst} {b}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn stack() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM SP,#102\nPUSH #1\nPUSH #2\nPUSH #3\nEND\n\n100 [0;2]\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.set_history_limit(2);
+
+        assert!(matches!(
+            exec.run_until_break(),
+            Status::Error(RtError::StackOverflow(100))
+        ));
+        assert_eq!(exec.ctx.sp, 100);
+        assert_eq!(exec.ctx.mem.get(&100).copied().unwrap(), 2);
+
+        // The failed push changed nothing
+        assert_eq!(exec.step_back(2), 2);
+        assert_eq!(exec.ctx.sp, 101);
+        assert_eq!(exec.ctx.mem.get(&100).copied().unwrap(), 0);
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM SP,#99\nPUSH #5\nPOP r1\nPOP\nEND\n\n98 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            exec.run_until_break(),
+            Status::Error(RtError::StackUnderflow(99))
+        ));
+        assert_eq!(exec.ctx.gprs[1], 5);
+
+        // A label named `SP` is used instead of the register
+        let mut exec = jit::<DefaultSet>(
+            "LDD SP\nEND\n\nSP: 5\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.exec_and_report();
+
+        assert_eq!(exec.ctx.acc, 5);
+    }
+
+    #[test]
+    fn signed() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #2\nSUB #5\nCMP #1\nEND\n\nNONE:\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.to_signed(exec.ctx.acc), -3);
+        assert!(!exec.ctx.lt);
+
+        exec.ctx.signed = true;
+        exec.ctx.compare(exec.ctx.acc, 1);
+        assert!(exec.ctx.lt);
+
+        // Sign extended from the display width
+        exec.ctx.set_width(Some(Width::W8));
+        assert_eq!(exec.ctx.to_signed(0b1111_1101), -3);
+        assert_eq!(exec.ctx.to_signed(0b0000_0101), 5);
+        assert_eq!(exec.ctx.display(0b1111_1101).to_string(), "-3");
+
+        exec.ctx.radix = Radix::Bin;
+        assert_eq!(exec.ctx.display(0b1111_1101).to_string(), "B11111101");
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn signed_io() {
+        use crate::parse::{jit, DefaultSet};
+
+        let out = crate::TestStdio::new(vec![]);
+        let mut exec = jit::<DefaultSet>(
+            "RIN\nSUB #3\nOUTN\nNL\nDBG ACC\nEND\n\nNONE:\n",
+            crate::make_io!(crate::TestStdio::new(&b"-2\n"[..]), out.clone()),
+        )
+        .unwrap();
+        exec.ctx.signed = true;
+        exec.ctx.set_width(Some(Width::W8));
+
+        exec.exec_and_report();
+        assert_eq!(out.try_to_string().unwrap(), "-5\n-5\n");
+    }
+
+    #[test]
+    fn word_width() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDD 200\nADD #10\nSTO 201\nLDM #300\nEND\n\n200 250\n201 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.ctx.set_width(Some(Width::W8));
+
+        for _ in 0..3 {
+            exec.step();
+        }
+        assert_eq!(exec.ctx.acc, 4);
+        assert_eq!(exec.ctx.mem.get(&201).copied().unwrap(), 4);
+
+        exec.step();
+        assert_eq!(exec.ctx.acc, 44);
+
+        // Existing values are wrapped too
+        exec.ctx.mem = Memory::new([(200, 0x1234)].into());
+        exec.ctx.set_width(Some(Width::W8));
+        assert_eq!(exec.ctx.mem.get(&200).copied().unwrap(), 0x34);
+
+        let word = exec.ctx.word();
+        assert!(word.fits(255) && !word.fits(256) && !word.fits(-1));
+
+        exec.ctx.signed = true;
+        let word = exec.ctx.word();
+        assert!(word.fits(127) && word.fits(-128) && !word.fits(128));
+        assert_eq!(word.value(0xFF), -1);
+    }
+
+    #[test]
+    fn negative_literals() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #-5\nCMP #-5\nJPN 0\nADD #-x10\nSTO 200\nEND\n\n200 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.ctx.set_width(Some(Width::W8));
+        exec.ctx.signed = true;
+        exec.exec_and_report();
+
+        assert_eq!(exec.ctx.mem.get(&200).copied().unwrap(), 0xEB);
+        assert_eq!(exec.ctx.to_signed(exec.ctx.acc), -21);
+        assert_eq!(exec.prog[0].op.to_string(), "#-5");
+        assert_eq!(Op::try_from("#-5"), Ok(Op::Literal(5_usize.wrapping_neg())));
+    }
+
+    #[test]
+    fn ex_tree() {
+        let inst = || ExecInst::new(0, "END", io::end, Op::Null);
+
+        let dense = [(1, inst()), (0, inst())].into_iter().collect::<ExTree>();
+        assert!(matches!(dense.0, inst::Insts::Dense(_)));
+        assert_eq!(dense.keys().collect::<Vec<_>>(), [0, 1]);
+
+        let sparse = [(0, inst()), (2, inst())].into_iter().collect::<ExTree>();
+        assert!(matches!(sparse.0, inst::Insts::Sparse(_)));
+        assert_eq!(sparse.keys().collect::<Vec<_>>(), [0, 2]);
+        assert!(sparse.get(1).is_none() && sparse.get(2).is_some());
+
+        let mut addrs = Vec::new();
+        for (addr, _) in &sparse {
+            addrs.push(addr);
+        }
+        assert_eq!(addrs, [0, 2]);
+        assert_eq!(
+            dense.into_iter().map(|(addr, _)| addr).collect::<Vec<_>>(),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn memory() {
+        let mut mem = Memory::new([(0, 1), (200, 2), (201, 3), (202, 4), (500, 5)].into());
+
+        assert_eq!(
+            mem.iter()
+                .map(|(&addr, &val)| (addr, val))
+                .collect::<Vec<_>>(),
+            [(0, 1), (200, 2), (201, 3), (202, 4), (500, 5)]
+        );
+        assert_eq!(mem.range(), Some(0..=500));
+        #[allow(deprecated)]
+        let before = mem.inner().get(&201).copied();
+        assert_eq!(before, Some(3));
+
+        *mem.get_mut(&201).unwrap() = 30;
+        *mem.get_mut(&500).unwrap() = 50;
+        assert_eq!(
+            (mem.get(&201).ok(), mem.get(&500).ok()),
+            (Some(&30), Some(&50))
+        );
+        #[allow(deprecated)]
+        let after = mem.inner().get(&201).copied();
+        assert_eq!(after, Some(30));
+        assert!(mem.get(&203).is_err() && mem.get_mut(&1).is_err());
+
+        let end = Memory::new([(usize::MAX - 1, 1), (usize::MAX, 2)].into());
+        assert_eq!(end.range(), Some(usize::MAX - 1..=usize::MAX));
+        assert_eq!(end.get(&usize::MAX).ok(), Some(&2));
+    }
+
+    #[test]
+    fn run_for() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #0\nLOOP: INC ACC\nCMP #5\nJPN LOOP\nEND\n\nNONE:\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+
+        assert!(matches!(exec.run_for(4), Status::Continue));
+        assert_eq!((exec.count(), exec.ctx.acc), (4, 1));
+
+        exec.add_breakpoint(4);
+        assert!(matches!(exec.run_for(100), Status::Breakpoint(4)));
+        assert!(matches!(exec.run_for(0), Status::Continue));
+        assert!(matches!(exec.run_for(100), Status::Complete));
+        assert_eq!(exec.ctx.acc, 5);
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn exec_async() {
+        use crate::parse::{jit, DefaultSet};
+        use std::{
+            future::Future,
+            pin::Pin,
+            sync::Arc,
+            task::{Context as TaskContext, Poll, Wake, Waker},
+        };
+
+        struct Noop;
+
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        /// Gives out one chunk every other time it is polled
+        struct Chunks(Vec<&'static [u8]>, bool);
+
+        impl AsyncInput for Chunks {
+            fn poll_read(
+                &mut self,
+                _: &mut TaskContext<'_>,
+                buf: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                self.1 = !self.1;
+
+                if self.1 {
+                    return Poll::Pending;
+                }
+
+                let chunk = if self.0.is_empty() {
+                    &[][..]
+                } else {
+                    self.0.remove(0)
+                };
+                buf[..chunk.len()].copy_from_slice(chunk);
+
+                Poll::Ready(Ok(chunk.len()))
+            }
+        }
+
+        struct Never;
+
+        impl AsyncInput for Never {
+            fn poll_read(
+                &mut self,
+                _: &mut TaskContext<'_>,
+                _: &mut [u8],
+            ) -> Poll<std::io::Result<usize>> {
+                Poll::Pending
+            }
+        }
+
+        let out = crate::TestStdio::new(vec![]);
+        let mut exec = jit::<DefaultSet>(
+            "IN\nOUT\nRIN\nOUTN\nRIN\nEND\n\nNONE:\n",
+            crate::make_io!(std::io::empty(), out.clone()),
+        )
+        .unwrap();
+
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = TaskContext::from_waker(&waker);
+        let mut run = exec.exec_async(Chunks(vec![b"a", b"1", b"2\n"], false));
+
+        let mut polls = 0;
+        let exit = loop {
+            polls += 1;
+
+            if let Poll::Ready(exit) = Pin::new(&mut run).poll(&mut cx) {
+                break exit;
+            }
+        };
+
+        drop(run);
+
+        // The second `RIN` reaches the end of the input, and instructions waiting for input are
+        // only counted once
+        assert!(exit.is_err());
+        assert_eq!(polls, 5);
+        assert!(out.try_to_string().unwrap().starts_with("a12"));
+        assert_eq!(exec.count(), 5);
+
+        // Waiting for input stops once cancelled
+        let mut exec = jit::<DefaultSet>("IN\nEND\n\nNONE:\n", crate::make_io!()).unwrap();
+        let token = CancellationToken::new();
+        exec.set_cancellation_token(token.clone());
+
+        let mut run = exec.exec_async(Never);
+        assert!(Pin::new(&mut run).poll(&mut cx).is_pending());
+
+        token.cancel();
+        assert!(matches!(
+            Pin::new(&mut run).poll(&mut cx),
+            Poll::Ready(Ok(ExecSummary {
+                cancelled: true,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn debug_info() {
+        use crate::parse::{jit, DefaultSet};
+
+        let exec = jit::<DefaultSet>(
+            "LDM #0\nLOOP: INC ACC // count\nSTO X\nJMP LOOP\n\nX: 0\n",
+            crate::make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        let debug_info = &exec.debug_info;
+
+        assert_eq!(debug_info.label_for_inst(1), Some("LOOP"));
+        assert_eq!(debug_info.label_for_inst(0), None);
+        assert!(
+            matches!(exec.prog[2].op, Op::Addr(addr) if debug_info.label_for_mem(addr) == Some("X"))
+        );
+        assert_eq!(debug_info.span_for_inst(2), Some(&(30..35)));
+        assert_eq!(debug_info.comment(1), Some("count"));
+        assert_eq!(
+            debug_info.source_line(1).map(|l| (l.line, l.text.as_str())),
+            Some((2, "LOOP: INC ACC // count"))
+        );
+
+        assert!(exec
+            .display_with_opcodes()
+            .contains("     1: LOOP: INC ACC // count\n"));
+    }
+
+    #[test]
+    fn builder() {
+        use crate::parse::{jit, DefaultSet};
+
+        let prog = jit::<DefaultSet>("LDM #250\nADD #10\nJMP 1\n\nNONE:\n", crate::make_io!())
+            .unwrap()
+            .prog;
+
+        let mut exec = Executor::builder(prog)
+            .with_width(Width::W8)
+            .with_step_limit(5)
+            .with_breakpoint(2)
+            .build();
+
+        assert!(matches!(exec.step(), Status::Continue));
+        assert!(matches!(exec.step(), Status::Continue));
+        assert_eq!(exec.ctx.acc, 4);
+        assert!(matches!(exec.step(), Status::Breakpoint(2)));
+
+        let fault = exec.exec().unwrap_err();
+        assert!(matches!(fault.error, RtError::StepLimit(5)));
+        assert_eq!(exec.count(), 5);
+    }
+
+    #[test]
+    fn mem_size() {
+        use crate::parse::{jit, DefaultSet};
+
+        let prog = jit::<DefaultSet>("LDD 8\nSTO 7\nEND\n\n7 0\n8 5\n", crate::make_io!())
+            .unwrap()
+            .prog;
+
+        let mut exec = Executor::builder(prog.clone())
+            .with_mem(Memory::new([(7, 0), (8, 5)].into()))
+            .with_mem_size(8)
+            .build();
+
+        let fault = exec.exec().unwrap_err();
+        assert!(matches!(fault.error, RtError::InvalidAddr(8)));
+
+        let mut exec = Executor::builder(prog)
+            .with_mem(Memory::new([(7, 0), (8, 5)].into()))
+            .with_mem_size(9)
+            .build();
+
+        exec.exec().unwrap();
+        assert_eq!(exec.ctx.mem.get(&7).copied().unwrap(), 5);
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn dmp() {
+        use crate::parse::{jit, DefaultSet};
+
+        let out = crate::TestStdio::new(vec![]);
+        let mut exec = jit::<DefaultSet>(
+            "DMP 200\nLDM #2\nDMP 200,ACC\nDMP\nEND\n\n200 5\n201 12\n210 255\n",
+            crate::make_io!(std::io::empty(), out.clone()),
+        )
+        .unwrap();
+        exec.ctx.set_width(Some(Width::W8));
+        exec.ctx.signed = true;
+        exec.exec().unwrap();
+
+        assert_eq!(
+            out.try_to_string().unwrap(),
+            "\
+200:  5 12 -- -- -- -- -- --
+200:  5 12
+200:  5 12 -- -- -- -- -- --
+208: -- -- -1
+"
+        );
+    }
+
+    #[test]
+    fn mem_from_file() {
+        let mut mem = Memory::new([(200, 1), (201, 2)].into());
+        mem.set_size(Some(256));
+        mem.extend(&Memory::from_csv("201,7\n\n202, 8\n").unwrap());
+        mem.extend(&Memory::from_bin(b"ab", 210));
+
+        assert_eq!(
+            mem.iter()
+                .map(|(&addr, &val)| (addr, val))
+                .collect::<Vec<_>>(),
+            [(200, 1), (201, 7), (202, 8), (210, 97), (211, 98)]
+        );
+        assert_eq!(mem.size(), Some(256));
+
+        assert_eq!(
+            Memory::from_csv("1,2\n3\n").unwrap_err(),
+            CsvError::Syntax(2)
+        );
+        assert!(matches!(
+            Memory::from_csv("address,value\nx,1\n"),
+            Err(CsvError::Int(2, _))
+        ));
+    }
+
+    #[test]
+    fn write_log() {
+        use crate::parse::{jit, DefaultSet};
+
+        let mut exec = jit::<DefaultSet>(
+            "LDM #5\nSTO 201\nLDM #7\nSTO 200\nSTO 201\nEND\n\n200 1\n201 2\n",
+            crate::make_io!(),
+        )
+        .unwrap();
+        exec.set_history_limit(8);
+        exec.ctx.mem.set_logging(true);
+        exec.exec().unwrap();
+
+        let write = |step, addr, old, new| MemWrite {
+            step,
+            addr,
+            old,
+            new,
+        };
+
+        assert_eq!(
+            exec.ctx.mem.writes_to(201).copied().collect::<Vec<_>>(),
+            [write(2, 201, 2, 5), write(5, 201, 5, 7)]
+        );
+        assert_eq!(exec.ctx.mem.write_log().len(), 3);
+
+        exec.step_back(2);
+        assert_eq!(
+            exec.ctx.mem.write_log(),
+            [write(2, 201, 2, 5), write(4, 200, 1, 7)]
+        );
+
+        exec.ctx.mem.set_logging(false);
+        assert!(exec.ctx.mem.write_log().is_empty());
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn mem_types() {
+        use crate::parse::{jit, DefaultSet};
+
+        let out = crate::TestStdio::new(vec![]);
+        let mut exec = jit::<DefaultSet>(
+            "LDD 200\nINC ACC\nSTO 200\nDBG\nDMP 200,#3\nEND\n\n200 \"HI\"\n202 5\n",
+            crate::make_io!(std::io::empty(), out.clone()),
+        )
+        .unwrap();
+        exec.exec().unwrap();
+
+        let out = out.try_to_string().unwrap();
+        assert!(out.contains("     200: \"II\",\n     202: 5,\n"));
+        assert!(out.ends_with("200: 'I' 'I'   5\n"));
+        assert_eq!(exec.ctx.mem.get(&200).ok(), Some(&73));
+
+        let mut mem = Memory::from_bin(&[10, 20], 0);
+        mem.set_type(1, MemType::Word);
+        exec.ctx.radix = Radix::Hex;
+        exec.ctx.mem = mem;
+
+        assert_eq!(
+            exec.ctx.mem.entries(),
+            [(0, MemEntry::Byte(10)), (1, MemEntry::Word(20))]
+        );
+        assert_eq!(exec.ctx.dump_mem(0..2), "0: &0A &14\n");
     }
 }
@@ -16,7 +16,7 @@ pub fn ldm(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         MultiOp(ops) => match ops[..] {
             [ref op, Literal(val)] if op.is_register() => {
-                *ctx.get_mut_register(op) = val;
+                *ctx.try_get_mut_register(op)? = val;
                 Ok(())
             }
             _ => Err(InvalidMultiOp),
@@ -44,7 +44,7 @@ pub fn ldd(ctx: &mut Context, op: &Op) -> RtResult {
         }
         MultiOp(ops) => match ops[..] {
             [ref reg, Addr(ref addr)] if reg.is_register() => {
-                *ctx.get_mut_register(reg) = ctx.mem.get(addr).copied()?;
+                *ctx.try_get_mut_register(reg)? = ctx.mem.get(addr).copied()?;
                 Ok(())
             }
             _ => Err(InvalidMultiOp),
@@ -80,7 +80,7 @@ pub fn ldi(ctx: &mut Context, op: &Op) -> RtResult {
             [ref reg, Addr(addr)] if reg.is_register() => {
                 let addr2 = ctx.mem.get(&addr)?;
 
-                *ctx.get_mut_register(reg) =
+                *ctx.try_get_mut_register(reg)? =
                     ctx.mem
                         .get(addr2)
                         .copied()
@@ -98,6 +98,16 @@ pub fn ldi(ctx: &mut Context, op: &Op) -> RtResult {
     }
 }
 
+fn indexed(ctx: &Context, addr: usize) -> RtResult<usize> {
+    addr.checked_add(ctx.ix)
+        .and_then(|src| ctx.mem.get(&src).ok())
+        .copied()
+        .ok_or(InvalidIndexedAddr {
+            src: addr,
+            offset: ctx.ix,
+        })
+}
+
 /// Load value from memory using indexed addressing into register
 ///
 /// # Syntax
@@ -107,27 +117,13 @@ pub fn ldi(ctx: &mut Context, op: &Op) -> RtResult {
 pub fn ldx(ctx: &mut Context, op: &Op) -> RtResult {
     match op {
         &Addr(addr) => {
-            ctx.acc = ctx
-                .mem
-                .get(&(addr + ctx.ix))
-                .copied()
-                .map_err(|_| InvalidIndexedAddr {
-                    src: addr,
-                    offset: ctx.ix,
-                })?;
+            ctx.acc = indexed(ctx, addr)?;
 
             Ok(())
         }
         MultiOp(ops) => match ops[..] {
             [ref reg, Addr(addr)] if reg.is_register() => {
-                *ctx.get_mut_register(reg) =
-                    ctx.mem
-                        .get(&(addr + ctx.ix))
-                        .copied()
-                        .map_err(|_| InvalidIndexedAddr {
-                            src: addr,
-                            offset: ctx.ix,
-                        })?;
+                *ctx.try_get_mut_register(reg)? = indexed(ctx, addr)?;
 
                 Ok(())
             }
@@ -169,7 +165,7 @@ pub fn mov(ctx: &mut Context, op: &Op) -> RtResult {
             }
             _ => return Err(InvalidMultiOp),
         },
-        reg if reg.is_register() => *ctx.get_mut_register(reg) = ctx.acc,
+        reg if reg.is_register() => *ctx.try_get_mut_register(reg)? = ctx.acc,
         Null => return Err(NoOperand),
         _ => return Err(InvalidOperand),
     }
@@ -192,3 +188,52 @@ pub fn sto(ctx: &mut Context, op: &Op) -> RtResult {
         _ => Err(InvalidOperand),
     }
 }
+
+/// Push a value onto the stack
+///
+/// The stack grows downwards from `SP`, which must first be set to one past the top of a region
+/// of memory, e.g. `LDM SP,#110` for `100 [0;10]`. Pushing below the region is a stack overflow.
+///
+/// # Syntax
+/// 1. `PUSH` - push `ACC`
+/// 2. `PUSH [lit | reg | addr]`
+#[cfg(feature = "extended")]
+pub fn push(ctx: &mut Context, op: &Op) -> RtResult {
+    let val = match op {
+        Null => ctx.acc,
+        src if src.is_usizeable() => ctx.read(src)?,
+        _ => return Err(InvalidOperand),
+    };
+
+    let sp = ctx.sp.checked_sub(1).ok_or(StackOverflow(ctx.sp))?;
+    *ctx.mem.get_mut(&sp).map_err(|_| StackOverflow(ctx.sp))? = val;
+    ctx.sp = sp;
+
+    Ok(())
+}
+
+/// Pop a value off the stack
+///
+/// Popping above the stack region is a stack underflow.
+///
+/// # Syntax
+/// 1. `POP` - pop to `ACC`
+/// 2. `POP [reg | addr]`
+#[cfg(feature = "extended")]
+pub fn pop(ctx: &mut Context, op: &Op) -> RtResult {
+    let val = ctx
+        .mem
+        .get(&ctx.sp)
+        .copied()
+        .map_err(|_| StackUnderflow(ctx.sp))?;
+
+    match op {
+        Null => ctx.acc = val,
+        dest if dest.is_read_write() => ctx.modify(dest, |d| *d = val)?,
+        _ => return Err(InvalidOperand),
+    }
+
+    ctx.sp += 1;
+
+    Ok(())
+}
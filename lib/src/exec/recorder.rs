@@ -0,0 +1,130 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{Context, ExecHook, HookInst, Memory, RtResult};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Write as _},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+#[derive(Default)]
+struct Recorded {
+    capacity: usize,
+    trace: VecDeque<String>,
+    /// Registers after the last instruction
+    registers: String,
+    /// Memory after the last instruction, copied once and then kept up to date with writes
+    mem: Option<Memory>,
+}
+
+/// [`ExecHook`] that remembers the last instructions executed and the current state, for
+/// diagnosing crashes
+///
+/// Clones share the same record, so a clone can be kept to [dump](FlightRecorder::dump) the
+/// record after the executor panics, e.g. from a panic hook.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{exec::FlightRecorder, make_io, parse::{jit, DefaultSet}};
+///
+/// let mut exec = jit::<DefaultSet>("LDM #5\nSTO 200\nEND\n\n200 0\n", make_io!())?;
+/// let recorder = FlightRecorder::new(16);
+/// exec.set_hook(recorder.clone());
+/// exec.exec_and_report();
+///
+/// assert!(recorder.to_string().contains("200: 5"));
+/// # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+/// ```
+#[derive(Clone)]
+pub struct FlightRecorder(Arc<Mutex<Recorded>>);
+
+impl FlightRecorder {
+    /// Remember the last `capacity` instructions
+    pub fn new(capacity: usize) -> Self {
+        Self(Arc::new(Mutex::new(Recorded {
+            capacity,
+            ..Recorded::default()
+        })))
+    }
+}
+
+impl ExecHook for FlightRecorder {
+    fn before(&mut self, inst: &HookInst<'_>, ctx: &Context) {
+        let mut rec = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if rec.mem.is_none() {
+            rec.mem = Some(ctx.mem.clone());
+        }
+
+        if rec.trace.len() == rec.capacity {
+            rec.trace.pop_front();
+        }
+
+        if rec.capacity > 0 {
+            let op = inst.op.display_aliased(&ctx.aliases);
+            let mut line = format!("{:>6}: {} {op}", inst.addr, inst.opcode);
+
+            if let Some(comment) = inst.comment {
+                let _ = write!(line, " // {comment}");
+            }
+
+            rec.trace.push_back(line);
+        }
+    }
+
+    fn after(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) {
+        let mut rec = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let rec = &mut *rec;
+
+        if let (Err(e), Some(line)) = (res, rec.trace.back_mut()) {
+            // Writing to a `String` cannot fail
+            let _ = write!(line, "  <- {e}");
+        }
+
+        rec.registers.clear();
+        let _ = write!(
+            rec.registers,
+            "MAR={} ACC={} IX={} AR={} SP={} CMP={} CALL DEPTH={}",
+            ctx.mar,
+            ctx.acc,
+            ctx.ix,
+            ctx.ret,
+            ctx.sp,
+            ctx.cmp,
+            ctx.call_depth()
+        );
+
+        if let Some(mem) = &mut rec.mem {
+            for addr in inst.writes {
+                if let (Ok(dest), Ok(&val)) = (mem.get_mut(addr), ctx.mem.get(addr)) {
+                    *dest = val;
+                }
+            }
+        }
+    }
+}
+
+impl Display for FlightRecorder {
+    /// The recorded instructions, oldest first, followed by the registers and memory
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rec = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        writeln!(f, "Last {} instructions:", rec.trace.len())?;
+
+        for line in &rec.trace {
+            writeln!(f, "{line}")?;
+        }
+
+        writeln!(f, "\nRegisters:\n{}", rec.registers)?;
+        writeln!(f, "\nMemory:")?;
+
+        for (addr, val) in rec.mem.iter().flatten() {
+            writeln!(f, "{addr:>6}: {val}")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{Context, Memory, Radix, Width};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Snapshot of an in-flight execution
+///
+/// Made with [`Executor::snapshot`](super::Executor::snapshot) and resumed with
+/// [`Executor::restore`](super::Executor::restore). Only the state of the execution is saved, so
+/// it must be restored into an [`Executor`](super::Executor) made from the same program.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ExecutorState {
+    pub cmp: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lt: bool,
+    pub mar: usize,
+    pub acc: usize,
+    pub ix: usize,
+    pub flow_override_reg: bool,
+    pub mem: Memory,
+    pub ret: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sp: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub call_stack: Vec<usize>,
+    pub gprs: [usize; 30],
+    pub end: bool,
+    pub radix: Radix,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub width: Option<Width>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub signed: bool,
+    /// Number of instructions executed so far
+    pub count: u64,
+}
+
+impl ExecutorState {
+    pub(crate) fn new(ctx: &Context, count: u64) -> Self {
+        Self {
+            cmp: ctx.cmp,
+            lt: ctx.lt,
+            mar: ctx.mar,
+            acc: ctx.acc,
+            ix: ctx.ix,
+            flow_override_reg: ctx.flow_override_reg,
+            mem: ctx.mem.clone(),
+            ret: ctx.ret,
+            sp: ctx.sp,
+            call_stack: ctx.call_stack.clone(),
+            gprs: ctx.gprs,
+            end: ctx.end,
+            radix: ctx.radix,
+            width: ctx.width,
+            signed: ctx.signed,
+            count,
+        }
+    }
+
+    /// Overwrite the state of `ctx`, returning the instruction count
+    pub(crate) fn apply(self, ctx: &mut Context) -> u64 {
+        ctx.cmp = self.cmp;
+        ctx.lt = self.lt;
+        ctx.mar = self.mar;
+        ctx.acc = self.acc;
+        ctx.ix = self.ix;
+        ctx.flow_override_reg = self.flow_override_reg;
+        ctx.mem = self.mem;
+        ctx.ret = self.ret;
+        ctx.sp = self.sp;
+        ctx.call_stack = self.call_stack;
+        ctx.gprs = self.gprs;
+        ctx.end = self.end;
+        ctx.radix = self.radix;
+        ctx.width = self.width;
+        ctx.signed = self.signed;
+
+        self.count
+    }
+}
+
+/// Registers and memory of a [`Context`], e.g. after a program has finished
+///
+/// Made with [`Executor::context_snapshot`](super::Executor::context_snapshot). Unlike
+/// [`ExecutorState`], it only holds what a program can change, and cannot be restored. It is
+/// displayed with [`Context::radix`] and [`Context::width`], leaving out general purpose
+/// registers that are zero:
+///
+/// ```text
+/// ACC: 5
+/// IX: 0
+/// AR: 0
+/// CMP: false
+/// r1: 3
+/// 200 (X): 5
+/// 201: 0
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextSnapshot {
+    pub acc: usize,
+    pub ix: usize,
+    pub ar: usize,
+    pub cmp: bool,
+    pub gprs: [usize; 30],
+    pub mem: BTreeMap<usize, usize>,
+    /// Labels of memory addresses, from the [`DebugInfo`](super::DebugInfo)
+    pub labels: BTreeMap<usize, String>,
+    /// Names of general purpose registers, see [`Context::aliases`]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub aliases: BTreeMap<usize, String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    radix: Radix,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    width: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    signed: bool,
+}
+
+impl ContextSnapshot {
+    pub(crate) fn new(ctx: &Context, labels: BTreeMap<usize, String>) -> Self {
+        Self {
+            acc: ctx.acc,
+            ix: ctx.ix,
+            ar: ctx.ret,
+            cmp: ctx.cmp,
+            gprs: ctx.gprs,
+            mem: ctx.mem.iter().map(|(&addr, &val)| (addr, val)).collect(),
+            labels,
+            aliases: ctx.aliases.clone(),
+            radix: ctx.radix,
+            width: ctx.display_bits(),
+            signed: ctx.signed,
+        }
+    }
+}
+
+impl Display for ContextSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fmt = |val| {
+            if self.signed {
+                self.radix.display_signed(val, self.width).to_string()
+            } else {
+                self.radix.display_width(val, self.width).to_string()
+            }
+        };
+
+        writeln!(f, "ACC: {}", fmt(self.acc))?;
+        writeln!(f, "IX: {}", fmt(self.ix))?;
+        writeln!(f, "AR: {}", fmt(self.ar))?;
+        writeln!(f, "CMP: {}", self.cmp)?;
+
+        for (idx, &val) in self.gprs.iter().enumerate() {
+            if val != 0 {
+                match self.aliases.get(&idx) {
+                    Some(name) => writeln!(f, "{name}: {}", fmt(val))?,
+                    None => writeln!(f, "r{idx}: {}", fmt(val))?,
+                }
+            }
+        }
+
+        for (addr, &val) in &self.mem {
+            match self.labels.get(addr) {
+                Some(label) => writeln!(f, "{addr} ({label}): {}", fmt(val))?,
+                None => writeln!(f, "{addr}: {}", fmt(val))?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::{self, Read, Write},
+    sync::{
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, TryRecvError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Marker carried by the [`io::Error`] returned when a [`TimeoutReader`] times out
+///
+/// Used to tell input timeouts apart from other [`io::ErrorKind::TimedOut`] errors when
+/// converting to [`RtError`](super::RtError)
+#[derive(Debug)]
+pub(crate) struct InputTimedOut;
+
+impl Display for InputTimedOut {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("timed out while waiting for input")
+    }
+}
+
+impl Error for InputTimedOut {}
+
+impl InputTimedOut {
+    pub(crate) fn is_cause_of(err: &io::Error) -> bool {
+        matches!(err.get_ref(), Some(e) if e.is::<Self>())
+    }
+}
+
+/// Marker carried by the [`io::Error`] returned when a [`Limited`] stream exceeds its limit
+#[derive(Debug)]
+pub(crate) enum LimitExceeded {
+    Input(u64),
+    Output(u64),
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Input(limit) => write!(f, "more than {limit} bytes of input read"),
+            Self::Output(limit) => write!(f, "more than {limit} bytes of output written"),
+        }
+    }
+}
+
+impl Error for LimitExceeded {}
+
+impl LimitExceeded {
+    pub(crate) fn cause_of(err: &io::Error) -> Option<&Self> {
+        err.get_ref().and_then(|e| e.downcast_ref())
+    }
+}
+
+/// Reader or writer that fails with an error once more than `limit` bytes pass through it
+///
+/// Use [`Io::set_input_limit`](super::Io::set_input_limit) and
+/// [`Io::set_output_limit`](super::Io::set_output_limit) to apply this to an [`Io`](super::Io).
+pub struct Limited<T> {
+    inner: T,
+    limit: u64,
+    used: u64,
+    /// Byte read past the limit to check for more input, kept so that it can be handed back by
+    /// [`Limited::into_parts`]
+    peeked: Option<u8>,
+}
+
+impl<T> Limited<T> {
+    pub fn new(inner: T, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            used: 0,
+            peeked: None,
+        }
+    }
+
+    /// The wrapped stream, and the byte read from it past the limit, if any
+    ///
+    /// Reading past the limit takes one byte from the wrapped reader to check whether there is
+    /// more input. That byte comes before any input left in the wrapped reader.
+    pub fn into_parts(self) -> (T, Option<u8>) {
+        (self.inner, self.peeked)
+    }
+
+    /// Number of bytes that may still pass through
+    #[allow(clippy::cast_possible_truncation)]
+    fn remaining(&self, want: usize) -> usize {
+        (self.limit - self.used).min(want as u64) as usize
+    }
+}
+
+impl<R: Read> Read for Limited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.used == self.limit && !buf.is_empty() {
+            // Only fail if there actually is more input
+            if self.peeked.is_none() {
+                let mut byte = [0];
+
+                if self.inner.read(&mut byte)? == 0 {
+                    return Ok(0);
+                }
+
+                self.peeked = Some(byte[0]);
+            }
+
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                LimitExceeded::Input(self.limit),
+            ));
+        }
+
+        let len = self.remaining(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.used += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Limited<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.used == self.limit && !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                LimitExceeded::Output(self.limit),
+            ));
+        }
+
+        let len = self.remaining(buf.len());
+        let n = self.inner.write(&buf[..len])?;
+        self.used += n as u64;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that fails with an error instead of blocking forever
+///
+/// The wrapped reader is moved to a background thread, and reads wait at most `timeout` for it
+/// to produce data. If the wrapped reader blocks forever, the background thread is leaked, since
+/// there is no portable way to cancel a blocking read.
+///
+/// Use [`Io::set_input_timeout`](super::Io::set_input_timeout) to apply this to an [`Io`](super::Io).
+pub struct TimeoutReader {
+    rx: Mutex<Receiver<io::Result<Vec<u8>>>>,
+    buf: Vec<u8>,
+    pos: usize,
+    timeout: Duration,
+    eof: bool,
+}
+
+/// Move `inner` to a background thread, which sends what it reads in chunks, and an empty chunk
+/// at the end of the input
+fn read_in_background(mut inner: impl Read + Send + 'static) -> Receiver<io::Result<Vec<u8>>> {
+    const CHUNK_SIZE: usize = 1024;
+
+    let (tx, rx) = sync_channel(1);
+
+    thread::spawn(move || loop {
+        let mut buf = vec![0; CHUNK_SIZE];
+
+        match inner.read(&mut buf) {
+            Ok(n) => {
+                buf.truncate(n);
+
+                // Stop when the receiver is gone or the reader is exhausted
+                if tx.send(Ok(buf)).is_err() || n == 0 {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                let _ = tx.send(Err(e));
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+impl TimeoutReader {
+    pub fn new(inner: impl Read + Send + 'static, timeout: Duration) -> Self {
+        Self {
+            rx: Mutex::new(read_in_background(inner)),
+            buf: Vec::new(),
+            pos: 0,
+            timeout,
+            eof: false,
+        }
+    }
+}
+
+impl Read for TimeoutReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            if self.eof {
+                return Ok(0);
+            }
+
+            let rx = self
+                .rx
+                .lock()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "input thread panicked"))?;
+
+            match rx.recv_timeout(self.timeout) {
+                Ok(chunk) => {
+                    self.buf = chunk?;
+                    self.pos = 0;
+
+                    if self.buf.is_empty() {
+                        self.eof = true;
+                        return Ok(0);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, InputTimedOut));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.eof = true;
+                    return Ok(0);
+                }
+            }
+        }
+
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+/// Capability of I/O providers that can tell whether input is available without blocking
+///
+/// Used by the `INKEY` instruction to check for key presses
+pub trait InputPoll: Send + Sync {
+    /// Whether there is input that can be read without blocking
+    fn is_ready(&mut self) -> io::Result<bool>;
+}
+
+/// Reader whose input can be checked for without blocking, with [`InputPoll`]
+///
+/// The wrapped reader is moved to a background thread, which reads ahead of the program. Clones
+/// share the same input.
+///
+/// Use [`Io::set_polled_input`](super::Io::set_polled_input) to apply this to an [`Io`](super::Io).
+#[derive(Clone)]
+pub struct PolledReader(Arc<Mutex<PolledState>>);
+
+struct PolledState {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl PolledReader {
+    pub fn new(inner: impl Read + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(PolledState {
+            rx: read_in_background(inner),
+            buf: Vec::new(),
+            pos: 0,
+            eof: false,
+        })))
+    }
+
+    fn with<U>(&self, f: impl FnOnce(&mut PolledState) -> U) -> U {
+        f(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl PolledState {
+    /// Replace the exhausted buffer with `chunk`, returning whether it has any input
+    fn refill(&mut self, chunk: io::Result<Vec<u8>>) -> io::Result<bool> {
+        self.buf = chunk?;
+        self.pos = 0;
+        self.eof = self.buf.is_empty();
+
+        Ok(!self.eof)
+    }
+}
+
+impl Read for PolledReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.with(|state| {
+            if state.pos == state.buf.len() {
+                if state.eof {
+                    return Ok(0);
+                }
+
+                // The background thread only stops without sending an empty chunk after an error
+                let chunk = state.rx.recv().unwrap_or_else(|_| Ok(Vec::new()));
+
+                if !state.refill(chunk)? {
+                    return Ok(0);
+                }
+            }
+
+            let len = out.len().min(state.buf.len() - state.pos);
+            out[..len].copy_from_slice(&state.buf[state.pos..state.pos + len]);
+            state.pos += len;
+
+            Ok(len)
+        })
+    }
+}
+
+impl InputPoll for PolledReader {
+    fn is_ready(&mut self) -> io::Result<bool> {
+        self.with(|state| {
+            if state.pos < state.buf.len() {
+                return Ok(true);
+            }
+
+            if state.eof {
+                return Ok(false);
+            }
+
+            match state.rx.try_recv() {
+                Ok(chunk) => state.refill(chunk),
+                Err(TryRecvError::Empty) => Ok(false),
+                Err(TryRecvError::Disconnected) => state.refill(Ok(Vec::new())),
+            }
+        })
+    }
+}
+
+/// Capability of I/O providers that can stop input from being echoed back
+///
+/// Used by the `INS` instruction to read passwords. Set with
+/// [`Io::set_echo_control`](super::Io::set_echo_control).
+pub trait EchoControl: Send + Sync {
+    fn set_echo(&mut self, enabled: bool) -> io::Result<()>;
+}
+
+/// Output buffer shared with an executor, so that it can be read after execution
+#[derive(Clone, Default)]
+pub(crate) struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Capture {
+    /// Take everything written so far
+    pub(crate) fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
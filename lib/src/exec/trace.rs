@@ -0,0 +1,417 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{Context, ExecHook, HookInst, RtResult};
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+/// [`ExecHook`] that writes one line of JSON for every executed instruction
+///
+/// Each line contains the address, opcode, operand and comment of the instruction, the registers
+/// after it was executed, the memory cells it wrote to, and the error it caused, if any:
+///
+/// ```json
+/// {"addr":1,"opcode":"STO","op":"201","comment":"save it","acc":5,"ix":0,"ar":0,"cmp":false,"gprs":[0,...],"writes":[[201,5]],"error":null}
+/// ```
+///
+/// With [`JsonTrace::with_timing`], each line also contains the wall time the instruction took,
+/// in nanoseconds, e.g. `"time_ns":1200`.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{exec::JsonTrace, make_io, parse::{jit, DefaultSet}};
+///
+/// let mut exec = jit::<DefaultSet>("LDM #5\nEND\n\nNONE:\n", make_io!())?;
+/// exec.set_hook(JsonTrace::new(std::io::sink()));
+/// exec.exec_and_report();
+/// # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+/// ```
+pub struct JsonTrace<W> {
+    out: W,
+    timing: bool,
+    line: String,
+    failed: bool,
+}
+
+impl<W: Write + Send + Sync> JsonTrace<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            timing: false,
+            line: String::new(),
+            failed: false,
+        }
+    }
+
+    /// Include the time each instruction took
+    #[must_use]
+    pub fn with_timing(mut self) -> Self {
+        self.timing = true;
+        self
+    }
+
+    fn write_line(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) -> io::Result<()> {
+        let line = &mut self.line;
+        line.clear();
+
+        // Writing to a `String` cannot fail
+        let _ = write!(line, r#"{{"addr":{},"opcode":"#, inst.addr);
+        push_str(line, inst.opcode);
+        line.push_str(r#","op":"#);
+        push_str(line, &inst.op.to_string());
+        line.push_str(r#","comment":"#);
+
+        match inst.comment {
+            Some(comment) => push_str(line, comment),
+            None => line.push_str("null"),
+        }
+
+        let _ = write!(
+            line,
+            r#","acc":{},"ix":{},"ar":{},"cmp":{},"gprs":["#,
+            ctx.acc, ctx.ix, ctx.ret, ctx.cmp
+        );
+
+        for (idx, val) in ctx.gprs.iter().enumerate() {
+            let sep = if idx == 0 { "" } else { "," };
+            let _ = write!(line, "{sep}{val}");
+        }
+
+        line.push_str(r#"],"writes":["#);
+
+        for (idx, addr) in inst.writes.iter().enumerate() {
+            let sep = if idx == 0 { "" } else { "," };
+            let val = ctx.mem.get(addr).copied().unwrap_or_default();
+            let _ = write!(line, "{sep}[{addr},{val}]");
+        }
+
+        line.push(']');
+
+        if self.timing {
+            let _ = write!(line, r#","time_ns":{}"#, inst.elapsed.as_nanos());
+        }
+
+        line.push_str(r#","error":"#);
+
+        match res {
+            Ok(()) => line.push_str("null"),
+            Err(e) => push_str(line, &e.to_string()),
+        }
+
+        line.push_str("}\n");
+
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+impl<W: Write + Send + Sync> ExecHook for JsonTrace<W> {
+    fn after(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) {
+        if let Err(e) = self.write_line(inst, ctx, res) {
+            // Warn once instead of for every instruction
+            if !self.failed {
+                warn!("Unable to write trace: {e}");
+                self.failed = true;
+            }
+        }
+    }
+}
+
+/// [`ExecHook`] that writes a CSV trace table, with one row for every executed instruction
+///
+/// The layout can be opened in spreadsheets and classroom simulators. Each row contains the step
+/// number, the address, opcode and operand of the instruction, the registers after it was
+/// executed, the watched memory cells, the memory cells it wrote to as `addr=value` pairs
+/// separated by `;`, the error it caused, if any, and the comment on its line:
+///
+/// ```csv
+/// step,addr,opcode,op,acc,ix,ar,cmp,201,writes,error,comment
+/// 1,0,LDM,#5,5,0,0,false,0,,,
+/// 2,1,STO,201,5,0,0,false,5,201=5,,save it
+/// ```
+///
+/// With [`CsvTrace::with_timing`], a last `time_ns` column contains the wall time each
+/// instruction took, in nanoseconds.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{exec::CsvTrace, make_io, parse::{jit, DefaultSet}};
+///
+/// let mut exec = jit::<DefaultSet>("LDM #5\nSTO 201\nEND\n\n201 0\n", make_io!())?;
+/// exec.set_hook(CsvTrace::new(std::io::sink()).with_memory([201]));
+/// exec.exec_and_report();
+/// # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+/// ```
+pub struct CsvTrace<W> {
+    out: W,
+    memory: Vec<usize>,
+    timing: bool,
+    step: usize,
+    line: String,
+    failed: bool,
+}
+
+impl<W: Write + Send + Sync> CsvTrace<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            memory: Vec::new(),
+            timing: false,
+            step: 0,
+            line: String::new(),
+            failed: false,
+        }
+    }
+
+    /// Add a column for each of these memory cells
+    #[must_use]
+    pub fn with_memory(mut self, addrs: impl IntoIterator<Item = usize>) -> Self {
+        self.memory.extend(addrs);
+        self
+    }
+
+    /// Add a column for the time each instruction took
+    #[must_use]
+    pub fn with_timing(mut self) -> Self {
+        self.timing = true;
+        self
+    }
+
+    fn write_line(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) -> io::Result<()> {
+        let line = &mut self.line;
+        line.clear();
+
+        if self.step == 0 {
+            line.push_str("step,addr,opcode,op,acc,ix,ar,cmp");
+
+            for addr in &self.memory {
+                let _ = write!(line, ",{addr}");
+            }
+
+            line.push_str(",writes,error,comment");
+
+            if self.timing {
+                line.push_str(",time_ns");
+            }
+
+            line.push('\n');
+        }
+
+        self.step += 1;
+
+        // Writing to a `String` cannot fail
+        let _ = write!(line, "{},{},", self.step, inst.addr);
+        push_field(line, inst.opcode);
+        line.push(',');
+        push_field(line, &inst.op.to_string());
+        let _ = write!(line, ",{},{},{},{}", ctx.acc, ctx.ix, ctx.ret, ctx.cmp);
+
+        for addr in &self.memory {
+            let _ = write!(line, ",{}", ctx.mem.get(addr).copied().unwrap_or_default());
+        }
+
+        line.push(',');
+
+        for (idx, addr) in inst.writes.iter().enumerate() {
+            let sep = if idx == 0 { "" } else { ";" };
+            let val = ctx.mem.get(addr).copied().unwrap_or_default();
+            let _ = write!(line, "{sep}{addr}={val}");
+        }
+
+        line.push(',');
+
+        if let Err(e) = res {
+            push_field(line, &e.to_string());
+        }
+
+        line.push(',');
+        push_field(line, inst.comment.unwrap_or_default());
+
+        if self.timing {
+            let _ = write!(line, ",{}", inst.elapsed.as_nanos());
+        }
+
+        line.push('\n');
+
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+impl<W: Write + Send + Sync> ExecHook for CsvTrace<W> {
+    fn after(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) {
+        if let Err(e) = self.write_line(inst, ctx, res) {
+            if !self.failed {
+                warn!("Unable to write trace: {e}");
+                self.failed = true;
+            }
+        }
+    }
+}
+
+/// [`ExecHook`] that writes one line of text for every executed instruction
+///
+/// Each line contains the step number, the address and code of the instruction, and the
+/// registers and memory cells it changed, with values displayed in the radix of the [`Context`]:
+///
+/// ```text
+///    1    0  LDM #5            ACC=5
+///    2    1  STO 201           [201]=5
+///    3    2  LDD 500           error: Invalid memory address `500`
+/// ```
+///
+/// With [`TextTrace::with_timing`], the wall time each instruction took follows its code:
+///
+/// ```text
+///    1    0  LDM #5                1.2µs  ACC=5
+/// ```
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{exec::TextTrace, make_io, parse::{jit, DefaultSet}};
+///
+/// let mut exec = jit::<DefaultSet>("LDM #5\nEND\n\nNONE:\n", make_io!())?;
+/// exec.set_hook(TextTrace::new(std::io::sink()));
+/// exec.exec_and_report();
+/// # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+/// ```
+pub struct TextTrace<W> {
+    out: W,
+    step: usize,
+    /// `ACC`, `IX`, `AR` and the general purpose registers before the current instruction
+    regs: Vec<usize>,
+    cmp: bool,
+    timing: bool,
+    line: String,
+    failed: bool,
+}
+
+impl<W: Write + Send + Sync> TextTrace<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            step: 0,
+            regs: Vec::new(),
+            cmp: false,
+            timing: false,
+            line: String::new(),
+            failed: false,
+        }
+    }
+
+    /// Include the time each instruction took
+    #[must_use]
+    pub fn with_timing(mut self) -> Self {
+        self.timing = true;
+        self
+    }
+
+    fn regs(ctx: &Context) -> impl Iterator<Item = (String, usize)> + '_ {
+        [("ACC", ctx.acc), ("IX", ctx.ix), ("AR", ctx.ret)]
+            .into_iter()
+            .map(|(name, val)| (name.to_string(), val))
+            .chain(
+                ctx.gprs
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, &val)| (ctx.gpr_name(idx).into_owned(), val)),
+            )
+    }
+
+    fn write_line(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) -> io::Result<()> {
+        let line = &mut self.line;
+        line.clear();
+
+        self.step += 1;
+
+        let code = format!("{} {}", inst.opcode, inst.op.display_aliased(&ctx.aliases));
+        // Writing to a `String` cannot fail
+        let _ = write!(
+            line,
+            "{:>4} {:>4}  {:<16}",
+            self.step,
+            inst.addr,
+            code.trim_end()
+        );
+
+        if self.timing {
+            let _ = write!(line, " {:>10}", format!("{:?}", inst.elapsed));
+        }
+
+        for ((name, val), &before) in Self::regs(ctx).zip(&self.regs) {
+            if val != before {
+                let _ = write!(line, "  {name}={}", ctx.display(val));
+            }
+        }
+
+        if ctx.cmp != self.cmp {
+            let _ = write!(line, "  CMP={}", ctx.cmp);
+        }
+
+        for addr in inst.writes {
+            let val = ctx.mem.get(addr).copied().unwrap_or_default();
+            let _ = write!(line, "  [{addr}]={}", ctx.display(val));
+        }
+
+        if let Err(e) = res {
+            let _ = write!(line, "  error: {e}");
+        }
+
+        let len = line.trim_end().len();
+        line.truncate(len);
+        line.push('\n');
+
+        self.out.write_all(line.as_bytes())
+    }
+}
+
+impl<W: Write + Send + Sync> ExecHook for TextTrace<W> {
+    fn before(&mut self, _: &HookInst<'_>, ctx: &Context) {
+        self.regs.clear();
+        self.regs.extend(Self::regs(ctx).map(|(_, val)| val));
+        self.cmp = ctx.cmp;
+    }
+
+    fn after(&mut self, inst: &HookInst<'_>, ctx: &Context, res: &RtResult) {
+        if let Err(e) = self.write_line(inst, ctx, res) {
+            if !self.failed {
+                warn!("Unable to write trace: {e}");
+                self.failed = true;
+            }
+        }
+    }
+}
+
+/// Push `s` as a CSV field, quoted only if needed
+fn push_field(out: &mut String, s: &str) {
+    if s.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&s.replace('"', r#""""#));
+        out.push('"');
+    } else {
+        out.push_str(s);
+    }
+}
+
+/// Push `s` as a quoted JSON string
+fn push_str(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str(r#"\""#),
+            '\\' => out.push_str(r"\\"),
+            '\n' => out.push_str(r"\n"),
+            '\r' => out.push_str(r"\r"),
+            '\t' => out.push_str(r"\t"),
+            c if c.is_control() => {
+                let _ = write!(out, r"\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
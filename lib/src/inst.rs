@@ -5,8 +5,11 @@
 
 #![allow(clippy::module_name_repetitions)]
 
-use crate::exec::{ExecFunc, ExecInst};
-use std::{fmt::Display, ops::Deref, str::FromStr};
+use crate::{
+    exec::{ExecFunc, ExecInst},
+    parse::ErrorKind,
+};
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -20,8 +23,10 @@ pub enum Op {
     Ix,
     Cmp,
     Ar,
+    Sp,
     Indirect(Box<Op>),
     Addr(usize),
+    /// Negative literals are stored in two's complement, and displayed as negative
     Literal(usize),
     Gpr(usize),
     MultiOp(Vec<Op>),
@@ -36,7 +41,7 @@ impl Op {
     pub fn is_register(&self) -> bool {
         match self {
             Op::Indirect(op) if op.is_register() => true,
-            _ => matches!(self, Op::Acc | Op::Ix | Op::Ar | Op::Gpr(_)),
+            _ => matches!(self, Op::Acc | Op::Ix | Op::Ar | Op::Sp | Op::Gpr(_)),
         }
     }
 
@@ -51,6 +56,21 @@ impl Op {
     pub fn is_usizeable(&self) -> bool {
         self.is_read_write() || matches!(self, Op::Literal(_))
     }
+
+    /// Like [`Display`], but with general purpose registers named by `aliases`, by register
+    /// number
+    pub fn display_aliased(&self, aliases: &BTreeMap<usize, String>) -> String {
+        match self {
+            Op::Gpr(r) => aliases.get(r).cloned().unwrap_or_else(|| self.to_string()),
+            Op::Indirect(op) => format!("({})", op.display_aliased(aliases)),
+            Op::MultiOp(ops) => ops
+                .iter()
+                .map(|op| op.display_aliased(aliases))
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => self.to_string(),
+        }
+    }
 }
 
 impl Display for Op {
@@ -64,7 +84,9 @@ impl Display for Op {
             Ix => "IX".into(),
             Cmp => "CMP".into(),
             Ar => "AR".into(),
+            Sp => "SP".into(),
             Addr(x) => format!("{x}"),
+            Literal(x) if *x > isize::MAX.unsigned_abs() => format!("#-{}", x.wrapping_neg()),
             Literal(x) => format!("#{x}"),
             Indirect(op) => format!("({op})"),
             Fail(x) => x.clone(),
@@ -80,76 +102,146 @@ impl Display for Op {
     }
 }
 
-fn get_literal(mut op: String) -> usize {
-    if op.starts_with('#') {
-        op.remove(0);
+fn get_literal(op: &str) -> Result<usize, ErrorKind> {
+    let lit = op.strip_prefix('#').ok_or(ErrorKind::InvalidOperand)?;
 
-        match op.chars().next().unwrap() {
-            'b' | 'B' => {
-                op.remove(0);
-                usize::from_str_radix(&op, 2).unwrap()
-            }
-            'x' | 'X' => {
-                op.remove(0);
-                usize::from_str_radix(&op, 16).unwrap()
-            }
-            'o' | 'O' => {
-                op.remove(0);
-                usize::from_str_radix(&op, 8).unwrap()
-            }
-            '0'..='9' => op.parse().unwrap(),
-            _ => unreachable!(),
-        }
-    } else {
-        panic!("Literal `{op}` is invalid")
+    if let Some(abs) = lit.strip_prefix('-') {
+        return get_literal(&format!("#{abs}")).map(usize::wrapping_neg);
     }
-}
 
-fn get_reg_no(mut op: String) -> usize {
-    op = op.to_lowercase();
-    op.remove(0);
+    let (radix, digits) = match lit.chars().next() {
+        Some('b' | 'B') => (2, &lit[1..]),
+        Some('x' | 'X' | '&') => (16, &lit[1..]),
+        Some('o' | 'O') => (8, &lit[1..]),
+        Some('0'..='9') => (10, lit),
+        _ => return Err(ErrorKind::InvalidOperand),
+    };
+
+    Ok(usize::from_str_radix(digits, radix)?)
+}
 
-    // Ensured by parser
-    op.parse().unwrap()
+fn get_reg_no(op: &str) -> Result<usize, ErrorKind> {
+    match op[1..].parse()? {
+        n @ 0..=29 => Ok(n),
+        _ => Err(ErrorKind::InvalidOperand),
+    }
 }
 
-impl<T: Deref<Target = str>> From<T> for Op {
-    fn from(inp: T) -> Self {
-        fn get_op(inp: &str) -> Op {
+impl TryFrom<&str> for Op {
+    type Error = ErrorKind;
+
+    /// Parse an operand, or several separated by commas
+    fn try_from(inp: &str) -> Result<Self, Self::Error> {
+        fn get_op(inp: &str) -> Result<Op, ErrorKind> {
             #[allow(clippy::enum_glob_use)]
             use Op::*;
 
-            if inp.is_empty() {
+            let op = if inp.is_empty() {
                 Null
             } else if let Ok(x) = inp.parse() {
                 Addr(x)
             } else if inp.contains('#') {
-                Literal(get_literal(inp.into()))
-            } else if inp.to_lowercase().starts_with('r')
-                && inp.trim_start_matches('r').chars().all(char::is_numeric)
+                Literal(get_literal(inp)?)
+            } else if inp.starts_with(['r', 'R'])
+                && inp.len() > 1
+                && inp[1..].chars().all(|c| c.is_ascii_digit())
             {
-                let x = get_reg_no(inp.into());
-
-                if x > 29 {
-                    panic!("Only registers from r0 to r29 are allowed")
-                } else {
-                    Gpr(x)
-                }
+                Gpr(get_reg_no(inp)?)
             } else {
                 match inp.to_lowercase().as_str() {
                     "acc" => Acc,
                     "cmp" => Cmp,
                     "ix" => Ix,
+                    "ar" => Ar,
+                    "sp" => Sp,
                     _ => Fail(inp.into()),
                 }
-            }
+            };
+
+            Ok(op)
         }
 
         if inp.contains(',') {
-            Op::MultiOp(inp.split(',').map(get_op).collect())
+            inp.split(',')
+                .map(get_op)
+                .collect::<Result<_, _>>()
+                .map(Op::MultiOp)
         } else {
-            get_op(&inp)
+            get_op(inp)
+        }
+    }
+}
+
+/// Kind of operand accepted by an instruction, as in the `# Syntax` section of its docs
+///
+/// Labels count as addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// `lit`
+    Lit,
+    /// `reg`
+    Reg,
+    /// `addr`, not indirect
+    Addr,
+    /// `reg | addr`, including indirect operands
+    Dest,
+    /// `lit | reg | addr`, including indirect operands
+    Val,
+}
+
+impl Operand {
+    pub fn matches(self, op: &Op) -> bool {
+        fn is_addr(op: &Op) -> bool {
+            matches!(op, Op::Addr(_) | Op::Fail(_))
         }
+
+        fn is_dest(op: &Op) -> bool {
+            match op {
+                Op::Indirect(op) => is_dest(op),
+                op => op.is_register() || is_addr(op),
+            }
+        }
+
+        match self {
+            Self::Lit => matches!(op, Op::Literal(_)),
+            Self::Reg => op.is_register(),
+            Self::Addr => is_addr(op),
+            Self::Dest => is_dest(op),
+            Self::Val => is_dest(op) || matches!(op, Op::Literal(_)),
+        }
+    }
+}
+
+/// One accepted form of operands, e.g. `[Dest, Val]` for `ADD r1,#1`, or `[]` for no operand
+pub type OpShape = &'static [Operand];
+
+/// Every form of operands an instruction accepts, or `None` if it is not checked
+pub type OperandSpec = Option<&'static [OpShape]>;
+
+/// Where execution can continue after an instruction
+///
+/// Jump targets are the addresses in the instruction's operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Always continues to the next instruction
+    Next,
+    /// Continues to the next instruction or jumps to a target
+    Branch,
+    /// Always jumps to a target
+    Jump,
+    /// Never continues, e.g. `END` or `RET`
+    Stop,
+}
+
+/// Whether `op` has the form of `shape`
+pub fn fits(shape: OpShape, op: &Op) -> bool {
+    match (shape, op) {
+        ([], op) => op.is_none(),
+        (kinds, Op::MultiOp(ops)) => {
+            kinds.len() == ops.len() && kinds.iter().zip(ops).all(|(k, op)| k.matches(op))
+        }
+        ([kind], op) => !op.is_none() && kind.matches(op),
+        _ => false,
     }
 }
 
@@ -164,22 +256,57 @@ where
     fn as_func_ptr(&self) -> ExecFunc;
     fn id(&self) -> u64;
     fn from_id(_: u64) -> Result<Self, <Self as FromStr>::Err>;
+
+    /// Forms of operands the instruction accepts, checked by the parser. `None` accepts
+    /// anything, leaving it to the instruction to fail when executed.
+    fn operands(&self) -> OperandSpec {
+        None
+    }
+
+    /// Where execution can go after the instruction, used to find instructions that can never
+    /// run. `None` if unknown, which keeps every instruction.
+    fn flow(&self) -> Option<Flow> {
+        None
+    }
+
+    /// Name of the instruction, as written in source
+    fn mnemonic(&self) -> &str;
+
+    /// Description of the instruction, empty if it has none
+    #[allow(clippy::unnecessary_literal_bound)]
+    fn doc(&self) -> &str {
+        ""
+    }
 }
 
 /// Macro to generate an instruction set
 ///
+/// The operands each instruction accepts can be given after a `;`, as [`Operand`]s separated by
+/// `|`, e.g. `LDM => mov::ldm; [Lit] | [Reg, Lit],`. They are checked by the parser.
+///
+/// Doc comments on an instruction are returned by [`InstSet::doc`].
+///
+/// How control leaves an instruction can be given last, as a [`Flow`] after `=>`, e.g.
+/// `JPE => cmp::jpe; [Addr] => Branch,`. It is returned by [`InstSet::flow`].
+///
 /// For an example, go to this [file](https://github.com/SaadiSave/cambridge-asm/blob/main/cambridge-asm/tests/int_test.rs)
 #[macro_export]
 macro_rules! inst_set {
-    ($(#[$outer:meta])* $vis:vis $name:ident { $( $inst:ident => $func:expr,)+ }) => {
-        inst_set! { $(#[$outer])* $vis $name use std; { $( $inst => $func,)+ } }
+    (@operands) => { None };
+    (@operands $( [ $( $kind:ident ),* ] )|+) => {
+        Some(&[ $( &[ $( $crate::inst::Operand::$kind ),* ] ),+ ])
     };
-    ($(#[$outer:meta])* $vis:vis $name:ident $using:item { $( $inst:ident => $func:expr,)+ }) => {
+    (@flow) => { None };
+    (@flow $flow:ident) => { Some($crate::inst::Flow::$flow) };
+    ($(#[$outer:meta])* $vis:vis $name:ident { $( $(#[doc = $doc:literal])* $inst:ident => $func:expr $(; $( [ $( $kind:ident ),* ] )|+ )? $(=> $flow:ident)?,)+ }) => {
+        inst_set! { $(#[$outer])* $vis $name use std; { $( $(#[doc = $doc])* $inst => $func $(; $( [ $( $kind ),* ] )|+ )? $(=> $flow)?,)+ } }
+    };
+    ($(#[$outer:meta])* $vis:vis $name:ident $using:item { $( $(#[doc = $doc:literal])* $inst:ident => $func:expr $(; $( [ $( $kind:ident ),* ] )|+ )? $(=> $flow:ident)?,)+ }) => {
         $(#[$outer])*
         #[repr(u64)]
         #[derive(Clone, Copy)]
         $vis enum $name {
-            $($inst,)+
+            $( $(#[doc = $doc])* $inst,)+
         }
 
         $(#[$outer])*
@@ -222,6 +349,30 @@ macro_rules! inst_set {
                     _ => Err(format!("0x{:X} is not a valid instruction ID", id)),
                 }
             }
+
+            fn operands(&self) -> $crate::inst::OperandSpec {
+                match self {
+                    $(Self::$inst => $crate::inst_set!(@operands $( $( [ $( $kind ),* ] )|+ )?),)+
+                }
+            }
+
+            fn flow(&self) -> Option<$crate::inst::Flow> {
+                match self {
+                    $(Self::$inst => $crate::inst_set!(@flow $($flow)?),)+
+                }
+            }
+
+            fn mnemonic(&self) -> &str {
+                match self {
+                    $(Self::$inst => stringify!($inst),)+
+                }
+            }
+
+            fn doc(&self) -> &str {
+                match self {
+                    $(Self::$inst => concat!($($doc),*).trim(),)+
+                }
+            }
         }
     };
 }
@@ -233,10 +384,10 @@ macro_rules! inst_set {
 /// Due to language limitations, do not use this macro within the same file twice
 #[macro_export]
 macro_rules! extend {
-    ($(#[$outer:meta])* $vis:vis $name:ident extends $parent:ident { $( $inst:ident => $func:expr,)+ }) => {
-        extend! { $(#[$outer])* $vis $name extends $parent use std; { $( $inst => $func,)+ } }
+    ($(#[$outer:meta])* $vis:vis $name:ident extends $parent:ident { $( $(#[doc = $doc:literal])* $inst:ident => $func:expr $(; $( [ $( $kind:ident ),* ] )|+ )? $(=> $flow:ident)?,)+ }) => {
+        extend! { $(#[$outer])* $vis $name extends $parent use std; { $( $(#[doc = $doc])* $inst => $func $(; $( [ $( $kind ),* ] )|+ )? $(=> $flow)?,)+ } }
     };
-    ($(#[$outer:meta])* $vis:vis $name:ident extends $parent:ident $using:item { $( $inst:ident => $func:expr,)+ }) => {
+    ($(#[$outer:meta])* $vis:vis $name:ident extends $parent:ident $using:item { $( $(#[doc = $doc:literal])* $inst:ident => $func:expr $(; $( [ $( $kind:ident ),* ] )|+ )? $(=> $flow:ident)?,)+ }) => {
         $(#[$outer])*
         $vis struct $name {
             __private: extend_priv::Combined<$parent>,
@@ -249,7 +400,7 @@ macro_rules! extend {
             #[repr(u64)]
             #[derive(Clone, Copy)]
             pub enum $name {
-                $($inst,)+
+                $( $(#[doc = $doc])* $inst,)+
                 #[allow(non_camel_case_types)]
                 LAST_INST_MARKER,
             }
@@ -284,6 +435,34 @@ macro_rules! extend {
                         _ => Err(format!("0x{id:X} is not a valid instruction ID")),
                     }
                 }
+
+                fn operands(&self) -> $crate::inst::OperandSpec {
+                    match self {
+                        $(Self::$inst => $crate::inst_set!(@operands $( $( [ $( $kind ),* ] )|+ )?),)+
+                        Self::LAST_INST_MARKER => None,
+                    }
+                }
+
+                fn flow(&self) -> Option<$crate::inst::Flow> {
+                    match self {
+                        $(Self::$inst => $crate::inst_set!(@flow $($flow)?),)+
+                        Self::LAST_INST_MARKER => None,
+                    }
+                }
+
+                fn mnemonic(&self) -> &'static str {
+                    match self {
+                        $(Self::$inst => stringify!($inst),)+
+                        Self::LAST_INST_MARKER => panic!("This should never happen, report this as a bug"),
+                    }
+                }
+
+                fn doc(&self) -> &'static str {
+                    match self {
+                        $(Self::$inst => concat!($($doc),*).trim(),)+
+                        Self::LAST_INST_MARKER => "",
+                    }
+                }
             }
 
             impl std::fmt::Display for $name {
@@ -328,6 +507,34 @@ macro_rules! extend {
                         Self::Parent(p) => p.as_func_ptr(),
                     }
                 }
+
+                pub fn operands(&self) -> $crate::inst::OperandSpec {
+                    match self {
+                        Self::Extension(e) => e.operands(),
+                        Self::Parent(p) => p.operands(),
+                    }
+                }
+
+                pub fn flow(&self) -> Option<$crate::inst::Flow> {
+                    match self {
+                        Self::Extension(e) => e.flow(),
+                        Self::Parent(p) => p.flow(),
+                    }
+                }
+
+                pub fn mnemonic(&self) -> &str {
+                    match self {
+                        Self::Extension(e) => e.mnemonic(),
+                        Self::Parent(p) => p.mnemonic(),
+                    }
+                }
+
+                pub fn doc(&self) -> &str {
+                    match self {
+                        Self::Extension(e) => e.doc(),
+                        Self::Parent(p) => p.doc(),
+                    }
+                }
             }
 
             impl std::str::FromStr for Combined<$parent> {
@@ -383,6 +590,22 @@ macro_rules! extend {
             fn from_id(id: u64) -> Result<Self, String> {
                 Ok( Self { __private: extend_priv::Combined::from_id(id)? })
             }
+
+            fn operands(&self) -> $crate::inst::OperandSpec {
+                self.__private.operands()
+            }
+
+            fn flow(&self) -> Option<$crate::inst::Flow> {
+                self.__private.flow()
+            }
+
+            fn mnemonic(&self) -> &str {
+                self.__private.mnemonic()
+            }
+
+            fn doc(&self) -> &str {
+                self.__private.doc()
+            }
         }
     };
 }
@@ -412,6 +635,6 @@ where
     }
 
     pub fn to_exec_inst(self) -> ExecInst {
-        ExecInst::new(self.id, self.inst.as_func_ptr(), self.op)
+        ExecInst::from_inst(&self.inst, self.op)
     }
 }
@@ -22,6 +22,24 @@ pub mod parse;
 #[cfg(feature = "compile")]
 pub mod compile;
 
+pub mod compat;
+
+#[cfg(feature = "compile")]
+pub mod embed;
+
+pub mod metrics;
+
+pub mod semantics;
+
+pub mod challenge;
+
+pub mod pool;
+
+pub mod registry;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
 #[cfg(test)]
 pub(crate) mod test_stdio {
     include!("../test_stdio.rs");
@@ -32,12 +50,19 @@ pub(crate) use test_stdio::TestStdio;
 
 #[cfg(test)]
 #[cfg(not(feature = "extended"))]
-const PROGRAMS: [(&str, usize, &[u8], &[u8]); 1] =
-    [(include_str!("../examples/hello.pasm"), 207, b"", b"HELLO\n")];
+const PROGRAMS: [(&str, usize, &[u8], &[u8]); 2] = [
+    (include_str!("../examples/hello.pasm"), 207, b"", b"HELLO\n"),
+    (
+        include_str!("../examples/strings.pasm"),
+        0,
+        b"",
+        b"Hi, there!\n",
+    ),
+];
 
 #[cfg(test)]
 #[cfg(feature = "extended")]
-const PROGRAMS: [(&str, usize, &[u8], &[u8]); 5] = [
+const PROGRAMS: [(&str, usize, &[u8], &[u8]); 8] = [
     (
         include_str!("../examples/division.pasm"),
         65,
@@ -51,7 +76,15 @@ const PROGRAMS: [(&str, usize, &[u8], &[u8]); 5] = [
         b"15625\n",
     ),
     (include_str!("../examples/hello.pasm"), 207, b"", b"HELLO\n"),
+    (
+        include_str!("../examples/strings.pasm"),
+        0,
+        b"",
+        b"Hi, there!\n",
+    ),
     (include_str!("../examples/functions.pasm"), 65, b"", b"A"),
+    (include_str!("../examples/stack.pasm"), 65, b"", b"CBA"),
+    (include_str!("../examples/lists.pasm"), 150, b"", b"150\n"),
     (
         include_str!("../examples/showoff.pasm"),
         68,
@@ -0,0 +1,75 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Opt-in counters for embedders
+//!
+//! Implement [`Metrics`] to forward the counters to a monitoring system such as Prometheus, and
+//! install it once with [`set_metrics`]. Until then, nothing is recorded and the cost is a single
+//! atomic load per instruction.
+
+use crate::{exec::RtError, parse::ErrorMap};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    RwLock,
+};
+
+/// Receives counters from the parser and executors
+///
+/// All methods do nothing by default. They may be called from several threads at once.
+pub trait Metrics: Send + Sync {
+    /// A program was parsed successfully
+    fn program_parsed(&self) {}
+
+    /// A program could not be parsed
+    #[allow(unused_variables)]
+    fn parse_failed(&self, errors: &ErrorMap) {}
+
+    /// An instruction was executed
+    fn instruction_executed(&self) {}
+
+    /// Execution stopped with an error. Use [`RtError::kind`] to group errors by kind.
+    #[allow(unused_variables)]
+    fn runtime_error(&self, err: &RtError) {}
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static METRICS: RwLock<Option<&'static dyn Metrics>> = RwLock::new(None);
+
+/// Install the global [`Metrics`] implementation, replacing the previous one
+///
+/// # Example
+/// ```
+/// use cambridge_asm::metrics::{set_metrics, Metrics};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// struct Counter(AtomicU64);
+///
+/// impl Metrics for Counter {
+///     fn instruction_executed(&self) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// static COUNTER: Counter = Counter(AtomicU64::new(0));
+///
+/// set_metrics(&COUNTER);
+/// ```
+pub fn set_metrics(metrics: &'static dyn Metrics) {
+    if let Ok(mut global) = METRICS.write() {
+        *global = Some(metrics);
+        ENABLED.store(true, Ordering::Release);
+    }
+}
+
+/// Call `f` with the global [`Metrics`], if one is installed
+#[inline]
+pub(crate) fn record(f: impl FnOnce(&dyn Metrics)) {
+    if ENABLED.load(Ordering::Acquire) {
+        if let Ok(Some(metrics)) = METRICS.read().as_deref() {
+            f(*metrics);
+        }
+    }
+}
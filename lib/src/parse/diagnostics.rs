@@ -0,0 +1,164 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::lexer::{ErrorKind, ErrorMap, Severity, Span};
+use std::{
+    fmt::{self, Display},
+    io,
+};
+
+/// An [`ErrorKind`] and where it is in the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    /// Line of the start of the span, from 1
+    pub line: usize,
+    /// Column of the start of the span in characters, from 1
+    pub column: usize,
+    pub kind: ErrorKind,
+}
+
+/// Errors and warnings from the parser, rendered as annotated snippets of the source
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{make_io, parse::{jit, DefaultSet, Diagnostics}};
+///
+/// let src = "LDM #1\nFOO\nEND\n\nNONE:\n";
+/// let errors = jit::<DefaultSet>(src, make_io!()).unwrap_err();
+///
+/// assert_eq!(
+///     Diagnostics::new(src, &errors).with_path("prog.pasm").to_string(),
+///     "error: Invalid opcode `FOO is not an instruction`
+///  --> prog.pasm:2:1
+///   |
+/// 2 | FOO
+///   | ^^^
+/// "
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostics<'a> {
+    src: &'a str,
+    path: Option<String>,
+    entries: Vec<Diagnostic>,
+}
+
+impl<'a> Diagnostics<'a> {
+    /// Locate `errors` in `src`, sorted by position
+    pub fn new(src: &'a str, errors: &ErrorMap) -> Self {
+        let mut entries = errors
+            .iter()
+            .map(|(span, kind)| {
+                let before = src.get(..span.start).unwrap_or(src);
+                let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+
+                Diagnostic {
+                    span: span.clone(),
+                    line: before.matches('\n').count() + 1,
+                    column: before[line_start..].chars().count() + 1,
+                    kind: kind.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        entries.sort_by_key(|d| (d.span.start, d.span.end));
+
+        Self {
+            src,
+            path: None,
+            entries,
+        }
+    }
+
+    /// Show `path` as the location of the source
+    #[must_use]
+    pub fn with_path(mut self, path: impl Display) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.entries.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of diagnostics with [`Severity::Error`]
+    pub fn errors(&self) -> usize {
+        self.iter()
+            .filter(|d| d.kind.severity() == Severity::Error)
+            .count()
+    }
+
+    /// Write every diagnostic to `w`
+    pub fn render(&self, w: &mut impl io::Write) -> io::Result<()> {
+        write!(w, "{self}")
+    }
+}
+
+impl Display for Diagnostics<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .iter()
+            .map(|d| d.line)
+            .max()
+            .unwrap_or_default()
+            .to_string()
+            .len();
+
+        for (i, diagnostic) in self.iter().enumerate() {
+            let Diagnostic {
+                span,
+                line,
+                column,
+                kind,
+            } = diagnostic;
+
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            let severity = match kind.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+
+            writeln!(f, "{severity}: {kind}")?;
+
+            match &self.path {
+                Some(path) => writeln!(f, "{:width$}--> {path}:{line}:{column}", "")?,
+                None => writeln!(f, "{:width$}--> {line}:{column}", "")?,
+            }
+
+            let text = self.src.lines().nth(line - 1).unwrap_or_default();
+            // Multi-line spans are only shown on their first line
+            let (prefix, rest) = text.split_at(
+                text.char_indices()
+                    .nth(column - 1)
+                    .map_or(text.len(), |(idx, _)| idx),
+            );
+            let len = rest
+                .get(..span.len().min(rest.len()))
+                .unwrap_or(rest)
+                .trim_end()
+                .chars()
+                .count();
+            // Tabs are kept so that the carets line up
+            let pad = prefix
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+
+            writeln!(f, "{:width$} |", "")?;
+            writeln!(f, "{line:>width$} | {text}")?;
+            writeln!(f, "{:width$} | {pad}{}", "", "^".repeat(len.max(1)))?;
+        }
+
+        Ok(())
+    }
+}
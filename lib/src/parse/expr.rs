@@ -0,0 +1,153 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::lexer::ErrorKind;
+use std::{iter::Peekable, str::CharIndices};
+
+/// Characters that can appear in an expression
+pub(crate) fn is_expr_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_&+-*/%() \t".contains(c)
+}
+
+/// Split `label+expression` into the label and the rest, starting with the operator
+pub(crate) fn split_label(expr: &str) -> (&str, &str) {
+    let idx = expr.find(['+', '-']).unwrap_or(expr.len());
+    expr.split_at(idx)
+}
+
+/// Evaluate an expression with `+`, `-`, `*`, `/`, `%` and brackets
+///
+/// Numbers are decimal, or hexadecimal with `&`, and names are looked up with `lookup`.
+pub(crate) fn eval(src: &str, lookup: &dyn Fn(&str) -> Option<usize>) -> Result<i128, ErrorKind> {
+    let mut eval = Eval {
+        src,
+        chars: src.char_indices().peekable(),
+        lookup,
+    };
+
+    let res = eval.sum()?;
+
+    match eval.next() {
+        None => Ok(res),
+        Some(_) => Err(ErrorKind::InvalidExpression),
+    }
+}
+
+struct Eval<'a> {
+    src: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    lookup: &'a dyn Fn(&str) -> Option<usize>,
+}
+
+impl Eval<'_> {
+    /// Next character that is not whitespace
+    fn peek(&mut self) -> Option<char> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c.is_whitespace() {
+                self.chars.next();
+            } else {
+                return Some(c);
+            }
+        }
+
+        None
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.chars.next();
+        c
+    }
+
+    fn sum(&mut self) -> Result<i128, ErrorKind> {
+        let mut res = self.product()?;
+
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.next();
+            let rhs = self.product()?;
+
+            res = match op {
+                '+' => res.checked_add(rhs),
+                _ => res.checked_sub(rhs),
+            }
+            .ok_or(ErrorKind::InvalidExpression)?;
+        }
+
+        Ok(res)
+    }
+
+    fn product(&mut self) -> Result<i128, ErrorKind> {
+        let mut res = self.unary()?;
+
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.next();
+            let rhs = self.unary()?;
+
+            res = match op {
+                '*' => res.checked_mul(rhs),
+                '/' => res.checked_div(rhs),
+                _ => res.checked_rem(rhs),
+            }
+            .ok_or(ErrorKind::InvalidExpression)?;
+        }
+
+        Ok(res)
+    }
+
+    fn unary(&mut self) -> Result<i128, ErrorKind> {
+        match self.peek() {
+            Some('-') => {
+                self.next();
+                self.unary()?
+                    .checked_neg()
+                    .ok_or(ErrorKind::InvalidExpression)
+            }
+            Some('(') => {
+                self.next();
+                let res = self.sum()?;
+
+                match self.next() {
+                    Some(')') => Ok(res),
+                    _ => Err(ErrorKind::InvalidExpression),
+                }
+            }
+            Some(_) => self.atom(),
+            None => Err(ErrorKind::InvalidExpression),
+        }
+    }
+
+    /// A number or a name
+    fn atom(&mut self) -> Result<i128, ErrorKind> {
+        let start = match self.chars.peek() {
+            Some(&(idx, _)) => idx,
+            None => return Err(ErrorKind::InvalidExpression),
+        };
+
+        let mut end = start;
+
+        while let Some(&(idx, c)) = self.chars.peek() {
+            if (idx == start && c == '&') || c.is_ascii_alphanumeric() || c == '_' {
+                end = idx + c.len_utf8();
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        let atom = &self.src[start..end];
+
+        let value = if let Some(hex) = atom.strip_prefix('&') {
+            usize::from_str_radix(&hex.replace('_', ""), 16)?
+        } else if atom.starts_with(|c: char| c.is_ascii_digit()) {
+            atom.replace('_', "").parse()?
+        } else if atom.is_empty() {
+            return Err(ErrorKind::InvalidExpression);
+        } else {
+            (self.lookup)(atom).ok_or_else(|| ErrorKind::UndefinedConstant(atom.into()))?
+        };
+
+        i128::try_from(value).map_err(|_| ErrorKind::InvalidExpression)
+    }
+}
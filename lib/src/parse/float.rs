@@ -0,0 +1,34 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::Extended;
+use crate::extend;
+
+extend! {
+    /// The floating point instruction set
+    ///
+    /// [`Extended`], plus arithmetic on IEEE 754 double precision floats (`FADD`, `FSUB`,
+    /// `FMUL`, `FDIV`), conversion to and from integers (`ITOF`, `FTOI`), and float output
+    /// (`FOUT`)
+    ///
+    /// Floats are stored as their bit patterns, so they need 64-bit words. They can be copied
+    /// and stored like any other value, but integer instructions treat them as integers.
+    pub Float extends Extended use crate::exec::float; {
+        /// Add a float to `ACC` or a destination
+        FADD => float::fadd; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Subtract a float from `ACC` or a destination
+        FSUB => float::fsub; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Multiply `ACC` or a destination by a float
+        FMUL => float::fmul; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Divide `ACC` or a destination by a float
+        FDIV => float::fdiv; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Convert the integer in `ACC` or a destination to a float
+        ITOF => float::itof; [] | [Dest] => Next,
+        /// Convert the float in `ACC` or a destination to an integer, rounding towards zero
+        FTOI => float::ftoi; [] | [Dest] => Next,
+        /// Write `ACC`, or a value, as a float
+        FOUT => float::fout; [] | [Val] | [Val, Val] => Next,
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{
+    lexer::{ErrorMap, Token},
+    refactor::{split_program, Line, Target},
+};
+
+/// A line split into columns
+struct Row {
+    /// Label, address, or a whole constant definition, which is not aligned
+    def: String,
+    code: String,
+    comment: Option<String>,
+}
+
+/// Format a program consistently, keeping its comments
+///
+/// * Labels are put in a column as wide as the longest label, in the program and the memory
+///   separately, and everything after them is aligned
+/// * Opcodes, `EQU` and `CONST` in constant definitions, and `ALIAS` in register aliases, are
+///   uppercase
+/// * Operands are separated by a comma without spaces, e.g. `ADD r1,#1`
+/// * Comments at the end of lines are aligned within each block of lines
+/// * Runs of blank lines are collapsed into one, and line endings are normalised to `\n`
+///
+/// # Example
+/// ```
+/// use cambridge_asm::parse::format;
+///
+/// let src = "ldm #0\nloop: inc acc // count\ncmp acc , #3\njpn loop // again\nend\n\n\nX: 1\n";
+///
+/// assert_eq!(
+///     format(src).unwrap(),
+///     "      LDM #0\nloop: INC acc  // count\n      CMP acc,#3\n      JPN loop // again\n      END\n\nX: 1\n"
+/// );
+/// ```
+pub fn format(src: &str) -> Result<String, ErrorMap> {
+    let (lines, prog_end) = split_program(src)?;
+
+    let rows = lines
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| row(src, line, idx < prog_end))
+        .collect::<Vec<_>>();
+
+    let mut out = String::with_capacity(src.len());
+
+    for (section, is_prog) in [(&rows[..prog_end], true), (&rows[prog_end..], false)] {
+        let def_width = section
+            .iter()
+            .filter(|r| !r.code.is_empty())
+            .map(|r| r.def.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        let blocks =
+            section.split(|r| r.def.is_empty() && r.code.is_empty() && r.comment.is_none());
+
+        for block in blocks.filter(|b| !b.is_empty()) {
+            if !out.is_empty() && !out.ends_with("\n\n") {
+                out.push('\n');
+            }
+
+            let codes = block
+                .iter()
+                .map(
+                    |Row { def, code, .. }| match (code.is_empty(), def.is_empty()) {
+                        (true, _) => def.clone(),
+                        (false, true) if is_prog && def_width > 0 => {
+                            format!("{:def_width$} {code}", "")
+                        }
+                        (false, true) => code.clone(),
+                        (false, false) => format!("{def:def_width$} {code}"),
+                    },
+                )
+                .collect::<Vec<_>>();
+
+            let comment_col = block
+                .iter()
+                .zip(&codes)
+                .filter(|(r, _)| r.comment.is_some())
+                .map(|(_, code)| code.chars().count())
+                .max()
+                .unwrap_or_default();
+
+            for (r, code) in block.iter().zip(codes) {
+                let line = match &r.comment {
+                    Some(comment) if code.is_empty() => comment.clone(),
+                    Some(comment) => format!("{code:comment_col$} {comment}"),
+                    None => code,
+                };
+
+                out.push_str(line.trim_end());
+                out.push('\n');
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+/// Split `line` into its label, the rest of its code, and its comment
+fn row(src: &str, line: &Line<'_>, is_prog: bool) -> Row {
+    let text = |idx: usize| &src[line.tokens[idx].0.clone()];
+
+    let comment_start = line
+        .tokens
+        .last()
+        .map_or(0, |(span, _)| span.end - line.offset);
+    let comment = line.text[comment_start..]
+        .trim()
+        .starts_with("//")
+        .then(|| line.text[comment_start..].trim().to_string());
+
+    let (def, rest) = match (line.def(), line.tokens.as_slice()) {
+        (_, [(_, Token::Text(cnst)), _, _]) if cnst.eq_ignore_ascii_case("const") => {
+            return Row {
+                def: format!("CONST {} {}", text(1), text(2)),
+                code: String::new(),
+                comment,
+            };
+        }
+        (_, [_, (_, Token::Text(equ)), _]) if equ.eq_ignore_ascii_case("equ") => {
+            return Row {
+                def: format!("{} EQU {}", text(0), text(2)),
+                code: String::new(),
+                comment,
+            };
+        }
+        (_, [_, (_, Token::Text(alias)), (_, Token::Gpr(_))])
+            if alias.eq_ignore_ascii_case("alias") =>
+        {
+            return Row {
+                def: format!("{} ALIAS {}", text(0), text(2)),
+                code: String::new(),
+                comment,
+            };
+        }
+        (_, [(_, Token::Text(_)), (_, Token::Colon), ..]) => (format!("{}:", text(0)), 2),
+        (Some((_, Target::Bare(_))), _) => (text(0).to_string(), 1),
+        (_, [(_, Token::BareNumber(_)), ..]) if !is_prog => (text(0).to_string(), 1),
+        _ => (String::new(), 0),
+    };
+
+    let mut tokens = line.tokens[rest..]
+        .iter()
+        .filter(|(_, t)| !matches!(t, Token::Comma))
+        .map(|(span, _)| &src[span.clone()]);
+
+    let code = match (is_prog, tokens.next()) {
+        (_, None) => String::new(),
+        (true, Some(opcode)) => {
+            let operands = tokens.collect::<Vec<_>>().join(",");
+            format!("{} {operands}", opcode.to_uppercase())
+                .trim_end()
+                .to_string()
+        }
+        (false, Some(first)) => std::iter::once(first)
+            .chain(tokens)
+            .collect::<Vec<_>>()
+            .join(","),
+    };
+
+    Row { def, code, comment }
+}
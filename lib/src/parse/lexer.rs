@@ -3,17 +3,27 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use super::{expr::is_expr_char, lint::Rule};
 use crate::inst::Op;
 use logos::{Lexer, Logos};
-use std::{collections::HashMap, fmt::Debug, num::ParseIntError, ops::Range};
+use std::{borrow::Cow, collections::HashMap, fmt::Debug, num::ParseIntError, ops::Range};
 use thiserror::Error;
 
-fn parse_num(lex: &mut Lexer<Token>) -> Result<usize, ErrorKind> {
-    let src = if lex.slice().as_bytes()[0] == b'#' {
-        &lex.slice()[1..]
-    } else {
-        lex.slice()
+/// Parse a number, ignoring `_` digit separators, and `,` separators inside `#[...]`
+///
+/// Negative literals are stored in two's complement.
+fn parse_num<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<usize, ErrorKind> {
+    let src = lex.slice().strip_prefix('#').unwrap_or(lex.slice());
+    let (negative, src) = match src.strip_prefix('-') {
+        Some(src) => (true, src),
+        None => (false, src),
+    };
+
+    let src = match src.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        Some(grouped) => grouped.replace([',', '_'], ""),
+        None => src.replace('_', ""),
     };
+    let src = src.as_str();
 
     let res = match src.as_bytes()[0] {
         b'b' | b'B' => usize::from_str_radix(&src[1..], 2),
@@ -22,31 +32,152 @@ fn parse_num(lex: &mut Lexer<Token>) -> Result<usize, ErrorKind> {
         _ => src.parse(),
     }?;
 
+    if !negative {
+        Ok(res)
+    } else if res <= isize::MIN.unsigned_abs() {
+        Ok(res.wrapping_neg())
+    } else {
+        Err(ErrorKind::InvalidOperand)
+    }
+}
+
+fn pop_parens<'a>(lex: &mut Lexer<'a, Token<'a>>) -> &'a str {
+    let slice = lex.slice();
+    &slice[1..slice.len() - 1]
+}
+
+/// Remove the quotes from a string and replace its escape sequences
+fn unescape<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<String, ErrorKind> {
+    let slice = lex.slice();
+    let mut chars = slice[1..slice.len() - 1].chars();
+    let mut res = String::new();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        res.push(match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '\'')) => c,
+            _ => return Err(ErrorKind::SyntaxError),
+        });
+    }
+
     Ok(res)
 }
 
-fn pop_parens(lex: &mut Lexer<Token>) -> String {
-    let mut chars = lex.slice().chars();
-    chars.next();
-    chars.next_back();
-    chars.collect()
+/// Take the rest of `#(...)`, up to the matching bracket
+fn literal_expr<'a>(lex: &mut Lexer<'a, Token<'a>>) -> Result<&'a str, ErrorKind> {
+    let mut depth = 1_usize;
+
+    let len = lex
+        .remainder()
+        .char_indices()
+        .take_while(|&(_, c)| is_expr_char(c))
+        .find_map(|(idx, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+
+            (depth == 0).then_some(idx)
+        })
+        .ok_or(ErrorKind::InvalidExpression)?;
+
+    let expr = &lex.remainder()[..len];
+    lex.bump(len + 1);
+
+    Ok(expr)
+}
+
+/// Take the rest of `label+...`, up to a comma, comment or the end of the line
+fn addr_expr<'a>(lex: &mut Lexer<'a, Token<'a>>) -> &'a str {
+    let rest = lex.remainder();
+
+    let len = rest
+        .char_indices()
+        .find(|&(idx, c)| !is_expr_char(c) || rest[idx..].starts_with("//"))
+        .map_or(rest.len(), |(idx, _)| idx);
+    let len = rest[..len].trim_end().len();
+
+    lex.bump(len);
+
+    lex.slice()
+}
+
+fn parse_gpr(num: &str) -> Result<usize, ErrorKind> {
+    match num.parse()? {
+        n @ 0..=29 => Ok(n),
+        _ => Err(ErrorKind::InvalidOperand),
+    }
 }
 
-#[derive(Error, Debug, Clone, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq, Default)]
 pub enum ErrorKind {
     #[error("Invalid integer format")]
     ParseIntError(#[from] ParseIntError),
     #[error("Syntax error")]
+    #[default]
     SyntaxError,
     #[error("Invalid opcode `{0}`")]
     InvalidOpcode(String),
     #[error("Invalid operand")]
     InvalidOperand,
+    #[error("Source must contain blank line(s) between the program and the memory, and the memory must not be empty")]
+    MissingMemory,
+    #[error("One of the memory addresses is too big")]
+    AddressTooBig,
+    #[error("{0} is an extension, not part of the Cambridge syntax")]
+    Extension(String),
+    #[error("Constant `{0}` is not defined")]
+    UndefinedConstant(String),
+    #[error("Constant `{0}` is defined more than once")]
+    DuplicateConstant(String),
+    #[error("Register alias `{0}` is defined more than once")]
+    DuplicateAlias(String),
+    #[error("Register alias `{0}` has the name of a register or label")]
+    AliasConflict(String),
+    #[error("Invalid expression")]
+    InvalidExpression,
+    #[error("Label `{0}` is defined more than once")]
+    DuplicateLabel(String),
+    #[error("Label `{0}` is not defined")]
+    UndefinedLabel(String),
+    #[error("Label `{0}` is never used")]
+    UnusedLabel(String),
+    #[error("Memory at `{0}` is never accessed")]
+    UnusedMemory(String),
+    #[error("Unreachable instruction(s)")]
+    Unreachable,
+    #[error("{message} ({rule})")]
+    Lint {
+        rule: Rule,
+        message: String,
+        severity: Severity,
+    },
 }
 
-impl Default for ErrorKind {
-    fn default() -> Self {
-        Self::SyntaxError
+/// Whether an [`ErrorKind`] stops the source from being parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// Reported alongside the parsed program, see [`DebugInfo::warnings`](crate::exec::DebugInfo::warnings)
+    Warning,
+}
+
+impl ErrorKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::UnusedLabel(_) | Self::UnusedMemory(_) | Self::Unreachable => Severity::Warning,
+            Self::Lint { severity, .. } => *severity,
+            _ => Severity::Error,
+        }
     }
 }
 
@@ -54,36 +185,49 @@ pub type ErrorMap = HashMap<Span, ErrorKind>;
 
 pub type ParseError = WithSpan<ErrorKind>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct LinearMemory {
-    pub init: usize,
-    pub len: usize,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinearMemory {
+    /// `[init;len]`, `len` addresses containing `init`
+    Repeat { init: usize, len: usize },
+    /// `[1,2,3]`, one address for each value
+    List(Vec<usize>),
 }
 
 impl LinearMemory {
-    pub(self) fn from_lexer(lexer: &mut Lexer<Token>) -> Self {
+    pub(self) fn from_lexer<'a>(lexer: &mut Lexer<'a, Token<'a>>) -> Result<Self, ErrorKind> {
         Self::from_str(lexer.slice())
     }
 
-    pub(self) fn from_str(s: &str) -> Self {
-        let mut decl = s.trim_matches(|c| c == '[' || c == ']').split(';');
-
-        let init = decl.next().unwrap().parse().unwrap();
-        let len = decl.next().unwrap().parse().unwrap();
-
-        Self { init, len }
+    pub(self) fn from_str(s: &str) -> Result<Self, ErrorKind> {
+        let s = s.trim_matches(|c| c == '[' || c == ']');
+
+        if let Some((init, len)) = s.split_once(';') {
+            Ok(Self::Repeat {
+                init: init.parse()?,
+                len: len.parse()?,
+            })
+        } else {
+            let values = s
+                .split(',')
+                .map(|v| v.trim().parse())
+                .collect::<Result<_, _>>()?;
+
+            Ok(Self::List(values))
+        }
     }
 }
 
 #[derive(Logos, Debug, Clone, PartialEq, Eq)]
 #[logos(skip r"[ \t]")]
 #[logos(error = ErrorKind)]
-pub enum Token {
+pub enum Token<'a> {
     #[regex(r"//[^\r\n]*", logos::skip)]
     Comment,
 
-    #[regex(r"\w*", |lex| lex.slice().to_string(), priority = 0)]
-    Text(String),
+    /// Borrowed from the source, unless it was made by the parser, e.g. `label+3` from an
+    /// [`AddrExpr`](Token::AddrExpr)
+    #[regex(r"\w*", |lex| Cow::Borrowed(lex.slice()), priority = 0)]
+    Text(Cow<'a, str>),
 
     #[token(":")]
     Colon,
@@ -91,30 +235,50 @@ pub enum Token {
     #[token(",")]
     Comma,
 
-    #[regex("r[0-9][0-9]?", |lex| lex.slice()[1..].parse())]
+    #[regex("r[0-9][0-9]?", |lex| parse_gpr(&lex.slice()[1..]))]
     Gpr(usize),
 
-    #[regex("#[&xXoObB][0-9a-fA-F]+", parse_num)]
-    #[regex("#[0-9]+", parse_num)]
+    #[regex("#-?[&xXoObB][0-9a-fA-F][0-9a-fA-F_]*", parse_num)]
+    #[regex("#-?[0-9][0-9_]*", parse_num)]
+    #[regex(r"#-?\[[0-9][0-9,_]*\]", parse_num)]
     Literal(usize),
 
+    /// `#(expression)`, evaluated by the parser
+    #[token("#(", literal_expr)]
+    LiteralExpr(&'a str),
+
+    /// `label+expression`, evaluated by the parser
+    #[regex(r"[a-zA-Z_]\w*[+-]", addr_expr)]
+    AddrExpr(&'a str),
+
+    /// `#NAME`, replaced with the value of the constant by the parser
+    #[regex(r"#[a-zA-Z_]\w*", |lex| &lex.slice()[1..], priority = 1)]
+    Constant(&'a str),
+
+    // Separators would make labels like `BEEF_1` numbers
     #[regex("[xXoObB][0-9a-fA-F]+", parse_num)]
-    #[regex("[0-9]+", parse_num)]
+    #[regex("[0-9][0-9_]*", parse_num)]
     BareNumber(usize),
 
     #[regex(r"\(\w*\)", pop_parens)]
-    Indirect(String),
+    Indirect(&'a str),
 
     #[regex(r"(?:\r\n)|\n")]
     Newline,
 
     #[regex(r"\[[0-9]+;[0-9]+\]", LinearMemory::from_lexer)]
+    #[regex(r"\[[0-9]+(?:,[ \t]*[0-9]+)*\]", LinearMemory::from_lexer)]
     LinearMemory(LinearMemory),
+
+    #[regex(r#""(?:[^"\\\r\n]|\\.)*""#, unescape)]
+    Str(String),
 }
 
-impl From<Token> for Op {
-    fn from(t: Token) -> Self {
-        match t {
+impl TryFrom<Token<'_>> for Op {
+    type Error = ErrorKind;
+
+    fn try_from(t: Token<'_>) -> Result<Self, Self::Error> {
+        let op = match t {
             Token::BareNumber(addr) => Op::Addr(addr),
             Token::Gpr(r) => Op::Gpr(r),
             Token::Literal(lit) => Op::Literal(lit),
@@ -123,11 +287,14 @@ impl From<Token> for Op {
                 "cmp" => Op::Cmp,
                 "ix" => Op::Ix,
                 "ar" => Op::Ar,
-                _ => Op::Fail(txt),
+                "sp" => Op::Sp,
+                _ => Op::Fail(txt.into_owned()),
             },
-            Token::Indirect(s) => Op::Indirect(Box::new(Op::from(s))),
-            _ => unreachable!(),
-        }
+            Token::Indirect(s) => Op::Indirect(Box::new(Op::try_from(s)?)),
+            _ => return Err(ErrorKind::InvalidOperand),
+        };
+
+        Ok(op)
     }
 }
 
@@ -136,10 +303,10 @@ pub type Span = Range<usize>;
 pub type WithSpan<T> = (Span, T);
 
 #[derive(Debug, Clone)]
-pub struct TokensWithError<'a>(pub Lexer<'a, Token>);
+pub struct TokensWithError<'a>(pub Lexer<'a, Token<'a>>);
 
-impl TokensWithError<'_> {
-    pub fn lines(mut self) -> (Vec<Vec<WithSpan<Token>>>, ErrorMap) {
+impl<'a> TokensWithError<'a> {
+    pub fn lines(mut self) -> (Vec<Vec<WithSpan<Token<'a>>>>, ErrorMap) {
         let mut errors = ErrorMap::new();
         let acc = self.by_ref().fold(vec![Vec::new()], |mut acc, (r, t)| {
             match t {
@@ -161,8 +328,8 @@ impl TokensWithError<'_> {
     }
 }
 
-impl Iterator for TokensWithError<'_> {
-    type Item = WithSpan<Result<Token, ErrorKind>>;
+impl<'a> Iterator for TokensWithError<'a> {
+    type Item = WithSpan<Result<Token<'a>, ErrorKind>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.0.next().map(|token| (self.0.span(), token))
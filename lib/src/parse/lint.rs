@@ -0,0 +1,208 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{
+    lexer::{ErrorKind, ErrorMap, Severity, Span},
+    parser::{InstIr, Parser},
+    refactor::{split_program, Target},
+};
+use crate::inst::{InstSet, Op};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A check made by [`lint`]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rule {
+    /// Labels should be uppercase, like opcodes
+    UppercaseLabels,
+    /// `LDR #n` loads `IX` more clearly than `LDM IX,#n`
+    PreferLdr,
+    /// A general purpose register used only once cannot carry a value between instructions, so
+    /// it is either never read or never written
+    UnusedGpr,
+}
+
+impl Rule {
+    pub const ALL: [Self; 3] = [Self::UppercaseLabels, Self::PreferLdr, Self::UnusedGpr];
+
+    /// Identifier of the rule, as used in configuration
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::UppercaseLabels => "uppercase-labels",
+            Self::PreferLdr => "prefer-ldr",
+            Self::UnusedGpr => "unused-gpr",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|rule| rule.id() == s)
+            .ok_or_else(|| format!("{s} is not a lint rule"))
+    }
+}
+
+/// How a [`Rule`] is reported
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Level {
+    /// Not checked
+    Allow,
+    /// Reported as a [`Severity::Warning`]
+    #[default]
+    Warn,
+    /// Reported as a [`Severity::Error`]
+    Deny,
+}
+
+/// Level of each [`Rule`], [`Level::Warn`] unless set
+///
+/// With the `serde` feature, this is a map from rule identifiers to levels, e.g.
+/// `{"uppercase-labels": "deny", "unused-gpr": "allow"}` in JSON.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    levels: BTreeMap<Rule, Level>,
+}
+
+impl LintConfig {
+    /// Report `rule` at `level`
+    #[must_use]
+    pub fn with(mut self, rule: Rule, level: Level) -> Self {
+        self.levels.insert(rule, level);
+        self
+    }
+
+    pub fn level(&self, rule: Rule) -> Level {
+        self.levels.get(&rule).copied().unwrap_or_default()
+    }
+}
+
+/// Check a program for style problems and likely mistakes, configured by `config`
+///
+/// Returns the problems found as [`ErrorKind::Lint`], or the errors if the program cannot be
+/// parsed.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::parse::{lint, DefaultSet, ErrorKind, Level, LintConfig, Rule, Severity};
+///
+/// let src = "loop: LDM IX,#0\nJMP loop\n\nNONE:\n";
+/// let config = LintConfig::default().with(Rule::PreferLdr, Level::Deny);
+/// let lints = lint::<DefaultSet>(src, &config).unwrap();
+///
+/// assert_eq!(
+///     lints.get(&(0..4)).map(ToString::to_string).as_deref(),
+///     Some("Label `loop` should be uppercase (uppercase-labels)")
+/// );
+/// assert_eq!(lints.get(&(0..15)).map(ErrorKind::severity), Some(Severity::Error));
+/// ```
+pub fn lint<T>(src: &str, config: &LintConfig) -> Result<ErrorMap, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let (insts, _, debug_info) = Parser::<T>::new(src).parse()?;
+    let (lines, _) = split_program(src)?;
+
+    let mut found = ErrorMap::new();
+    let mut report = |rule: Rule, span: Span, message: String| {
+        let severity = match config.level(rule) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+
+        found.entry(span).or_insert(ErrorKind::Lint {
+            rule,
+            message,
+            severity,
+        });
+    };
+
+    for line in &lines {
+        if let Some((span, Target::Label(label))) = line.def() {
+            if label != label.to_uppercase() {
+                report(
+                    Rule::UppercaseLabels,
+                    span.clone(),
+                    format!("Label `{label}` should be uppercase"),
+                );
+            }
+        }
+    }
+
+    let insts = debug_info
+        .inst_spans
+        .iter()
+        .zip(&insts)
+        .map(|(span, InstIr { inst, .. })| (span, inst))
+        .collect::<Vec<_>>();
+
+    for (span, inst) in &insts {
+        if !inst.inst.mnemonic().eq_ignore_ascii_case("LDM") {
+            continue;
+        }
+
+        if let Op::MultiOp(ops) = &inst.op {
+            if let [Op::Ix, Op::Literal(lit)] = ops.as_slice() {
+                report(
+                    Rule::PreferLdr,
+                    (*span).clone(),
+                    format!("Use `LDR #{lit}` to load a literal into `IX`"),
+                );
+            }
+        }
+    }
+
+    let mut gprs = BTreeMap::<usize, Vec<&Span>>::new();
+
+    for (span, inst) in &insts {
+        for gpr in find_gprs(&inst.op) {
+            gprs.entry(gpr).or_default().push(span);
+        }
+    }
+
+    for (gpr, spans) in gprs {
+        if let [span] = spans.as_slice() {
+            report(
+                Rule::UnusedGpr,
+                (*span).clone(),
+                format!("General purpose register `r{gpr}` is only used once"),
+            );
+        }
+    }
+
+    Ok(found)
+}
+
+fn find_gprs(op: &Op) -> Vec<usize> {
+    match op {
+        Op::Gpr(gpr) => vec![*gpr],
+        Op::Indirect(op) => find_gprs(op),
+        Op::MultiOp(ops) => ops.iter().flat_map(find_gprs).collect(),
+        _ => Vec::new(),
+    }
+}
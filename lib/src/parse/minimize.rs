@@ -0,0 +1,100 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+/// Remove lines from a program while `predicate` still holds, to produce a minimal reproducer
+///
+/// Uses delta debugging: increasingly small chunks of lines are removed, and a removal is kept
+/// if `predicate` returns `true` for the smaller program. Blank lines are never removed, since
+/// they separate the program from the memory.
+///
+/// `predicate` must return `true` for `src`, otherwise `src` is returned unchanged. To reduce a
+/// program that panics, use [`std::panic::catch_unwind`] inside `predicate`.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::{
+///     exec::{RtError, Status},
+///     make_io,
+///     parse::{jit, minimize, DefaultSet},
+/// };
+///
+/// let src = "LDM #1\nSTO 201\nLDD 201\nLDD 500\nEND\n\n201 0\n202 0\n";
+///
+/// let reads_invalid_addr = |src: &str| {
+///     // The parser panics if the memory is removed entirely
+///     std::panic::catch_unwind(|| {
+///         let io = make_io!(std::io::empty(), std::io::sink());
+///
+///         jit::<DefaultSet>(src, io).map_or(false, |mut exec| {
+///             matches!(
+///                 exec.run_until_break(),
+///                 Status::Error(RtError::InvalidAddr(500))
+///             )
+///         })
+///     })
+///     .unwrap_or(false)
+/// };
+///
+/// assert_eq!(minimize(src, reads_invalid_addr), "LDD 500\n\n201 0\n");
+/// ```
+pub fn minimize(src: &str, mut predicate: impl FnMut(&str) -> bool) -> String {
+    fn join(lines: &[&str], blanks: &[usize], keep: &[usize]) -> String {
+        let mut idxs = blanks.iter().chain(keep).copied().collect::<Vec<_>>();
+        idxs.sort_unstable();
+
+        idxs.into_iter().fold(String::new(), |mut acc, idx| {
+            acc.push_str(lines[idx]);
+            acc.push('\n');
+            acc
+        })
+    }
+
+    let lines = src.lines().collect::<Vec<_>>();
+
+    let (blanks, mut keep): (Vec<_>, Vec<_>) =
+        (0..lines.len()).partition(|&idx| lines[idx].trim().is_empty());
+
+    if !predicate(&join(&lines, &blanks, &keep)) {
+        return src.to_string();
+    }
+
+    let mut chunks = 2;
+
+    while keep.len() >= 2 {
+        let chunk_len = (keep.len() + chunks - 1) / chunks;
+        let mut reduced = false;
+
+        for start in (0..keep.len()).step_by(chunk_len) {
+            let end = (start + chunk_len).min(keep.len());
+
+            let candidate = keep[..start]
+                .iter()
+                .chain(&keep[end..])
+                .copied()
+                .collect::<Vec<_>>();
+
+            if predicate(&join(&lines, &blanks, &candidate)) {
+                keep = candidate;
+                reduced = true;
+                break;
+            }
+        }
+
+        if reduced {
+            chunks = (chunks - 1).max(2);
+        } else if chunks >= keep.len() {
+            break;
+        } else {
+            chunks = (chunks * 2).min(keep.len());
+        }
+    }
+
+    // A single remaining line may not be needed either
+    if keep.len() == 1 && predicate(&join(&lines, &blanks, &[])) {
+        keep.clear();
+    }
+
+    join(&lines, &blanks, &keep)
+}
@@ -6,17 +6,43 @@
 #![allow(clippy::upper_case_acronyms)]
 
 use crate::{
-    exec::{Context, DebugInfo, ExecInst, Executor, Io, Memory, Source},
+    exec::{DebugInfo, ExTree, Executor, Io, Memory, Source, SourceLine},
     extend,
     inst::InstSet,
     inst_set,
 };
 use std::{collections::BTreeMap, fmt::Display, ops::Deref, path::Path, str::FromStr};
 
+mod diagnostics;
+mod expr;
+mod format;
 mod lexer;
+mod lint;
+mod minimize;
+mod obfuscate;
 mod parser;
+mod refactor;
+mod syntax;
+mod table;
 
-pub use lexer::{ErrorKind, ErrorMap, Span};
+#[cfg(feature = "float")]
+mod float;
+
+pub use diagnostics::{Diagnostic, Diagnostics};
+pub use format::format;
+pub use lexer::{ErrorKind, ErrorMap, Severity, Span};
+pub use lint::{lint, Level, LintConfig, Rule};
+pub use minimize::minimize;
+pub use obfuscate::obfuscate;
+pub use refactor::{extract_function, ExtractError};
+pub use syntax::Syntax;
+pub use table::from_table;
+
+#[cfg(feature = "float")]
+pub use float::Float;
+
+#[cfg(feature = "compile")]
+pub(crate) use parser::outline;
 
 inst_set! {
     /// The core instruction set
@@ -32,49 +58,115 @@ inst_set! {
     ///
     /// * Bit manipulation: `AND`, `OR`, `XOR`, `LSL`, `LSR`
     pub Core use crate::exec::{mov, cmp, io, arith, bitman}; {
-        LDM => mov::ldm,
-        LDD => mov::ldd,
-        LDI => mov::ldi,
-        LDX => mov::ldx,
-        LDR => mov::ldr,
-        MOV => mov::mov,
-        STO => mov::sto,
-
-        CMP => cmp::cmp,
-        JPE => cmp::jpe,
-        JPN => cmp::jpn,
-        JMP => cmp::jmp,
-        CMI => cmp::cmi,
-
-        IN => io::inp,
-        OUT => io::out,
-        END => io::end,
-
-        INC => arith::inc,
-        DEC => arith::dec,
-        ADD => arith::add,
-        SUB => arith::sub,
-
-        AND => bitman::and,
-        OR => bitman::or,
-        XOR => bitman::xor,
-        LSL => bitman::lsl,
-        LSR => bitman::lsr,
+        /// Load a literal into `ACC` or a register
+        LDM => mov::ldm; [Lit] | [Reg, Lit] => Next,
+        /// Load the value at an address into `ACC` or a register
+        LDD => mov::ldd; [Addr] | [Reg, Addr] => Next,
+        /// Load the value at the address stored at an address into `ACC` or a register
+        LDI => mov::ldi; [Addr] | [Reg, Addr] => Next,
+        /// Load the value at an address plus `IX` into `ACC` or a register
+        LDX => mov::ldx; [Addr] | [Reg, Addr] => Next,
+        /// Load a literal into `IX`
+        LDR => mov::ldr; [Lit] => Next,
+        /// Copy `ACC` into a register, or a value into a register or address
+        MOV => mov::mov; [Reg] | [Dest, Val] => Next,
+        /// Store `ACC` at an address
+        STO => mov::sto; [Addr] => Next,
+
+        /// Compare `ACC`, or the first operand, with a value
+        CMP => cmp::cmp; [Val] | [Val, Val] => Next,
+        /// Jump to an address if the last comparison was equal
+        JPE => cmp::jpe; [Addr] => Branch,
+        /// Jump to an address if the last comparison was not equal
+        JPN => cmp::jpn; [Addr] => Branch,
+        /// Jump to an address, or to the first of two if the last comparison was equal, else the second
+        JMP => cmp::jmp; [Addr] | [Addr, Addr] => Jump,
+        /// Compare `ACC`, or a value, with the value at the address stored at an address
+        CMI => cmp::cmi; [Addr] | [Val, Addr] => Next,
+
+        /// Read a character from input into `ACC` or a destination
+        IN => io::inp; [] | [Dest] => Next,
+        /// Write `ACC`, or a value, as a character
+        OUT => io::out; [] | [Val] => Next,
+        /// Stop the program
+        END => io::end; [] => Stop,
+
+        /// Add 1 to a register or address
+        INC => arith::inc; [Dest] => Next,
+        /// Subtract 1 from a register or address
+        DEC => arith::dec; [Dest] => Next,
+        /// Add a value to `ACC` or a destination
+        ADD => arith::add; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Subtract a value from `ACC` or a destination
+        SUB => arith::sub; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+
+        /// Bitwise AND a value with `ACC` or a destination
+        AND => bitman::and; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Bitwise OR a value with `ACC` or a destination
+        OR => bitman::or; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Bitwise XOR a value with `ACC` or a destination
+        XOR => bitman::xor; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Shift `ACC` or a destination left by a number of bits
+        LSL => bitman::lsl; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Shift `ACC` or a destination right by a number of bits
+        LSR => bitman::lsr; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
     }
 }
 
 extend! {
     /// The extended instruction set
     ///
-    /// [`Core`], plus debugging (`DBG`), raw input (`RIN`), function `CALL` and return (`RET`), and no-op (`NOP`) instructions
+    /// [`Core`], plus ordered jumps (`JPG`, `JPL`, `JGE`, `JLE`), arithmetic (`MUL`, `DIV`, `MOD`), debugging (`DBG`), memory dumps (`DMP`), raw input (`RIN`), secure input (`INS`), line input (`INL`), key input (`INKEY`), numbered ports (`INP`, `OUTP`), numeric output (`OUTN`), newline (`NL`), `FLUSH`, function `CALL` and return (`RET`), stack (`PUSH`, `POP`), and no-op (`NOP`) instructions
     #[cfg(feature = "extended")]
-    pub Extended extends Core use crate::exec::{io, arith::zero}; {
-        ZERO => zero,
-        DBG => io::dbg,
-        RIN => io::rin,
-        CALL => io::call,
-        RET => io::ret,
-        NOP => io::nop,
+    pub Extended extends Core use crate::exec::{io, mov, cmp, arith}; {
+        /// Set registers or addresses to 0
+        ZERO => arith::zero => Next,
+        /// Multiply `ACC` or a destination by a value
+        MUL => arith::mul; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Divide `ACC` or a destination by a value
+        DIV => arith::div; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Set `ACC` or a destination to its remainder when divided by a value
+        MOD => arith::rem; [Val] | [Dest, Val] | [Dest, Val, Val] => Next,
+        /// Jump to an address if the last comparison was greater
+        JPG => cmp::jpg; [Addr] => Branch,
+        /// Jump to an address if the last comparison was less
+        JPL => cmp::jpl; [Addr] => Branch,
+        /// Jump to an address if the last comparison was greater or equal
+        JGE => cmp::jge; [Addr] => Branch,
+        /// Jump to an address if the last comparison was less or equal
+        JLE => cmp::jle; [Addr] => Branch,
+        /// Print the state of the executor, or of the operands
+        DBG => io::dbg => Next,
+        /// Read an integer from input into `ACC` or a destination
+        RIN => io::rin; [] | [Dest] => Next,
+        /// Read a line of input, of up to a number of characters, into memory starting at an address without echoing it, and its length into `ACC`
+        INS => io::ins; [Addr] | [Addr, Val] => Next,
+        /// Write `ACC`, or a value, as a number
+        OUTN => io::outn; [] | [Val] | [Val, Val] | [Val, Val, Val] => Next,
+        /// Write a newline
+        NL => io::nl; [] => Next,
+        /// Flush the output
+        FLUSH => io::flush; [] => Next,
+        /// Call the function at an address
+        CALL => io::call; [Addr] => Branch,
+        /// Return from the current function
+        RET => io::ret; [] => Stop,
+        /// Push `ACC`, or a value, onto the stack
+        PUSH => mov::push; [] | [Val] => Next,
+        /// Pop the top of the stack into `ACC` or a destination
+        POP => mov::pop; [] | [Dest] => Next,
+        /// Do nothing
+        NOP => io::nop; [] => Next,
+        /// Print a table of memory cells
+        DMP => io::dmp; [] | [Addr] | [Addr, Val] => Next,
+        /// Read a line of input, of up to a number of characters, into memory starting at an address, and its length into `ACC`
+        INL => io::inl; [Addr] | [Addr, Val] => Next,
+        /// Read a character into `ACC` or a destination if there is input, without waiting for it
+        INKEY => io::inkey; [] | [Dest] => Next,
+        /// Write a value as a character to a numbered output port
+        OUTP => io::port_out; [Val, Val] => Next,
+        /// Read a character from a numbered input port into a destination
+        INP => io::port_in; [Val, Dest] => Next,
     }
 }
 
@@ -95,22 +187,47 @@ pub type DefaultSet = _default_set::DefaultSet;
 #[allow(clippy::type_complexity)]
 pub(crate) fn parse<T>(
     prog: impl Deref<Target = str>,
-) -> Result<
-    (
-        BTreeMap<usize, ExecInst>,
-        BTreeMap<usize, usize>,
-        Source,
-        DebugInfo,
-    ),
-    ErrorMap,
->
+    syntax: Syntax,
+) -> Result<(ExTree, BTreeMap<usize, usize>, Source, DebugInfo), ErrorMap>
 where
     T: InstSet,
     <T as FromStr>::Err: Display,
 {
-    let (insts, mem, debug_info) = parser::Parser::<T>::new(&prog).parse()?;
-    let src = Source::from(prog);
+    let (insts, mem, mut debug_info) = parser::Parser::<T>::new(&prog)
+        .with_syntax(syntax)
+        .parse()
+        .map_err(|errors| {
+            crate::metrics::record(|m| m.parse_failed(&errors));
+            errors
+        })?;
+
+    crate::metrics::record(|m| m.program_parsed());
+    let (insts, mem) = by_addr(insts, mem);
+    let src = map_source(&prog, &insts, &mut debug_info);
 
+    Ok((insts, mem, src, debug_info))
+}
+
+/// Find the line of each instruction in `src`, so that runtime errors can show it
+fn map_source(src: &str, prog: &ExTree, debug_info: &mut DebugInfo) -> Source {
+    debug_info.source_map = prog
+        .keys()
+        .zip(&debug_info.inst_spans)
+        .map(|(addr, span)| (addr, SourceLine::of_span(src, span)))
+        .collect();
+
+    Source::from_source_map(&debug_info.source_map)
+}
+
+/// Instructions and memory contents by address
+fn by_addr<T>(
+    insts: Vec<parser::InstIr<T>>,
+    mem: Vec<parser::MemIr>,
+) -> (ExTree, BTreeMap<usize, usize>)
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
     let mem = mem
         .into_iter()
         .map(|parser::MemIr { addr, data }| (addr, data))
@@ -121,7 +238,7 @@ where
         .map(|parser::InstIr::<T> { addr, inst }| (addr, inst.to_exec_inst()))
         .collect();
 
-    Ok((prog, mem, src, debug_info))
+    (prog, mem)
 }
 
 /// Parse a string into an [`Executor`]
@@ -150,26 +267,87 @@ where
     T: InstSet,
     <T as FromStr>::Err: Display,
 {
-    let (prog, mem, src, debug_info) = parse::<T>(prog)?;
+    jit_with_syntax::<T>(prog, io, Syntax::default())
+}
 
-    let exe = Executor::new(
-        src,
-        prog,
-        Context::with_io(Memory::new(mem), io),
-        debug_info,
-    );
+/// Parse a string into an [`Executor`], accepting only the given [`Syntax`]
+///
+/// # Example
+///
+/// ```
+/// # use cambridge_asm::make_io;
+/// # use cambridge_asm::parse::{Core, ErrorKind, Syntax, jit_with_syntax};
+/// let res = jit_with_syntax::<Core>("ADD ACC,#1\nEND\n\nNONE:\n", make_io!(), Syntax::Cambridge);
+///
+/// let errors = res.err().unwrap();
+/// assert!(matches!(errors.get(&(0..10)), Some(ErrorKind::Extension(_))));
+/// ```
+pub fn jit_with_syntax<T>(
+    prog: impl Deref<Target = str>,
+    io: Io,
+    syntax: Syntax,
+) -> Result<Executor, ErrorMap>
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let (prog, mem, src, debug_info) = parse::<T>(prog, syntax)?;
+
+    let exe = Executor::builder(prog)
+        .with_source(src)
+        .with_mem(Memory::new(mem))
+        .with_io(io)
+        .with_debug_info(debug_info)
+        .build();
 
     info!("Executor created");
-    debug!(
-        "{}\n",
-        exe.display_with_opcodes::<T>()
-            .unwrap_or_else(|s| panic!("{s}"))
-    );
+    debug!("{}\n", exe.display_with_opcodes());
     debug!("The initial context:\n{}\n", exe.ctx);
 
     Ok(exe)
 }
 
+/// Parse as much of a string as possible into an [`Executor`], along with every error and
+/// warning
+///
+/// Lines with errors are left out, so that editors can still work with the rest of a broken
+/// program. Jumps to missing lines fail when they are executed. If there is no memory, the
+/// whole source is treated as the program.
+///
+/// # Example
+///
+/// ```
+/// # use cambridge_asm::make_io;
+/// # use cambridge_asm::parse::{DefaultSet, ErrorKind, Syntax, jit_partial};
+/// let (exec, diagnostics) =
+///     jit_partial::<DefaultSet>("LDM #1\nFOO\nEND", make_io!(), Syntax::default());
+///
+/// assert_eq!(exec.prog.len(), 2);
+/// assert_eq!(diagnostics.errors(), 2);
+/// assert!(diagnostics.iter().any(|d| d.kind == ErrorKind::MissingMemory));
+/// ```
+pub fn jit_partial<T>(prog: &str, io: Io, syntax: Syntax) -> (Executor, Diagnostics<'_>)
+where
+    T: InstSet,
+    <T as FromStr>::Err: Display,
+{
+    let ((insts, mem, mut debug_info), errors) = parser::Parser::<T>::new(prog)
+        .with_syntax(syntax)
+        .parse_partial();
+
+    let (insts, mem) = by_addr(insts, mem);
+    let src = map_source(prog, &insts, &mut debug_info);
+
+    let exe = Executor::builder(insts)
+        .with_source(src)
+        .with_mem(Memory::new(mem))
+        .with_io(io)
+        .with_debug_info(debug_info)
+        .build();
+
+    (exe, Diagnostics::new(prog, &errors))
+}
+
 /// Parse a file into an [`Executor`]
 ///
 /// # Arguments
@@ -206,6 +384,7 @@ where
 #[cfg(test)]
 mod parse_tests {
     use crate::{
+        exec::Capture,
         make_io,
         parse::{jit, DefaultSet},
         TestStdio, PROGRAMS,
@@ -225,7 +404,7 @@ mod parse_tests {
 
             t = Instant::now();
 
-            exe.exec::<DefaultSet>();
+            exe.exec_and_report();
 
             println!("Execution time: {:?}", t.elapsed());
 
@@ -244,6 +423,174 @@ mod parse_tests {
         }
     }
 
+    #[test]
+    #[cfg(feature = "extended")]
+    fn formatted_output() {
+        const PROG: &str = "LDM #42\nOUTN\nOUTN ACC,#5\nNL\nOUTN #7,#3,#48\nEND\n\nNONE:\n";
+
+        let s = TestStdio::new(vec![]);
+        let mut exe = jit::<DefaultSet>(PROG, make_io!(std::io::empty(), s.clone())).unwrap();
+        exe.exec_and_report();
+
+        assert_eq!(s.try_to_string().unwrap(), "42   42\n007");
+
+        let mut exe = jit::<DefaultSet>("OUTN #1,#257\nEND\n\nNONE:\n", make_io!()).unwrap();
+        assert!(matches!(
+            exe.exec().map_err(|fault| fault.error),
+            Err(crate::exec::RtError::WidthTooLarge {
+                width: 257,
+                max: 256
+            })
+        ));
+    }
+
+    #[test]
+    fn obfuscated() {
+        use crate::parse::obfuscate;
+
+        for (prog, exp, inp, out) in PROGRAMS {
+            let obfuscated = obfuscate(prog).unwrap();
+
+            assert!(!obfuscated.contains("//"));
+
+            let s = TestStdio::new(vec![]);
+            let mut exec =
+                jit::<DefaultSet>(obfuscated, make_io!(TestStdio::new(inp), s.clone())).unwrap();
+            exec.exec_and_report();
+
+            assert_eq!(exec.ctx.acc, exp);
+            assert_eq!(s.to_vec(), out);
+        }
+    }
+
+    #[test]
+    fn formatted() {
+        use crate::parse::format;
+
+        for (prog, exp, inp, out) in PROGRAMS {
+            let formatted = format(prog).unwrap();
+
+            assert_eq!(format(&formatted).unwrap(), formatted);
+
+            let s = TestStdio::new(vec![]);
+            let mut exec =
+                jit::<DefaultSet>(formatted, make_io!(TestStdio::new(inp), s.clone())).unwrap();
+            exec.exec_and_report();
+
+            assert_eq!(exec.ctx.acc, exp);
+            assert_eq!(s.to_vec(), out);
+        }
+
+        assert_eq!(
+            format("  x equ 5\r\nconst Y 2\r\nLDM #x\n100 ADD #Y\nlabel:\n\n").unwrap(),
+            "x EQU 5\nCONST Y 2\n    LDM #x\n100 ADD #Y\nlabel:\n"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn debug_radix() {
+        use crate::exec::Radix;
+
+        const PROG: &str = "LDM #255\nDBG ACC,#10\nEND\n\nNONE:\n";
+
+        for (radix, exp) in [
+            (Radix::Dec, "255, 10\n"),
+            (Radix::Hex, "&FF, &A\n"),
+            (Radix::Bin, "B11111111, B1010\n"),
+        ] {
+            let s = TestStdio::new(vec![]);
+            let mut exe = jit::<DefaultSet>(PROG, make_io!(std::io::empty(), s.clone())).unwrap();
+            exe.ctx.radix = radix;
+            exe.exec_and_report();
+
+            assert_eq!(s.try_to_string().unwrap(), exp);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn debug_width() {
+        use crate::exec::{Radix, Width};
+
+        // -3 in two's complement
+        const PROG: &str = "LDM #0\nSUB #3\nDBG ACC,#10\nEND\n\nNONE:\n";
+
+        for (radix, width, exp) in [
+            (Radix::Bin, Some(Width::W8), "B11111101, B00001010\n"),
+            (Radix::Hex, Some(Width::W8), "&FD, &0A\n"),
+            (Radix::Hex, Some(Width::W16), "&FFFD, &000A\n"),
+            (Radix::Dec, Some(Width::W8), "253, 10\n"),
+            (
+                Radix::Hex,
+                Some(Width::W64),
+                "&FFFFFFFFFFFFFFFD, &000000000000000A\n",
+            ),
+            (Radix::Hex, None, "&FFFFFFFFFFFFFFFD, &A\n"),
+        ] {
+            let s = TestStdio::new(vec![]);
+            let mut exe = jit::<DefaultSet>(PROG, make_io!(std::io::empty(), s.clone())).unwrap();
+            exe.ctx.radix = radix;
+            exe.ctx.set_width(width);
+            exe.exec_and_report();
+
+            assert_eq!(s.try_to_string().unwrap(), exp);
+        }
+    }
+
+    #[test]
+    fn cambridge_syntax() {
+        use crate::parse::{jit_with_syntax, ErrorKind, Syntax};
+
+        const VALID: &str = "LDM #5\nLDR #0\nLDX X\nMOV IX\nINC IX\nDEC ACC\nADD X\nSUB #1\nCMP #&FF\nAND #B101\nLSL #1\nJPN 0\nIN\nOUT\nEND\n\nX: 1\n";
+        let run = |src: &str| {
+            jit_with_syntax::<DefaultSet>(
+                src,
+                make_io!(std::io::empty(), std::io::sink()),
+                Syntax::Cambridge,
+            )
+        };
+
+        assert!(run(VALID).is_ok());
+
+        for (src, exp) in [
+            ("ADD ACC,#1", "`ADD` with 2 operands"),
+            ("LDM r1,#1", "`LDM` with 2 operands"),
+            ("INC r1", "General purpose register `r1`"),
+            ("LDI (r1)", "Indirect operand `(r1)`"),
+            ("OUT ACC", "`OUT` with operand `ACC`"),
+            ("LDM 200", "`LDM` with operand `200`"),
+            ("LDD", "`LDD` without an operand"),
+            #[cfg(feature = "extended")]
+            ("DBG ACC", "The `DBG` instruction"),
+        ] {
+            let errors = run(&format!("{src}\nEND\n\nNONE:\n")).err().unwrap();
+
+            assert_eq!(
+                errors.get(&(0..src.len())).map(ToString::to_string),
+                Some(format!(
+                    "{exp} is an extension, not part of the Cambridge syntax"
+                )),
+                "{src}"
+            );
+            assert!(matches!(
+                errors.get(&(0..src.len())),
+                Some(ErrorKind::Extension(_))
+            ));
+
+            // Accepted by default, unless the instruction never accepts the operands
+            let default = jit::<DefaultSet>(format!("{src}\nEND\n\nNONE:\n"), make_io!());
+            if ["LDI (r1)", "LDM 200", "LDD"].contains(&src) {
+                assert_eq!(
+                    default.err().unwrap().get(&(0..src.len())),
+                    Some(&ErrorKind::InvalidOperand)
+                );
+            } else {
+                assert!(default.is_ok(), "{src}");
+            }
+        }
+    }
+
     #[test]
     #[should_panic(
         expected = "called `Result::unwrap()` on an `Err` value: {4..7: ParseIntError(ParseIntError { kind: InvalidDigit })}"
@@ -254,6 +601,508 @@ mod parse_tests {
             make_io!(std::io::stdin(), std::io::sink()),
         )
         .unwrap();
-        exec.exec::<DefaultSet>();
+        exec.exec_and_report();
+    }
+
+    #[test]
+    fn table() {
+        use crate::parse::from_table;
+
+        // Tab separated, with a comment column, and memory in the same table
+        const TABLE: &str = "Address\tInstruction\tComment
+100\tLDD 150\tLoad counter
+101\tOUT
+102\tDEC ACC
+103\tSTO 150
+104\tCMP #&40
+105\tJPN 100
+106\tEND
+150\t0100 0011\tCounter
+";
+
+        let src = from_table(TABLE);
+        assert_eq!(
+            src,
+            "100 LDD 150 // Load counter\n101 OUT\n102 DEC ACC\n103 STO 150\n104 CMP #&40\n\
+             105 JPN 100\n106 END\n\n150 B01000011 // Counter\n"
+        );
+
+        let out = TestStdio::new(vec![]);
+        jit::<DefaultSet>(src, make_io!(std::io::empty(), out.clone()))
+            .unwrap()
+            .exec_and_report();
+
+        assert_eq!(out.to_vec(), b"CBA");
+
+        // Space separated, without memory
+        assert_eq!(
+            from_table("10 LDM #5\n11 END\n"),
+            "10 LDM #5\n11 END\n\nNONE:\n"
+        );
+    }
+
+    #[test]
+    fn digit_separators() {
+        let mut exec = jit::<DefaultSet>(
+            "LDM #1_000_000\nADD #[1,000]\nADD #xFF_FF\nADD #b1_0\nSTO 1_000\nLDD B1_0\nEND\n\n1_000 0\nB1_0: 7\n",
+            make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.exec_and_report();
+
+        assert_eq!(exec.ctx.mem.get(&1000).copied().unwrap(), 1_066_537);
+        // Labels are not numbers with separators
+        assert_eq!(exec.ctx.acc, 7);
+    }
+
+    #[test]
+    fn strings() {
+        let out = Capture::default();
+        let mut exec = jit::<DefaultSet>(
+            "LOOP: LDX MSG\nCMP #0\nJPE DONE\nOUT\nINC IX\nJMP LOOP\nDONE: LDD 300\nOUT\nEND\n\nMSG: \"Hi, \\\"you\\\"\\0\"\n300 \"!\"\n",
+            make_io!(std::io::empty(), out.clone()),
+        )
+        .unwrap();
+        exec.exec_and_report();
+
+        assert_eq!(out.take(), b"Hi, \"you\"!");
+    }
+
+    #[test]
+    fn value_lists() {
+        let mut exec = jit::<DefaultSet>(
+            "LDX TABLE\nADD 203\nADD TABLE\nADD PAIR\nEND\n\n200 [1,2,3,4]\nTABLE: [5;10]\nPAIR: [7, 8]\n",
+            make_io!(std::io::empty(), std::io::sink()),
+        )
+        .unwrap();
+        exec.exec_and_report();
+
+        assert_eq!(exec.ctx.acc, 5 + 4 + 5 + 7);
+        assert_eq!(exec.ctx.mem.get(&201).copied().unwrap(), 2);
+        assert_eq!(exec.ctx.mem.get(&9).copied().unwrap(), 5);
+        assert_eq!(exec.ctx.mem.get(&10).copied().unwrap(), 7);
+        assert_eq!(exec.ctx.mem.get(&11).copied().unwrap(), 8);
+    }
+
+    #[cfg(feature = "extended")]
+    #[test]
+    fn extract() {
+        use super::{extract_function, Extended, ExtractError};
+
+        let src = "\
+    LDM #0
+    IN
+    CMP #65
+    JPE SKIP
+    OUT
+SKIP: INC COUNT
+    LDD COUNT
+    CMP #3
+    JPN 0
+    END
+
+COUNT: 0
+";
+        let extracted = extract_function(src, 1..5, "READ").unwrap();
+        assert_eq!(
+            extracted,
+            "\
+    LDM #0
+    CALL READ
+SKIP: INC COUNT
+    LDD COUNT
+    CMP #3
+    JPN 0
+    END
+    READ: IN
+    CMP #65
+    JPE READ_EXIT
+    OUT
+    READ_EXIT: RET
+
+COUNT: 0
+"
+        );
+
+        let out = Capture::default();
+        let mut exec = jit::<Extended>(
+            extracted.as_str(),
+            make_io!(std::io::Cursor::new(b"ABC"), out.clone()),
+        )
+        .unwrap();
+        exec.exec_and_report();
+        assert_eq!(out.take(), b"BC");
+
+        assert_eq!(
+            extract_function(src, 4..7, "F"),
+            Err(ExtractError::JumpInto("SKIP".into()))
+        );
+        assert_eq!(
+            extract_function(src, 2..4, "F"),
+            Err(ExtractError::JumpOut("SKIP".into()))
+        );
+        assert_eq!(
+            extract_function(src, 1..3, "SKIP"),
+            Err(ExtractError::NameTaken("SKIP".into()))
+        );
+        assert_eq!(
+            extract_function(src, 9..12, "F"),
+            Err(ExtractError::OutsideProgram)
+        );
+    }
+
+    #[test]
+    fn constants() {
+        use super::{jit_with_syntax, ErrorKind, Syntax};
+
+        let src =
+            "SIZE EQU 3\nconst NEWLINE #10\n\nLDM #SIZE\nADD #NEWLINE\nCMP #xA\nEND\n\nNONE:\n";
+
+        let mut exec = jit::<DefaultSet>(src, make_io!()).unwrap();
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.acc, 13);
+
+        let errors =
+            jit::<DefaultSet>("A EQU 1\nA EQU 2\nLDM #B\nEND\n\nNONE:\n", make_io!()).unwrap_err();
+        assert_eq!(
+            errors.get(&(8..15)),
+            Some(&ErrorKind::DuplicateConstant("A".into()))
+        );
+        assert_eq!(
+            errors.get(&(20..22)),
+            Some(&ErrorKind::UndefinedConstant("B".into()))
+        );
+
+        let errors = jit_with_syntax::<DefaultSet>(src, make_io!(), Syntax::Cambridge).unwrap_err();
+        assert!(matches!(
+            errors.get(&(0..10)),
+            Some(ErrorKind::Extension(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "extended")]
+    fn register_aliases() {
+        use super::{format, jit_with_syntax, ErrorKind, Syntax};
+
+        let src = "COUNT ALIAS r29\nLDM #3\nMOV COUNT,ACC\nDBG COUNT\nDBG\nEND\n\nNONE:\n";
+
+        let out = TestStdio::new(vec![]);
+        let mut exec = jit::<DefaultSet>(src, make_io!(std::io::empty(), out.clone())).unwrap();
+        exec.exec_and_report();
+
+        assert_eq!(exec.ctx.gprs[29], 3);
+        assert!(out.try_to_string().unwrap().starts_with("3\n"));
+        assert!(out.try_to_string().unwrap().contains("COUNT = 3]"));
+        assert!(exec.display_with_opcodes().contains("MOV COUNT,ACC"));
+
+        let errors =
+            jit::<DefaultSet>("A ALIAS r1\nA ALIAS r2\nEND\n\nNONE:\n", make_io!()).unwrap_err();
+        assert_eq!(
+            errors.get(&(11..21)),
+            Some(&ErrorKind::DuplicateAlias("A".into()))
+        );
+
+        // Aliases cannot take the name of a register or a label
+        let errors = jit::<DefaultSet>(
+            "SP ALIAS r29\nLDM #3\nMOV SP,ACC\nEND\n\nNONE:\n",
+            make_io!(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors.get(&(0..12)),
+            Some(&ErrorKind::AliasConflict("SP".into()))
+        );
+
+        let errors =
+            jit::<DefaultSet>("LR ALIAS r28\nLDD LR\nEND\n\nLR: 1\n", make_io!()).unwrap_err();
+        assert_eq!(
+            errors.get(&(0..12)),
+            Some(&ErrorKind::AliasConflict("LR".into()))
+        );
+
+        let errors = jit_with_syntax::<DefaultSet>(src, make_io!(), Syntax::Cambridge).unwrap_err();
+        assert!(matches!(
+            errors.get(&(0..15)),
+            Some(ErrorKind::Extension(_))
+        ));
+
+        assert_eq!(
+            format("count alias r29\nLDM #1\nEND\n\nNONE:\n").unwrap(),
+            "count ALIAS r29\nLDM #1\nEND\n\nNONE:\n"
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        use super::ErrorKind;
+
+        let src = "\
+SIZE EQU 4
+
+    LDM #(SIZE * (2 + &10) % 7)
+    JMP SKIP+1
+SKIP: END
+    ADD TABLE+2
+    ADD TABLE+ SIZE - 1 // Last element
+    STO TABLE+1
+    ADD SIZE+196
+    END
+
+200 7
+TABLE: [1, 2, 3, 4]
+";
+        let mut exec = jit::<DefaultSet>(src, make_io!()).unwrap();
+        exec.exec_and_report();
+
+        // `TABLE` is placed before 200
+        assert_eq!(exec.ctx.mem.get(&1).copied().unwrap(), 2 + 3 + 4);
+        assert_eq!(exec.ctx.acc, 2 + 3 + 4 + 7);
+        assert_eq!(
+            super::obfuscate("JMP X+1\nX: END\n\nNONE:\n").unwrap(),
+            "JMP L0+1\nL0: END\n\nL1:\n"
+        );
+
+        for (src, span, err) in [
+            (
+                "LDM #(&FFFFFFFFFFFFFFFF + 1)",
+                4..28,
+                ErrorKind::InvalidExpression,
+            ),
+            ("LDM #(1 / 0)", 4..12, ErrorKind::InvalidExpression),
+            (
+                "LDM #(1 + X)",
+                4..12,
+                ErrorKind::UndefinedConstant("X".into()),
+            ),
+            ("LDM #(1 + 2", 4..6, ErrorKind::InvalidExpression),
+        ] {
+            let errors = jit::<DefaultSet>(format!("{src}\n\nNONE:\n"), make_io!()).unwrap_err();
+            assert_eq!(errors.get(&span), Some(&err), "{src}");
+        }
+    }
+
+    #[test]
+    fn warnings() {
+        use super::{ErrorKind, Severity};
+
+        let src = "\
+    LDM #0
+LOOP: INC ACC
+UNUSED: CMP #3
+    JPN LOOP
+    JMP DONE
+    OUT
+    OUT
+DONE: END
+
+200 \"AB\"
+X: 1
+NONE:
+";
+        let exec = jit::<DefaultSet>(src, make_io!()).unwrap();
+        let mut warnings = exec.debug_info.warnings.into_iter().collect::<Vec<_>>();
+        warnings.sort_by_key(|(span, _)| span.start);
+
+        assert_eq!(
+            warnings,
+            [
+                (21..35, ErrorKind::UnusedLabel("UNUSED".into())),
+                (66..77, ErrorKind::Unreachable),
+                (89..92, ErrorKind::UnusedMemory("200".into())),
+                (98..99, ErrorKind::UnusedMemory("X".into())),
+            ]
+        );
+        assert!(warnings
+            .iter()
+            .all(|(_, w)| w.severity() == Severity::Warning));
+
+        // Cells walked by a pointer are used if the block they are in is
+        let exec =
+            jit::<DefaultSet>(include_str!("../../examples/hello.pasm"), make_io!()).unwrap();
+        assert!(exec.debug_info.warnings.is_empty());
+
+        let errors = jit::<DefaultSet>(
+            "LDM #0
+FOO
+X: END
+
+NONE:
+",
+            make_io!(),
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors.get(&(11..17)),
+            Some(&ErrorKind::UnusedLabel("X".into()))
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        use super::{Diagnostics, ErrorKind};
+
+        let src = "// Ünïcödé\n  LDD 200\n\tX: END\n\n200 1 2\n";
+        let errors = jit::<DefaultSet>(src, make_io!()).unwrap_err();
+        let diagnostics = Diagnostics::new(src, &errors);
+
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.line, d.column, d.kind.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (3, 2, ErrorKind::UnusedLabel("X".into())),
+                (5, 5, ErrorKind::SyntaxError),
+            ]
+        );
+        assert_eq!(diagnostics.errors(), 1);
+        assert_eq!(
+            diagnostics.to_string(),
+            "warning: Label `X` is never used
+ --> 3:2
+  |
+3 | \tX: END
+  | \t^^^^^^
+
+error: Syntax error
+ --> 5:5
+  |
+5 | 200 1 2
+  |     ^^^
+"
+        );
+    }
+
+    #[test]
+    fn partial() {
+        use super::{jit_partial, ErrorKind, Syntax};
+
+        let (mut exec, diagnostics) = jit_partial::<DefaultSet>(
+            "LDD X\nFOO X\nSTO X\nJMP DONE\nDONE: END\n\nX: 5\n",
+            make_io!(),
+            Syntax::default(),
+        );
+
+        assert!(matches!(
+            diagnostics
+                .iter()
+                .map(|d| &d.kind)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            [ErrorKind::InvalidOpcode(_)]
+        ));
+        assert_eq!(exec.prog.len(), 4);
+
+        exec.exec_and_report();
+        assert_eq!(exec.ctx.acc, 5);
+    }
+
+    #[test]
+    fn invalid_operands() {
+        use super::ErrorKind;
+
+        for (src, span) in [("LDI (r30)", 4..9), ("ADD ACC,(r123)", 8..14)] {
+            let errors =
+                jit::<DefaultSet>(format!("{src}\nEND\n\nNONE:\n"), make_io!()).unwrap_err();
+            assert_eq!(errors.get(&span), Some(&ErrorKind::InvalidOperand), "{src}");
+        }
+    }
+
+    #[test]
+    fn operand_shapes() {
+        use super::ErrorKind;
+
+        for src in [
+            "STO #1",
+            "JMP #5",
+            "INC #1",
+            "END ACC",
+            "CMP",
+            "ADD r1,r2,r3,r4",
+        ] {
+            let errors = jit::<DefaultSet>(format!("LDM #1\n{src}\nEND\n\nNONE:\n"), make_io!())
+                .unwrap_err();
+            assert_eq!(errors.len(), 1, "{src}");
+            assert_eq!(
+                errors.get(&(7..7 + src.len())),
+                Some(&ErrorKind::InvalidOperand),
+                "{src}"
+            );
+        }
+
+        for src in ["STO 200", "JMP 0", "ADD r1,#1", "ADD r1,r2,#3", "OUT ACC"] {
+            assert!(
+                jit::<DefaultSet>(format!("{src}\nEND\n\n200 0\n"), make_io!()).is_ok(),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn lints() {
+        use super::{lint, ErrorKind, Level, LintConfig, Rule, Severity};
+
+        let src = "LDM IX,#3\nloop: LDM r1,#1\nADD r2,r1\nDEC IX\nJMP loop\n\nNONE:\n";
+        let rules = |config: &LintConfig| {
+            let mut found = lint::<DefaultSet>(src, config)
+                .unwrap()
+                .into_iter()
+                .map(|(span, kind)| match kind {
+                    ErrorKind::Lint { rule, severity, .. } => (span, rule, severity),
+                    kind => panic!("{kind:?}"),
+                })
+                .collect::<Vec<_>>();
+            found.sort_by_key(|(span, ..)| span.start);
+            found
+        };
+
+        assert_eq!(
+            rules(&LintConfig::default()),
+            [
+                (0..9, Rule::PreferLdr, Severity::Warning),
+                (10..14, Rule::UppercaseLabels, Severity::Warning),
+                (26..35, Rule::UnusedGpr, Severity::Warning),
+            ]
+        );
+
+        let config = LintConfig::default()
+            .with(Rule::UppercaseLabels, Level::Allow)
+            .with(Rule::UnusedGpr, Level::Deny);
+        assert_eq!(
+            rules(&config),
+            [
+                (0..9, Rule::PreferLdr, Severity::Warning),
+                (26..35, Rule::UnusedGpr, Severity::Error),
+            ]
+        );
+
+        #[cfg(feature = "serde")]
+        assert_eq!(
+            serde_json::from_str::<LintConfig>(
+                r#"{"uppercase-labels": "allow", "unused-gpr": "deny"}"#
+            )
+            .unwrap(),
+            config
+        );
+
+        assert_eq!("prefer-ldr".parse(), Ok(Rule::PreferLdr));
+        assert!(lint::<DefaultSet>("FOO\n\nNONE:\n", &config).is_err());
+    }
+
+    #[test]
+    fn runtime_error_lines() {
+        let src = "// Load\nSTART: LDM #1\n\n// Fails\nLDD 500 // oops\nEND\n\nNONE:\n";
+
+        let out = TestStdio::new(vec![]);
+        jit::<DefaultSet>(src, make_io!(std::io::empty(), out.clone()))
+            .unwrap()
+            .exec_and_report();
+
+        assert_eq!(
+            out.try_to_string().unwrap(),
+            "Runtime Error:\n\n2    START: LDM #1\n5    LDD 500 // oops <-\n6    END\n\nmessage: Invalid memory address `500`\n\n"
+        );
     }
 }
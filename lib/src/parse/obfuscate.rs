@@ -0,0 +1,113 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{
+    expr::split_label,
+    lexer::{ErrorMap, Token, TokensWithError, WithSpan},
+};
+use logos::Logos;
+use std::collections::HashMap;
+
+/// Rename labels to neutral names and strip comments, without changing behaviour
+///
+/// Labels are renamed to `L0`, `L1`, ... in order of definition. Lines left empty after
+/// removing comments are kept as a single blank line, since blank lines separate the program
+/// from the memory. Line endings are normalised to `\n`.
+///
+/// Useful for making "what does this code do?" exercises out of existing solutions.
+///
+/// # Example
+/// ```
+/// use cambridge_asm::parse::obfuscate;
+///
+/// let src = "// Count to 3\nLOOP: INC COUNT\nCMP COUNT,#3\nJPN LOOP\nEND\n\nCOUNT: 0\n";
+///
+/// assert_eq!(
+///     obfuscate(src).unwrap(),
+///     "L0: INC L1\nCMP L1,#3\nJPN L0\nEND\n\nL1: 0\n"
+/// );
+/// ```
+pub fn obfuscate(src: &str) -> Result<String, ErrorMap> {
+    fn finish_line(line: &mut String, out: &mut String) {
+        let trimmed = line.trim_end();
+
+        // Collapse runs of blank lines, and drop them entirely at the start
+        if !trimmed.is_empty() || !(out.is_empty() || out.ends_with("\n\n")) {
+            out.push_str(trimmed);
+            out.push('\n');
+        }
+
+        line.clear();
+    }
+
+    let mut errors = ErrorMap::new();
+
+    let tokens = TokensWithError(Token::lexer(src))
+        .filter_map(|(span, res)| match res {
+            Ok(t) => Some((span, t)),
+            Err(e) => {
+                errors.entry(span).or_insert(e);
+                None
+            }
+        })
+        .collect::<Vec<WithSpan<Token<'_>>>>();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut names = HashMap::new();
+
+    for pair in tokens.windows(2) {
+        if let [(_, Token::Text(label)), (_, Token::Colon)] = pair {
+            let next = names.len();
+            names.entry(&**label).or_insert_with(|| format!("L{next}"));
+        }
+    }
+
+    let mut out = String::with_capacity(src.len());
+    let mut line = String::new();
+    let mut seen_opcode = false;
+    let mut last_end = 0;
+
+    for (idx, (span, token)) in tokens.iter().enumerate() {
+        // Comments run to the end of the line, so the gap before a token can only be whitespace
+        let gap = &src[last_end..span.start];
+        last_end = span.end;
+
+        let renamed = match token {
+            Token::Newline => {
+                finish_line(&mut line, &mut out);
+                seen_opcode = false;
+                continue;
+            }
+            Token::Text(text) => {
+                let is_def = matches!(tokens.get(idx + 1), Some((_, Token::Colon)));
+
+                if !is_def && !seen_opcode {
+                    seen_opcode = true;
+                    None
+                } else {
+                    names.get(&**text).cloned()
+                }
+            }
+            Token::Indirect(text) => names.get(text).map(|name| format!("({name})")),
+            Token::AddrExpr(expr) => {
+                let (label, rest) = split_label(expr);
+                names.get(label).map(|name| format!("{name}{rest}"))
+            }
+            _ => None,
+        };
+
+        line.push_str(gap);
+        line.push_str(renamed.as_deref().unwrap_or(&src[span.clone()]));
+    }
+
+    if !line.trim().is_empty() {
+        finish_line(&mut line, &mut out);
+    }
+
+    Ok(out)
+}
@@ -4,15 +4,21 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::{
-    exec::{self, DebugInfo},
+    exec::{self, DebugInfo, MemType},
     inst::{self, InstSet, Op},
-    parse::lexer::{
-        ErrorKind, ErrorMap, LinearMemory, ParseError, Span, Token, TokensWithError, WithSpan,
+    parse::{
+        expr::{eval, split_label},
+        lexer::{
+            ErrorKind, ErrorMap, LinearMemory, ParseError, Severity, Span, Token, TokensWithError,
+            WithSpan,
+        },
+        syntax::{check_cambridge, Syntax},
     },
 };
 use logos::Logos;
 use std::{
-    collections::BTreeMap,
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::{Debug, Display},
     marker::PhantomData,
     ops::Range,
@@ -25,15 +31,22 @@ macro_rules! store_err {
     };
 }
 
-type Line = Vec<WithSpan<Token>>;
+type Line<'a> = Vec<WithSpan<Token<'a>>>;
+
+/// Instructions, memory and debug info from [`Parser::parse`]
+pub type Parsed<I> = (Vec<InstIr<I>>, Vec<MemIr>, DebugInfo);
 
 #[derive(Clone)]
 pub struct Parser<'a, I> {
-    #[allow(dead_code)]
     pub src: &'a str,
-    lines: Vec<Line>,
+    lines: Vec<Line<'a>>,
     err: ErrorMap,
     debug_info: DebugInfo,
+    syntax: Syntax,
+    /// Register numbers of the names defined with `NAME ALIAS rN`
+    aliases: HashMap<String, usize>,
+    /// Recover from errors that would otherwise panic
+    partial: bool,
     _inst_set: PhantomData<I>,
 }
 
@@ -49,11 +62,19 @@ where
             lines,
             err,
             debug_info: DebugInfo::default(),
+            syntax: Syntax::default(),
+            aliases: HashMap::new(),
+            partial: false,
             _inst_set: PhantomData,
         }
     }
 
-    fn get_inst(line: &[WithSpan<Token>]) -> Result<Option<WithSpan<Inst<I>>>, ParseError> {
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    fn get_inst(line: &[WithSpan<Token<'a>>]) -> Result<Option<WithSpan<Inst<'a, I>>>, ParseError> {
         let span = {
             let ((s, _), (e, _)) = (line.first().unwrap(), line.last().unwrap());
             s.start..e.end
@@ -100,12 +121,11 @@ where
             return Err((span, ErrorKind::InvalidOperand));
         }
 
-        let mut ops = rest
+        let mut ops = line[restidx..]
             .iter()
-            .filter(|t| !matches!(t, Token::Comma))
-            .cloned()
-            .map(Op::from)
-            .collect::<Vec<_>>();
+            .filter(|(_, t)| !matches!(t, Token::Comma))
+            .map(|(span, t)| Op::try_from(t.clone()).map_err(|e| (span.clone(), e)))
+            .collect::<Result<Vec<_>, _>>()?;
 
         let op = match ops.len() {
             0 => Op::Null,
@@ -123,10 +143,11 @@ where
         Ok(Some((span, Inst { addr, opcode, op })))
     }
 
-    fn get_mem(line: &[WithSpan<Token>]) -> Result<Option<MemEnum>, ParseError> {
+    fn get_mem(line: &[WithSpan<Token<'a>>]) -> Result<Option<MemEnum<'a>>, ParseError> {
         enum DataEnum {
-            LinearMemory(LinearMemory),
+            LinearMemory { init: usize, len: usize },
             Normal(usize),
+            Cells(Vec<usize>, MemType),
         }
 
         let rawline = line.iter().map(|(_, t)| t).cloned().collect::<Vec<_>>();
@@ -140,7 +161,17 @@ where
         let get_data = |t: &[Token], start_idx: usize| -> Result<DataEnum, ParseError> {
             match t {
                 &[Token::BareNumber(n)] => Ok(DataEnum::Normal(n)),
-                &[Token::LinearMemory(mem)] => Ok(DataEnum::LinearMemory(mem)),
+                [Token::LinearMemory(LinearMemory::List(values))] => {
+                    Ok(DataEnum::Cells(values.clone(), MemType::Word))
+                }
+                &[Token::LinearMemory(LinearMemory::Repeat { init, len })] => {
+                    Ok(DataEnum::LinearMemory { init, len })
+                }
+                [Token::Str(s)] => {
+                    let data = s.chars().map(|c| c as usize).collect::<Vec<_>>();
+                    let ty = MemType::Str(data.len());
+                    Ok(DataEnum::Cells(data, ty))
+                }
                 [] => Ok(DataEnum::Normal(0)),
                 _ => Err((line[start_idx].0.start..end, ErrorKind::SyntaxError)),
             }
@@ -149,41 +180,225 @@ where
         match rawline.as_slice() {
             &[Token::BareNumber(addr), ref rest @ ..] => {
                 let res = match get_data(rest, 1)? {
-                    DataEnum::LinearMemory(mem) => Some(MemEnum::Linear(
-                        (addr..addr + mem.len)
+                    DataEnum::LinearMemory { init, len } => Some(MemEnum::Linear(
+                        (addr..addr
+                            .checked_add(len)
+                            .ok_or((start..end, ErrorKind::SyntaxError))?)
                             .map(Addr::Bare)
-                            .map(move |addr| (addr, mem.init))
+                            .map(move |addr| (addr, init))
                             .map(Mem::from)
                             .collect(),
                     )),
-                    DataEnum::Normal(data) => Some(MemEnum::One(Mem {
-                        addr: Addr::Bare(addr),
-                        data,
-                    })),
+                    DataEnum::Normal(data) => {
+                        Some(MemEnum::One(Mem::from((Addr::Bare(addr), data))))
+                    }
+                    DataEnum::Cells(data, ty) => {
+                        // Address of the last cell
+                        addr.checked_add(data.len().saturating_sub(1))
+                            .ok_or((start..end, ErrorKind::AddressTooBig))?;
+
+                        Some(MemEnum::One(Mem {
+                            addr: Addr::Bare(addr),
+                            data,
+                            ty,
+                        }))
+                    }
                 };
 
                 Ok(res)
             }
-            [Token::Text(label), Token::Colon, rest @ ..] => Ok(Some(MemEnum::One(Mem {
-                addr: Addr::Label(label.clone()),
-                data: match get_data(rest, 2)? {
-                    DataEnum::LinearMemory(_) => Err((start..end, ErrorKind::SyntaxError))?,
-                    DataEnum::Normal(data) => data,
-                },
-            }))),
+            [Token::Text(label), Token::Colon, rest @ ..] => {
+                let (data, ty) = match get_data(rest, 2)? {
+                    DataEnum::Normal(data) => (vec![data], MemType::Word),
+                    // The label is bound to the first cell
+                    DataEnum::LinearMemory { init, len } if len > 0 => {
+                        (vec![init; len], MemType::Word)
+                    }
+                    DataEnum::Cells(data, ty) if !data.is_empty() => (data, ty),
+                    DataEnum::LinearMemory { .. } | DataEnum::Cells(..) => {
+                        Err((start..end, ErrorKind::SyntaxError))?
+                    }
+                };
+
+                Ok(Some(MemEnum::One(Mem {
+                    addr: Addr::Label(label.clone()),
+                    data,
+                    ty,
+                })))
+            }
             [] => Ok(None),
             _ => Err((start..end, ErrorKind::SyntaxError)),
         }
     }
 
-    fn get_insts_and_mems(&mut self) -> (Vec<Span>, Vec<Inst<I>>, Vec<Mem>) {
+    /// Remove constant definitions (`NAME EQU 42` or `CONST NAME 42`), replace `#NAME` with the
+    /// value of the constant, and evaluate expressions
+    fn substitute_constants(&mut self) {
+        let mut constants = HashMap::new();
+        let (err, syntax) = (&mut self.err, self.syntax);
+
+        self.lines.retain(|line| {
+            let (span, name, value) = match line.as_slice() {
+                [(s, Token::Text(name)), (_, Token::Text(equ)), (e, Token::BareNumber(value) | Token::Literal(value))]
+                    if equ.eq_ignore_ascii_case("equ") =>
+                {
+                    (s.start..e.end, name, *value)
+                }
+                [(s, Token::Text(cnst)), (_, Token::Text(name)), (e, Token::BareNumber(value) | Token::Literal(value))]
+                    if cnst.eq_ignore_ascii_case("const") =>
+                {
+                    (s.start..e.end, name, *value)
+                }
+                _ => return true,
+            };
+
+            if syntax == Syntax::Cambridge {
+                store_err!(err, span.clone(), ErrorKind::Extension(format!("Named constant `{name}`")));
+            }
+
+            if constants.insert(name.clone(), value).is_some() {
+                store_err!(err, span, ErrorKind::DuplicateConstant(name.to_string()));
+            }
+
+            false
+        });
+
+        let lookup = |name: &str| constants.get(name).copied();
+
+        for (span, token) in self.lines.iter_mut().flatten() {
+            let res = match token {
+                Token::Constant(name) => lookup(name)
+                    .map(Token::Literal)
+                    .ok_or_else(|| ErrorKind::UndefinedConstant((*name).to_string())),
+                Token::LiteralExpr(expr) => eval(expr, &lookup).and_then(|value| {
+                    // Negative values are stored in two's complement
+                    let abs = usize::try_from(value.unsigned_abs())
+                        .ok()
+                        .filter(|&abs| value >= 0 || abs <= isize::MIN.unsigned_abs())
+                        .ok_or(ErrorKind::InvalidExpression)?;
+
+                    Ok(Token::Literal(if value < 0 {
+                        abs.wrapping_neg()
+                    } else {
+                        abs
+                    }))
+                }),
+                Token::AddrExpr(expr) => Self::eval_addr(expr, &lookup),
+                _ => continue,
+            };
+
+            if syntax == Syntax::Cambridge && !matches!(token, Token::Constant(_)) {
+                store_err!(err, span.clone(), ErrorKind::Extension("Expression".into()));
+            }
+
+            match res {
+                Ok(res) => *token = res,
+                Err(e) => {
+                    store_err!(err, span.clone(), e);
+                }
+            }
+        }
+    }
+
+    /// Remove register aliases (`NAME ALIAS rN`), so that `NAME` can be used in place of `rN`
+    ///
+    /// Aliases cannot have the name of a register, such as `ACC`, or of a label.
+    fn collect_aliases(&mut self) {
+        let labels = self
+            .lines
+            .iter()
+            .filter_map(|line| match line.as_slice() {
+                [(_, Token::Text(label)), (_, Token::Colon), ..] => Some(label.to_string()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        let (err, syntax) = (&mut self.err, self.syntax);
+        let (aliases, names) = (&mut self.aliases, &mut self.debug_info.aliases);
+
+        self.lines.retain(|line| {
+            let (span, name, reg) = match line.as_slice() {
+                [(s, Token::Text(name)), (_, Token::Text(alias)), (e, Token::Gpr(reg))]
+                    if alias.eq_ignore_ascii_case("alias") =>
+                {
+                    (s.start..e.end, name, *reg)
+                }
+                _ => return true,
+            };
+
+            if syntax == Syntax::Cambridge {
+                store_err!(
+                    err,
+                    span.clone(),
+                    ErrorKind::Extension(format!("Register alias `{name}`"))
+                );
+            }
+
+            let is_register = !matches!(Op::try_from(Token::Text(name.clone())), Ok(Op::Fail(_)));
+
+            if is_register || labels.contains(name.as_ref()) {
+                store_err!(err, span, ErrorKind::AliasConflict(name.to_string()));
+                return false;
+            }
+
+            if aliases.insert(name.to_string(), reg).is_some() {
+                store_err!(err, span, ErrorKind::DuplicateAlias(name.to_string()));
+            }
+
+            // The first name given to a register is used to display it
+            names.entry(reg).or_insert_with(|| name.to_string());
+
+            false
+        });
+    }
+
+    /// `label+expression` as a label with an offset, or a number if `label` is a constant
+    fn eval_addr(
+        expr: &str,
+        lookup: &dyn Fn(&str) -> Option<usize>,
+    ) -> Result<Token<'a>, ErrorKind> {
+        let (label, rest) = split_label(expr);
+
+        if lookup(label).is_some() {
+            let value = eval(expr, lookup)?;
+            return usize::try_from(value)
+                .map(Token::BareNumber)
+                .map_err(|_| ErrorKind::InvalidExpression);
+        }
+
+        let offset = eval(&format!("0{rest}"), lookup)?;
+        let abs =
+            usize::try_from(offset.unsigned_abs()).map_err(|_| ErrorKind::InvalidExpression)?;
+        let sign = if offset < 0 { '-' } else { '+' };
+
+        Ok(Token::Text(format!("{label}{sign}{abs}").into()))
+    }
+
+    fn get_insts_and_mems(&mut self) -> (Vec<Span>, Vec<Inst<'a, I>>, Vec<Mem<'a>>) {
+        self.collect_aliases();
+        self.substitute_constants();
+
         let mut blocks = self
             .lines
             .split(Vec::is_empty)
             .filter(|v| !v.is_empty())
             .collect::<Vec<_>>();
 
-        assert!(blocks.len() >= 2, "Unable to parse. Your source may not contain blank line(s) between the program and the memory, or the memory might be absent");
+        if blocks.len() < 2 {
+            assert!(
+                self.partial || cfg!(feature = "strict-no-panic"),
+                "Unable to parse. Your source may not contain blank line(s) between the program and the memory, or the memory might be absent"
+            );
+
+            store_err!(self.err, 0..self.src.len(), ErrorKind::MissingMemory);
+
+            if !self.partial {
+                return (Vec::new(), Vec::new(), Vec::new());
+            }
+
+            // Everything is treated as the program, with empty memory
+            blocks.push(&[]);
+        }
 
         let mems = blocks
             .pop()
@@ -202,15 +417,65 @@ where
                 match mem {
                     MemEnum::Linear(mems) => acc.extend(mems),
                     MemEnum::One(mem) => acc.push(mem),
-                };
+                }
 
                 acc
             });
 
+        // `SP` is a register, unless a program defines it as a label
+        let sp_label = self.lines.iter().find_map(|line| match line.as_slice() {
+            [(_, Token::Text(label)), (_, Token::Colon), ..]
+                if label.eq_ignore_ascii_case("sp") =>
+            {
+                Some(label.to_string())
+            }
+            _ => None,
+        });
+
+        let (syntax, src, aliases) = (self.syntax, self.src, &self.aliases);
+        // Operands may be missing because of these
+        let lexer_errors = self.err.keys().cloned().collect::<Vec<_>>();
+        let line_has_errors = |span: &Span| {
+            let start = src[..span.start].rfind('\n').map_or(0, |idx| idx + 1);
+            let end = src[span.end..]
+                .find('\n')
+                .map_or(src.len(), |idx| span.end + idx);
+
+            lexer_errors.iter().any(|e| (start..end).contains(&e.start))
+        };
+
         let (inst_spans, insts): (Vec<_>, Vec<_>) = blocks
             .concat()
             .iter()
             .map(|line| Self::get_inst(line))
+            .map(|res| {
+                res.map(|inst| {
+                    inst.map(|(span, mut inst)| {
+                        resolve_aliases(&mut inst.op, aliases);
+
+                        if let Some(label) = &sp_label {
+                            shadow_sp(&mut inst.op, label);
+                        }
+
+                        (span, inst)
+                    })
+                })
+            })
+            .map(|res| match (res, syntax) {
+                (Ok(Some((span, inst))), Syntax::Cambridge) => {
+                    match check_cambridge(&inst.opcode.to_string(), &inst.op) {
+                        Ok(()) => Ok(Some((span, inst))),
+                        Err(ext) => Err((span, ErrorKind::Extension(ext))),
+                    }
+                }
+                (res, _) => res,
+            })
+            .map(|res| match res {
+                Ok(Some((span, inst))) if !inst.has_valid_operands() && !line_has_errors(&span) => {
+                    Err((span, ErrorKind::InvalidOperand))
+                }
+                res => res,
+            })
             .filter_map(|res| match res {
                 Ok(inst @ Some(_)) => inst,
                 Ok(None) => None,
@@ -224,11 +489,11 @@ where
         (inst_spans, insts, mems)
     }
 
-    fn process_insts(&mut self, insts: Vec<Inst<I>>) -> Vec<InstIr<I>> {
+    fn process_insts(&mut self, insts: Vec<Inst<'a, I>>) -> Vec<InstIr<I>> {
         fn op_addr_eq(op: &Op, addr: &Addr) -> bool {
             match (op, addr) {
                 (Op::Addr(x), Addr::Bare(bare)) => x == bare,
-                (Op::Fail(x), Addr::Label(label)) => x == label,
+                (Op::Fail(x), Addr::Label(label)) => split_offset(x).0 == label,
                 (Op::Indirect(op), addr) => op_addr_eq(op.as_ref(), addr),
                 _ => false,
             }
@@ -275,19 +540,20 @@ where
                 Op::MultiOp(ops) => {
                     let mut ops = ops.clone();
                     // unwrap ok because mutiop_idx will always exist if operand is multiop
-                    ops[multiop_idx.unwrap()] = Op::Addr(to);
+                    let idx = multiop_idx.unwrap();
+                    ops[idx] = linked(&ops[idx], to);
                     ir[from].1.op = Op::MultiOp(ops);
                 }
-                Op::Addr(_) | Op::Fail(_) => ir[from].1.op = Op::Addr(to),
+                Op::Addr(_) | Op::Fail(_) => ir[from].1.op = linked(&ir[from].1.op, to),
                 Op::Indirect(_) => {
                     if let Op::Indirect(op) = &mut ir[from].1.op {
                         if matches!(op.as_ref(), Op::Addr(_) | Op::Fail(_)) {
-                            *op.as_mut() = Op::Addr(to);
+                            **op = linked(op, to);
                         }
                     }
                 }
                 _ => {}
-            };
+            }
         }
 
         ir.into_iter()
@@ -295,10 +561,10 @@ where
             .collect()
     }
 
-    fn process_mems(&mut self, mems: Vec<Mem>, prog: &mut [InstIr<I>]) -> Vec<MemIr> {
+    fn process_mems(&mut self, mems: Vec<Mem<'a>>, prog: &mut [InstIr<I>]) -> Vec<MemIr> {
         fn op_label_eq(op: &Op, label: &str) -> bool {
             match op {
-                Op::Fail(x) => x == label,
+                Op::Fail(x) => split_offset(x).0 == label,
                 Op::Indirect(op) => op_label_eq(op.as_ref(), label),
                 _ => false,
             }
@@ -307,16 +573,20 @@ where
         let mut label_mems = Vec::new();
         let mut raw_mems = Vec::new();
 
-        for Mem { addr, data } in mems {
+        for Mem { addr, data, ty } in mems {
             match addr {
-                Addr::Bare(bare) => raw_mems.push((bare, data)),
-                Addr::Label(label) => label_mems.push((label, data)),
+                // Overflow is checked when parsing
+                Addr::Bare(bare) => {
+                    self.set_mem_type(bare, ty);
+                    raw_mems.extend(data.into_iter().enumerate().map(|(i, d)| (bare + i, d)));
+                }
+                Addr::Label(label) => label_mems.push((label, data, ty)),
             }
         }
 
         let mut links = vec![];
 
-        for (i, (addr, _)) in label_mems.iter().enumerate() {
+        for (i, (addr, ..)) in label_mems.iter().enumerate() {
             for (
                 j,
                 InstIr {
@@ -342,53 +612,61 @@ where
             }
         }
 
-        let unused_addrs: Vec<_> = {
-            let mut used_addr = raw_mems.iter().map(|x| x.0).collect::<Vec<_>>();
+        // Each label is placed once, in the order they are first used
+        let mut used = Vec::new();
 
-            used_addr.sort_unstable();
+        for &(memaddr, ..) in &links {
+            let label = &label_mems[memaddr].0;
 
-            let (first, last) = if used_addr.is_empty() {
-                (0, 0)
-            } else {
-                // unwrap ok because vector is guaranteed to not be empty
-                (
-                    used_addr.first().copied().unwrap(),
-                    used_addr.last().copied().unwrap(),
-                )
-            };
+            if !used.iter().any(|&i: &usize| label_mems[i].0 == *label) {
+                used.push(memaddr);
+            }
+        }
 
-            (0..first).chain(last + 1..).take(links.len()).collect()
-        };
+        let unused_addrs = label_addrs(&raw_mems, used.iter().map(|&i| label_mems[i].1.len()));
 
-        assert!(
-            unused_addrs.len() >= links.len(),
-            "One of the memory addresses is too big"
-        );
+        let unused_addrs = match unused_addrs {
+            Some(addrs) => addrs,
+            None if self.partial || cfg!(feature = "strict-no-panic") => {
+                store_err!(self.err, 0..self.src.len(), ErrorKind::AddressTooBig);
+                return Vec::new();
+            }
+            None => panic!("One of the memory addresses is too big"),
+        };
 
-        let mut newlinks = BTreeMap::new();
+        let newlinks = used
+            .into_iter()
+            .zip(unused_addrs)
+            .map(|(i, uid)| {
+                let (addr, data, _) = &label_mems[i];
+                (addr, (uid, data))
+            })
+            .collect::<BTreeMap<_, _>>();
 
         // linking
-        for ((memaddr, progaddr, multiop_idx), uid) in links.into_iter().zip(unused_addrs) {
-            let (addr, data) = &label_mems[memaddr];
-
-            let uid = newlinks.entry(addr).or_insert((uid, *data)).0;
+        for (memaddr, progaddr, multiop_idx) in links {
+            let addr = &label_mems[memaddr].0;
+            let uid = newlinks[addr].0;
 
             self.debug_info
                 .mem
                 .entry(uid)
-                .or_insert_with(|| addr.clone());
+                .or_insert_with(|| addr.to_string());
+
+            self.set_mem_type(uid, label_mems[memaddr].2);
 
             let cir = &mut prog[progaddr];
 
             match cir.inst.op {
                 Op::MultiOp(ref mut ops) if multiop_idx.is_some() => {
-                    ops[multiop_idx.unwrap()] = Op::Addr(uid);
+                    let idx = multiop_idx.unwrap();
+                    ops[idx] = linked(&ops[idx], uid);
                 }
-                Op::Fail(_) => cir.inst.op = Op::Addr(uid),
+                Op::Fail(_) => cir.inst.op = linked(&cir.inst.op, uid),
                 Op::Indirect(_) => {
                     if let Op::Indirect(op) = &mut cir.inst.op {
                         if matches!(op.as_ref(), Op::Fail(_)) {
-                            *op.as_mut() = Op::Addr(uid);
+                            **op = linked(op, uid);
                         }
                     }
                 }
@@ -397,47 +675,370 @@ where
         }
 
         newlinks
-            .values()
-            .copied()
+            .into_values()
+            .flat_map(|(uid, data)| data.iter().enumerate().map(move |(i, &d)| (uid + i, d)))
             .chain(raw_mems)
             .map(|(addr, data)| MemIr { addr, data })
             .collect()
     }
 
-    #[allow(clippy::type_complexity)]
-    pub fn parse(mut self) -> Result<(Vec<InstIr<I>>, Vec<MemIr>, DebugInfo), ErrorMap> {
+    /// Record the type of the memory at `addr` in the debug info, unless it is a word
+    fn set_mem_type(&mut self, addr: usize, ty: MemType) {
+        if ty != MemType::Word {
+            self.debug_info.mem_types.insert(addr, ty);
+        }
+    }
+
+    /// Warn about unused labels, instructions after `JMP` or `END` that can never run, and
+    /// memory that is never accessed
+    fn find_warnings(&mut self, inst_spans: &[Span], insts: &[Inst<'a, I>]) {
+        #[derive(Default)]
+        struct Refs<'a> {
+            labels: Vec<&'a str>,
+            addrs: Vec<usize>,
+            literals: Vec<usize>,
+        }
+
+        fn find_refs<'a>(op: &'a Op, refs: &mut Refs<'a>) {
+            match op {
+                Op::Fail(label) => refs.labels.push(split_offset(label).0),
+                Op::Addr(addr) => refs.addrs.push(*addr),
+                Op::Literal(lit) => refs.literals.push(*lit),
+                Op::Indirect(op) => find_refs(op, refs),
+                Op::MultiOp(ops) => ops.iter().for_each(|op| find_refs(op, refs)),
+                _ => {}
+            }
+        }
+
+        let mut refs = Refs::default();
+
+        for Inst { op, .. } in insts {
+            find_refs(op, &mut refs);
+        }
+
+        let mut warnings = Vec::new();
+        let mut reachable = true;
+        let mut dead: Option<Span> = None;
+
+        for (span, Inst { addr, opcode, .. }) in inst_spans.iter().zip(insts) {
+            let target = match addr {
+                Some(Addr::Label(label)) => refs.labels.contains(&&**label),
+                Some(Addr::Bare(bare)) => refs.addrs.contains(bare),
+                None => false,
+            };
+
+            if let (Some(Addr::Label(label)), false) = (addr, target) {
+                warnings.push((span.clone(), ErrorKind::UnusedLabel(label.to_string())));
+            }
+
+            reachable |= target;
+
+            if reachable {
+                warnings.extend(dead.take().map(|span| (span, ErrorKind::Unreachable)));
+            } else {
+                let start = dead.as_ref().map_or(span.start, |d| d.start);
+                dead = Some(start..span.end);
+            }
+
+            let opcode = opcode.to_string();
+            if opcode.eq_ignore_ascii_case("jmp") || opcode.eq_ignore_ascii_case("end") {
+                reachable = false;
+            }
+        }
+
+        warnings.extend(dead.map(|span| (span, ErrorKind::Unreachable)));
+        // Literals may be addresses, e.g. for `LDI`
+        refs.addrs.extend(refs.literals);
+        warnings.extend(self.unused_mems(&refs.labels, &refs.addrs));
+
+        for (span, warning) in warnings {
+            store_err!(self.err, span, warning);
+        }
+    }
+
+    /// Memory that is not referred to by `labels`, and does not contain any of `addrs` or any
+    /// address stored in memory
+    ///
+    /// Indexed and indirect accesses cannot be followed, so cells at consecutive addresses form a
+    /// block, which counts as accessed if any of its cells, or the address after it, is.
+    fn unused_mems(&self, labels: &[&str], addrs: &[usize]) -> Vec<ParseError> {
+        let blocks = self
+            .lines
+            .split(Vec::is_empty)
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>();
+
+        let mems = match blocks.as_slice() {
+            [_, .., mems] => *mems,
+            _ => return Vec::new(),
+        };
+
+        let pointers = mems
+            .iter()
+            .flat_map(|line| line.iter().skip(1))
+            .flat_map(|(_, token)| match token {
+                Token::BareNumber(n) => vec![*n],
+                Token::LinearMemory(LinearMemory::Repeat { init, .. }) => vec![*init],
+                Token::LinearMemory(LinearMemory::List(values)) => values.clone(),
+                _ => Vec::new(),
+            })
+            .chain(addrs.iter().copied())
+            .collect::<Vec<_>>();
+
+        let mut warnings = Vec::new();
+        // Runs of cells at consecutive addresses, with their start, end, and warnings if unused
+        let mut runs: Vec<(usize, usize, Vec<ParseError>)> = Vec::new();
+
+        for line in mems {
+            match line.as_slice() {
+                // Lines without a value, like `NONE:`, are placeholders
+                [(span, Token::Text(label)), (_, Token::Colon), _, ..]
+                    if !labels.contains(&&**label) =>
+                {
+                    warnings.push((span.clone(), ErrorKind::UnusedMemory(label.to_string())));
+                }
+                [(span, Token::BareNumber(addr)), data @ ..] if !data.is_empty() => {
+                    let len = match data {
+                        [(_, Token::LinearMemory(LinearMemory::Repeat { len, .. }))] => *len,
+                        [(_, Token::LinearMemory(LinearMemory::List(values)))] => values.len(),
+                        [(_, Token::Str(s))] => s.chars().count(),
+                        _ => 1,
+                    };
+
+                    let unused = (span.clone(), ErrorKind::UnusedMemory(addr.to_string()));
+
+                    match runs.last_mut() {
+                        Some((_, end, cells)) if end == addr => {
+                            *end = end.saturating_add(len);
+                            cells.push(unused);
+                        }
+                        _ => runs.push((*addr, addr.saturating_add(len), vec![unused])),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (start, end, cells) in runs {
+            // Pointers just past the end are used for stacks, and as loop limits
+            if !pointers.iter().any(|p| (start..=end).contains(p)) {
+                warnings.extend(cells);
+            }
+        }
+
+        warnings.sort_by_key(|(span, _)| span.start);
+        warnings
+    }
+
+    pub fn parse(self) -> Result<Parsed<I>, ErrorMap> {
+        let (parsed, err) = self.parse_all();
+
+        if err.values().all(|e| e.severity() == Severity::Warning) {
+            Ok(parsed)
+        } else {
+            Err(err)
+        }
+    }
+
+    /// Parse everything that does not have errors, and return it with all errors and warnings
+    ///
+    /// Unlike [`Parser::parse`], this does not panic if the memory is missing.
+    pub fn parse_partial(mut self) -> (Parsed<I>, ErrorMap) {
+        self.partial = true;
+        self.parse_all()
+    }
+
+    fn parse_all(mut self) -> (Parsed<I>, ErrorMap) {
         let (inst_spans, insts, mems) = self.get_insts_and_mems();
 
+        self.find_warnings(&inst_spans, &insts);
+
+        self.debug_info.comments = inst_spans
+            .iter()
+            .enumerate()
+            .filter_map(|(addr, span)| {
+                let rest = self.src[span.end..].lines().next().unwrap_or_default();
+                let comment = rest.trim_start().strip_prefix("//")?.trim();
+
+                (!comment.is_empty()).then(|| (addr, comment.to_string()))
+            })
+            .collect();
         self.debug_info.inst_spans = inst_spans;
 
         let mut inst_ir = self.process_insts(insts);
 
         let mem_ir = self.process_mems(mems, &mut inst_ir);
 
-        if self.err.is_empty() {
-            Ok((inst_ir, mem_ir, self.debug_info))
-        } else {
-            Err(self.err)
+        self.debug_info.warnings = self
+            .err
+            .iter()
+            .filter(|(_, e)| e.severity() == Severity::Warning)
+            .map(|(span, e)| (span.clone(), e.clone()))
+            .collect();
+
+        ((inst_ir, mem_ir, self.debug_info), self.err)
+    }
+}
+
+/// Labels defined in a source, and where its memory is, found without parsing it
+#[cfg(feature = "compile")]
+#[derive(Debug, Default)]
+pub(crate) struct Outline {
+    /// Labels of instructions and memory, with the spans of their lines
+    pub labels: Vec<WithSpan<String>>,
+    /// Span of the memory, from its first entry to its last, if there is one
+    pub mem: Option<Span>,
+}
+
+#[cfg(feature = "compile")]
+pub(crate) fn outline(src: &str) -> Outline {
+    let (lines, _) = TokensWithError(Token::lexer(src)).lines();
+
+    let labels = lines
+        .iter()
+        .filter_map(|line| match (line.as_slice(), line.last()) {
+            ([(start, Token::Text(label)), (_, Token::Colon), ..], Some((end, _))) => {
+                Some((start.start..end.end, label.to_string()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let blocks = lines
+        .split(Vec::is_empty)
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+
+    let mem = match blocks[..] {
+        [_, .., mem] => {
+            let start = mem.first().and_then(|line| line.first()).map(|t| t.0.start);
+            let end = mem.last().and_then(|line| line.last()).map(|t| t.0.end);
+            start.zip(end).map(|(start, end)| start..end)
+        }
+        _ => None,
+    };
+
+    Outline { labels, mem }
+}
+
+/// Split `label+3` or `label-3` into the label and the offset to add, wrapping if negative
+fn split_offset(label: &str) -> (&str, usize) {
+    match label.find(['+', '-']) {
+        Some(idx) => {
+            let offset = label[idx + 1..].parse::<usize>().unwrap_or_default();
+
+            let offset = if label[idx..].starts_with('-') {
+                0_usize.wrapping_sub(offset)
+            } else {
+                offset
+            };
+
+            (&label[..idx], offset)
+        }
+        None => (label, 0),
+    }
+}
+
+/// Replace names defined with `NAME ALIAS rN` in `op` with the registers they stand for
+fn resolve_aliases(op: &mut Op, aliases: &HashMap<String, usize>) {
+    match op {
+        Op::Fail(name) => {
+            if let Some(&reg) = aliases.get(name.as_str()) {
+                *op = Op::Gpr(reg);
+            }
+        }
+        Op::Indirect(op) => resolve_aliases(op, aliases),
+        Op::MultiOp(ops) => {
+            for op in ops {
+                resolve_aliases(op, aliases);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Replace the `SP` register in `op` with `label`, a label of the same name
+fn shadow_sp(op: &mut Op, label: &str) {
+    match op {
+        Op::Sp => *op = Op::Fail(label.into()),
+        Op::Indirect(op) => shadow_sp(op, label),
+        Op::MultiOp(ops) => {
+            for op in ops {
+                shadow_sp(op, label);
+            }
         }
+        _ => {}
     }
 }
 
+/// The address `op` refers to once its label is linked to `addr`, including any offset
+///
+/// Addresses out of range wrap around, and fail when the instruction is executed.
+fn linked(op: &Op, addr: usize) -> Op {
+    match op {
+        Op::Fail(label) => Op::Addr(addr.wrapping_add(split_offset(label).1)),
+        _ => Op::Addr(addr),
+    }
+}
+
+/// Give each label the unused addresses before the first and after the last bare address, with
+/// strings in consecutive addresses
+///
+/// Returns `None` if the addresses run out.
+fn label_addrs(
+    raw_mems: &[(usize, usize)],
+    lens: impl Iterator<Item = usize>,
+) -> Option<Vec<usize>> {
+    let mut used_addr = raw_mems.iter().map(|x| x.0).collect::<Vec<_>>();
+
+    used_addr.sort_unstable();
+
+    let (first, last) = if used_addr.is_empty() {
+        (0, 0)
+    } else {
+        // unwrap ok because vector is guaranteed to not be empty
+        (
+            used_addr.first().copied().unwrap(),
+            used_addr.last().copied().unwrap(),
+        )
+    };
+
+    let after = last.checked_add(1);
+    // `None` once every address is used
+    let mut next = Some(0_usize);
+
+    lens.map(|len| {
+        let current = next?;
+
+        let start = match current.checked_add(len) {
+            Some(end) if end <= first => current,
+            _ => current.max(after?),
+        };
+
+        // `len` is never 0
+        next = start.checked_add(len - 1)?.checked_add(1);
+
+        Some(start)
+    })
+    .collect()
+}
+
 #[derive(Debug, Clone)]
-pub enum Addr {
+pub enum Addr<'a> {
     Bare(usize),
-    Label(String),
+    Label(Cow<'a, str>),
 }
 
-impl Addr {
+impl Addr<'_> {
     fn as_dbg_string(&self) -> String {
         match self {
-            Addr::Label(label) => label.clone(),
+            Addr::Label(label) => label.to_string(),
             Addr::Bare(bare) => bare.to_string(),
         }
     }
 }
 
-impl Display for Addr {
+impl Display for Addr<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Bare(addr) => write!(f, "{addr}"),
@@ -478,13 +1079,26 @@ where
     }
 }
 
-pub struct Inst<I> {
-    pub addr: Option<Addr>,
+pub struct Inst<'a, I> {
+    pub addr: Option<Addr<'a>>,
     pub opcode: I,
     pub op: Op,
 }
 
-impl<I> Debug for Inst<I>
+impl<I> Inst<'_, I>
+where
+    I: InstSet,
+    <I as FromStr>::Err: Display,
+{
+    /// Whether the operands have one of the forms accepted by the instruction
+    fn has_valid_operands(&self) -> bool {
+        self.opcode.operands().map_or(true, |shapes| {
+            shapes.iter().any(|&shape| inst::fits(shape, &self.op))
+        })
+    }
+}
+
+impl<I> Debug for Inst<'_, I>
 where
     I: Display,
 {
@@ -497,7 +1111,7 @@ where
     }
 }
 
-impl<I> Display for Inst<I>
+impl<I> Display for Inst<'_, I>
 where
     I: Display,
 {
@@ -515,19 +1129,26 @@ where
     }
 }
 
-enum MemEnum {
-    Linear(Vec<Mem>),
-    One(Mem),
+enum MemEnum<'a> {
+    Linear(Vec<Mem<'a>>),
+    One(Mem<'a>),
 }
 
-pub struct Mem {
-    pub addr: Addr,
-    pub data: usize,
+/// Contents of consecutive addresses, starting at `addr`
+pub struct Mem<'a> {
+    pub addr: Addr<'a>,
+    pub data: Vec<usize>,
+    /// Type of the first cell
+    pub ty: MemType,
 }
 
-impl From<(Addr, usize)> for Mem {
-    fn from((addr, data): (Addr, usize)) -> Self {
-        Self { addr, data }
+impl<'a> From<(Addr<'a>, usize)> for Mem<'a> {
+    fn from((addr, data): (Addr<'a>, usize)) -> Self {
+        Self {
+            addr,
+            data: vec![data],
+            ty: MemType::Word,
+        }
     }
 }
 
@@ -0,0 +1,295 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use super::{
+    expr::split_label,
+    lexer::{ErrorMap, Span, Token, TokensWithError, WithSpan},
+};
+use logos::Logos;
+use std::ops::Range;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ExtractError {
+    #[error("Source could not be parsed")]
+    Parse(ErrorMap),
+    #[error("Selection does not contain any instructions")]
+    EmptySelection,
+    #[error("Selection is not part of the program")]
+    OutsideProgram,
+    #[error("`{0}` is already defined")]
+    NameTaken(String),
+    #[error("`{0}` is jumped to from outside the selection")]
+    JumpInto(String),
+    #[error("`{0}` is outside the selection, and is not the instruction after it")]
+    JumpOut(String),
+}
+
+/// Where an instruction can be jumped to from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Target {
+    Label(String),
+    Bare(usize),
+}
+
+impl Target {
+    fn name(&self) -> String {
+        match self {
+            Self::Label(label) => label.clone(),
+            Self::Bare(addr) => addr.to_string(),
+        }
+    }
+}
+
+pub(super) struct Line<'a> {
+    pub text: &'a str,
+    /// Start of `text` in the source
+    pub offset: usize,
+    pub tokens: Vec<WithSpan<Token<'a>>>,
+}
+
+impl Line<'_> {
+    pub fn def(&self) -> Option<(&Span, Target)> {
+        match self.tokens.as_slice() {
+            [(span, Token::Text(label)), (_, Token::Colon), ..] => {
+                Some((span, Target::Label(label.to_string())))
+            }
+            [(span, Token::BareNumber(addr)), (_, Token::Text(_)), ..] => {
+                Some((span, Target::Bare(*addr)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Operands that could refer to an instruction
+    fn refs(&self) -> impl Iterator<Item = (&Span, Target)> {
+        let skip = match self.def() {
+            Some((_, Target::Label(_))) => 3,
+            Some((_, Target::Bare(_))) => 2,
+            None => 1,
+        };
+
+        self.tokens
+            .iter()
+            .skip(skip)
+            .filter_map(|(span, token)| match token {
+                Token::Text(label) => Some((span, Target::Label(label.to_string()))),
+                Token::Indirect(label) => Some((span, Target::Label((*label).to_string()))),
+                Token::AddrExpr(expr) => Some((span, Target::Label(split_label(expr).0.into()))),
+                Token::BareNumber(addr) => Some((span, Target::Bare(*addr))),
+                _ => None,
+            })
+    }
+
+    fn indent(&self) -> &str {
+        &self.text[..self.text.len() - self.text.trim_start().len()]
+    }
+
+    /// `text` with the tokens at `spans` replaced
+    fn replace(&self, replacements: &[(Span, String)]) -> String {
+        let mut out = String::with_capacity(self.text.len());
+        let mut last = 0;
+
+        for (span, new) in replacements {
+            out.push_str(&self.text[last..span.start - self.offset]);
+            out.push_str(new);
+            last = span.end - self.offset;
+        }
+
+        out.push_str(&self.text[last..]);
+        out
+    }
+}
+
+/// The lines of `src`, and the index after the last line of the program
+pub(super) fn split_program(src: &str) -> Result<(Vec<Line<'_>>, usize), ErrorMap> {
+    let (tokens, errors) = TokensWithError(Token::lexer(src)).lines();
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut offset = 0;
+    let all = src
+        .split_inclusive('\n')
+        .zip(tokens)
+        .map(|(raw, tokens)| {
+            let line = Line {
+                text: raw.trim_end_matches(['\r', '\n']),
+                offset,
+                tokens,
+            };
+            offset += raw.len();
+            line
+        })
+        .collect::<Vec<_>>();
+
+    // The memory is the last block of lines, after a blank line
+    let last_code = all.iter().rposition(|l| !l.tokens.is_empty()).unwrap_or(0);
+    let mem_start = all[..last_code]
+        .iter()
+        .rposition(|l| l.tokens.is_empty())
+        .map_or(all.len(), |idx| idx + 1);
+    let prog_end = all[..mem_start]
+        .iter()
+        .rposition(|l| !l.tokens.is_empty())
+        .map_or(0, |idx| idx + 1);
+
+    Ok((all, prog_end))
+}
+
+/// Operand `old` referring to `new` instead, keeping brackets and offsets
+fn renamed(old: &str, new: &str) -> String {
+    if old.starts_with('(') {
+        format!("({new})")
+    } else {
+        format!("{new}{}", split_label(old).1)
+    }
+}
+
+/// Move the program lines in `lines` (0-indexed, end exclusive) into a function called `name`
+///
+/// The selected lines are replaced with `CALL name`, and the function, ending in `RET`, is
+/// appended to the program. Jumps are updated to keep the program working:
+///
+/// * If the first instruction has a label, the `CALL` keeps it, and jumps to it from inside
+///   the selection now go to `name`.
+/// * Jumps from inside the selection to the instruction after it return from the function
+///   instead, through a `name_EXIT` label on the `RET`.
+///
+/// Any other jump into or out of the selection is an error. The program should stop with
+/// `END` before reaching the function, and must be parsed with an instruction set that has
+/// `CALL` and `RET`, such as [`Extended`](super::Extended).
+///
+/// # Example
+/// ```
+/// use cambridge_asm::parse::extract_function;
+///
+/// let src = "LDM #3\nLOOP: OUT\nDEC ACC\nCMP #0\nJPN LOOP\nEND\n\nNONE:\n";
+///
+/// assert_eq!(
+///     extract_function(src, 1..5, "COUNTDOWN").unwrap(),
+///     "LDM #3\nLOOP: CALL COUNTDOWN\nEND\nCOUNTDOWN: OUT\nDEC ACC\nCMP #0\nJPN COUNTDOWN\nRET\n\nNONE:\n"
+/// );
+/// ```
+pub fn extract_function(
+    src: &str,
+    lines: Range<usize>,
+    name: &str,
+) -> Result<String, ExtractError> {
+    let (all, prog_end) = split_program(src).map_err(ExtractError::Parse)?;
+
+    if lines.start >= lines.end || lines.end > prog_end {
+        return Err(ExtractError::OutsideProgram);
+    }
+
+    let first = (lines.clone())
+        .find(|&idx| !all[idx].tokens.is_empty())
+        .ok_or(ExtractError::EmptySelection)?;
+    let after = (lines.end..prog_end).find(|&idx| !all[idx].tokens.is_empty());
+
+    let exit = format!("{name}_EXIT");
+    for (_, def) in all.iter().filter_map(Line::def) {
+        for taken in [name, &exit] {
+            if def == Target::Label(taken.into()) {
+                return Err(ExtractError::NameTaken(taken.into()));
+            }
+        }
+    }
+
+    let entry = all[first].def().map(|(_, t)| t);
+    let inner = lines
+        .clone()
+        .filter_map(|idx| all[idx].def().map(|(_, t)| t))
+        .collect::<Vec<_>>();
+    let outer = all[..prog_end]
+        .iter()
+        .filter_map(Line::def)
+        .map(|(_, t)| t)
+        .filter(|def| !inner.contains(def))
+        .collect::<Vec<_>>();
+    let next = after.and_then(|idx| all[idx].def().map(|(_, t)| t));
+
+    for (idx, line) in all[..prog_end].iter().enumerate() {
+        if lines.contains(&idx) {
+            continue;
+        }
+
+        if let Some((_, target)) = line
+            .refs()
+            .find(|(_, t)| inner.contains(t) && Some(t) != entry.as_ref())
+        {
+            return Err(ExtractError::JumpInto(target.name()));
+        }
+    }
+
+    let mut exits = false;
+    let mut body = Vec::new();
+
+    for idx in lines.clone() {
+        let line = &all[idx];
+        let mut replacements = Vec::new();
+
+        if idx == first {
+            match line.def() {
+                Some((span, Target::Label(_))) => replacements.push((span.clone(), name.into())),
+                Some((span, Target::Bare(_))) => {
+                    replacements.push((span.clone(), format!("{name}:")));
+                }
+                None => {
+                    let start = line.offset + line.indent().len();
+                    replacements.push((start..start, format!("{name}: ")));
+                }
+            }
+        }
+
+        for (span, target) in line.refs() {
+            let new = if Some(&target) == entry.as_ref() {
+                name
+            } else if outer.contains(&target) {
+                if Some(&target) != next.as_ref() {
+                    return Err(ExtractError::JumpOut(target.name()));
+                }
+
+                exits = true;
+                &exit
+            } else {
+                continue;
+            };
+
+            replacements.push((span.clone(), renamed(&src[span.clone()], new)));
+        }
+
+        body.push(line.replace(&replacements));
+    }
+
+    let indent = all[first].indent();
+    let call = match &entry {
+        Some(Target::Label(label)) => format!("{indent}{label}: CALL {name}"),
+        Some(Target::Bare(addr)) => format!("{indent}{addr} CALL {name}"),
+        None => format!("{indent}CALL {name}"),
+    };
+    let ret = if exits {
+        format!("{indent}{exit}: RET")
+    } else {
+        format!("{indent}RET")
+    };
+
+    let mut out = Vec::with_capacity(all.len() + 2);
+    out.extend(all[..lines.start].iter().map(|l| l.text.to_string()));
+    out.push(call);
+    out.extend(all[lines.end..prog_end].iter().map(|l| l.text.to_string()));
+    out.extend(body);
+    out.push(ret);
+    out.extend(all[prog_end..].iter().map(|l| l.text.to_string()));
+
+    let mut out = out.join("\n");
+
+    if src.ends_with('\n') {
+        out.push('\n');
+    }
+
+    Ok(out)
+}
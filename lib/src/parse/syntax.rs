@@ -0,0 +1,77 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::inst::Op;
+
+/// Operand syntax accepted by the parser
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// All operands supported by the instruction set, including general purpose registers,
+    /// indirect operands and multiple operands
+    #[default]
+    Extended,
+    /// Only the instructions and operands in the Cambridge 9618 instruction table
+    ///
+    /// Anything else is rejected with [`ErrorKind::Extension`](super::ErrorKind::Extension), so
+    /// that practice programs also work in the exam.
+    Cambridge,
+}
+
+/// Operands allowed by the Cambridge instruction table
+#[derive(Clone, Copy)]
+enum Allowed {
+    None,
+    Addr,
+    Literal,
+    AddrOrLiteral,
+    /// `ACC` or `IX`
+    Register,
+    Ix,
+}
+
+fn allowed(opcode: &str) -> Option<Allowed> {
+    use Allowed::{Addr, AddrOrLiteral, Ix, Literal, None, Register};
+
+    let allowed = match opcode {
+        "LDM" | "LDR" | "LSL" | "LSR" => Literal,
+        "LDD" | "LDI" | "LDX" | "STO" | "JMP" | "JPE" | "JPN" | "CMI" => Addr,
+        "ADD" | "SUB" | "CMP" | "AND" | "OR" | "XOR" => AddrOrLiteral,
+        "INC" | "DEC" => Register,
+        "MOV" => Ix,
+        "IN" | "OUT" | "END" => None,
+        _ => return Option::None,
+    };
+
+    Some(allowed)
+}
+
+/// Check that `opcode` and `op` are in the Cambridge instruction table, returning a description
+/// of the extension otherwise
+pub(crate) fn check_cambridge(opcode: &str, op: &Op) -> Result<(), String> {
+    let opcode = opcode.to_uppercase();
+
+    let allowed = allowed(&opcode).ok_or_else(|| format!("The `{opcode}` instruction"))?;
+
+    let ok = match op {
+        Op::Gpr(_) => return Err(format!("General purpose register `{op}`")),
+        Op::Indirect(_) => return Err(format!("Indirect operand `{op}`")),
+        Op::MultiOp(ops) => return Err(format!("`{opcode}` with {} operands", ops.len())),
+        Op::Null => matches!(allowed, Allowed::None),
+        // Labels are resolved later, so they are addresses
+        Op::Addr(_) | Op::Fail(_) => matches!(allowed, Allowed::Addr | Allowed::AddrOrLiteral),
+        Op::Literal(_) => matches!(allowed, Allowed::Literal | Allowed::AddrOrLiteral),
+        Op::Acc => matches!(allowed, Allowed::Register),
+        Op::Ix => matches!(allowed, Allowed::Register | Allowed::Ix),
+        Op::Cmp | Op::Ar | Op::Sp => false,
+    };
+
+    if ok {
+        Ok(())
+    } else if let Op::Null = op {
+        Err(format!("`{opcode}` without an operand"))
+    } else {
+        Err(format!("`{opcode}` with operand `{op}`"))
+    }
+}
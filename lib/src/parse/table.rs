@@ -0,0 +1,142 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+/// Convert a program in the tabular layout used in question papers into pseudoassembly
+///
+/// Each row starts with an address, followed by either an instruction (opcode and operand,
+/// in one column or two) or the contents of a memory location. Columns are separated by `|`,
+/// tabs or spaces, and any further columns are kept as comments. Rows that do not start with an
+/// address, such as headers and separators, are ignored, so instructions and memory can be in
+/// one table or two.
+///
+/// Instruction addresses become labels, so jumps to them work as in the question. Memory
+/// contents written as 8 or more binary digits (`0000 0101`) are read as binary, `&` is read as
+/// hexadecimal, and empty contents are 0.
+///
+/// The result can be passed to [`jit`](super::jit).
+///
+/// # Example
+/// ```
+/// use cambridge_asm::parse::from_table;
+///
+/// let table = "\
+/// | Address | Opcode | Operand |
+/// |---------|--------|---------|
+/// | 200     | LDD    | 300     |
+/// | 201     | ADD    | #1      |
+/// | 202     | END    |         |
+///
+/// | Address | Contents  |
+/// |---------|-----------|
+/// | 300     | 0000 0101 |
+/// ";
+///
+/// assert_eq!(
+///     from_table(table),
+///     "200 LDD 300\n201 ADD #1\n202 END\n\n300 B00000101\n"
+/// );
+/// ```
+pub fn from_table(src: &str) -> String {
+    let mut insts = String::new();
+    let mut mems = String::new();
+
+    for line in src.lines() {
+        let cells = cells(line);
+
+        let (addr, rest) = match cells.split_first() {
+            Some((addr, rest)) if is_addr(addr) => (addr, rest),
+            _ => continue,
+        };
+
+        match rest.first() {
+            Some(first)
+                if first.starts_with(|c: char| c.is_ascii_alphabetic()) && !is_value(first) =>
+            {
+                // The opcode and operand may share a column
+                let (inst, comments) = if first.contains(char::is_whitespace) || rest.len() < 2 {
+                    ((*first).to_string(), &rest[1..])
+                } else {
+                    (format!("{first} {}", rest[1]), &rest[2..])
+                };
+
+                push_line(&mut insts, &format!("{addr} {inst}"), comments);
+            }
+            _ => {
+                // Whitespace-separated binary digits are split into several cells
+                let len = rest.iter().take_while(|cell| is_value(cell)).count();
+                let (digits, comments) = rest.split_at(len);
+
+                push_line(
+                    &mut mems,
+                    &format!("{addr} {}", value(&digits.concat())),
+                    comments,
+                );
+            }
+        }
+    }
+
+    // The parser requires a memory section
+    if mems.is_empty() {
+        mems.push_str("NONE:\n");
+    }
+
+    format!("{insts}\n{mems}")
+}
+
+fn cells(line: &str) -> Vec<&str> {
+    let cells = if line.contains('|') {
+        line.split('|').collect::<Vec<_>>()
+    } else if line.contains('\t') {
+        line.split('\t').collect()
+    } else {
+        line.split_whitespace().collect()
+    };
+
+    cells
+        .into_iter()
+        .map(str::trim)
+        .filter(|cell| !cell.is_empty())
+        .collect()
+}
+
+fn is_addr(cell: &str) -> bool {
+    cell.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Memory contents, possibly one of several groups of binary digits
+fn is_value(cell: &str) -> bool {
+    let cell = cell.replace(char::is_whitespace, "");
+
+    match cell.strip_prefix(|c| matches!(c, '&' | '#' | 'b' | 'B' | 'x' | 'X')) {
+        Some(digits) => !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_hexdigit()),
+        None => !cell.is_empty() && cell.bytes().all(|b| b.is_ascii_digit()),
+    }
+}
+
+fn value(cell: &str) -> String {
+    let cell = cell.replace(char::is_whitespace, "");
+    let cell = cell.trim_start_matches('#');
+
+    if cell.is_empty() {
+        "0".into()
+    } else if let Some(hex) = cell.strip_prefix('&') {
+        format!("x{hex}")
+    } else if cell.len() >= 8 && cell.bytes().all(|b| b == b'0' || b == b'1') {
+        format!("B{cell}")
+    } else {
+        cell.into()
+    }
+}
+
+fn push_line(out: &mut String, line: &str, comments: &[&str]) {
+    out.push_str(line);
+
+    if !comments.is_empty() {
+        out.push_str(" // ");
+        out.push_str(&comments.join(" "));
+    }
+
+    out.push('\n');
+}
@@ -0,0 +1,443 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Pool of worker threads for running many programs concurrently
+//!
+//! Programs are parsed once into a [`ProgramImage`] and registered with an [`ExecutorPool`]
+//! under an id. Each [`ExecutorPool::submit`] runs a fresh copy of the program with its own
+//! input on one of the workers, and returns a [`Submission`] that can be awaited or waited on
+//! for the [`RunReport`].
+//!
+//! The queue of pending runs is bounded, so [`ExecutorPool::submit`] blocks and
+//! [`ExecutorPool::try_submit`] fails with [`PoolError::Busy`] while it is full.
+//!
+//! # Example
+//! ```
+//! use cambridge_asm::{parse::DefaultSet, pool::{ExecutorPool, PoolConfig, ProgramImage}};
+//!
+//! let pool = ExecutorPool::new(PoolConfig::default());
+//! pool.register("echo", ProgramImage::parse::<DefaultSet>("IN\nOUT\nEND\n\nNONE:\n")?);
+//!
+//! let report = pool.submit("echo", "A").unwrap().wait();
+//!
+//! assert!(report.result.is_ok());
+//! assert_eq!(report.output, b"A");
+//! # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+//! ```
+
+use crate::{
+    exec::{Capture, DebugInfo, ExTree, Executor, ExecutorState, Io, Memory, RtResult, Source},
+    inst::InstSet,
+    parse::{parse, ErrorMap, Syntax},
+};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    io::Cursor,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc, Condvar, Mutex, PoisonError, RwLock,
+    },
+    task::{Context as TaskContext, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// A parsed program, ready to be run any number of times
+#[derive(Clone)]
+pub struct ProgramImage {
+    prog: ExTree,
+    mem: Memory,
+    source: Source,
+    debug_info: DebugInfo,
+}
+
+impl ProgramImage {
+    pub fn parse<T>(src: &str) -> Result<Self, ErrorMap>
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        let (prog, mem, source, debug_info) = parse::<T>(src, Syntax::default())?;
+
+        Ok(Self {
+            prog,
+            mem: Memory::new(mem),
+            source,
+            debug_info,
+        })
+    }
+
+    /// Make an [`Executor`] that runs a fresh copy of the program
+    pub fn to_executor(&self, io: Io) -> Executor {
+        Executor::builder(self.prog.clone())
+            .with_source(self.source.clone())
+            .with_mem(self.mem.clone())
+            .with_io(io)
+            .with_debug_info(self.debug_info.clone())
+            .build()
+    }
+}
+
+/// Takes the program and initial memory of `exec`, e.g. one made from a
+/// [`CompiledProg`](crate::compile::CompiledProg)
+impl From<Executor> for ProgramImage {
+    fn from(exec: Executor) -> Self {
+        Self {
+            prog: exec.prog,
+            mem: exec.ctx.mem,
+            source: exec.source,
+            debug_info: exec.debug_info,
+        }
+    }
+}
+
+/// Settings for an [`ExecutorPool`]
+#[derive(Debug, Clone, Copy)]
+pub struct PoolConfig {
+    /// Number of worker threads
+    pub workers: usize,
+    /// Number of runs that can wait for a worker before submitting blocks
+    pub queue: usize,
+    /// Maximum duration of each run, see [`Executor::exec_with_timeout`]
+    pub timeout: Option<Duration>,
+    /// Maximum bytes of output of each run, see [`Io::set_output_limit`]
+    pub output_limit: Option<u64>,
+}
+
+impl Default for PoolConfig {
+    /// One worker per CPU, a queue of 64 runs, and no limits
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(1, Into::into),
+            queue: 64,
+            timeout: None,
+            output_limit: None,
+        }
+    }
+}
+
+/// Outcome of one run of a program
+#[derive(Debug)]
+pub struct RunReport {
+    pub result: RtResult,
+    pub output: Vec<u8>,
+    /// State of execution when the run ended
+    pub state: ExecutorState,
+    pub duration: Duration,
+}
+
+/// Errors returned when submitting to an [`ExecutorPool`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    #[error("No program registered as `{0}`")]
+    UnknownProgram(String),
+    #[error("The queue is full")]
+    Busy,
+    #[error("All workers have stopped")]
+    Closed,
+}
+
+#[derive(Default)]
+struct Slot {
+    report: Option<RunReport>,
+    waker: Option<Waker>,
+}
+
+type Shared = Arc<(Mutex<Slot>, Condvar)>;
+
+/// A pending run, which completes with its [`RunReport`]
+///
+/// Can be awaited in any async runtime, or waited on with [`Submission::wait`].
+pub struct Submission(Shared);
+
+impl Submission {
+    /// Block until the run is complete
+    pub fn wait(self) -> RunReport {
+        let (slot, done) = &*self.0;
+        let mut slot = slot.lock().unwrap_or_else(PoisonError::into_inner);
+
+        loop {
+            if let Some(report) = slot.report.take() {
+                return report;
+            }
+
+            slot = done.wait(slot).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl Future for Submission {
+    type Output = RunReport;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let mut slot = self.0 .0.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(report) = slot.report.take() {
+            Poll::Ready(report)
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct Job {
+    image: Arc<ProgramImage>,
+    input: Vec<u8>,
+    shared: Shared,
+}
+
+/// Runs registered programs on a fixed set of worker threads
+///
+/// Dropping the pool waits for all submitted runs to finish.
+pub struct ExecutorPool {
+    programs: RwLock<HashMap<String, Arc<ProgramImage>>>,
+    sender: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ExecutorPool {
+    pub fn new(config: PoolConfig) -> Self {
+        let (sender, receiver) = sync_channel(config.queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..config.workers.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || Self::work(&receiver, config))
+            })
+            .collect();
+
+        Self {
+            programs: RwLock::default(),
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Register `image` as `id`, returning the program previously registered as `id`
+    ///
+    /// Runs that were already submitted are not affected.
+    pub fn register(
+        &self,
+        id: impl Into<String>,
+        image: ProgramImage,
+    ) -> Option<Arc<ProgramImage>> {
+        self.programs
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id.into(), Arc::new(image))
+    }
+
+    pub fn unregister(&self, id: &str) -> Option<Arc<ProgramImage>> {
+        self.programs
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(id)
+    }
+
+    /// Run the program registered as `id` with `input`, blocking while the queue is full
+    pub fn submit(&self, id: &str, input: impl Into<Vec<u8>>) -> Result<Submission, PoolError> {
+        let (job, submission) = self.job(id, input.into())?;
+
+        self.sender()?.send(job).map_err(|_| PoolError::Closed)?;
+
+        Ok(submission)
+    }
+
+    /// Run the program registered as `id` with `input`, failing with [`PoolError::Busy`] if
+    /// the queue is full
+    pub fn try_submit(&self, id: &str, input: impl Into<Vec<u8>>) -> Result<Submission, PoolError> {
+        let (job, submission) = self.job(id, input.into())?;
+
+        self.sender()?.try_send(job).map_err(|e| match e {
+            TrySendError::Full(_) => PoolError::Busy,
+            TrySendError::Disconnected(_) => PoolError::Closed,
+        })?;
+
+        Ok(submission)
+    }
+
+    fn sender(&self) -> Result<&SyncSender<Job>, PoolError> {
+        self.sender.as_ref().ok_or(PoolError::Closed)
+    }
+
+    fn job(&self, id: &str, input: Vec<u8>) -> Result<(Job, Submission), PoolError> {
+        let image = self
+            .programs
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(id)
+            .cloned()
+            .ok_or_else(|| PoolError::UnknownProgram(id.to_string()))?;
+
+        let shared = Shared::default();
+
+        let job = Job {
+            image,
+            input,
+            shared: Arc::clone(&shared),
+        };
+
+        Ok((job, Submission(shared)))
+    }
+
+    fn work(receiver: &Mutex<Receiver<Job>>, config: PoolConfig) {
+        loop {
+            // The lock is released as soon as a job is received
+            let job = receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+
+            let Job {
+                image,
+                input,
+                shared,
+            } = match job {
+                Ok(job) => job,
+                // The pool was dropped
+                Err(_) => break,
+            };
+
+            let report = Self::run(&image, input, config);
+
+            let (slot, done) = &*shared;
+            let mut slot = slot.lock().unwrap_or_else(PoisonError::into_inner);
+            slot.report = Some(report);
+
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+
+            done.notify_all();
+        }
+    }
+
+    fn run(image: &ProgramImage, input: Vec<u8>, config: PoolConfig) -> RunReport {
+        let start = Instant::now();
+        let out = Capture::default();
+
+        let mut io = Io::new(Cursor::new(input), out.clone());
+
+        if let Some(limit) = config.output_limit {
+            io.set_output_limit(limit);
+        }
+
+        let mut exec = image.to_executor(io);
+
+        // A panicking instruction must not take the worker down with it
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            exec.exec_with_timeout(config.timeout.unwrap_or(Duration::MAX))
+                .map(|_| ())
+                .map_err(|fault| fault.error)
+        }))
+        .unwrap_or_else(|_| Err("Execution panicked".into()));
+
+        RunReport {
+            result,
+            output: out.take(),
+            state: exec.snapshot(),
+            duration: start.elapsed(),
+        }
+    }
+}
+
+impl Drop for ExecutorPool {
+    fn drop(&mut self) {
+        // Workers finish the queued runs, then stop once the channel is closed
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod pool_tests {
+    use super::{ExecutorPool, PoolConfig, PoolError, ProgramImage};
+    use crate::{exec::RtError, parse::DefaultSet};
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        time::Duration,
+    };
+
+    #[test]
+    fn runs() {
+        let pool = ExecutorPool::new(PoolConfig {
+            workers: 2,
+            queue: 4,
+            timeout: Some(Duration::from_millis(100)),
+            output_limit: None,
+        });
+
+        pool.register(
+            "inc",
+            ProgramImage::parse::<DefaultSet>("IN\nINC ACC\nOUT\nEND\n\nNONE:\n").unwrap(),
+        );
+        pool.register(
+            "loop",
+            ProgramImage::parse::<DefaultSet>("X: JMP X\n\nNONE:\n").unwrap(),
+        );
+
+        let submissions = (b'A'..=b'J')
+            .map(|c| pool.submit("inc", [c]).unwrap())
+            .collect::<Vec<_>>();
+
+        let outputs = submissions
+            .into_iter()
+            .map(|s| s.wait().output)
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, (b'B'..=b'K').map(|c| vec![c]).collect::<Vec<_>>());
+
+        let report = pool.submit("loop", []).unwrap().wait();
+        assert!(matches!(report.result, Err(RtError::Timeout(_))));
+
+        assert!(matches!(
+            pool.submit("none", []),
+            Err(PoolError::UnknownProgram(id)) if id == "none"
+        ));
+    }
+
+    #[test]
+    fn poll() {
+        struct Noop;
+
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let pool = ExecutorPool::new(PoolConfig::default());
+        pool.register(
+            "out",
+            ProgramImage::parse::<DefaultSet>("LDM #65\nOUT\nEND\n\nNONE:\n").unwrap(),
+        );
+
+        let mut submission = pool.submit("out", []).unwrap();
+        let waker = Waker::from(Arc::new(Noop));
+        let mut cx = Context::from_waker(&waker);
+
+        let report = loop {
+            if let Poll::Ready(report) = Pin::new(&mut submission).poll(&mut cx) {
+                break report;
+            }
+
+            std::thread::yield_now();
+        };
+
+        assert_eq!(report.output, b"A");
+        assert_eq!(report.state.acc, 65);
+    }
+}
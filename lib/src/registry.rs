@@ -0,0 +1,499 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Instruction sets assembled at runtime
+//!
+//! An [`InstRegistry`] holds instructions registered by name, for hosts such as GUIs and
+//! scripting environments that cannot define an instruction set type with [`inst_set`]. The
+//! generic APIs take [`DynInstSet`] as the instruction set, which looks instructions up in the
+//! registry entered with [`InstRegistry::scope`]. [`InstRegistry::jit`] and
+//! [`InstRegistry::exec`] do this for the common case.
+//!
+//! # Example
+//! ```
+//! use cambridge_asm::{
+//!     exec::{Context, RtResult},
+//!     inst::Op,
+//!     make_io,
+//!     parse::Core,
+//!     registry::InstRegistry,
+//! };
+//!
+//! fn double(ctx: &mut Context, _: &Op) -> RtResult {
+//!     ctx.acc *= 2;
+//!     Ok(())
+//! }
+//!
+//! let registry = InstRegistry::from_set::<Core>().with("DOUBLE", double, Some(&[&[]]));
+//!
+//! let mut exec = registry.jit("LDM #21\nDOUBLE\nEND\n\nNONE:\n", make_io!())?;
+//! registry.exec(&mut exec);
+//!
+//! assert_eq!(exec.ctx.acc, 42);
+//! # Ok::<_, cambridge_asm::parse::ErrorMap>(())
+//! ```
+//!
+//! Registries can also be described by a [`SetSpec`], e.g. loaded from a file, to choose a
+//! subset of the built-in instructions without recompiling.
+
+use crate::{
+    exec::{ExecFunc, Executor, Exit, Io},
+    inst::{Flow, InstSet, OperandSpec},
+    parse::{jit, ErrorMap},
+};
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    ops::Deref,
+    str::FromStr,
+    sync::Arc,
+};
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The largest built-in instruction set, which [`SetSpec`] picks instructions from
+#[cfg(feature = "float")]
+pub type Builtins = crate::parse::Float;
+
+/// The largest built-in instruction set, which [`SetSpec`] picks instructions from
+#[cfg(not(feature = "float"))]
+pub type Builtins = crate::parse::DefaultSet;
+
+/// Description of an instruction set made of built-in instructions
+///
+/// With the `serde` feature, it can be loaded from a file, e.g. in JSON:
+///
+/// ```json
+/// {
+///     "instructions": [
+///         { "name": "LDM" },
+///         { "name": "LOAD", "builtin": "LDD", "aliases": ["LD"] },
+///         { "name": "END" }
+///     ]
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SetSpec {
+    pub instructions: Vec<InstSpec>,
+}
+
+/// An instruction of a [`SetSpec`]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstSpec {
+    /// Name of the instruction in source
+    pub name: String,
+    /// Instruction of [`Builtins`] to run, `name` if not given
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub builtin: Option<String>,
+    /// Other names for the instruction
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub aliases: Vec<String>,
+}
+
+/// Errors in a [`SetSpec`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SpecError {
+    #[error("`{0}` is not a built-in instruction")]
+    UnknownBuiltin(String),
+    #[error("`{0}` is defined more than once")]
+    Duplicate(String),
+}
+
+#[derive(Clone)]
+struct Entry {
+    name: String,
+    func: ExecFunc,
+    operands: OperandSpec,
+    flow: Option<Flow>,
+    doc: String,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<InstRegistry>> = const { RefCell::new(None) };
+}
+
+/// Instructions registered at runtime, see the [module docs](self)
+///
+/// Clones share the registered instructions until one of them registers more.
+#[derive(Clone, Default)]
+pub struct InstRegistry {
+    entries: Arc<Vec<Entry>>,
+}
+
+impl InstRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry containing every instruction of `T`
+    pub fn from_set<T>() -> Self
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        let mut registry = Self::new();
+        registry.extend_from::<T>();
+        registry
+    }
+
+    /// A registry containing the instructions described by `spec`
+    ///
+    /// # Example
+    /// ```
+    /// use cambridge_asm::{
+    ///     make_io,
+    ///     registry::{InstRegistry, InstSpec, SetSpec},
+    /// };
+    ///
+    /// let inst = |name: &str, builtin: Option<&str>| InstSpec {
+    ///     name: name.into(),
+    ///     builtin: builtin.map(Into::into),
+    ///     aliases: Vec::new(),
+    /// };
+    /// let spec = SetSpec {
+    ///     instructions: vec![inst("LOAD", Some("LDM")), inst("END", None)],
+    /// };
+    /// let registry = InstRegistry::from_spec(&spec)?;
+    ///
+    /// assert!(registry.jit("LOAD #1\nEND\n\nNONE:\n", make_io!()).is_ok());
+    /// assert!(registry.jit("LDM #1\nEND\n\nNONE:\n", make_io!()).is_err());
+    /// # Ok::<_, Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_spec(spec: &SetSpec) -> Result<Self, SpecError> {
+        let builtins = Self::from_set::<Builtins>();
+        let mut registry = Self::new();
+
+        for InstSpec {
+            name,
+            builtin,
+            aliases,
+        } in &spec.instructions
+        {
+            let builtin = builtin.as_ref().unwrap_or(name).to_uppercase();
+            let entry = builtins
+                .entries
+                .iter()
+                .find(|e| e.name == builtin)
+                .ok_or(SpecError::UnknownBuiltin(builtin))?;
+
+            for name in std::iter::once(name).chain(aliases) {
+                let name = name.to_uppercase();
+
+                if registry.names().any(|n| n == name) {
+                    return Err(SpecError::Duplicate(name));
+                }
+
+                registry.insert(Entry {
+                    name,
+                    ..entry.clone()
+                });
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// A registry containing the built-in instructions that `lookup` gives a function for
+    ///
+    /// Operands, flow and docs are taken from the built-in instruction of the same name.
+    pub(crate) fn from_lookup(mut lookup: impl FnMut(&str) -> Option<ExecFunc>) -> Self {
+        let builtins = Self::from_set::<Builtins>();
+        let mut registry = Self::new();
+
+        for entry in builtins.entries.iter() {
+            if let Some(func) = lookup(&entry.name) {
+                registry.insert(Entry {
+                    func,
+                    ..entry.clone()
+                });
+            }
+        }
+
+        registry
+    }
+
+    /// Register `name`, replacing any instruction with the same name
+    ///
+    /// Operands are checked against `operands` when parsing, see [`InstSet::operands`].
+    pub fn register(&mut self, name: &str, func: ExecFunc, operands: OperandSpec) -> &mut Self {
+        self.insert(Entry {
+            name: name.to_uppercase(),
+            func,
+            operands,
+            flow: None,
+            doc: String::new(),
+        });
+        self
+    }
+
+    /// Register `name`, see [`InstRegistry::register`]
+    #[must_use]
+    pub fn with(mut self, name: &str, func: ExecFunc, operands: OperandSpec) -> Self {
+        self.register(name, func, operands);
+        self
+    }
+
+    /// Register every instruction of `T`, with its operands, flow and docs
+    pub fn extend_from<T>(&mut self) -> &mut Self
+    where
+        T: InstSet,
+        <T as FromStr>::Err: Display,
+    {
+        // IDs generated by `inst_set` and `extend` are consecutive from 0
+        for inst in (0..).map_while(|id| T::from_id(id).ok()) {
+            self.insert(Entry {
+                name: inst.mnemonic().to_uppercase(),
+                func: inst.as_func_ptr(),
+                operands: inst.operands(),
+                flow: inst.flow(),
+                doc: inst.doc().to_string(),
+            });
+        }
+
+        self
+    }
+
+    /// Names of the registered instructions, in order of registration
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| e.name.as_str())
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        let entries = Arc::make_mut(&mut self.entries);
+
+        match entries.iter_mut().find(|e| e.name == entry.name) {
+            Some(existing) => *existing = entry,
+            None => entries.push(entry),
+        }
+    }
+
+    fn get(&self, id: u64) -> Option<DynInstSet> {
+        let idx = usize::try_from(id).ok()?;
+
+        self.entries.get(idx).map(|entry| DynInstSet {
+            id,
+            entry: entry.clone(),
+        })
+    }
+
+    /// Run `f` with this registry providing the instructions of [`DynInstSet`] on this thread
+    ///
+    /// Use this to call generic APIs such as [`compile`](crate::compile::compile) or
+    /// [`Executor::step`] with [`DynInstSet`]. Scopes can be nested, and the previous registry is
+    /// restored afterwards.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Restore(Option<InstRegistry>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let prev = self.0.take();
+                CURRENT.with(|current| *current.borrow_mut() = prev);
+            }
+        }
+
+        let prev = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let _restore = Restore(prev);
+
+        f()
+    }
+
+    /// Parse `prog` into an [`Executor`] using the registered instructions
+    pub fn jit(&self, prog: impl Deref<Target = str>, io: Io) -> Result<Executor, ErrorMap> {
+        self.scope(|| jit::<DynInstSet>(prog, io))
+    }
+
+    /// Execute `exec` until it ends, see [`Executor::exec_and_report`]
+    pub fn exec(&self, exec: &mut Executor) -> Exit {
+        self.scope(|| exec.exec_and_report())
+    }
+}
+
+/// An instruction from the [`InstRegistry`] in [scope](InstRegistry::scope)
+///
+/// Parsing or decoding an instruction outside of a scope fails.
+#[derive(Clone)]
+pub struct DynInstSet {
+    id: u64,
+    entry: Entry,
+}
+
+impl FromStr for DynInstSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CURRENT.with(|current| {
+            let current = current.borrow();
+            let registry = current.as_ref().ok_or_else(no_registry)?;
+            let name = s.to_uppercase();
+
+            registry
+                .entries
+                .iter()
+                .position(|e| e.name == name)
+                .and_then(|idx| registry.get(idx as u64))
+                .ok_or_else(|| format!("{s} is not an instruction"))
+        })
+    }
+}
+
+impl Display for DynInstSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.entry.name)
+    }
+}
+
+impl InstSet for DynInstSet {
+    fn as_func_ptr(&self) -> ExecFunc {
+        self.entry.func
+    }
+
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn from_id(id: u64) -> Result<Self, String> {
+        CURRENT.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .ok_or_else(no_registry)?
+                .get(id)
+                .ok_or_else(|| format!("0x{id:X} is not a valid instruction ID"))
+        })
+    }
+
+    fn operands(&self) -> OperandSpec {
+        self.entry.operands
+    }
+
+    fn flow(&self) -> Option<Flow> {
+        self.entry.flow
+    }
+
+    fn mnemonic(&self) -> &str {
+        &self.entry.name
+    }
+
+    fn doc(&self) -> &str {
+        &self.entry.doc
+    }
+}
+
+fn no_registry() -> String {
+    "No instruction registry is in scope, see `InstRegistry::scope`".into()
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use super::{DynInstSet, InstRegistry};
+    use crate::{
+        exec::{Context, RtResult},
+        inst::{InstSet, Op},
+        make_io,
+        parse::{jit, DefaultSet, ErrorKind},
+        TestStdio, PROGRAMS,
+    };
+
+    #[test]
+    fn same_as_static() {
+        let registry = InstRegistry::from_set::<DefaultSet>();
+
+        for (prog, exp, inp, out) in PROGRAMS {
+            let s = TestStdio::new(vec![]);
+            let mut exec = registry
+                .jit(prog, make_io!(TestStdio::new(inp), s.clone()))
+                .unwrap();
+            registry.exec(&mut exec);
+
+            assert_eq!(exec.ctx.acc, exp);
+            assert_eq!(s.to_vec(), out);
+        }
+    }
+
+    #[test]
+    fn custom() {
+        fn twice(ctx: &mut Context, op: &Op) -> RtResult {
+            ctx.acc = ctx.read(op)? * 2;
+            Ok(())
+        }
+
+        let mut registry = InstRegistry::new();
+        registry
+            .register("twice", twice, None)
+            .extend_from::<crate::parse::Core>();
+
+        assert_eq!(registry.names().next(), Some("TWICE"));
+
+        let mut exec = registry
+            .jit("TWICE #4\nEND\n\nNONE:\n", make_io!())
+            .unwrap();
+        registry.exec(&mut exec);
+        assert_eq!(exec.ctx.acc, 8);
+
+        // `STO` only accepts an address
+        let errors = registry
+            .jit("STO #1\nEND\n\nNONE:\n", make_io!())
+            .unwrap_err();
+        assert_eq!(errors.get(&(0..6)), Some(&ErrorKind::InvalidOperand));
+
+        let ldr = registry.scope(|| "ldr".parse::<DynInstSet>().ok().unwrap());
+        assert_eq!(ldr.doc(), "Load a literal into `IX`");
+
+        // Nothing is in scope any more
+        assert!(jit::<DynInstSet>("END\n\nNONE:\n", make_io!()).is_err());
+        assert!(DynInstSet::from_id(0).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn spec() {
+        use super::{SetSpec, SpecError};
+
+        let spec = serde_json::from_str::<SetSpec>(
+            r#"{"instructions": [
+                {"name": "ldm", "aliases": ["SET"]},
+                {"name": "PRINT", "builtin": "out"},
+                {"name": "END"}
+            ]}"#,
+        )
+        .unwrap();
+        let registry = InstRegistry::from_spec(&spec).unwrap();
+
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["LDM", "SET", "PRINT", "END"]
+        );
+
+        let s = TestStdio::new(vec![]);
+        let mut exec = registry
+            .jit(
+                "LDM #65\nPRINT\nSET #66\nPRINT\nEND\n\nNONE:\n",
+                make_io!(std::io::empty(), s.clone()),
+            )
+            .unwrap();
+        registry.exec(&mut exec);
+        assert_eq!(s.to_vec(), b"AB");
+
+        assert!(registry.jit("OUT\nEND\n\nNONE:\n", make_io!()).is_err());
+
+        for (json, err) in [
+            (
+                r#"{"instructions": [{"name": "FOO"}]}"#,
+                SpecError::UnknownBuiltin("FOO".into()),
+            ),
+            (
+                r#"{"instructions": [{"name": "OUT"}, {"name": "PRINT", "builtin": "OUT", "aliases": ["out"]}]}"#,
+                SpecError::Duplicate("OUT".into()),
+            ),
+        ] {
+            let spec = serde_json::from_str::<SetSpec>(json).unwrap();
+            assert_eq!(InstRegistry::from_spec(&spec).err(), Some(err));
+        }
+    }
+}
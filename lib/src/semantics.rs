@@ -0,0 +1,161 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Machine-readable record of changes to instruction semantics
+//!
+//! The semantics version is bumped whenever an existing program could behave differently after
+//! upgrading, independently of the crate version. Tools that depend on exact behaviour, such as
+//! graders, can [`pin`] the version they were written against, and inspect the [`CHANGELOG`] to
+//! see what changed.
+
+use thiserror::Error;
+
+/// Current version of the instruction semantics
+pub const VERSION: u32 = 5;
+
+/// Current version of the instruction semantics
+pub fn semantics_version() -> u32 {
+    VERSION
+}
+
+/// A change in the behaviour of one or more instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    /// Semantics version that introduced the change
+    pub version: u32,
+    /// Affected instructions, in uppercase
+    pub insts: &'static [&'static str],
+    pub summary: &'static str,
+}
+
+/// All changes since version 1, oldest first
+pub const CHANGELOG: &[Change] = &[
+    Change {
+        version: 2,
+        insts: &["ADD", "SUB", "INC", "DEC"],
+        summary: "Overflow wraps around instead of panicking in debug builds",
+    },
+    Change {
+        version: 2,
+        insts: &["LSL", "LSR"],
+        summary: "Shifting by the word width or more is masked instead of panicking in debug builds",
+    },
+    Change {
+        version: 2,
+        insts: &["LDX"],
+        summary: "An index that overflows the address is an invalid indexed address error instead of a panic",
+    },
+    Change {
+        version: 2,
+        insts: &["IN", "RIN"],
+        summary: "Pending output is flushed before reading input",
+    },
+    Change {
+        version: 2,
+        insts: &["DBG"],
+        summary: "Without an operand, the context is printed in a readable format instead of its debug representation",
+    },
+    Change {
+        version: 3,
+        insts: &["CALL", "RET"],
+        summary: "Calls are nested: `RET` restores the return address of the enclosing call",
+    },
+    Change {
+        version: 4,
+        insts: &["CMP"],
+        summary: "With a word width, values are wrapped to the width before comparing, so negative literals are equal to their two's complement",
+    },
+    Change {
+        version: 5,
+        insts: &[
+            "LDM", "LDD", "LDI", "LDX", "MOV", "STO", "CMP", "JPE", "JPN", "JMP", "CMI", "IN", "OUT",
+            "INC", "DEC", "ADD", "SUB", "AND", "OR", "XOR", "LSL", "LSR",
+        ],
+        summary: "The operand `SP` is the stack pointer register, unless the program defines a label named `SP`",
+    },
+];
+
+/// Changes made after `version`, oldest first
+pub fn changes_since(version: u32) -> impl Iterator<Item = &'static Change> {
+    CHANGELOG
+        .iter()
+        .filter(move |change| change.version > version)
+}
+
+/// Changes that affect `inst`, oldest first. The opcode is not case sensitive.
+pub fn changes_for(inst: &str) -> impl Iterator<Item = &'static Change> + '_ {
+    CHANGELOG.iter().filter(move |change| {
+        change
+            .insts
+            .iter()
+            .any(|other| other.eq_ignore_ascii_case(inst))
+    })
+}
+
+/// Errors returned by [`pin`]
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum SemanticsError {
+    #[error("Semantics version {0} is newer than the supported version {VERSION}")]
+    Unknown(u32),
+    #[error("Semantics changed from version {pinned} to {VERSION} ({} changes)", changes.len())]
+    Changed {
+        pinned: u32,
+        changes: Vec<&'static Change>,
+    },
+}
+
+/// Check that instructions still behave as they did in `version`
+///
+/// Older semantics are not emulated, so this fails if anything changed since `version`. Changes
+/// that do not affect the instructions a program uses can be found with [`changes_since`] and
+/// [`changes_for`] instead.
+pub fn pin(version: u32) -> Result<(), SemanticsError> {
+    if version > VERSION {
+        return Err(SemanticsError::Unknown(version));
+    }
+
+    let changes = changes_since(version).collect::<Vec<_>>();
+
+    if changes.is_empty() {
+        Ok(())
+    } else {
+        Err(SemanticsError::Changed {
+            pinned: version,
+            changes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod semantics_tests {
+    use super::*;
+
+    #[test]
+    fn changelog() {
+        // Sorted, and every version up to the current one is recorded
+        assert!(CHANGELOG.windows(2).all(|w| w[0].version <= w[1].version));
+        assert_eq!(CHANGELOG.last().unwrap().version, VERSION);
+        assert!(CHANGELOG
+            .iter()
+            .flat_map(|change| change.insts)
+            .all(|inst| inst.chars().all(|c| c.is_ascii_uppercase())));
+
+        assert_eq!(changes_since(VERSION).count(), 0);
+        assert_eq!(changes_since(0).count(), CHANGELOG.len());
+        assert_eq!(changes_for("ret").count(), 1);
+        assert_eq!(changes_for("LDM").count(), 1);
+        assert_eq!(changes_for("END").count(), 0);
+    }
+
+    #[test]
+    fn pinning() {
+        assert_eq!(pin(VERSION), Ok(()));
+        assert_eq!(pin(VERSION + 1), Err(SemanticsError::Unknown(VERSION + 1)));
+        assert!(matches!(
+            pin(2),
+            Err(SemanticsError::Changed { pinned: 2, changes }) if changes.len() == 3
+        ));
+    }
+}
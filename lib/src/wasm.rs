@@ -0,0 +1,266 @@
+// Copyright (c) 2021 Saadi Save
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Bindings for JavaScript with `wasm-bindgen`, for browser playgrounds
+//!
+//! A [`Playground`] runs a program with the [`DefaultSet`], reading input from and writing
+//! output to JavaScript callbacks:
+//!
+//! ```js
+//! const playground = new Playground(src, () => prompt("Input"), (text) => console.log(text));
+//!
+//! while (playground.step() === StepStatus.Continue) {
+//!     highlight(playground.currentSpan());
+//! }
+//! ```
+
+use crate::{
+    exec::{Executor, Io, Status},
+    parse::{jit, DefaultSet, Diagnostics},
+};
+use js_sys::Function;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Read, Write},
+    sync::atomic::{AtomicU32, Ordering},
+};
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    /// Callbacks of each [`Playground`], kept here because JavaScript values cannot be sent
+    /// between threads, as [`Io`] requires
+    static CALLBACKS: RefCell<HashMap<u32, Callbacks>> = RefCell::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+struct Callbacks {
+    input: Function,
+    output: Function,
+}
+
+fn with_callbacks<T>(id: u32, f: impl FnOnce(&Callbacks) -> io::Result<T>) -> io::Result<T> {
+    CALLBACKS.with(|callbacks| match callbacks.borrow().get(&id) {
+        Some(callbacks) => f(callbacks),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            "Callbacks are only available on the thread that created the playground",
+        )),
+    })
+}
+
+fn js_error(e: &JsValue) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{e:?}"))
+}
+
+/// Reads lines returned by the input callback, until it returns `null` or `undefined`
+struct JsReader {
+    id: u32,
+    buf: Vec<u8>,
+}
+
+impl Read for JsReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.buf.is_empty() {
+            let input = with_callbacks(self.id, |c| {
+                c.input.call0(&JsValue::NULL).map_err(|e| js_error(&e))
+            })?;
+
+            match input.as_string() {
+                Some(line) if line.ends_with('\n') => self.buf = line.into_bytes(),
+                Some(line) => self.buf = format!("{line}\n").into_bytes(),
+                None => return Ok(0),
+            }
+        }
+
+        let len = out.len().min(self.buf.len());
+        out[..len].copy_from_slice(&self.buf[..len]);
+        self.buf.drain(..len);
+
+        Ok(len)
+    }
+}
+
+/// Passes output to the output callback as strings, holding back incomplete characters
+struct JsWriter {
+    id: u32,
+    buf: Vec<u8>,
+}
+
+impl Write for JsWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+
+        let valid = match std::str::from_utf8(&self.buf) {
+            Ok(s) => s.len(),
+            // Invalid bytes are replaced rather than held back forever
+            Err(e) if e.error_len().is_some() => self.buf.len(),
+            Err(e) => e.valid_up_to(),
+        };
+
+        if valid > 0 {
+            let text = String::from_utf8_lossy(&self.buf[..valid]).into_owned();
+            self.buf.drain(..valid);
+
+            with_callbacks(self.id, |c| {
+                c.output
+                    .call1(&JsValue::NULL, &JsValue::from_str(&text))
+                    .map_err(|e| js_error(&e))
+            })?;
+        }
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Result of running a [`Playground`]
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Continue = "continue",
+    Complete = "complete",
+    /// See [`Playground::error`]
+    Error = "error",
+    Breakpoint = "breakpoint",
+    Cancelled = "cancelled",
+}
+
+/// A program being run from JavaScript
+#[wasm_bindgen]
+pub struct Playground {
+    id: u32,
+    exec: Executor,
+    error: Option<String>,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    /// Parse `src`, reading input from `input` and writing output to `output`
+    ///
+    /// `input` is called with no arguments whenever input is needed, and returns a line, or
+    /// `null` at the end of input. `output` is called with each piece of output as a string.
+    /// If `src` cannot be parsed, the error is the problems rendered as text.
+    #[wasm_bindgen(constructor)]
+    pub fn new(src: &str, input: Function, output: Function) -> Result<Playground, JsError> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        let io = Io::new(
+            JsReader {
+                id,
+                buf: Vec::new(),
+            },
+            JsWriter {
+                id,
+                buf: Vec::new(),
+            },
+        );
+
+        let exec = jit::<DefaultSet>(src, io)
+            .map_err(|errors| JsError::new(&Diagnostics::new(src, &errors).to_string()))?;
+
+        CALLBACKS.with(|callbacks| {
+            callbacks
+                .borrow_mut()
+                .insert(id, Callbacks { input, output })
+        });
+
+        Ok(Self {
+            id,
+            exec,
+            error: None,
+        })
+    }
+
+    /// Execute one instruction
+    pub fn step(&mut self) -> StepStatus {
+        let status = self.exec.step();
+        self.status(status)
+    }
+
+    /// Execute up to `max_steps` instructions, stopping early if the program stops
+    pub fn run(&mut self, max_steps: u32) -> StepStatus {
+        let status = self.exec.run_for(max_steps.into());
+        self.status(status)
+    }
+
+    fn status(&mut self, status: Status) -> StepStatus {
+        match status {
+            Status::Continue => StepStatus::Continue,
+            Status::Complete => StepStatus::Complete,
+            Status::Error(e) => {
+                self.error = Some(e.to_string());
+                StepStatus::Error
+            }
+            Status::Breakpoint(_) => StepStatus::Breakpoint,
+            Status::Cancelled => StepStatus::Cancelled,
+        }
+    }
+
+    /// Message of the last runtime error
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn acc(&self) -> usize {
+        self.exec.ctx.acc
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ix(&self) -> usize {
+        self.exec.ctx.ix
+    }
+
+    /// Address of the next instruction
+    #[wasm_bindgen(getter)]
+    pub fn mar(&self) -> usize {
+        self.exec.ctx.mar
+    }
+
+    /// Result of the last comparison
+    #[wasm_bindgen(getter)]
+    pub fn cmp(&self) -> bool {
+        self.exec.ctx.cmp
+    }
+
+    /// Value of general purpose register `r{idx}`
+    pub fn gpr(&self, idx: usize) -> Option<usize> {
+        self.exec.ctx.gprs.get(idx).copied()
+    }
+
+    /// Addresses in memory, in ascending order
+    #[wasm_bindgen(js_name = memoryAddresses)]
+    pub fn memory_addresses(&self) -> Vec<usize> {
+        self.exec.ctx.mem.iter().map(|(&addr, _)| addr).collect()
+    }
+
+    /// Value in memory at `addr`
+    #[wasm_bindgen(js_name = readMemory)]
+    pub fn read_memory(&self, addr: usize) -> Option<usize> {
+        self.exec.ctx.mem.get(&addr).ok().copied()
+    }
+
+    /// Start and end of the next instruction in the source
+    #[wasm_bindgen(js_name = currentSpan)]
+    pub fn current_span(&self) -> Option<Vec<usize>> {
+        self.exec
+            .debug_info
+            .span_for_inst(self.exec.ctx.mar)
+            .map(|span| vec![span.start, span.end])
+    }
+}
+
+impl Drop for Playground {
+    fn drop(&mut self) {
+        // Nothing to clean up if this is not the thread that created it
+        let _ = CALLBACKS.try_with(|callbacks| callbacks.borrow_mut().remove(&self.id));
+    }
+}
@@ -18,6 +18,7 @@ mod extension {
 
     extend! {
         Ext extends Core use super::*; {
+            /// Print a message
             EXT => ext,
         }
     }
@@ -43,11 +44,25 @@ NONE:
 
         let mut e = cambridge_asm::parse::jit::<Ext>(PROG, make_io!(std::io::stdin(), out.clone()))
             .unwrap();
-        e.exec::<Ext>();
+        e.exec_and_report();
         assert_eq!(e.ctx.acc, 65);
         assert_eq!(e.ctx.gprs[0], 20);
         assert_eq!(out.to_vec(), b"AThis is a custom instruction\nA");
     }
+
+    #[test]
+    fn metadata() {
+        use cambridge_asm::inst::InstSet;
+
+        for (inst, doc) in [
+            ("EXT", "Print a message"),
+            ("LDR", "Load a literal into `IX`"),
+        ] {
+            let inst = inst.parse::<Ext>().unwrap();
+            assert_eq!(inst.doc(), doc);
+            assert_eq!(inst.mnemonic(), inst.to_string());
+        }
+    }
 }
 
 /// Using a completely custom instruction set
@@ -78,6 +93,8 @@ mod custom {
 
     inst_set! {
         Custom {
+            /// Greet someone,
+            /// or everyone
             GREET => greet,
             FROM => from,
             END => cambridge_asm::exec::io::end,
@@ -102,8 +119,54 @@ NONE:
                         .for_each(|(r, e)| println!("{} : {e:?}", &PROG[r.clone()]));
                     panic!()
                 });
-        e.exec::<Custom>();
+        e.exec_and_report();
 
         assert_eq!(out.to_vec(), b"Hello!\nFrom Pseudoassembly\n");
     }
+
+    #[test]
+    fn metadata() {
+        use cambridge_asm::inst::InstSet;
+
+        let greet = "greet".parse::<Custom>().unwrap();
+        assert_eq!(greet.mnemonic(), "GREET");
+        assert_eq!(greet.doc(), "Greet someone, or everyone");
+        assert_eq!("END".parse::<Custom>().unwrap().doc(), "");
+    }
+}
+
+/// Using the floating point extension
+#[cfg(feature = "float")]
+mod float {
+    use super::TestStdio;
+    use cambridge_asm::parse::{jit, Float};
+
+    #[test]
+    fn float() {
+        const PROG: &str = r#"LDM r1,#3
+ITOF r1
+LDM #2
+ITOF
+FDIV r1,ACC
+FOUT r1
+NL
+FMUL r1,r1
+FSUB r1,#0
+FOUT r1,#3
+NL
+MOV ACC,r1
+FTOI
+END
+
+NONE:
+"#;
+
+        let out = TestStdio::new(vec![]);
+
+        let mut e = jit::<Float>(PROG, make_io!(std::io::empty(), out.clone())).unwrap();
+        e.exec_and_report();
+
+        assert_eq!(out.to_vec(), b"1.5\n2.250\n");
+        assert_eq!(e.ctx.acc, 2);
+    }
 }
@@ -0,0 +1,57 @@
+use cambridge_asm::{
+    exec::RtError,
+    make_io,
+    metrics::{set_metrics, Metrics},
+    parse::{jit, DefaultSet, ErrorMap},
+};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+#[derive(Default)]
+struct Counters {
+    parsed: AtomicU64,
+    parse_failed: AtomicU64,
+    executed: AtomicU64,
+    errors: Mutex<Vec<&'static str>>,
+}
+
+impl Metrics for Counters {
+    fn program_parsed(&self) {
+        self.parsed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn parse_failed(&self, _: &ErrorMap) {
+        self.parse_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn instruction_executed(&self) {
+        self.executed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn runtime_error(&self, err: &RtError) {
+        self.errors.lock().unwrap().push(err.kind());
+    }
+}
+
+#[test]
+fn counters() {
+    let counters: &'static Counters = Box::leak(Box::default());
+    set_metrics(counters);
+
+    let io = || make_io!(std::io::empty(), std::io::sink());
+
+    jit::<DefaultSet>("LDM #1\nINC ACC\nEND\n\nNONE:\n", io())
+        .unwrap()
+        .exec_and_report();
+    jit::<DefaultSet>("LDD 500\nEND\n\nNONE:\n", io())
+        .unwrap()
+        .exec_and_report();
+    assert!(jit::<DefaultSet>("FOO\n\nNONE:\n", io()).is_err());
+
+    assert_eq!(counters.parsed.load(Ordering::Relaxed), 2);
+    assert_eq!(counters.parse_failed.load(Ordering::Relaxed), 1);
+    assert_eq!(counters.executed.load(Ordering::Relaxed), 4);
+    assert_eq!(*counters.errors.lock().unwrap(), ["InvalidAddr"]);
+}
@@ -0,0 +1,123 @@
+//! Adversarial inputs that must produce errors instead of panics with `strict-no-panic`
+
+#![cfg(feature = "strict-no-panic")]
+
+use cambridge_asm::{
+    exec::{Executor, Status},
+    make_io,
+    parse::{jit, DefaultSet},
+};
+
+const SOURCES: &[&str] = &[
+    "",
+    "\n\n\n",
+    "LDM #1\nEND\n",
+    "LDM #1\nEND\n\n\n",
+    "// only a comment",
+    "LDM\n\nNONE:\n",
+    "LDD 99999999999999999999999999\n\nNONE:\n",
+    "LDM #xFFFFFFFFFFFFFFFFFFFF\n\nNONE:\n",
+    "LDM r30,#1\nEND\n\nNONE:\n",
+    "LDI (r99)\nEND\n\nNONE:\n",
+    "LDI (r)\nEND\n\nNONE:\n",
+    "LDI (r99999999999999999999999)\nEND\n\nNONE:\n",
+    "LDI ()\nEND\n\nNONE:\n",
+    "END\n\n0 [0;99999999999999999999999]\n",
+    "END\n\n18446744073709551615 [0;2]\n",
+    "END\n\n18446744073709551615 0\n",
+    "LDD A\nEND\n\n0 1\n18446744073709551615 1\nA: 1\n",
+    "JMP 500\n\nNONE:\n",
+    "JMP #18446744073709551615\n\nNONE:\n",
+    "LDM #18446744073709551615\nINC ACC\nADD ACC,#18446744073709551615\nEND\n\nNONE:\n",
+    "DEC ACC\nSUB #5\nEND\n\nNONE:\n",
+    "LDM #1\nLSL #200\nLSR #200\nLSL ACC,#64\nLSR ACC,ACC,#64\nEND\n\nNONE:\n",
+    "LDR #18446744073709551615\nLDX 1\nEND\n\n0 0\n",
+    "ADD ACC,ACC,ACC,ACC\nOUT #300\nMOV\nSTO #1\nCMP\nEND\n\nNONE:\n",
+    "LABEL: LABEL: LABEL\n\nLABEL:\n",
+    ":::\n,,,\n\n:\n",
+    "가나다 라마\n\n바:\n",
+    "LDM #1 // \u{0}\u{FFFF}\r\nEND\r\n\r\nNONE:\r\n",
+];
+
+fn run(mut exec: Executor) {
+    for _ in 0..1000 {
+        match exec.step() {
+            Status::Continue | Status::Breakpoint(_) => {}
+            Status::Complete | Status::Cancelled | Status::Error(_) => return,
+        }
+    }
+}
+
+#[test]
+fn adversarial_sources() {
+    for src in SOURCES {
+        let io = make_io!(std::io::empty(), std::io::sink());
+
+        if let Ok(exec) = jit::<DefaultSet>(*src, io) {
+            run(exec);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "compile")]
+fn adversarial_compiled() {
+    use cambridge_asm::compile::CompiledProg;
+
+    const PROGS: &[&str] = &[
+        r#"{"header":{"version":1,"inst_set":""},"prog":{"0":{"id":0,"inst":"FOO","op":"Null"}},"mem":{},"debug_info":null}"#,
+        r#"{"header":{"version":1,"inst_set":""},"prog":{"0":{"id":0,"inst":"LDM","op":{"Gpr":99}}},"mem":{},"debug_info":null}"#,
+        r#"{"header":{"version":1,"inst_set":""},"prog":{"0":{"id":0,"inst":"LDD","op":{"Indirect":{"Gpr":30}}}},"mem":{},"debug_info":null}"#,
+    ];
+
+    for prog in PROGS {
+        let prog = serde_json::from_str::<CompiledProg>(prog).unwrap();
+        assert!(prog
+            .try_to_executor::<DefaultSet>(make_io!(std::io::empty(), std::io::sink()))
+            .is_err());
+    }
+
+    // Valid instruction, but the program jumps outside itself
+    let prog = r#"{"header":{"version":1,"inst_set":""},"prog":{"0":{"id":9999,"inst":"JMP","op":{"Addr":7}}},"mem":{},"debug_info":null}"#;
+    let exec = serde_json::from_str::<CompiledProg>(prog)
+        .unwrap()
+        .try_to_executor::<DefaultSet>(make_io!(std::io::empty(), std::io::sink()))
+        .unwrap();
+
+    run(exec);
+}
+
+#[test]
+fn adversarial_api() {
+    use cambridge_asm::{exec::RtError, inst::Op};
+
+    let mut exec = jit::<DefaultSet>("END\n\nNONE:\n", make_io!()).unwrap();
+
+    assert!(matches!(
+        exec.ctx.try_get_register(&Op::Addr(0)),
+        Err(RtError::InvalidOperand)
+    ));
+    assert!(matches!(
+        exec.ctx.try_get_mut_register(&Op::Literal(0)),
+        Err(RtError::InvalidOperand)
+    ));
+
+    assert!(matches!(
+        exec.ctx.read(&Op::Indirect(Box::new(Op::Null))),
+        Err(RtError::InvalidOperand)
+    ));
+    assert!(matches!(
+        exec.ctx.try_get_register(&Op::Gpr(30)),
+        Err(RtError::InvalidOperand)
+    ));
+    assert!(matches!(
+        exec.ctx.modify(&Op::Gpr(30), |_| ()),
+        Err(RtError::InvalidOperand)
+    ));
+
+    let missing = exec.prog[5].clone();
+    assert!(matches!(
+        (missing.func)(&mut exec.ctx, &missing.op),
+        Err(RtError::InvalidInstAddr(0))
+    ));
+}